@@ -0,0 +1,556 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use node::config::Config;
+use node::prelude::*;
+use node::topology::{diameter, generate};
+use serde::{Deserialize, Serialize};
+
+// the workload's own messages, see "Body".
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Gossip {
+    // gossip of values queued for a neighbor, see "Node::queue_gossip". acknowledged with a
+    // "broadcast_ok", "hops" is the fewest any of the values took.
+    BroadcastBatch {
+        msg_id: MessageId,
+        messages: Vec<BroadcastMessage>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        hops: Option<u32>,
+    },
+}
+
+impl Body for Gossip {
+    const TYPES: &'static [&'static str] = &["broadcast_batch"];
+}
+
+// gossip goes through the outbox, a neighbor that doesn't acknowledge gets it again.
+// it goes no further than the diameter of the topology, see "Node::push_broadcast_message_hops",
+// and waits for the next batch with a "Config::gossip_interval" (see "gossip").
+fn broadcast_message(
+    node: &mut Node,
+    src: NodeId,
+    message: BroadcastMessage,
+    hops: u32,
+    out: &mut dyn Sink,
+) -> Result<()> {
+    if node.push_broadcast_message_hops(message, hops)? {
+        let neighbors = node.neighbors().clone(); // FIXME
+        for neighbor in neighbors {
+            if neighbor != src && !node.queue_gossip(neighbor, message, hops + 1) {
+                let body = Workload::Broadcast {
+                    msg_id: node.gen_msg_id(),
+                    message,
+                    hops: Some(hops + 1),
+                };
+                out.send(node.send_reliably(neighbor, body)?);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn handler_broadcast(node: &mut Node, msg: Message, out: &mut dyn Sink) -> Result<()> {
+    expect_body!(
+        msg,
+        Broadcast {
+            msg_id,
+            message,
+            hops
+        }
+    );
+    broadcast_message(node, msg.src, message, hops.unwrap_or(0), out)?;
+    out.send(node.reply_to((msg.src, msg_id), Workload::broadcast_ok));
+    Ok(())
+}
+
+fn handler_broadcast_batch(
+    node: &mut Node,
+    msg: Message<Gossip>,
+    out: &mut dyn Sink,
+) -> Result<()> {
+    let Gossip::BroadcastBatch {
+        msg_id,
+        messages,
+        hops,
+    } = msg.body;
+    for message in messages {
+        broadcast_message(node, msg.src, message, hops.unwrap_or(0), out)?;
+    }
+    out.send(node.reply_to((msg.src, msg_id), Workload::broadcast_ok));
+    Ok(())
+}
+
+// the gossip queued since the last batch, one message per neighbor.
+fn gossip(node: &mut Node, now: Instant) -> Result<Replies> {
+    let mut replies = Replies::new();
+    for (neighbor, messages, hops) in node.due_gossip(now) {
+        let body = Gossip::BroadcastBatch {
+            msg_id: node.gen_msg_id(),
+            messages,
+            hops: Some(hops),
+        };
+        replies.push(node.send_reliably(neighbor, Workload::custom(&body)?)?);
+    }
+    Ok(replies)
+}
+
+fn handler_read(node: &mut Node, msg: Message, out: &mut dyn Sink) -> Result<()> {
+    expect_body!(msg, Read { msg_id, .. });
+    let messages = Some(node.broadcast_snapshot());
+    let reply = node.reply_to((msg.src, msg_id), |in_reply_to, msg_id| Workload::ReadOk {
+        in_reply_to,
+        msg_id,
+        messages,
+        value: None,
+    });
+    out.send(reply);
+    Ok(())
+}
+
+fn handler_topology(node: &mut Node, msg: Message, out: &mut dyn Sink) -> Result<()> {
+    expect_body!(msg, Topology { msg_id, mut topology });
+    let node_id = node.node_id();
+    // unless the configuration picks a topology of its own.
+    let config = node.config();
+    if let Some(generated) = generate(config.topology, node.node_ids(), config.fanout) {
+        topology = generated;
+    }
+    node.set_max_hops(Some(diameter(&topology)));
+    let neighbors = topology.remove(&node_id).unwrap_or(Vec::new());
+    node.set_neighbors(neighbors);
+    out.send(node.reply_to((msg.src, msg_id), Workload::topology_ok));
+    Ok(())
+}
+
+// the reply cache, failure detector, hinted handoff, rate limit and memory guard are
+// enabled as "config" says, along with what every node takes from it.
+pub fn create_node(config: &Config) -> Node {
+    let mut handlers: HashMap<Type, Handler> = HashMap::new();
+    handlers.insert(Type::Broadcast, handler_broadcast);
+    handlers.insert(Type::Read, handler_read);
+    handlers.insert(Type::Topology, handler_topology);
+    let mut node = Node::with_config(handlers, config);
+    node.register_body(handler_broadcast_batch);
+    node.add_tick_hook(gossip);
+    // a retried broadcast is answered again, not gossiped again, be it from a client or from
+    // a neighbor's outbox.
+    node.enable_reply_cache(config.reply_cache);
+    if let Some((interval, timeout)) = config.failure_detector {
+        node.enable_failure_detector(interval, timeout);
+    }
+    if let Some(capacity) = config.hinted_handoff {
+        node.enable_hinted_handoff(capacity);
+    }
+    if let Some((rate, burst)) = config.rate_limit {
+        node.enable_rate_limit(rate, burst);
+    }
+    if let Some(threshold) = config.memory_limit {
+        node.enable_memory_guard(threshold);
+        node.add_eviction_hook(|node, _| {
+            node.prune_reply_cache(Duration::from_secs(60));
+        });
+    }
+    node
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use node::testing::convergence::Convergence;
+    use node::testing::message::{init, msg};
+    use node::testing::network::Network;
+    use node::testing::sim::simulate;
+    use std::time::Duration;
+
+    #[test]
+    fn test_broadcast() {
+        let mut node = create_node(&Config::default());
+        let init_json = r#"{"src":"c1","dest":"n1","body":{"type":"init","msg_id":1,"node_id":"n1","node_ids":["n1","n2","n3"]}}"#;
+        let init_message = serde_json::from_str::<Message>(init_json).unwrap();
+        let _ = node.process(init_message);
+
+        let broadcast_json =
+            r#"{"src":"c1","dest":"n1","body":{"type":"broadcast","message":1000,"msg_id":1}}"#;
+        let broadcast_message = serde_json::from_str::<Message>(broadcast_json).unwrap();
+        let reply = node.process(broadcast_message);
+        assert!(reply.is_ok());
+
+        let reply = serde_json::to_string(&reply.unwrap().first().unwrap()).unwrap();
+        assert_eq!(
+            reply,
+            r#"{"src":"n1","dest":"c1","body":{"type":"broadcast_ok","in_reply_to":1,"msg_id":1}}"#
+        );
+
+        let broadcast_json =
+            r#"{"src":"c1","dest":"n1","body":{"type":"broadcast","message":10,"msg_id":2}}"#;
+        let broadcast_message = serde_json::from_str::<Message>(broadcast_json).unwrap();
+        let _ = node.process(broadcast_message);
+
+        let read_json = r#"{"src":"c1","dest":"n1","body":{"type":"read","msg_id":2}}"#;
+        let read_message = serde_json::from_str::<Message>(read_json).unwrap();
+        let reply = node.process(read_message);
+        assert!(reply.is_ok());
+
+        let reply = serde_json::to_string(&reply.unwrap().first().unwrap()).unwrap();
+        assert_eq!(
+            reply,
+            r#"{"src":"n1","dest":"c1","body":{"type":"read_ok","in_reply_to":2,"msg_id":3,"messages":[1000,10]}}"#
+        );
+    }
+
+    #[test]
+    fn test_broadcast_multi_node() {
+        let mut network = Network::new(3, || create_node(&Config::default()));
+        let topology_json = r#"{"src":"c1","dest":"n1","body":{"type":"topology","msg_id":1,"topology":{"n1":["n2"],"n2":["n1","n3"],"n3":["n2"]}}}"#;
+        for node_id in network.node_ids() {
+            let mut topology = serde_json::from_str::<Message>(topology_json).unwrap();
+            topology.dest = node_id;
+            network.send(topology);
+        }
+        network.run(100);
+
+        let broadcast_json =
+            r#"{"src":"c1","dest":"n1","body":{"type":"broadcast","message":42,"msg_id":2}}"#;
+        network.send(serde_json::from_str::<Message>(broadcast_json).unwrap());
+        network.run(100);
+        for node_id in network.node_ids() {
+            assert_eq!(network.node(&node_id).broadcast_messages(), vec![42]);
+        }
+    }
+
+    #[test]
+    fn test_broadcast_simulated() {
+        // random delays reorder gossip, every value still reaches every node.
+        simulate(20, |seed| {
+            let mut network = Network::new(5, || create_node(&Config::default())).with_seed(seed);
+            network.set_delay(1, 50);
+            let topology_json = r#"{"src":"c1","dest":"n1","body":{"type":"topology","msg_id":1,"topology":{"n1":["n2"],"n2":["n1","n3"],"n3":["n2","n4"],"n4":["n3","n5"],"n5":["n4"]}}}"#;
+            for node_id in network.node_ids() {
+                let mut topology = serde_json::from_str::<Message>(topology_json).unwrap();
+                topology.dest = node_id;
+                network.send(topology);
+            }
+
+            let values: Vec<BroadcastMessage> = (0..10).collect();
+            for value in &values {
+                let dest = format!("n{}", network.rng().between(1, 5));
+                network.send(msg().to(&dest).id(2).broadcast(*value));
+            }
+            network.run_for(2_000, 100);
+
+            let mut convergence = Convergence::new(values);
+            for node_id in network.node_ids() {
+                let seen = network.node(&node_id).broadcast_messages().iter().copied();
+                convergence.read(node_id, seen);
+            }
+            if let Err(error) = convergence.check() {
+                panic!("{error}");
+            }
+        });
+    }
+
+    #[test]
+    fn test_broadcast_lossy() {
+        // lost gossip (or acknowledgements) is sent again until it gets through.
+        simulate(10, |seed| {
+            let mut network = Network::new(5, || create_node(&Config::default())).with_seed(seed);
+            network.set_delay(1, 20);
+            network.set_drop_probability(0.3);
+            // client requests skip the network so they can't be dropped, and the first round of
+            // gossip they cause is dropped instead.
+            let topology_json = r#"{"src":"c1","dest":"n1","body":{"type":"topology","msg_id":1,"topology":{"n1":["n2"],"n2":["n1","n3"],"n3":["n2","n4"],"n4":["n3","n5"],"n5":["n4"]}}}"#;
+            for node_id in network.node_ids() {
+                let mut topology = serde_json::from_str::<Message>(topology_json).unwrap();
+                topology.dest = node_id;
+                network
+                    .node_mut(&topology.dest.clone())
+                    .process(topology)
+                    .unwrap();
+            }
+
+            let values: Vec<BroadcastMessage> = (0..10).collect();
+            for value in &values {
+                let dest = format!("n{}", network.rng().between(1, 5));
+                let request = msg()
+                    .to(&dest)
+                    .id(*value as MessageId + 2)
+                    .broadcast(*value);
+                network.node_mut(&dest).process(request).unwrap();
+            }
+            network.run_for(10_000, 100);
+            network.set_drop_probability(0.0);
+            network.run_for(10_000, 100);
+
+            let mut convergence = Convergence::new(values);
+            for node_id in network.node_ids() {
+                let seen = network.node(&node_id).broadcast_messages().iter().copied();
+                convergence.read(node_id, seen);
+                assert!(network.node(&node_id).outbox().is_none_or(|o| o.is_empty()));
+            }
+            if let Err(error) = convergence.check() {
+                panic!("{error}");
+            }
+        });
+    }
+
+    #[test]
+    fn test_broadcast_hop_limit() {
+        // a line of 4 nodes, n2 is 2 hops from both ends.
+        let mut node = create_node(&Config::default());
+        init(&mut node, "n2", &["n1", "n2", "n3", "n4"]);
+        let topology = [
+            ("n1", &["n2"][..]),
+            ("n2", &["n1", "n3"]),
+            ("n3", &["n2", "n4"]),
+            ("n4", &["n3"]),
+        ];
+        node.process(msg().to("n2").topology(&topology)).unwrap();
+        assert_eq!(node.max_hops(), Some(3));
+
+        let gossip = |msg_id, hops| {
+            let body = Workload::Broadcast {
+                msg_id,
+                message: 7,
+                hops: Some(hops),
+            };
+            msg().from("n3").to("n2").body(body)
+        };
+        let forwarded = |replies: Replies| -> Vec<Option<u32>> {
+            replies
+                .into_iter()
+                .filter_map(|reply| match reply.body {
+                    Workload::Broadcast { hops, .. } => Some(hops),
+                    _ => None,
+                })
+                .collect()
+        };
+        // that many hops already, it goes no further.
+        assert!(forwarded(node.process(gossip(1, 3)).unwrap()).is_empty());
+        // in fewer hops it does, the copies sent before may have stopped short.
+        assert_eq!(
+            forwarded(node.process(gossip(2, 1)).unwrap()),
+            vec![Some(2)]
+        );
+        assert!(forwarded(node.process(gossip(3, 2)).unwrap()).is_empty());
+    }
+
+    #[test]
+    fn test_broadcast_hinted_handoff() {
+        // n1 gossips to n2 only, and can't reach it: n3 holds the value for n2 and delivers it
+        // while n1 is still cut off from n2.
+        let mut network = Network::new(3, || {
+            create_node(&Config {
+                failure_detector: Some((Duration::from_millis(100), Duration::from_millis(500))),
+                hinted_handoff: Some(64),
+                ..Config::default()
+            })
+        });
+        let topology_json = r#"{"src":"c1","dest":"n1","body":{"type":"topology","msg_id":1,"topology":{"n1":["n2"],"n2":["n1"],"n3":[]}}}"#;
+        for node_id in network.node_ids() {
+            let mut topology = serde_json::from_str::<Message>(topology_json).unwrap();
+            topology.dest = node_id;
+            network.send(topology);
+        }
+        network.run(100);
+        network.partition(&["n1"], &["n2"]);
+
+        network.send(msg().to("n1").id(2).broadcast(42));
+        network.run_for(2_000, 100);
+        assert_eq!(network.node("n1").suspects(), vec!["n2"]);
+        assert_eq!(network.node("n2").broadcast_messages(), vec![42]);
+        assert!(network.node("n3").broadcast_messages().is_empty());
+
+        // once healed, n2's acknowledgement empties n1's outbox.
+        network.heal();
+        network.run_for(10_000, 100);
+        for node_id in network.node_ids() {
+            assert!(network.node(&node_id).outbox().is_none_or(|o| o.is_empty()));
+            assert_eq!(network.node(&node_id).state()["hints"], 0);
+        }
+    }
+
+    #[test]
+    fn test_broadcast_rate_limited() {
+        // retries piling up behind a partition wait in n1's queue, once each, and go out no
+        // faster than the rate limit once it heals.
+        let mut network = Network::new(2, || {
+            create_node(&Config {
+                rate_limit: Some((10.0, 5)),
+                ..Config::default()
+            })
+        });
+        let topology_json = r#"{"src":"c1","dest":"n1","body":{"type":"topology","msg_id":1,"topology":{"n1":["n2"],"n2":["n1"]}}}"#;
+        for node_id in network.node_ids() {
+            let mut topology = serde_json::from_str::<Message>(topology_json).unwrap();
+            topology.dest = node_id;
+            network.send(topology);
+        }
+        network.run(100);
+        network.partition(&["n1"], &["n2"]);
+
+        let values: Vec<BroadcastMessage> = (0..30).collect();
+        for value in &values {
+            let request = msg().to("n1").id(*value as MessageId + 2).broadcast(*value);
+            network.send(request);
+        }
+        network.run_for(5_000, 100);
+        let queued = network.node("n1").state()["throttled"].as_u64().unwrap();
+        assert!(queued <= values.len() as u64, "{queued} queued");
+
+        network.heal();
+        network.run_for(10_000, 100);
+        let sent = network.node("n1").counters().sent_count("broadcast");
+        assert!(sent <= 5 + 10 * 15 + 1, "{sent} sent");
+        let mut seen = network.node("n2").broadcast_messages().to_vec();
+        seen.sort();
+        assert_eq!(seen, values);
+        assert!(network.node("n1").outbox().is_none_or(|o| o.is_empty()));
+    }
+
+    #[test]
+    fn test_broadcast_configured() {
+        // gossip every 200ms over a binary tree instead of the line maelstrom sends.
+        let config = Config {
+            gossip_interval: Some(Duration::from_millis(200)),
+            fanout: 2,
+            topology: node::config::TopologyStrategy::Tree,
+            ..Default::default()
+        };
+        let mut network = Network::new(5, || create_node(&config));
+        let topology_json = r#"{"src":"c1","dest":"n1","body":{"type":"topology","msg_id":1,"topology":{"n1":["n2"],"n2":["n1","n3"],"n3":["n2","n4"],"n4":["n3","n5"],"n5":["n4"]}}}"#;
+        for node_id in network.node_ids() {
+            let mut topology = serde_json::from_str::<Message>(topology_json).unwrap();
+            topology.dest = node_id;
+            network.send(topology);
+        }
+        network.run(100);
+        assert_eq!(network.node("n1").neighbors(), &["n2", "n3"]);
+        assert_eq!(network.node("n2").neighbors(), &["n1", "n4", "n5"]);
+
+        let values: Vec<BroadcastMessage> = (0..20).collect();
+        for value in &values {
+            network.send(msg().to("n1").id(*value as MessageId + 2).broadcast(*value));
+        }
+        network.run_for(2_000, 100);
+        for node_id in network.node_ids() {
+            let mut seen = network.node(&node_id).broadcast_messages().to_vec();
+            seen.sort();
+            assert_eq!(seen, values, "{node_id} is missing values");
+        }
+        // the values went out together, not one message each.
+        let counters = network.node("n1").counters();
+        assert_eq!(counters.sent_count("broadcast"), 0);
+        assert!((1..=4).contains(&counters.sent_count("broadcast_batch")));
+    }
+
+    #[test]
+    fn test_broadcast_history() {
+        // why didn't n3 learn 42? n2 kept sending it, nothing came back.
+        let config = Config {
+            history: Some(64),
+            ..Default::default()
+        };
+        let mut network = Network::new(3, || create_node(&config));
+        let topology = [("n1", &["n2"][..]), ("n2", &["n1", "n3"]), ("n3", &["n2"])];
+        for node_id in network.node_ids() {
+            network.send(msg().to(&node_id).topology(&topology));
+        }
+        network.run(100);
+        network.partition(&["n2"], &["n3"]);
+        network.send(msg().to("n1").id(2).broadcast(42));
+        network.run_for(1_000, 100);
+
+        let mentioning = |node_id: &str| -> Vec<String> {
+            let history = network.node(node_id).history().unwrap();
+            let events = history.mentioning(&serde_json::json!(42));
+            events.iter().map(|event| event.handler.clone()).collect()
+        };
+        assert!(mentioning("n3").is_empty());
+        let n2 = mentioning("n2");
+        assert_eq!(n2[0], "broadcast");
+        assert!(n2[1..].iter().all(|handler| handler == "tick"), "{n2:?}");
+        let history = network.node("n2").history().unwrap();
+        let retries = history
+            .events()
+            .flat_map(|event| &event.sent)
+            .filter(|sent| sent.dest == "n3")
+            .count();
+        assert!(retries > 1);
+        // and nothing ever came back from n3.
+        let from_n3 = history
+            .with_peer("n3")
+            .into_iter()
+            .filter_map(|e| e.received.as_ref());
+        assert_eq!(from_n3.filter(|received| received.src == "n3").count(), 0);
+
+        let state = network.node("n2").state();
+        assert_eq!(state["history"].as_array().unwrap().len(), history.len());
+    }
+
+    #[test]
+    fn test_broadcast_crash_recovery() {
+        // n2, in the middle of a line, crashes and comes back with what it had persisted.
+        let dir = std::env::temp_dir().join(format!("broadcast-crash-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let start = || {
+            let mut node = create_node(&Config::default());
+            node.enable_persistence(&dir, Duration::from_millis(100));
+            node
+        };
+        let mut network = Network::new(3, start);
+        let topology = [("n1", &["n2"][..]), ("n2", &["n1", "n3"]), ("n3", &["n2"])];
+        for node_id in network.node_ids() {
+            network.send(msg().to(&node_id).topology(&topology));
+        }
+        network.send(msg().to("n1").id(2).broadcast(1));
+        network.run_for(1_000, 100);
+
+        network.crash("n2");
+        network.send(msg().to("n1").id(3).broadcast(2));
+        network.send(msg().to("n3").id(3).broadcast(3));
+        network.run_for(1_000, 100);
+        assert_eq!(network.node("n1").broadcast_messages(), &[1, 2]);
+
+        // no topology is sent again, n2 gossips to the neighbors it persisted.
+        network.restart("n2", start());
+        assert_eq!(network.node("n2").broadcast_messages(), &[1]);
+        assert_eq!(network.node("n2").neighbors(), &["n1", "n3"]);
+        network.run_for(10_000, 100);
+        for node_id in network.node_ids() {
+            let node = network.node(&node_id);
+            let mut seen = node.broadcast_messages().to_vec();
+            seen.sort();
+            assert_eq!(seen, vec![1, 2, 3], "{node_id} is missing values");
+            assert!(node.outbox().is_none_or(|o| o.is_empty()));
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_broadcast_wal() {
+        // acknowledged values survive a crash right after the ok, long before the next save.
+        let dir = std::env::temp_dir().join(format!("broadcast-wal-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let start = || {
+            let mut node = create_node(&Config::default());
+            node.enable_persistence(&dir, Duration::from_secs(3600));
+            node.enable_wal();
+            node
+        };
+        let mut network = Network::new(2, start);
+        network.send(msg().to("n1").id(2).broadcast(5));
+        network.run(100);
+        assert_eq!(network.take_outbox().len(), 1);
+
+        network.crash("n1");
+        network.restart("n1", start());
+        assert_eq!(network.node("n1").broadcast_messages(), &[5]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_conformance() {
+        conformance::check(|| create_node(&Config::default()));
+    }
+}
@@ -0,0 +1,115 @@
+// the parts of the maelstrom protocol every workload binary shares: init, requests before
+// init, a second init, and malformed input. each binary runs "check" with its node factory
+// from its tests, so they all behave the same way at the protocol level.
+// nodes are driven through a "Runner" over in-memory input and output, the way maelstrom
+// drives them over STDIN and STDOUT.
+
+use std::io::{Cursor, Write};
+use std::sync::{Arc, Mutex};
+
+use node::prelude::*;
+use serde_json::{json, Value};
+
+const INIT: &str = r#"{"src":"c0","dest":"n1","body":{"type":"init","msg_id":1,"node_id":"n1","node_ids":["n1","n2","n3"]}}"#;
+const DUMP_STATE: &str = r#"{"src":"c1","dest":"n1","body":{"type":"dump_state","msg_id":9}}"#;
+
+#[derive(Clone, Default)]
+struct Output(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+// feeds "lines" to a fresh node until EOF and returns what it sent to clients. messages to
+// other nodes and services (gossip, rpcs) are left out, nobody answers them here.
+pub fn exchange<F: Fn() -> Node>(factory: &F, lines: &[&str]) -> Vec<Value> {
+    let input = Cursor::new(lines.join("\n") + "\n");
+    let output = Output::default();
+    Runner::with_io(factory(), input, output.clone())
+        .with_verbosity(Verbosity::Off)
+        .start();
+
+    let output = output.0.lock().unwrap();
+    String::from_utf8_lossy(&output)
+        .lines()
+        .map(|line| serde_json::from_str::<Value>(line).expect("Output should be JSON."))
+        .filter(|message| {
+            message["dest"]
+                .as_str()
+                .is_some_and(|dest| dest.starts_with('c'))
+        })
+        .collect()
+}
+
+// runs every exchange below, panicking on the first deviation.
+pub fn check<F: Fn() -> Node>(factory: F) {
+    init(&factory);
+    requests_before_init(&factory);
+    reinit(&factory);
+    malformed_input(&factory);
+}
+
+// "init" is answered with "init_ok", from the node id it was given, and nothing else.
+pub fn init<F: Fn() -> Node>(factory: &F) {
+    let output = exchange(factory, &[INIT]);
+    assert_eq!(
+        output,
+        vec![json!({"src":"n1","dest":"c0","body":{"type":"init_ok","in_reply_to":1}})]
+    );
+}
+
+// requests before "init" are dropped (and logged), and don't get in the way of the "init"
+// that follows.
+pub fn requests_before_init<F: Fn() -> Node>(factory: &F) {
+    let output = exchange(factory, &[DUMP_STATE, INIT, DUMP_STATE]);
+    assert_eq!(output.len(), 2, "unexpected output: {output:?}");
+    assert_eq!(output[0]["body"]["type"], "init_ok");
+    assert_eq!(output[1]["body"]["type"], "dump_state_ok");
+    assert_eq!(output[1]["body"]["in_reply_to"], 9);
+}
+
+// a second "init" is rejected without a reply, and the node keeps its identity.
+pub fn reinit<F: Fn() -> Node>(factory: &F) {
+    let other = INIT.replace(r#""node_id":"n1""#, r#""node_id":"n2""#);
+    let output = exchange(factory, &[INIT, &other, DUMP_STATE]);
+    assert_eq!(output.len(), 2, "unexpected output: {output:?}");
+    assert_eq!(output[1]["src"], "n1");
+    assert_eq!(output[1]["body"]["state"]["node_id"], "n1");
+}
+
+// lines that aren't messages (not json, unknown types, missing fields, wrong types) are
+// dropped, and the node keeps serving.
+pub fn malformed_input<F: Fn() -> Node>(factory: &F) {
+    let output = exchange(
+        factory,
+        &[
+            INIT,
+            "",
+            "not json",
+            r#"{"src":"c1","dest":"n1","body":{"type":"no_such_type","msg_id":2}}"#,
+            r#"{"src":"c1","dest":"n1","body":{"type":"dump_state"}}"#,
+            r#"{"src":"c1","dest":"n1","body":{"type":"dump_state","msg_id":"3"}}"#,
+            r#"{"src":"c1","body":{"type":"dump_state","msg_id":4}}"#,
+            r#"{"src":"c1","dest":"n1","body":{"type":"dump_state","msg_id":5}"#,
+            DUMP_STATE,
+        ],
+    );
+    assert_eq!(output.len(), 2, "unexpected output: {output:?}");
+    assert_eq!(output[1]["body"]["in_reply_to"], 9);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_node() {
+        check(Node::default);
+    }
+}
@@ -0,0 +1,56 @@
+use std::collections::HashMap;
+
+use node::config::Config;
+use node::prelude::*;
+
+fn handler_echo(node: &mut Node, msg: Message, out: &mut dyn Sink) -> Result<()> {
+    expect_body!(msg, Echo { msg_id, echo });
+    let reply = node.reply_to((msg.src, msg_id), |in_reply_to, msg_id| {
+        Workload::echo_ok(in_reply_to, msg_id, echo)
+    });
+    out.send(reply);
+    Ok(())
+}
+
+pub fn create_node(config: &Config) -> Node {
+    let mut handlers: HashMap<Type, Handler> = HashMap::new();
+    handlers.insert(Type::Echo, handler_echo);
+    Node::with_config(handlers, config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use node::testing::maelstrom::assert_replays;
+
+    #[test]
+    fn test_echo() {
+        let mut node = create_node(&Config::default());
+        let init_json = r#"{"src":"c1","dest":"n1","body":{"type":"init","msg_id":1,"node_id":"n1","node_ids":["n1","n2","n3"]}}"#;
+        let init_message = serde_json::from_str::<Message>(init_json).unwrap();
+        let _ = node.process(init_message);
+
+        let echo_json =
+            r#"{"src":"c1","dest":"n1","body":{"type":"echo","echo":"Hello, World!","msg_id":1}}"#;
+        let echo_message = serde_json::from_str::<Message>(echo_json).unwrap();
+        let reply = node.process(echo_message);
+        assert!(reply.is_ok());
+
+        let reply = serde_json::to_string(&reply.unwrap().first().unwrap()).unwrap();
+        assert_eq!(
+            reply,
+            r#"{"src":"n1","dest":"c1","body":{"type":"echo_ok","in_reply_to":1,"msg_id":1,"echo":"Hello, World!"}}"#
+        );
+    }
+
+    #[test]
+    fn test_echo_replays_maelstrom_run() {
+        let log = include_str!("../fixtures/jepsen.log");
+        assert_replays(&mut create_node(&Config::default()), "n1", log.as_bytes());
+    }
+
+    #[test]
+    fn test_conformance() {
+        conformance::check(|| create_node(&Config::default()));
+    }
+}
@@ -0,0 +1,244 @@
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{BufRead, BufWriter, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use node::config::{Config, TopologyStrategy};
+use node::prelude::{Node, Runner};
+use node::viz::TraceFormat;
+
+// every workload in one binary: "glomers broadcast" runs the broadcast node.
+#[derive(Parser, Debug)]
+#[command(version, about = "Gossip Glomers workloads, one subcommand each")]
+struct Cli {
+    #[command(subcommand)]
+    challenge: Challenge,
+    #[command(flatten)]
+    tuning: Tuning,
+    // serves one TCP connection instead of STDIN/STDOUT, see "Runner::accept".
+    #[arg(
+        long,
+        global = true,
+        value_name = "ADDR",
+        help = "Speak the protocol over a TCP connection accepted on ADDR instead of STDIN/STDOUT"
+    )]
+    listen: Option<String>,
+    #[arg(
+        long,
+        global = true,
+        value_name = "PATH",
+        help = "Write which node gossiped which value to whom, and when, to PATH"
+    )]
+    gossip_trace: Option<PathBuf>,
+    #[arg(
+        long,
+        global = true,
+        default_value = "json",
+        help = "Format of the gossip trace: json (lines) or dot (graphviz)"
+    )]
+    trace_format: TraceFormat,
+}
+
+// "Config" from the command line, each flag overrides its "GLOMERS_*" variable.
+#[derive(Args, Debug, Default)]
+struct Tuning {
+    #[arg(
+        long,
+        global = true,
+        help = "Batch gossip every that many ms, 0 sends it right away"
+    )]
+    gossip_interval_ms: Option<u64>,
+    #[arg(
+        long,
+        global = true,
+        help = "Let output wait that many ms to be written with more"
+    )]
+    batch_window_ms: Option<u64>,
+    #[arg(
+        long,
+        global = true,
+        help = "First retry of unacknowledged messages, in ms"
+    )]
+    retry_timeout_ms: Option<u64>,
+    #[arg(long, global = true, help = "Children per node of a tree topology")]
+    fanout: Option<usize>,
+    #[arg(long, global = true, help = "maelstrom, tree or total")]
+    topology: Option<TopologyStrategy>,
+    #[arg(long, global = true, help = "Events kept for dump_state, 0 keeps none")]
+    history: Option<usize>,
+}
+
+impl Tuning {
+    fn apply(&self, mut config: Config) -> Config {
+        if let Some(ms) = self.gossip_interval_ms {
+            config.gossip_interval = (ms > 0).then(|| Duration::from_millis(ms));
+        }
+        if let Some(ms) = self.batch_window_ms {
+            config.batch_window = Duration::from_millis(ms);
+        }
+        if let Some(ms) = self.retry_timeout_ms {
+            config.retry_timeout = Duration::from_millis(ms);
+        }
+        if let Some(fanout) = self.fanout {
+            config.fanout = fanout;
+        }
+        if let Some(topology) = self.topology {
+            config.topology = topology;
+        }
+        if let Some(capacity) = self.history {
+            config.history = (capacity > 0).then_some(capacity);
+        }
+        config
+    }
+}
+
+#[derive(Subcommand, Debug, Clone, Copy, PartialEq, Eq)]
+enum Challenge {
+    #[command(about = "Challenge #1: Echo")]
+    Echo,
+    #[command(alias = "uniqueids", about = "Challenge #2: Unique ID Generation")]
+    UniqueIds,
+    #[command(about = "Challenge #3b: Multi-Node Broadcast")]
+    Broadcast,
+    #[command(about = "Challenge #5c: Efficient Kafka-Style Log")]
+    Kafka,
+    #[command(about = "Challenge #6c: Totally-Available, Read Committed Transactions")]
+    Txn,
+    #[command(alias = "linkv", about = "Linearizable Key-Value Store")]
+    LinKv,
+    #[command(alias = "totalorder", about = "Total-Order Broadcast")]
+    TotalOrder,
+}
+
+impl Challenge {
+    fn create_node(self, config: &Config) -> Node {
+        match self {
+            Challenge::Echo => workloads::echo(config),
+            Challenge::UniqueIds => workloads::unique_ids(config),
+            Challenge::Broadcast => workloads::broadcast(config),
+            Challenge::Kafka => workloads::kafka(config),
+            Challenge::Txn => workloads::txn(config),
+            Challenge::LinKv => workloads::lin_kv(config),
+            Challenge::TotalOrder => workloads::total_order(config),
+        }
+    }
+
+    // client reads shouldn't queue behind gossip, see "Runner::with_source_shards".
+    fn source_shards(self) -> bool {
+        self == Challenge::Broadcast
+    }
+}
+
+// maelstrom runs "--bin" without arguments: invoked through a link named after a workload
+// ("broadcast -> glomers"), the name is the subcommand.
+fn arguments(mut args: Vec<OsString>) -> Vec<OsString> {
+    let name = args
+        .first()
+        .and_then(|program| Path::new(program).file_stem())
+        .and_then(|name| name.to_str())
+        .map(str::to_owned);
+    if let Some(name) = name {
+        if Cli::command().find_subcommand(&name).is_some() {
+            args.insert(1, name.into());
+        }
+    }
+    args
+}
+
+fn main() {
+    let cli = Cli::parse_from(arguments(std::env::args_os().collect()));
+    let config = Config::from_env().expect("GLOMERS_* variables should be valid.");
+    let config = cli.tuning.apply(config);
+    let node = cli.challenge.create_node(&config);
+    let shards = cli.challenge.source_shards();
+    let trace = cli.gossip_trace.map(|path| {
+        let file = File::create(&path).expect("Gossip trace should be created.");
+        (file, cli.trace_format)
+    });
+    match cli.listen {
+        Some(addr) => {
+            let listener = TcpListener::bind(&addr).expect("Address should be free to listen on.");
+            let addr = listener
+                .local_addr()
+                .expect("Listener should have an address.");
+            eprintln!("listening on {addr}");
+            let runner = Runner::accept(node, &listener).expect("Connection should be accepted.");
+            start(runner, &config, trace, shards);
+        }
+        None => start(Runner::new(node), &config, trace, shards),
+    }
+}
+
+// what's left to set up whichever way the node talks.
+fn start<R, W>(
+    runner: Runner<R, W>,
+    config: &Config,
+    trace: Option<(File, TraceFormat)>,
+    shards: bool,
+) where
+    R: BufRead + Send + 'static,
+    W: Write + Send + 'static,
+{
+    let mut runner = runner.with_config(config);
+    if let Some((file, format)) = trace {
+        runner = runner.with_gossip_trace(BufWriter::new(file), format);
+    }
+    if shards {
+        runner = runner.with_source_shards(4);
+    }
+    runner.start();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Challenge {
+        let args = args.iter().map(OsString::from).collect();
+        Cli::try_parse_from(arguments(args)).unwrap().challenge
+    }
+
+    #[test]
+    fn test_challenge_subcommands() {
+        Cli::command().debug_assert();
+        assert_eq!(parse(&["glomers", "echo"]), Challenge::Echo);
+        assert_eq!(parse(&["glomers", "unique-ids"]), Challenge::UniqueIds);
+        assert_eq!(parse(&["glomers", "linkv"]), Challenge::LinKv);
+        assert_eq!(parse(&["/usr/bin/total-order"]), Challenge::TotalOrder);
+        assert_eq!(parse(&["target/release/kafka"]), Challenge::Kafka);
+        assert!(Cli::try_parse_from(["glomers", "counter"]).is_err());
+    }
+
+    #[test]
+    fn test_tuning_flags() {
+        let cli = Cli::try_parse_from([
+            "glomers",
+            "broadcast",
+            "--gossip-interval-ms",
+            "150",
+            "--topology",
+            "tree",
+            "--fanout",
+            "3",
+        ])
+        .unwrap();
+        let config = cli.tuning.apply(Config::default());
+        assert_eq!(config.gossip_interval, Some(Duration::from_millis(150)));
+        assert_eq!(
+            (config.fanout, config.topology),
+            (3, TopologyStrategy::Tree)
+        );
+        assert_eq!(config.retry_timeout, Config::default().retry_timeout);
+
+        // flags come after the subcommand, or before it.
+        let cli = Cli::try_parse_from(["glomers", "--retry-timeout-ms", "0", "echo"]).unwrap();
+        assert_eq!(
+            cli.tuning.apply(Config::default()).retry_timeout,
+            Duration::ZERO
+        );
+        assert!(Cli::try_parse_from(["glomers", "echo", "--topology", "star"]).is_err());
+    }
+}
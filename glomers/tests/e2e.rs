@@ -0,0 +1,356 @@
+// the workloads as maelstrom runs them: the "glomers" binary in a child process, spoken to over
+// its STDIN and STDOUT. what the unit tests can't see, the runner's threads, buffering and
+// shutdown, is covered here.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{Shutdown, TcpStream};
+use std::process::{Child, ChildStdin, Command, ExitStatus, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use node::core::{code, Message, Workload};
+use serde_json::json;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+struct Process {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    lines: Receiver<String>,
+}
+
+impl Process {
+    fn spawn(workload: &str) -> Self {
+        Self::spawn_with(&[workload])
+    }
+
+    fn spawn_with(args: &[&str]) -> Self {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_glomers"))
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("glomers should start");
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        let (tx, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in stdout.lines().map_while(|line| line.ok()) {
+                if tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        let stdin = child.stdin.take();
+        Self {
+            child,
+            stdin,
+            lines,
+        }
+    }
+
+    fn send(&mut self, message: serde_json::Value) {
+        let stdin = self.stdin.as_mut().expect("STDIN is still open");
+        writeln!(stdin, "{message}").unwrap();
+    }
+
+    fn send_raw(&mut self, line: &str) {
+        let stdin = self.stdin.as_mut().expect("STDIN is still open");
+        writeln!(stdin, "{line}").unwrap();
+    }
+
+    fn recv(&self) -> Message {
+        let line = self
+            .lines
+            .recv_timeout(TIMEOUT)
+            .expect("a message should be written");
+        serde_json::from_str(&line).unwrap_or_else(|e| panic!("{e}: {line}"))
+    }
+
+    fn request(&mut self, src: &str, body: serde_json::Value) -> Message {
+        self.send(json!({"src": src, "dest": "n1", "body": body}));
+        self.recv()
+    }
+
+    fn init(&mut self, node_ids: &[&str]) {
+        let body = json!({"type": "init", "msg_id": 1, "node_id": "n1", "node_ids": node_ids});
+        let reply = self.request("c1", body);
+        assert_eq!(reply.body, Workload::InitOk { in_reply_to: 1 });
+    }
+
+    // closes STDIN, the process is expected to exit on its own.
+    fn close(mut self) -> (Vec<String>, ExitStatus) {
+        drop(self.stdin.take());
+        let status = self.wait();
+        (self.lines.try_iter().collect(), status)
+    }
+
+    fn wait(&mut self) -> ExitStatus {
+        let deadline = Instant::now() + TIMEOUT;
+        loop {
+            if let Some(status) = self.child.try_wait().unwrap() {
+                return status;
+            }
+            assert!(Instant::now() < deadline, "glomers should have exited");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[test]
+fn test_e2e_echo() {
+    let mut process = Process::spawn("echo");
+    // nothing is answered before init, and bad input is skipped.
+    let early = json!({"type": "echo", "msg_id": 9, "echo": "early"});
+    process.send(json!({"src": "c1", "dest": "n1", "body": early}));
+    process.init(&["n1"]);
+    process.send_raw("not json");
+    process.send(json!({"src": "c1", "dest": "n1", "body": {"type": "echo", "msg_id": 2}}));
+    for msg_id in 3..6 {
+        let body = json!({"type": "echo", "msg_id": msg_id, "echo": format!("hi {msg_id}")});
+        let reply = process.request("c1", body);
+        assert_eq!(reply.dest, "c1");
+        match reply.body {
+            Workload::EchoOk {
+                in_reply_to, echo, ..
+            } => {
+                assert_eq!(in_reply_to, msg_id);
+                assert_eq!(echo, format!("hi {msg_id}"));
+            }
+            body => panic!("expected echo_ok, got {body:?}"),
+        }
+    }
+
+    let (rest, status) = process.close();
+    assert!(status.success());
+    assert!(rest.is_empty(), "unexpected output: {rest:?}");
+}
+
+#[test]
+fn test_e2e_unique_ids() {
+    let mut process = Process::spawn("unique-ids");
+    process.init(&["n1", "n2"]);
+    let reply = process.request("c1", json!({"type": "generate", "msg_id": 2, "n": 3}));
+    let Workload::GenerateOk { ids: Some(ids), .. } = reply.body else {
+        panic!("expected a batch of ids, got {:?}", reply.body);
+    };
+    assert_eq!(ids.len(), 3);
+
+    let reply = process.request(
+        "c1",
+        json!({"type": "generate", "msg_id": 3, "n": 1_000_000}),
+    );
+    let Workload::Error {
+        in_reply_to, code, ..
+    } = reply.body
+    else {
+        panic!("expected an error, got {:?}", reply.body);
+    };
+    assert_eq!((in_reply_to, code), (3, code::MALFORMED_REQUEST));
+    assert!(process.close().1.success());
+}
+
+#[test]
+fn test_e2e_broadcast() {
+    let mut process = Process::spawn("broadcast");
+    process.init(&["n1", "n2"]);
+    let topology = json!({"n1": ["n2"], "n2": ["n1"]});
+    let reply = process.request(
+        "c1",
+        json!({"type": "topology", "msg_id": 2, "topology": topology}),
+    );
+    assert_eq!(reply.body.name(), "topology_ok");
+
+    // a value from a client is acknowledged and gossiped to n2, which acknowledges it.
+    let broadcast = json!({"type": "broadcast", "msg_id": 3, "message": 42});
+    process.send(json!({"src": "c1", "dest": "n1", "body": broadcast}));
+    let mut replies = [process.recv(), process.recv()];
+    replies.sort_by_key(|reply| reply.dest);
+    assert_eq!(replies[0].dest, "c1");
+    assert_eq!(replies[0].body.name(), "broadcast_ok");
+    assert_eq!(replies[1].dest, "n2");
+    let Workload::Broadcast {
+        msg_id,
+        message: 42,
+        hops: Some(1),
+    } = replies[1].body
+    else {
+        panic!("expected gossip, got {:?}", replies[1].body);
+    };
+    let ack = json!({"type": "broadcast_ok", "in_reply_to": msg_id, "msg_id": 1});
+    process.send(json!({"src": "n2", "dest": "n1", "body": ack}));
+
+    // gossip from n2 isn't sent back to it.
+    let gossip = json!({"type": "broadcast", "msg_id": 2, "message": 7, "hops": 1});
+    let reply = process.request("n2", gossip);
+    assert_eq!(
+        (reply.dest.as_str(), reply.body.name()),
+        ("n2", "broadcast_ok")
+    );
+
+    let reply = process.request("c1", json!({"type": "read", "msg_id": 4}));
+    let Workload::ReadOk { messages, .. } = reply.body else {
+        panic!("expected read_ok, got {:?}", reply.body);
+    };
+    assert_eq!(messages.as_deref(), Some(&vec![42, 7]));
+    assert!(process.close().1.success());
+}
+
+#[test]
+fn test_e2e_gossip_trace() {
+    let path = std::env::temp_dir().join(format!("gossip-trace-{}.dot", std::process::id()));
+    let trace = path.to_str().unwrap();
+    let args = [
+        "broadcast",
+        "--gossip-trace",
+        trace,
+        "--trace-format",
+        "dot",
+    ];
+    let mut process = Process::spawn_with(&args);
+    process.init(&["n1", "n2", "n3"]);
+    let topology = json!({"n1": ["n2", "n3"], "n2": ["n1"], "n3": ["n1"]});
+    let body = json!({"type": "topology", "msg_id": 2, "topology": topology});
+    process.request("c1", body);
+    let broadcast = json!({"type": "broadcast", "msg_id": 3, "message": 42});
+    process.send(json!({"src": "c1", "dest": "n1", "body": broadcast}));
+    for _ in 0..3 {
+        process.recv();
+    }
+    assert!(process.close().1.success());
+
+    let trace = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let mut lines: Vec<&str> = trace.lines().collect();
+    assert_eq!(lines.remove(0), "digraph gossip {");
+    assert_eq!(lines.pop(), Some("}"));
+    lines.sort();
+    assert_eq!(lines.len(), 2, "{trace}");
+    assert!(lines[0].starts_with(r#"  "n1" -> "n2" [label="42 @"#));
+    assert!(lines[1].starts_with(r#"  "n1" -> "n3" [label="42 @"#));
+}
+
+#[test]
+fn test_e2e_kafka_with_lin_kv() {
+    let mut process = Process::spawn("kafka");
+    process.init(&["n1"]);
+    let reply = process.request(
+        "c1",
+        json!({"type": "send", "msg_id": 2, "key": "k1", "msg": 7}),
+    );
+    assert!(matches!(reply.body, Workload::SendOk { offset: 0, .. }));
+
+    // committed offsets are kept in lin-kv, played by the test.
+    let commit = json!({"type": "commit_offsets", "msg_id": 3, "offsets": {"k1": 0}});
+    process.send(json!({"src": "c1", "dest": "n1", "body": commit}));
+    let mut stored = None;
+    let reply = loop {
+        let message = process.recv();
+        if message.dest != "lin-kv" {
+            break message;
+        }
+        let body = match message.body {
+            Workload::Write { msg_id, value, .. } => {
+                stored = Some(value);
+                json!({"type": "write_ok", "in_reply_to": msg_id})
+            }
+            Workload::Read { msg_id, .. } => match &stored {
+                Some(value) => json!({"type": "read_ok", "in_reply_to": msg_id, "value": value}),
+                None => {
+                    json!({"type": "error", "in_reply_to": msg_id, "code": 20, "text": "no key"})
+                }
+            },
+            Workload::Cas { msg_id, to, .. } => {
+                stored = Some(to);
+                json!({"type": "cas_ok", "in_reply_to": msg_id})
+            }
+            body => panic!("unexpected lin-kv request {body:?}"),
+        };
+        process.send(json!({"src": "lin-kv", "dest": "n1", "body": body}));
+    };
+    assert_eq!(
+        (reply.dest.as_str(), reply.body.name()),
+        ("c1", "commit_offsets_ok")
+    );
+    assert_eq!(stored, Some(json!(0)));
+    assert!(process.close().1.success());
+}
+
+#[test]
+fn test_e2e_exits_on_eof_and_sigterm() {
+    // EOF right after init.
+    let mut process = Process::spawn("txn");
+    process.init(&["n1"]);
+    let (rest, status) = process.close();
+    assert!(status.success());
+    assert!(rest.is_empty());
+
+    // SIGTERM with STDIN still open, the way maelstrom stops nodes.
+    let mut process = Process::spawn("lin-kv");
+    process.init(&["n1"]);
+    let pid = process.child.id().to_string();
+    let killed = Command::new("kill").args(["-TERM", &pid]).status().unwrap();
+    assert!(killed.success());
+    assert!(process.wait().success());
+}
+
+#[test]
+fn test_e2e_tcp() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_glomers"))
+        .args(["echo", "--listen", "127.0.0.1:0"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("glomers should start");
+    let mut stderr = BufReader::new(child.stderr.take().unwrap());
+    let mut listening = String::new();
+    stderr.read_line(&mut listening).unwrap();
+    let addr = listening
+        .trim()
+        .strip_prefix("listening on ")
+        .unwrap_or_else(|| panic!("unexpected output: {listening}"));
+
+    // what a netcat session would type.
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let mut lines = BufReader::new(stream.try_clone().unwrap()).lines();
+    let init = json!({"type": "init", "msg_id": 1, "node_id": "n1", "node_ids": ["n1"]});
+    writeln!(
+        stream,
+        "{}",
+        json!({"src": "c1", "dest": "n1", "body": init})
+    )
+    .unwrap();
+    let echo = json!({"type": "echo", "msg_id": 2, "echo": "over tcp"});
+    writeln!(
+        stream,
+        "{}",
+        json!({"src": "c1", "dest": "n1", "body": echo})
+    )
+    .unwrap();
+    let replies: Vec<Message> = (0..2)
+        .map(|_| serde_json::from_str(&lines.next().unwrap().unwrap()).unwrap())
+        .collect();
+    assert_eq!(replies[0].body, Workload::InitOk { in_reply_to: 1 });
+    assert_eq!(replies[1].body.name(), "echo_ok");
+
+    // closing the connection ends the process, like EOF on STDIN.
+    stream.shutdown(Shutdown::Write).unwrap();
+    let deadline = Instant::now() + TIMEOUT;
+    let status = loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            break status;
+        }
+        assert!(Instant::now() < deadline, "glomers should have exited");
+        thread::sleep(Duration::from_millis(10));
+    };
+    assert!(status.success());
+}
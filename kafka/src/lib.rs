@@ -0,0 +1,676 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use node::config::Config;
+use node::prelude::*;
+use serde::{Deserialize, Serialize};
+
+// the workload's own messages, see "Body".
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Replication {
+    // replicates an entry to the other nodes, acknowledged once the replica holds every entry
+    // of the key up to it.
+    LogAppend {
+        msg_id: MessageId,
+        key: LogKey,
+        offset: Offset,
+        msg: LogMessage,
+        #[serde(default)]
+        lamport: Timestamp,
+    },
+    LogAppendOk {
+        in_reply_to: MessageId,
+        msg_id: MessageId,
+    },
+}
+
+impl Body for Replication {
+    const TYPES: &'static [&'static str] = &["log_append", "log_append_ok"];
+}
+
+// every key is owned by exactly one node, which allocates its offsets locally.
+// FNV-1a keeps the mapping stable across nodes and toolchains.
+fn owner(node: &Node, key: &LogKey) -> Option<NodeId> {
+    let node_ids = node.node_ids();
+    if node_ids.is_empty() {
+        return None;
+    }
+    let hash = key.bytes().fold(0xcbf29ce484222325_u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    Some(node_ids[(hash % node_ids.len() as u64) as usize])
+}
+
+// how long a send forwarded to its owner waits for a reply, until there's a round trip measured.
+const FORWARD_TIMEOUT: Duration = Duration::from_secs(1);
+
+fn commit_key(key: &LogKey) -> KvKey {
+    format!("commit-{key}").into()
+}
+
+// passes on to the client a request failed with, or an unexpected reply to it.
+fn error_reply(node: &mut Node, (src, msg_id): (NodeId, MessageId), body: Workload) -> Message {
+    let (code, text) = match body {
+        Workload::Error { code, text, .. } => (code, text),
+        body => (code::CRASH, format!("unexpected reply {body:?}")),
+    };
+    node.reply(src, Workload::error(msg_id, code, text))
+}
+
+fn append(
+    node: &mut Node,
+    request: (NodeId, MessageId),
+    key: LogKey,
+    offset: Offset,
+    message: LogMessage,
+    out: &mut dyn Sink,
+) -> Result<()> {
+    // sent until acknowledged, a replica missing an entry would serve polls with a hole.
+    let node_id = node.node_id();
+    let peers = node.node_ids().to_vec();
+    for peer in peers.into_iter().filter(|peer| *peer != node_id) {
+        let body = Replication::LogAppend {
+            msg_id: node.gen_msg_id(),
+            key: key.clone(),
+            offset,
+            msg: message,
+            lamport: 0, // stamped by "reply".
+        };
+        out.send(node.send_reliably(peer, Workload::custom(&body)?)?);
+    }
+    out.send(node.reply_to(request, |in_reply_to, msg_id| {
+        Workload::send_ok(in_reply_to, msg_id, offset)
+    }));
+    Ok(())
+}
+
+fn handler_send(node: &mut Node, msg: Message, out: &mut dyn Sink) -> Result<()> {
+    expect_body!(
+        msg,
+        Send {
+            msg_id,
+            key,
+            msg: message,
+        }
+    );
+    let owner = owner(node, &key).unwrap_or(node.node_id());
+    if owner == node.node_id() {
+        let offset = node.logs_mut().append(key.clone(), message)?;
+        return append(node, (msg.src, msg_id), key, offset, message, out);
+    }
+
+    // proxy the request to the owner and relay its reply back to the client.
+    let body = Workload::Send {
+        msg_id: node.gen_msg_id(),
+        key,
+        msg: message,
+    };
+    // a send isn't safe to repeat, it isn't retried: an owner that doesn't answer in time gets
+    // the client a "timeout" error, which it retries itself.
+    let request = (msg.src, msg_id);
+    let timeout = node.rpc_timeout(&owner, FORWARD_TIMEOUT);
+    let request = node.rpc_with_timeout(owner, body, timeout, move |node, reply| {
+        let reply = match reply.body {
+            Workload::SendOk { offset, .. } => node.reply_to(request, |in_reply_to, msg_id| {
+                Workload::send_ok(in_reply_to, msg_id, offset)
+            }),
+            body => error_reply(node, request, body),
+        };
+        Ok(smallvec![reply])
+    })?;
+    out.send(request);
+    Ok(())
+}
+
+fn handler_replication(
+    node: &mut Node,
+    msg: Message<Replication>,
+    out: &mut dyn Sink,
+) -> Result<()> {
+    let (msg_id, key, offset, message) = match msg.body {
+        Replication::LogAppend {
+            msg_id,
+            key,
+            offset,
+            msg,
+            ..
+        } => (msg_id, key, offset, msg),
+        // an acknowledgement the outbox had already, e.g. of an entry sent twice.
+        Replication::LogAppendOk { .. } => return Ok(()),
+    };
+    // an entry past a hole isn't acknowledged, the owner sends it again until the ones before
+    // it arrived.
+    if offset > node.logs().next_offset(&key) {
+        return Ok(());
+    }
+    node.logs_mut().insert(key, offset, message)?;
+    let reply = Replication::LogAppendOk {
+        in_reply_to: msg_id,
+        msg_id: node.gen_msg_id(),
+    };
+    out.send(node.reply(msg.src, Workload::custom(&reply)?));
+    Ok(())
+}
+
+fn handler_poll(node: &mut Node, msg: Message, out: &mut dyn Sink) -> Result<()> {
+    expect_body!(msg, Poll { msg_id, offsets });
+    let msgs = node.logs().poll(&offsets);
+    let reply = node.reply_to((msg.src, msg_id), |in_reply_to, msg_id| {
+        Workload::poll_ok(in_reply_to, msg_id, msgs)
+    });
+    out.send(reply);
+    Ok(())
+}
+
+// a "commit_offsets" waiting on lin-kv: the keys not committed yet, and the client, until
+// it's answered.
+struct Commit {
+    pending: usize,
+    client: Option<(NodeId, MessageId)>,
+}
+
+// the client gets "commit_offsets_ok" once every key is committed, or the first failure.
+fn answer(node: &mut Node, commit: &Mutex<Commit>, failure: Option<Workload>) -> Replies {
+    let mut commit = commit.lock().expect("Commit should not be poisoned.");
+    let reply = match failure {
+        None => {
+            commit.pending -= 1;
+            if commit.pending > 0 {
+                return Replies::new();
+            }
+            commit
+                .client
+                .take()
+                .map(|request| node.reply_to(request, Workload::commit_offsets_ok))
+        }
+        Some(body) => commit
+            .client
+            .take()
+            .map(|request| error_reply(node, request, body)),
+    };
+    reply.into_iter().collect()
+}
+
+// moves the offset committed for "key" in lin-kv forward to "offset": it's read, and swapped
+// with a "cas" from what was read, read again if another commit got in between. an offset
+// behind the one committed is left alone, a retry or a concurrent commit can't move it back.
+fn commit_offset(
+    node: &mut Node,
+    key: LogKey,
+    offset: Offset,
+    commit: Arc<Mutex<Commit>>,
+) -> Result<Message> {
+    let body = Workload::read(node.gen_msg_id(), commit_key(&key));
+    node.rpc_with_retry(LIN_KV.into(), body, move |node, reply| {
+        let from = match reply.body {
+            Workload::ReadOk {
+                value: Some(value), ..
+            } => {
+                if value.as_u64().is_some_and(|committed| committed >= offset) {
+                    return Ok(answer(node, &commit, None));
+                }
+                Some(value)
+            }
+            Workload::Error {
+                code: code::KEY_DOES_NOT_EXIST,
+                ..
+            } => None,
+            body => return Ok(answer(node, &commit, Some(body))),
+        };
+        // a key that doesn't exist is created, whatever "from" says.
+        let create = from.is_none();
+        let from = from.unwrap_or(offset.into());
+        let body = Workload::cas(
+            node.gen_msg_id(),
+            commit_key(&key),
+            from,
+            offset.into(),
+            create,
+        );
+        let request =
+            node.rpc_with_retry(LIN_KV.into(), body, move |node, reply| match reply.body {
+                Workload::CasOk { .. } => Ok(answer(node, &commit, None)),
+                Workload::Error {
+                    code: code::PRECONDITION_FAILED | code::KEY_DOES_NOT_EXIST,
+                    ..
+                } => Ok(smallvec![commit_offset(node, key, offset, commit)?]),
+                body => Ok(answer(node, &commit, Some(body))),
+            })?;
+        Ok(smallvec![request])
+    })
+}
+
+fn handler_commit_offsets(node: &mut Node, msg: Message, out: &mut dyn Sink) -> Result<()> {
+    expect_body!(msg, CommitOffsets { msg_id, offsets });
+    node.logs_mut().commit(offsets.clone())?;
+
+    // reply once lin-kv has every key.
+    if offsets.is_empty() {
+        out.send(node.reply_to((msg.src, msg_id), Workload::commit_offsets_ok));
+        return Ok(());
+    }
+    let commit = Arc::new(Mutex::new(Commit {
+        pending: offsets.len(),
+        client: Some((msg.src, msg_id)),
+    }));
+    for (key, offset) in offsets {
+        out.send(commit_offset(node, key, offset, commit.clone())?);
+    }
+    Ok(())
+}
+
+// a "list_committed_offsets" waiting on lin-kv: the keys not read yet, the offsets read so far,
+// and the client, until it's answered.
+struct Listing {
+    pending: usize,
+    offsets: HashMap<LogKey, Offset>,
+    client: Option<(NodeId, MessageId)>,
+}
+
+// the client gets "list_committed_offsets_ok" once every key is read, or the first failure.
+fn answer_listing(
+    node: &mut Node,
+    listing: &Mutex<Listing>,
+    read: std::result::Result<Option<(LogKey, Offset)>, Workload>,
+) -> Replies {
+    let mut listing = listing.lock().expect("Listing should not be poisoned.");
+    let reply = match read {
+        Ok(offset) => {
+            listing.offsets.extend(offset);
+            listing.pending -= 1;
+            if listing.pending > 0 {
+                return Replies::new();
+            }
+            let offsets = std::mem::take(&mut listing.offsets);
+            listing.client.take().map(|request| {
+                node.reply_to(request, |in_reply_to, msg_id| {
+                    Workload::list_committed_offsets_ok(in_reply_to, msg_id, offsets)
+                })
+            })
+        }
+        Err(body) => listing
+            .client
+            .take()
+            .map(|request| error_reply(node, request, body)),
+    };
+    reply.into_iter().collect()
+}
+
+fn handler_list_committed_offsets(node: &mut Node, msg: Message, out: &mut dyn Sink) -> Result<()> {
+    expect_body!(msg, ListCommittedOffsets { msg_id, keys });
+    // collect offsets from lin-kv, keys that were never committed are left out.
+    if keys.is_empty() {
+        out.send(node.reply_to((msg.src, msg_id), |in_reply_to, msg_id| {
+            Workload::list_committed_offsets_ok(in_reply_to, msg_id, HashMap::new())
+        }));
+        return Ok(());
+    }
+    let listing = Arc::new(Mutex::new(Listing {
+        pending: keys.len(),
+        offsets: HashMap::new(),
+        client: Some((msg.src, msg_id)),
+    }));
+    for key in keys {
+        let body = Workload::read(node.gen_msg_id(), commit_key(&key));
+        let listing = listing.clone();
+        let request = node.rpc_with_retry(LIN_KV.into(), body, move |node, reply| {
+            let read = match reply.body {
+                Workload::ReadOk {
+                    value: Some(value), ..
+                } => Ok(Some((key, value.as_u64().unwrap_or_default()))),
+                Workload::Error {
+                    code: code::KEY_DOES_NOT_EXIST,
+                    ..
+                } => Ok(None),
+                body => Err(body),
+            };
+            Ok(answer_listing(node, &listing, read))
+        })?;
+        out.send(request);
+    }
+    Ok(())
+}
+
+pub fn create_node(config: &Config) -> Node {
+    let mut handlers: HashMap<Type, Handler> = HashMap::new();
+    handlers.insert(Type::Send, handler_send);
+    handlers.insert(Type::Poll, handler_poll);
+    handlers.insert(Type::CommitOffsets, handler_commit_offsets);
+    handlers.insert(Type::ListCommittedOffsets, handler_list_committed_offsets);
+    let mut node = Node::with_config(handlers, config);
+    node.register_body(handler_replication);
+    // lin-kv writes and reads are safe to repeat. a quick first retry, jittered so that nodes
+    // backing off together don't come back together.
+    let policy = Exponential::new(Duration::from_millis(100), Duration::from_secs(1))
+        .with_jitter(0.2, std::process::id().into())
+        .max_attempts(5);
+    node.set_retry_policy(policy);
+    node
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use node::testing::message::{init, msg};
+    use serde_json::json;
+    use std::time::Instant;
+
+    fn process(node: &mut Node, message: Message) -> Vec<String> {
+        let replies = node.process(message).unwrap();
+        replies
+            .iter()
+            .map(|reply| serde_json::to_string(reply).unwrap())
+            .collect()
+    }
+
+    // what lin-kv answers.
+    fn lin_kv(body: Workload) -> Message {
+        msg().from(LIN_KV).body(body)
+    }
+
+    // a replication message from n2.
+    fn replication(body: Replication) -> Message {
+        msg().from("n2").body(Workload::custom(&body).unwrap())
+    }
+
+    #[test]
+    fn test_kafka() {
+        let mut node = create_node(&Config::default());
+        init(&mut node, "n1", &["n1", "n2"]);
+        assert_eq!(owner(&node, &"k2".to_owned()), Some("n1".into()));
+
+        for (msg_id, value) in [(1, 9), (2, 5)] {
+            process(&mut node, msg().id(msg_id).send("k2", value));
+        }
+
+        // entries replicated from other nodes are served by poll as well.
+        let append = Replication::LogAppend {
+            msg_id: 7,
+            key: "k1".to_owned(),
+            offset: 0,
+            msg: 3,
+            lamport: 0,
+        };
+        process(&mut node, replication(append));
+        let replies = process(&mut node, msg().id(3).poll(&[("k2", 1), ("k1", 0)]));
+        let reply = serde_json::from_str::<Message>(&replies[0]).unwrap();
+        assert!(match reply.body {
+            Workload::PollOk { msgs, .. } =>
+                msgs["k2"] == vec![(1, 5)] && msgs["k1"] == vec![(0, 3)],
+            _ => false,
+        });
+    }
+
+    #[test]
+    fn test_kafka_replicate_reliably() {
+        let mut node = create_node(&Config::default());
+        init(&mut node, "n1", &["n1", "n2"]);
+
+        // the owner sends the entry until the replica acknowledges it.
+        let replies = node.process(msg().id(1).send("k2", 9)).unwrap();
+        assert_eq!(replies[0].dest, "n2");
+        assert!(matches!(
+            replies[0].clone().decode::<Replication>().unwrap().body,
+            Replication::LogAppend { msg_id: 1, .. }
+        ));
+        assert_eq!(node.outbox().unwrap().len(), 1);
+        let ack = Replication::LogAppendOk {
+            in_reply_to: 1,
+            msg_id: 5,
+        };
+        process(&mut node, replication(ack));
+        assert!(node.outbox().unwrap().is_empty());
+
+        // a replica doesn't take (nor acknowledge) an entry past a hole, until the hole is filled.
+        let append = |msg_id, offset, msg| {
+            replication(Replication::LogAppend {
+                msg_id,
+                key: "k1".to_owned(),
+                offset,
+                msg,
+                lamport: 0,
+            })
+        };
+        assert!(process(&mut node, append(7, 1, 4)).is_empty());
+        assert_eq!(
+            process(&mut node, append(6, 0, 3)),
+            vec![
+                r#"{"src":"n1","dest":"n2","body":{"in_reply_to":6,"msg_id":3,"type":"log_append_ok"}}"#
+            ]
+        );
+        assert_eq!(process(&mut node, append(7, 1, 4)).len(), 1);
+        // a retry of an entry held is acknowledged again.
+        assert_eq!(process(&mut node, append(6, 0, 3)).len(), 1);
+        let polled = node.logs().poll(&HashMap::from([("k1".to_owned(), 0)]));
+        assert_eq!(polled["k1"], vec![(0, 3), (1, 4)]);
+    }
+
+    #[test]
+    fn test_kafka_forward_send() {
+        let mut node = create_node(&Config::default());
+        init(&mut node, "n1", &["n1", "n2"]);
+        assert_eq!(owner(&node, &"k1".to_owned()), Some("n2".into()));
+
+        let replies = process(&mut node, msg().id(4).send("k1", 9));
+        assert_eq!(
+            replies,
+            vec![
+                r#"{"src":"n1","dest":"n2","body":{"type":"send","msg_id":1,"key":"k1","msg":9}}"#
+            ]
+        );
+        let replies = process(&mut node, msg().from("n2").body(Workload::send_ok(1, 8, 3)));
+        assert_eq!(
+            replies,
+            vec![
+                r#"{"src":"n1","dest":"c1","body":{"type":"send_ok","in_reply_to":4,"msg_id":2,"offset":3}}"#
+            ]
+        );
+
+        // an error of the owner is relayed back to the client.
+        process(&mut node, msg().id(5).send("k1", 7));
+        let crashed = Workload::error(3, code::CRASH, "crashed".to_owned());
+        let replies = process(&mut node, msg().from("n2").body(crashed));
+        assert_eq!(
+            replies,
+            vec![
+                r#"{"src":"n1","dest":"c1","body":{"type":"error","in_reply_to":5,"code":13,"text":"crashed"}}"#
+            ]
+        );
+
+        // an owner cut off by a partition gets the client a timeout, and nothing stays pending.
+        process(&mut node, msg().id(6).send("k1", 7));
+        let replies = node.tick(Instant::now() + FORWARD_TIMEOUT).unwrap();
+        assert_eq!(replies.len(), 1);
+        assert!(matches!(
+            replies[0].body,
+            Workload::Error {
+                in_reply_to: 6,
+                code: code::TIMEOUT,
+                ..
+            }
+        ));
+        assert_eq!(replies[0].dest, "c1");
+        assert_eq!(node.pending_rpcs(), 0);
+    }
+
+    #[test]
+    fn test_kafka_committed_offsets() {
+        let mut node = create_node(&Config::default());
+        init(&mut node, "n1", &["n1"]);
+
+        // the offset is read, then swapped in, the key is created if it doesn't exist.
+        let replies = process(&mut node, msg().id(1).commit_offsets(&[("k1", 1)]));
+        assert_eq!(
+            replies,
+            vec![
+                r#"{"src":"n1","dest":"lin-kv","body":{"type":"read","msg_id":1,"key":"commit-k1"}}"#
+            ]
+        );
+        let not_found = |in_reply_to| {
+            lin_kv(Workload::error(
+                in_reply_to,
+                code::KEY_DOES_NOT_EXIST,
+                "not found".to_owned(),
+            ))
+        };
+        let replies = process(&mut node, not_found(1));
+        assert_eq!(
+            replies,
+            vec![
+                r#"{"src":"n1","dest":"lin-kv","body":{"type":"cas","msg_id":2,"key":"commit-k1","from":1,"to":1,"create_if_not_exists":true}}"#
+            ]
+        );
+        let replies = process(&mut node, lin_kv(Workload::cas_ok(2, 0)));
+        assert_eq!(
+            replies,
+            vec![
+                r#"{"src":"n1","dest":"c1","body":{"type":"commit_offsets_ok","in_reply_to":1,"msg_id":3}}"#
+            ]
+        );
+
+        let replies = process(&mut node, msg().id(2).list_committed_offsets(&["k1", "k2"]));
+        assert_eq!(replies.len(), 2);
+        process(&mut node, lin_kv(Workload::kv_read_ok(4, 0, json!(1))));
+        let replies = process(&mut node, not_found(5));
+        assert_eq!(
+            replies,
+            vec![
+                r#"{"src":"n1","dest":"c1","body":{"type":"list_committed_offsets_ok","in_reply_to":2,"msg_id":6,"offsets":{"k1":1}}}"#
+            ]
+        );
+    }
+
+    #[test]
+    fn test_kafka_list_committed_offsets_fails() {
+        let mut node = create_node(&Config::default());
+        init(&mut node, "n1", &["n1"]);
+
+        // the first failure is passed on to the client, what comes after it is dropped.
+        let replies = process(&mut node, msg().id(2).list_committed_offsets(&["k1", "k2"]));
+        assert_eq!(replies.len(), 2);
+        let unavailable =
+            Workload::error(1, code::TEMPORARILY_UNAVAILABLE, "unavailable".to_owned());
+        let replies = process(&mut node, lin_kv(unavailable));
+        assert_eq!(
+            replies,
+            vec![
+                r#"{"src":"n1","dest":"c1","body":{"type":"error","in_reply_to":2,"code":11,"text":"unavailable"}}"#
+            ]
+        );
+        let replies = process(&mut node, lin_kv(Workload::kv_read_ok(2, 0, json!(1))));
+        assert!(replies.is_empty());
+
+        // so is lin-kv not answering, once the retries gave up.
+        process(&mut node, msg().id(3).list_committed_offsets(&["k1"]));
+        let mut now = Instant::now();
+        let mut replies = Vec::new();
+        while replies.is_empty() {
+            now += Duration::from_secs(1);
+            replies = node
+                .tick(now)
+                .unwrap()
+                .into_iter()
+                .filter(|reply| reply.dest == "c1")
+                .collect();
+        }
+        assert!(matches!(
+            replies[0].body,
+            Workload::Error {
+                in_reply_to: 3,
+                code: code::TIMEOUT,
+                ..
+            }
+        ));
+        assert!(node.tick(now + Duration::from_secs(1)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_kafka_commit_moves_forward() {
+        let mut node = create_node(&Config::default());
+        init(&mut node, "n1", &["n1"]);
+
+        // a commit behind the one in lin-kv leaves it alone.
+        process(&mut node, msg().id(1).commit_offsets(&[("k1", 3)]));
+        let replies = process(&mut node, lin_kv(Workload::kv_read_ok(1, 0, json!(5))));
+        assert!(replies[0].contains(r#""type":"commit_offsets_ok","in_reply_to":1"#));
+
+        // another commit got in between the read and the swap, it's read again.
+        process(&mut node, msg().id(2).commit_offsets(&[("k1", 8)]));
+        let replies = process(&mut node, lin_kv(Workload::kv_read_ok(3, 0, json!(5))));
+        assert!(replies[0].contains(r#""type":"cas","msg_id":4,"key":"commit-k1","from":5,"to":8"#));
+        let changed = Workload::error(4, code::PRECONDITION_FAILED, "changed".to_owned());
+        let replies = process(&mut node, lin_kv(changed));
+        assert!(replies[0].contains(r#""type":"read","msg_id":5"#));
+
+        // a failure is passed on to the client.
+        let unavailable =
+            Workload::error(5, code::TEMPORARILY_UNAVAILABLE, "unavailable".to_owned());
+        let replies = process(&mut node, lin_kv(unavailable));
+        assert_eq!(
+            replies,
+            vec![
+                r#"{"src":"n1","dest":"c1","body":{"type":"error","in_reply_to":2,"code":11,"text":"unavailable"}}"#
+            ]
+        );
+    }
+
+    #[test]
+    fn test_kafka_log_storage() {
+        use node::storage::FileStorage;
+
+        let path = std::env::temp_dir().join(format!("kafka-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let start = || {
+            let mut node = create_node(&Config::default());
+            node.set_log_storage(FileStorage::open(&path).unwrap())
+                .unwrap();
+            init(&mut node, "n1", &["n1"]);
+            node
+        };
+
+        let mut node = start();
+        process(&mut node, msg().id(2).send("k1", 9));
+        drop(node);
+
+        // a restarted node still has the entry, and appends after it.
+        let mut node = start();
+        let replies = process(&mut node, msg().id(3).send("k1", 5));
+        assert!(replies[0].contains(r#""offset":1"#));
+        let replies = process(&mut node, msg().id(4).poll(&[("k1", 0)]));
+        assert!(replies[0].contains(r#""msgs":{"k1":[[0,9],[1,5]]}"#));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_kafka_crash_recovery() {
+        use node::storage::MemoryStorage;
+        use node::testing::network::Network;
+
+        let disk = Arc::new(Mutex::new(MemoryStorage::default()));
+        let start = || {
+            let mut node = create_node(&Config::default());
+            node.set_log_storage(disk.clone()).unwrap();
+            node
+        };
+        let mut network = Network::new(1, start);
+        network.send(msg().id(1).send("k1", 9));
+        network.send(msg().id(2).send("k1", 5));
+        network.send(msg().id(3).commit_offsets(&[("k1", 1)]));
+        network.run(100);
+
+        // acknowledged entries and commits survive the crash.
+        network.crash("n1");
+        network.restart("n1", start());
+        let logs = network.node("n1").logs();
+        let polled = logs.poll(&HashMap::from([("k1".to_owned(), 0)]));
+        assert_eq!(polled["k1"], vec![(0, 9), (1, 5)]);
+        assert_eq!(logs.committed(&["k1".to_owned()])["k1"], 1);
+    }
+
+    #[test]
+    fn test_conformance() {
+        conformance::check(|| create_node(&Config::default()));
+    }
+}
@@ -0,0 +1,9 @@
+use node::config::Config;
+use node::Runner;
+
+fn main() {
+    let config = Config::from_env().expect("GLOMERS_* variables should be valid.");
+    let node = kafka::create_node(&config);
+    let mut runner = Runner::new(node).with_config(&config);
+    runner.start();
+}
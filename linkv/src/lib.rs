@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+
+use node::config::Config;
+use node::prelude::*;
+
+fn handler_read(node: &mut Node, msg: Message, out: &mut dyn Sink) -> Result<()> {
+    expect_body!(msg, Read { msg_id, key });
+    let Some(key) = key else {
+        let body = Workload::error(msg_id, code::MALFORMED_REQUEST, "missing key".to_owned());
+        out.send(node.reply(msg.src, body));
+        return Ok(());
+    };
+    let reply = match node.kv().read(&key) {
+        Ok(value) => node.reply_to((msg.src, msg_id), |in_reply_to, msg_id| {
+            Workload::kv_read_ok(in_reply_to, msg_id, value)
+        }),
+        Err((code, text)) => node.reply(msg.src, Workload::error(msg_id, code, text)),
+    };
+    out.send(reply);
+    Ok(())
+}
+
+fn handler_write(node: &mut Node, msg: Message, out: &mut dyn Sink) -> Result<()> {
+    expect_body!(msg, Write { msg_id, key, value });
+    node.kv_mut().write(&key, value);
+    out.send(node.reply_to((msg.src, msg_id), Workload::write_ok));
+    Ok(())
+}
+
+fn handler_cas(node: &mut Node, msg: Message, out: &mut dyn Sink) -> Result<()> {
+    expect_body!(
+        msg,
+        Cas {
+            msg_id,
+            key,
+            from,
+            to,
+            create_if_not_exists
+        }
+    );
+    let reply = match node.kv_mut().cas(&key, &from, to, create_if_not_exists) {
+        Ok(()) => node.reply_to((msg.src, msg_id), Workload::cas_ok),
+        Err((code, text)) => node.reply(msg.src, Workload::error(msg_id, code, text)),
+    };
+    out.send(reply);
+    Ok(())
+}
+
+pub fn create_node(config: &Config) -> Node {
+    let mut handlers: HashMap<Type, Handler> = HashMap::new();
+    handlers.insert(Type::Read, handler_read);
+    handlers.insert(Type::Write, handler_write);
+    handlers.insert(Type::Cas, handler_cas);
+    let mut node = Node::with_config(handlers, config);
+    node.enable_sessions();
+    node
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use node::testing::linearizability::History;
+    use node::testing::message::{init, msg};
+    use node::testing::network::Network;
+    use node::testing::sim::simulate;
+    use serde_json::json;
+
+    fn process(node: &mut Node, message: Message) -> String {
+        let reply = node.process(message).unwrap();
+        serde_json::to_string(reply.first().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_linkv() {
+        let mut node = create_node(&Config::default());
+        init(&mut node, "n1", &["n1"]);
+
+        let reply = process(&mut node, msg().id(1).kv_read(json!(0)));
+        assert_eq!(
+            reply,
+            r#"{"src":"n1","dest":"c1","body":{"type":"error","in_reply_to":1,"code":20,"text":"key 0 does not exist"}}"#
+        );
+
+        let reply = process(&mut node, msg().id(2).write(json!(0), json!(3)));
+        assert_eq!(
+            reply,
+            r#"{"src":"n1","dest":"c1","body":{"type":"write_ok","in_reply_to":2,"msg_id":1}}"#
+        );
+
+        let reply = process(&mut node, msg().id(3).cas(json!(0), json!(1), json!(4)));
+        assert_eq!(
+            reply,
+            r#"{"src":"n1","dest":"c1","body":{"type":"error","in_reply_to":3,"code":22,"text":"expected 1, but had 3"}}"#
+        );
+
+        let reply = process(&mut node, msg().id(4).cas(json!(0), json!(3), json!(4)));
+        assert_eq!(
+            reply,
+            r#"{"src":"n1","dest":"c1","body":{"type":"cas_ok","in_reply_to":4,"msg_id":2}}"#
+        );
+
+        let reply = process(&mut node, msg().id(5).kv_read(json!(0)));
+        assert_eq!(
+            reply,
+            r#"{"src":"n1","dest":"c1","body":{"type":"read_ok","in_reply_to":5,"msg_id":3,"value":4}}"#
+        );
+    }
+
+    #[test]
+    fn test_linkv_linearizable() {
+        simulate(20, |seed| {
+            let mut network = Network::new(1, || create_node(&Config::default())).with_seed(seed);
+            let mut history = History::default();
+            for msg_id in 1..=100 {
+                let rng = network.rng();
+                let client = format!("c{}", rng.between(1, 3));
+                let key = rng.between(0, 2);
+                let value = rng.between(0, 4);
+                let request = msg().from(&client).id(msg_id);
+                let request = match rng.between(0, 2) {
+                    0 => request.kv_read(key.into()),
+                    1 => request.write(key.into(), value.into()),
+                    _ => request.cas(key.into(), value.into(), rng.between(0, 4).into()),
+                };
+                history.invoke(network.now(), &request);
+                network.send(request);
+                let pause = network.rng().between(0, 3);
+                network.run_for(pause, 10);
+                for reply in network.take_outbox() {
+                    history.complete(network.now(), &reply);
+                }
+            }
+            if let Err(error) = history.check() {
+                panic!("{error}");
+            }
+        });
+    }
+
+    #[test]
+    fn test_conformance() {
+        conformance::check(|| create_node(&Config::default()));
+    }
+}
@@ -0,0 +1,323 @@
+// deserialize -> dispatch -> serialize for every workload, the broadcast gossip paths,
+// and a driver pushing a batch of messages through a Runner.
+// handlers mirror the workload binaries, which can't be linked from here.
+
+use std::collections::HashMap;
+use std::io::{sink, Cursor};
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use node::prelude::*;
+
+fn handler_echo(node: &mut Node, msg: Message, out: &mut dyn Sink) -> Result<()> {
+    let Workload::Echo { msg_id, echo } = msg.body else {
+        unreachable!()
+    };
+    let body = Workload::echo_ok(msg_id, node.gen_msg_id(), echo);
+    out.send(node.reply(msg.src, body));
+    Ok(())
+}
+
+fn handler_generate(node: &mut Node, msg: Message, out: &mut dyn Sink) -> Result<()> {
+    let Workload::Generate { msg_id, .. } = msg.body else {
+        unreachable!()
+    };
+    let body = Workload::generate_ok(msg_id, node.gen_msg_id(), node.gen_unique_id().unwrap());
+    out.send(node.reply(msg.src, body));
+    Ok(())
+}
+
+fn handler_broadcast(node: &mut Node, msg: Message, out: &mut dyn Sink) -> Result<()> {
+    let Workload::Broadcast {
+        msg_id, message, ..
+    } = msg.body
+    else {
+        unreachable!()
+    };
+    if node.push_broadcast_message(message).unwrap() {
+        for neighbor in node.neighbors().clone() {
+            if neighbor != msg.src {
+                let body = Workload::Broadcast {
+                    msg_id: node.gen_msg_id(),
+                    message,
+                    hops: None,
+                };
+                out.send(node.reply(neighbor, body));
+            }
+        }
+    }
+    let body = Workload::broadcast_ok(msg_id, node.gen_msg_id());
+    out.send(node.reply(msg.src, body));
+    Ok(())
+}
+
+fn handler_read(node: &mut Node, msg: Message, out: &mut dyn Sink) -> Result<()> {
+    let Workload::Read { msg_id, .. } = msg.body else {
+        unreachable!()
+    };
+    let body = Workload::read_ok(msg_id, node.gen_msg_id(), node.broadcast_snapshot());
+    out.send(node.reply(msg.src, body));
+    Ok(())
+}
+
+fn handler_write(node: &mut Node, msg: Message, out: &mut dyn Sink) -> Result<()> {
+    let Workload::Write { msg_id, key, value } = msg.body else {
+        unreachable!()
+    };
+    node.kv_mut().write(&key, value);
+    let body = Workload::write_ok(msg_id, node.gen_msg_id());
+    out.send(node.reply(msg.src, body));
+    Ok(())
+}
+
+fn handler_send(node: &mut Node, msg: Message, out: &mut dyn Sink) -> Result<()> {
+    let Workload::Send {
+        msg_id,
+        key,
+        msg: message,
+    } = msg.body
+    else {
+        unreachable!()
+    };
+    let offset = node.logs_mut().append(key, message)?;
+    let body = Workload::send_ok(msg_id, node.gen_msg_id(), offset);
+    out.send(node.reply(msg.src, body));
+    Ok(())
+}
+
+fn handler_poll(node: &mut Node, msg: Message, out: &mut dyn Sink) -> Result<()> {
+    let Workload::Poll { msg_id, offsets } = msg.body else {
+        unreachable!()
+    };
+    let msgs = node.logs().poll(&offsets);
+    let body = Workload::poll_ok(msg_id, node.gen_msg_id(), msgs);
+    out.send(node.reply(msg.src, body));
+    Ok(())
+}
+
+fn handler_txn(node: &mut Node, msg: Message, out: &mut dyn Sink) -> Result<()> {
+    let Workload::Txn { msg_id, txn } = msg.body else {
+        unreachable!()
+    };
+    let txn = node.store_mut().execute(txn).unwrap();
+    let body = Workload::txn_ok(msg_id, node.gen_msg_id(), txn);
+    out.send(node.reply(msg.src, body));
+    Ok(())
+}
+
+fn handler_topology(node: &mut Node, msg: Message, out: &mut dyn Sink) -> Result<()> {
+    let Workload::Topology {
+        msg_id,
+        mut topology,
+    } = msg.body
+    else {
+        unreachable!()
+    };
+    let neighbors = topology.remove(&node.node_id()).unwrap_or_default();
+    node.set_neighbors(neighbors);
+    let body = Workload::topology_ok(msg_id, node.gen_msg_id());
+    out.send(node.reply(msg.src, body));
+    Ok(())
+}
+
+fn create_node() -> Node {
+    let handlers: HashMap<Type, Handler> = HashMap::from([
+        (Type::Echo, handler_echo as Handler),
+        (Type::Generate, handler_generate),
+        (Type::Broadcast, handler_broadcast),
+        (Type::Read, handler_read),
+        (Type::Write, handler_write),
+        (Type::Send, handler_send),
+        (Type::Poll, handler_poll),
+        (Type::Txn, handler_txn),
+        (Type::Topology, handler_topology),
+    ]);
+    Node::new(handlers)
+}
+
+// 25 nodes in a star around n1, as in the efficient broadcast challenge.
+fn initialized_node() -> Node {
+    let node_ids: Vec<String> = (1..=25).map(|i| format!("n{i}")).collect();
+    let mut node = create_node();
+    let init = format!(
+        r#"{{"src":"c0","dest":"n1","body":{{"type":"init","msg_id":1,"node_id":"n1","node_ids":{}}}}}"#,
+        serde_json::to_string(&node_ids).unwrap()
+    );
+    let topology = format!(
+        r#"{{"src":"c0","dest":"n1","body":{{"type":"topology","msg_id":2,"topology":{{"n1":{}}}}}}}"#,
+        serde_json::to_string(&node_ids[1..]).unwrap()
+    );
+    round_trip(&mut node, init.as_bytes(), &mut Vec::new());
+    round_trip(&mut node, topology.as_bytes(), &mut Vec::new());
+    node
+}
+
+// the work the runner does per message, minus the channels.
+fn round_trip(node: &mut Node, line: &[u8], output: &mut Vec<u8>) -> usize {
+    output.clear();
+    let message = serde_json::from_slice::<Message>(line).unwrap();
+    let replies = node.process(message).unwrap();
+    for reply in &replies {
+        serde_json::to_writer(&mut *output, reply).unwrap();
+        output.push(b'\n');
+    }
+    replies.len()
+}
+
+fn bench_workloads(c: &mut Criterion) {
+    let messages = [
+        (
+            "echo",
+            r#"{"src":"c1","dest":"n1","body":{"type":"echo","msg_id":3,"echo":"Please echo 35"}}"#,
+        ),
+        (
+            "generate",
+            r#"{"src":"c1","dest":"n1","body":{"type":"generate","msg_id":3}}"#,
+        ),
+        (
+            "read",
+            r#"{"src":"c1","dest":"n1","body":{"type":"read","msg_id":3}}"#,
+        ),
+        (
+            "send",
+            r#"{"src":"c1","dest":"n1","body":{"type":"send","msg_id":3,"key":"k1","msg":123}}"#,
+        ),
+        (
+            "poll",
+            r#"{"src":"c1","dest":"n1","body":{"type":"poll","msg_id":3,"offsets":{"k1":0}}}"#,
+        ),
+        (
+            "txn",
+            r#"{"src":"c1","dest":"n1","body":{"type":"txn","msg_id":3,"txn":[["r",1,null],["w",1,6],["w",2,9]]}}"#,
+        ),
+        (
+            "kv_write",
+            r#"{"src":"c1","dest":"n1","body":{"type":"write","msg_id":3,"key":1,"value":2}}"#,
+        ),
+    ];
+
+    let mut group = c.benchmark_group("workload");
+    for (name, line) in messages {
+        let mut node = initialized_node();
+        let mut output = Vec::new();
+        group.bench_function(name, |b| {
+            b.iter(|| round_trip(&mut node, line.as_bytes(), &mut output))
+        });
+    }
+    group.finish();
+}
+
+fn bench_broadcast(c: &mut Criterion) {
+    let mut group = c.benchmark_group("broadcast");
+    let mut output = Vec::new();
+
+    // a new value fans out to all 24 neighbors.
+    let mut node = initialized_node();
+    let mut value = 0u64;
+    group.bench_function("gossip", |b| {
+        b.iter(|| {
+            value += 1;
+            let line = format!(
+                r#"{{"src":"c1","dest":"n1","body":{{"type":"broadcast","msg_id":3,"message":{value}}}}}"#
+            );
+            round_trip(&mut node, line.as_bytes(), &mut output)
+        })
+    });
+
+    // a value we have already seen is only acknowledged.
+    let line = r#"{"src":"n2","dest":"n1","body":{"type":"broadcast","msg_id":3,"message":1}}"#;
+    group.bench_function("dedup", |b| {
+        b.iter(|| round_trip(&mut node, line.as_bytes(), &mut output))
+    });
+
+    // "read" with 10k values known.
+    let mut node = initialized_node();
+    for value in 0..10_000 {
+        node.push_broadcast_message(value).unwrap();
+    }
+    let line = r#"{"src":"c1","dest":"n1","body":{"type":"read","msg_id":3}}"#;
+    group.bench_function("read_10k", |b| {
+        b.iter(|| round_trip(&mut node, line.as_bytes(), &mut output))
+    });
+    group.finish();
+}
+
+// synthetic driver: N messages through the whole runner pipeline (reader, processing and
+// writer threads), reported as messages per second.
+fn bench_runner(c: &mut Criterion) {
+    const MESSAGES: u64 = 10_000;
+    let mut input = String::from(
+        r#"{"src":"c0","dest":"n1","body":{"type":"init","msg_id":1,"node_id":"n1","node_ids":["n1"]}}"#,
+    );
+    input.push('\n');
+    for msg_id in 2..MESSAGES + 2 {
+        input.push_str(&format!(
+            r#"{{"src":"c1","dest":"n1","body":{{"type":"echo","msg_id":{msg_id},"echo":"Please echo {msg_id}"}}}}"#
+        ));
+        input.push('\n');
+    }
+
+    let mut group = c.benchmark_group("runner");
+    group.throughput(Throughput::Elements(MESSAGES));
+    group.sample_size(10);
+    group.bench_function("echo", |b| {
+        b.iter_batched(
+            || Runner::with_io(create_node(), Cursor::new(input.clone()), sink()),
+            |mut runner| runner.start(),
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+// serde against the precomputed templates, for the replies they cover.
+#[cfg(feature = "bench")]
+fn bench_templates(c: &mut Criterion) {
+    let replies = [
+        (
+            "echo_ok",
+            Workload::echo_ok(3, 4, "Please echo 35".to_owned()),
+        ),
+        (
+            "broadcast_ok",
+            Workload::BroadcastOk {
+                in_reply_to: 3,
+                msg_id: 4,
+            },
+        ),
+    ];
+
+    let mut group = c.benchmark_group("template");
+    let mut output = Vec::new();
+    for (name, body) in replies {
+        let reply = Message {
+            src: "n1".into(),
+            dest: "c1".into(),
+            body,
+        };
+        group.bench_function(format!("{name}/serde"), |b| {
+            b.iter(|| {
+                output.clear();
+                serde_json::to_writer(&mut output, &reply).unwrap();
+            })
+        });
+        group.bench_function(format!("{name}/template"), |b| {
+            b.iter(|| {
+                output.clear();
+                node::template::write_message(&mut output, &reply).unwrap();
+            })
+        });
+    }
+    group.finish();
+}
+
+#[cfg(not(feature = "bench"))]
+fn bench_templates(_: &mut Criterion) {}
+
+criterion_group!(
+    benches,
+    bench_workloads,
+    bench_broadcast,
+    bench_runner,
+    bench_templates
+);
+criterion_main!(benches);
@@ -0,0 +1,17 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use node::core::Message;
+use node_fuzz::{check, node};
+
+// arbitrary bytes, as they could arrive on STDIN.
+fuzz_target!(|data: &[u8]| {
+    let Ok(message) = serde_json::from_slice::<Message>(data) else {
+        return;
+    };
+    // what was parsed serializes back to the same message.
+    let json = serde_json::to_vec(&message).unwrap();
+    assert_eq!(serde_json::from_slice::<Message>(&json).unwrap(), message);
+
+    check(&mut node(), message);
+});
@@ -0,0 +1,83 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use node::core::Message;
+use node_fuzz::{check, node};
+use serde_json::{json, Map, Value};
+
+// bytes rarely make it past the parser, these are messages of known types with known field
+// names, but values of any shape: what a buggy peer or client could send.
+const TYPES: &[&str] = &[
+    "init", "echo", "generate", "broadcast", "broadcast_batch", "read", "write", "cas", "topology",
+    "send", "poll", "commit_offsets", "list_committed_offsets", "txn", "txn_replicate",
+    "log_append", "raft", "heartbeat", "sequence", "deliver", "dump_state", "hint", "join",
+    "leave", "error", "read_ok", "init_ok",
+];
+const FIELDS: &[&str] = &[
+    "msg_id", "in_reply_to", "node_id", "node_ids", "echo", "message", "messages", "topology",
+    "key", "value", "from", "to", "msg", "offset", "offsets", "keys", "txn", "lamport", "seq",
+    "code", "text", "rpc", "create_if_not_exists",
+];
+const NODES: &[&str] = &["c1", "n1", "n2", "n3", "lin-kv", ""];
+
+#[derive(Arbitrary, Debug)]
+enum Fuzzed {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Uint(u64),
+    Float(f64),
+    Str(String),
+    Node(u8),
+    Array(Vec<Fuzzed>),
+    Object(Vec<(u8, Fuzzed)>),
+}
+
+impl Fuzzed {
+    fn to_value(&self, depth: usize) -> Value {
+        match self {
+            _ if depth > 4 => Value::Null,
+            Fuzzed::Null => Value::Null,
+            Fuzzed::Bool(b) => json!(b),
+            Fuzzed::Int(i) => json!(i),
+            Fuzzed::Uint(u) => json!(u),
+            Fuzzed::Float(f) => json!(f),
+            Fuzzed::Str(s) => json!(s),
+            Fuzzed::Node(n) => json!(pick(NODES, *n)),
+            Fuzzed::Array(values) => values.iter().map(|v| v.to_value(depth + 1)).collect(),
+            Fuzzed::Object(fields) => Value::Object(object(fields, depth + 1)),
+        }
+    }
+}
+
+#[derive(Arbitrary, Debug)]
+struct FuzzedMessage {
+    src: u8,
+    kind: u8,
+    fields: Vec<(u8, Fuzzed)>,
+}
+
+fn pick<'a>(values: &[&'a str], index: u8) -> &'a str {
+    values[index as usize % values.len()]
+}
+
+fn object(fields: &[(u8, Fuzzed)], depth: usize) -> Map<String, Value> {
+    fields
+        .iter()
+        .map(|(name, value)| (pick(FIELDS, *name).to_owned(), value.to_value(depth)))
+        .collect()
+}
+
+// a whole conversation against one node, so earlier messages can set up state.
+fuzz_target!(|messages: Vec<FuzzedMessage>| {
+    let mut node = node();
+    for fuzzed in messages {
+        let mut body = object(&fuzzed.fields, 0);
+        body.insert("type".to_owned(), json!(pick(TYPES, fuzzed.kind)));
+        let message = json!({"src": pick(NODES, fuzzed.src), "dest": "n1", "body": body});
+        if let Ok(message) = serde_json::from_value::<Message>(message) {
+            check(&mut node, message);
+        }
+    }
+});
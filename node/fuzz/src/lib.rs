@@ -0,0 +1,30 @@
+use node::core::{code, Message, Node, Workload};
+use node::helper::Error;
+
+// a node past its init sequence, so requests reach the handlers.
+pub fn node() -> Node {
+    let mut node = Node::default();
+    let init = r#"{"src":"c1","dest":"n1","body":{"type":"init","msg_id":1,"node_id":"n1","node_ids":["n1","n2","n3"]}}"#;
+    node.process(serde_json::from_str(init).unwrap()).unwrap();
+    node
+}
+
+// whatever the message, processing it either answers or fails with an error, it never panics.
+// panics are caught by the node and turned into "crash" errors, those are what we look for.
+pub fn check(node: &mut Node, message: Message) {
+    match node.process(message) {
+        Ok(replies) => {
+            for reply in replies {
+                if let Workload::Error { code, text, .. } = &reply.body {
+                    assert_ne!(*code, code::CRASH, "handler panicked: {text}");
+                }
+            }
+        }
+        Err(e) => {
+            if let Some(Error::Panicked { reason }) = e.downcast_ref::<Error>() {
+                panic!("handler panicked: {reason}");
+            }
+            assert!(!e.to_string().is_empty());
+        }
+    }
+}
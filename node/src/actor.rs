@@ -0,0 +1,119 @@
+use std::sync::mpsc::{self, Sender};
+use std::thread::{self, JoinHandle};
+
+// where a component sends the answer to a request, see "Actor::ask".
+pub(crate) type Reply<T> = Sender<T>;
+
+// a part of the node that owns its state, on a thread of its own, and is only reached through
+// the requests sent to its "Actor". requests are handled one at a time, in the order they were
+// sent.
+pub(crate) trait Component: Send + 'static {
+    type Request: Send + 'static;
+
+    fn handle(&mut self, request: Self::Request);
+}
+
+// the handle on a running component. dropping it lets the component finish what was sent to it,
+// and waits for it to.
+pub(crate) struct Actor<C: Component> {
+    tx: Option<Sender<C::Request>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl<C: Component> Actor<C> {
+    pub(crate) fn spawn(name: &str, mut component: C) -> Self {
+        let (tx, rx) = mpsc::channel::<C::Request>();
+        let thread = thread::Builder::new()
+            .name(name.to_owned())
+            .spawn(move || {
+                for request in rx {
+                    component.handle(request);
+                }
+            })
+            .expect("Component thread should be spawned.");
+        Self {
+            tx: Some(tx),
+            thread: Some(thread),
+        }
+    }
+
+    // a request nothing waits on.
+    pub(crate) fn tell(&self, request: C::Request) {
+        self.tx
+            .as_ref()
+            .expect("Actor should have a channel until dropped.")
+            .send(request)
+            .expect("Component should outlive its actor.");
+    }
+
+    // a request and its answer, "request" is built around where the answer goes.
+    pub(crate) fn ask<T: Send + 'static>(&self, request: impl FnOnce(Reply<T>) -> C::Request) -> T {
+        let (reply, answer) = mpsc::channel();
+        self.tell(request(reply));
+        answer
+            .recv()
+            .expect("Component should answer every request.")
+    }
+}
+
+impl<C: Component> Drop for Actor<C> {
+    fn drop(&mut self) {
+        drop(self.tx.take());
+        if let Some(thread) = self.thread.take() {
+            // a component that panicked already failed the request it was handling.
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    enum Request {
+        Add(u32),
+        Sum(Reply<u32>),
+    }
+
+    struct Adder {
+        sum: u32,
+        seen: Arc<Mutex<Vec<u32>>>,
+    }
+
+    impl Component for Adder {
+        type Request = Request;
+
+        fn handle(&mut self, request: Request) {
+            match request {
+                Request::Add(n) => {
+                    self.sum += n;
+                    self.seen.lock().unwrap().push(n);
+                }
+                Request::Sum(reply) => {
+                    let _ = reply.send(self.sum);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_actor_in_order() {
+        let seen = Arc::default();
+        let adder = Adder {
+            sum: 0,
+            seen: Arc::clone(&seen),
+        };
+        let actor = Actor::spawn("adder", adder);
+        for n in 1..=10 {
+            actor.tell(Request::Add(n));
+        }
+        // asked after the adds, answered after them too.
+        assert_eq!(actor.ask(Request::Sum), 55);
+        actor.tell(Request::Add(100));
+        drop(actor);
+        // dropping it waited for the last add.
+        assert_eq!(seen.lock().unwrap().last(), Some(&100));
+        assert_eq!(seen.lock().unwrap().len(), 11);
+    }
+}
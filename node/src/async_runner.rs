@@ -0,0 +1,406 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use tokio::io::BufReader;
+use tokio::io::{stdin, stdout, AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use tokio::sync::oneshot;
+use tokio::task::{spawn_local, yield_now, LocalSet};
+use tokio::time::{interval, MissedTickBehavior};
+use tracing::{debug_span, warn, Instrument};
+
+use crate::core::{smallvec, Message, MessageId, Node, NodeId, Replies, Type, Workload};
+use crate::helper::Result;
+use crate::logging::{self, LogFormat, Verbosity};
+use crate::{parse_line, write_message};
+
+pub type LocalBoxFuture<'a> = Pin<Box<dyn Future<Output = Result<()>> + 'a>>;
+
+// a handler that can await the replies to its rpcs, registered with
+// "AsyncRunner::with_async_handler". "async fn"s are boxed at registration:
+// "|ctx, msg| Box::pin(handler_read(ctx, msg))".
+pub type AsyncHandler = for<'a> fn(&'a mut Ctx, Message) -> LocalBoxFuture<'a>;
+
+// what an async handler gets instead of "&mut Node": the node is only borrowed in between
+// awaits, other messages are processed while a handler waits for a reply.
+pub struct Ctx {
+    node: Rc<RefCell<Node>>,
+    outbox: UnboundedSender<Message>,
+}
+
+impl Ctx {
+    pub fn node_id(&self) -> NodeId {
+        self.node.borrow().node_id()
+    }
+
+    // runs "f" on the node, which mustn't be held on to across an await.
+    pub fn node<T>(&self, f: impl FnOnce(&mut Node) -> T) -> T {
+        f(&mut self.node.borrow_mut())
+    }
+
+    pub fn send(&self, message: Message) {
+        self.node.borrow_mut().sent(&message);
+        // the runner is gone, and stdout with it.
+        let _ = self.outbox.send(message);
+    }
+
+    pub fn reply_to<F>(&self, request: (NodeId, MessageId), body: F)
+    where
+        F: FnOnce(MessageId, MessageId) -> Workload,
+    {
+        let reply = self.node.borrow_mut().reply_to(request, body);
+        self.send(reply);
+    }
+
+    // sends "body" and waits for the reply, an error reply included.
+    pub async fn rpc(&self, dest: NodeId, body: Workload) -> Result<Message> {
+        let (sender, receiver) = oneshot::channel();
+        let request = self.node.borrow_mut().rpc(dest, body, move |_, reply| {
+            let _ = sender.send(reply);
+            Ok(Replies::new())
+        })?;
+        self.send(request);
+        Ok(receiver.await?)
+    }
+
+    // like "rpc", a "timeout" (code 0) error reply is made up when no reply came in time.
+    pub async fn rpc_with_timeout(
+        &self,
+        dest: NodeId,
+        body: Workload,
+        timeout: Duration,
+    ) -> Result<Message> {
+        let (sender, receiver) = oneshot::channel();
+        let request =
+            self.node
+                .borrow_mut()
+                .rpc_with_timeout(dest, body, timeout, move |_, reply| {
+                    let _ = sender.send(reply);
+                    Ok(Replies::new())
+                })?;
+        self.send(request);
+        Ok(receiver.await?)
+    }
+}
+
+// same contract as "Runner", but reading stdin doesn't block timers:
+// the node is ticked on "tick_interval" even when no input arrives.
+// everything runs on a current-thread runtime, so the node never leaves its thread.
+pub struct AsyncRunner {
+    node: Rc<RefCell<Node>>,
+    async_handlers: HashMap<Type, AsyncHandler>,
+    tick_interval: Duration,
+    verbosity: Verbosity,
+    log_format: LogFormat,
+}
+
+impl AsyncRunner {
+    pub fn new(node: Node) -> Self {
+        Self {
+            node: Rc::new(RefCell::new(node)),
+            async_handlers: HashMap::new(),
+            tick_interval: Duration::from_millis(100),
+            verbosity: Verbosity::default(),
+            log_format: LogFormat::default(),
+        }
+    }
+
+    // requests of type "key" are handled by "handler", each in a task of its own, and not by
+    // the node. replies to rpcs and requests before "init" still go to the node.
+    pub fn with_async_handler(mut self, key: Type, handler: AsyncHandler) -> Self {
+        self.async_handlers.insert(key, handler);
+        self
+    }
+
+    pub fn with_tick_interval(mut self, tick_interval: Duration) -> Self {
+        self.tick_interval = tick_interval;
+        self
+    }
+
+    pub fn with_verbosity(mut self, verbosity: Verbosity) -> Self {
+        self.verbosity = verbosity;
+        self
+    }
+
+    // JSON lines instead of text on STDERR, see "LogFormat".
+    pub fn with_log_format(mut self, log_format: LogFormat) -> Self {
+        self.log_format = log_format;
+        self
+    }
+
+    pub fn start(&mut self) {
+        logging::init(self.verbosity, self.log_format);
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("Tokio runtime should be created.")
+            .block_on(self.run());
+    }
+
+    pub async fn run(&mut self) {
+        self.run_with_io(BufReader::new(stdin()), stdout()).await;
+    }
+
+    // any newline-delimited reader/writer pair instead of STDIN/STDOUT.
+    pub async fn run_with_io<R, W>(&mut self, reader: R, mut writer: W)
+    where
+        R: AsyncBufRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let mut lines = reader.lines();
+        let mut buffer = Vec::new();
+        let mut ticker = interval(self.tick_interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let (outbox, mut sent) = unbounded_channel();
+
+        let tasks = LocalSet::new();
+        tasks
+            .run_until(async {
+                loop {
+                    let replies = tokio::select! {
+                        line = lines.next_line() => match line {
+                            Ok(Some(line)) => parse_line(&mut line.into_bytes())
+                                .inspect_err(|e| warn!(error = %e, "unparsable input"))
+                                .and_then(|message| self.dispatch(message, &outbox)),
+                            _ => break, // EOF or broken stdin.
+                        },
+                        Some(message) = sent.recv() => Ok(smallvec![message]),
+                        _ = ticker.tick() => self.node.borrow_mut().tick(Instant::now()),
+                    };
+
+                    // errors are traced by the node.
+                    if let Ok(replies) = replies {
+                        Self::write(&mut writer, &mut buffer, &replies).await;
+                    }
+                    // handlers spawned for this message run up to their first await, so
+                    // that what they send goes out before the next line is read.
+                    yield_now().await;
+                }
+
+                // handlers still waiting for a reply at EOF never get it.
+                yield_now().await;
+                while let Ok(message) = sent.try_recv() {
+                    Self::write(&mut writer, &mut buffer, &[message]).await;
+                }
+            })
+            .await;
+
+        self.node.borrow_mut().shutdown();
+        writer
+            .flush()
+            .await
+            .expect("STDOUT should be flushed on shutdown.");
+    }
+
+    fn dispatch(&self, message: Message, outbox: &UnboundedSender<Message>) -> Result<Replies> {
+        let handler = match self.async_handlers.get(&message.body.key()) {
+            Some(&handler)
+                if message.body.in_reply_to().is_none() && self.node.borrow().is_initialized() =>
+            {
+                handler
+            }
+            _ => return self.node.borrow_mut().process(message),
+        };
+
+        let span = debug_span!(
+            "message",
+            r#type = message.body.name(),
+            src = %message.src,
+            msg_id = message.body.msg_id(),
+        );
+        self.node.borrow_mut().observe(&message);
+        let mut ctx = Ctx {
+            node: self.node.clone(),
+            outbox: outbox.clone(),
+        };
+        let task = async move {
+            if let Err(e) = handler(&mut ctx, message).await {
+                warn!(error = %e, "failed");
+            }
+        };
+        spawn_local(task.instrument(span));
+        Ok(Replies::new())
+    }
+
+    // all replies are serialized into "buffer", reused across calls, and written at once.
+    async fn write<W: AsyncWrite + Unpin>(
+        writer: &mut W,
+        buffer: &mut Vec<u8>,
+        replies: &[Message],
+    ) {
+        if replies.is_empty() {
+            return;
+        }
+        buffer.clear();
+        for reply in replies {
+            write_message(buffer, reply).expect("Interpreter should serialize the message.");
+            buffer.push(b'\n');
+        }
+        writer
+            .write_all(buffer)
+            .await
+            .expect("A message should be written to STDOUT.");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{Handler, LIN_KV};
+    use crate::expect_body;
+    use crate::helper::Error;
+
+    // the callback-free version of a read served from lin-kv.
+    async fn handler_read(ctx: &mut Ctx, msg: Message) -> Result<()> {
+        expect_body!(msg, Read { msg_id, key });
+        let body = ctx.node(|node| Workload::read(node.gen_msg_id(), key.unwrap_or_default()));
+        let reply = ctx.rpc(LIN_KV.into(), body).await?;
+        let value = match reply.body {
+            Workload::ReadOk {
+                value: Some(value), ..
+            } => value,
+            Workload::Error { code, text, .. } => return Err(Box::new(Error::Rpc { code, text })),
+            body => return Err(format!("unexpected reply {body:?}").into()),
+        };
+        ctx.reply_to((msg.src, msg_id), |in_reply_to, msg_id| {
+            Workload::kv_read_ok(in_reply_to, msg_id, value)
+        });
+        Ok(())
+    }
+
+    async fn exchange(lines: &[&str]) -> Vec<String> {
+        let input = lines.join("\n") + "\n";
+        let mut output = Vec::new();
+        AsyncRunner::new(Node::default())
+            .with_async_handler(Type::Read, |ctx, msg| Box::pin(handler_read(ctx, msg)))
+            .run_with_io(input.as_bytes(), &mut output)
+            .await;
+        String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(str::to_owned)
+            .collect()
+    }
+
+    async fn handler_echo(ctx: &mut Ctx, msg: Message) -> Result<()> {
+        expect_body!(msg, Echo { msg_id, echo });
+        ctx.reply_to((msg.src, msg_id), |in_reply_to, msg_id| {
+            Workload::echo_ok(in_reply_to, msg_id, echo)
+        });
+        Ok(())
+    }
+
+    // a read lin-kv doesn't answer in time fails with the made up "timeout".
+    async fn handler_read_timeout(ctx: &mut Ctx, msg: Message) -> Result<()> {
+        expect_body!(msg, Read { msg_id, key });
+        let body = ctx.node(|node| Workload::read(node.gen_msg_id(), key.unwrap_or_default()));
+        let timeout = Duration::from_millis(20);
+        let reply = ctx.rpc_with_timeout(LIN_KV.into(), body, timeout).await?;
+        if let Workload::Error { code, text, .. } = reply.body {
+            let error = ctx.node(|node| node.reply(msg.src, Workload::error(msg_id, code, text)));
+            ctx.send(error);
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_async_runner_echo() {
+        let input = concat!(
+            r#"{"src":"c1","dest":"n1","body":{"type":"init","msg_id":1,"node_id":"n1","node_ids":["n1"]}}"#,
+            "\n",
+            r#"{"src":"c1","dest":"n1","body":{"type":"echo","echo":"Please echo 35","msg_id":2}}"#,
+            "\n",
+        );
+        // by a handler of the node, and by an async one.
+        let mut handlers: HashMap<Type, Handler> = HashMap::new();
+        handlers.insert(Type::Echo, |node, msg, out| {
+            expect_body!(msg, Echo { msg_id, echo });
+            out.send(node.reply_to((msg.src, msg_id), |in_reply_to, msg_id| {
+                Workload::echo_ok(in_reply_to, msg_id, echo)
+            }));
+            Ok(())
+        });
+        let mut output = Vec::new();
+        AsyncRunner::new(Node::new(handlers))
+            .run_with_io(input.as_bytes(), &mut output)
+            .await;
+        let mut async_output = Vec::new();
+        AsyncRunner::new(Node::default())
+            .with_async_handler(Type::Echo, |ctx, msg| Box::pin(handler_echo(ctx, msg)))
+            .run_with_io(input.as_bytes(), &mut async_output)
+            .await;
+
+        let expected = concat!(
+            r#"{"src":"n1","dest":"c1","body":{"type":"init_ok","in_reply_to":1}}"#,
+            "\n",
+            r#"{"src":"n1","dest":"c1","body":{"type":"echo_ok","in_reply_to":2,"msg_id":1,"echo":"Please echo 35"}}"#,
+            "\n",
+        );
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+        assert_eq!(String::from_utf8(async_output).unwrap(), expected);
+    }
+
+    #[tokio::test]
+    async fn test_async_rpc_timeout() {
+        let (mut input, reader) = tokio::io::duplex(1024);
+        let mut output = Vec::new();
+        let mut runner = AsyncRunner::new(Node::default())
+            .with_async_handler(Type::Read, |ctx, msg| {
+                Box::pin(handler_read_timeout(ctx, msg))
+            })
+            .with_tick_interval(Duration::from_millis(5));
+        let client = async move {
+            let lines = concat!(
+                r#"{"src":"c1","dest":"n1","body":{"type":"init","msg_id":1,"node_id":"n1","node_ids":["n1"]}}"#,
+                "\n",
+                r#"{"src":"c1","dest":"n1","body":{"type":"read","msg_id":2,"key":0}}"#,
+                "\n",
+            );
+            input.write_all(lines.as_bytes()).await.unwrap();
+            // lin-kv stays quiet until well past the timeout, then the input ends.
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        };
+        tokio::join!(
+            runner.run_with_io(BufReader::new(reader), &mut output),
+            client
+        );
+
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                r#"{"src":"n1","dest":"c1","body":{"type":"init_ok","in_reply_to":1}}"#,
+                r#"{"src":"n1","dest":"lin-kv","body":{"type":"read","msg_id":1,"key":0}}"#,
+                r#"{"src":"n1","dest":"c1","body":{"type":"error","in_reply_to":2,"code":0,"text":"rpc timed out"}}"#,
+            ]
+        );
+        assert_eq!(runner.node.borrow().pending_rpcs(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_async_handler() {
+        let output = exchange(&[
+            r#"{"src":"c1","dest":"n1","body":{"type":"init","msg_id":1,"node_id":"n1","node_ids":["n1"]}}"#,
+            r#"{"src":"c1","dest":"n1","body":{"type":"read","msg_id":2,"key":0}}"#,
+            r#"{"src":"c2","dest":"n1","body":{"type":"read","msg_id":3,"key":1}}"#,
+            r#"{"src":"lin-kv","dest":"n1","body":{"type":"error","in_reply_to":2,"code":20}}"#,
+            r#"{"src":"lin-kv","dest":"n1","body":{"type":"read_ok","in_reply_to":1,"value":5}}"#,
+        ])
+        .await;
+        assert_eq!(
+            output,
+            vec![
+                r#"{"src":"n1","dest":"c1","body":{"type":"init_ok","in_reply_to":1}}"#,
+                r#"{"src":"n1","dest":"lin-kv","body":{"type":"read","msg_id":1,"key":0}}"#,
+                r#"{"src":"n1","dest":"lin-kv","body":{"type":"read","msg_id":2,"key":1}}"#,
+                // the second read failed, the first one is answered once lin-kv is.
+                r#"{"src":"n1","dest":"c1","body":{"type":"read_ok","in_reply_to":2,"msg_id":3,"value":5}}"#,
+            ]
+        );
+    }
+}
@@ -0,0 +1,74 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// where a node takes the time from: rpc deadlines, round trip times, liveness and unique ids.
+// "SystemClock" outside of tests, "ManualClock" where a test decides when time passes.
+pub trait Clock: Send {
+    // monotonic, for timers and deadlines.
+    fn now(&self) -> Instant;
+    // wall clock, in milliseconds since the unix epoch, for ids.
+    fn unix_millis(&self) -> u64;
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn unix_millis(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Clock: should be able to get unix epoch.")
+            .as_millis() as u64
+    }
+}
+
+// a clock that only moves when told to. clones share their time, so a test keeps one to
+// advance the clock it gave a node.
+#[derive(Clone, Debug)]
+pub struct ManualClock {
+    start: Instant,
+    unix_millis: u64,
+    // nanoseconds, so that the clock and its clones can be on different threads.
+    elapsed: Arc<AtomicU64>,
+}
+
+impl ManualClock {
+    // stopped at "unix_millis" on the wall clock.
+    pub fn new(unix_millis: u64) -> Self {
+        Self {
+            start: Instant::now(),
+            unix_millis,
+            elapsed: Arc::default(),
+        }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.elapsed
+            .fetch_add(by.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    // time since the clock was created. it never goes back, an earlier "elapsed" is ignored.
+    pub fn set_elapsed(&self, elapsed: Duration) {
+        self.elapsed
+            .fetch_max(elapsed.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_nanos(self.elapsed.load(Ordering::Relaxed))
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed()
+    }
+
+    fn unix_millis(&self) -> u64 {
+        self.unix_millis + self.elapsed().as_millis() as u64
+    }
+}
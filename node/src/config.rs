@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+use std::env;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::helper::{Error, Result};
+
+// how a node picks the neighbors it gossips to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TopologyStrategy {
+    // the "topology" message, as maelstrom sends it.
+    #[default]
+    Maelstrom,
+    // a tree over the node ids, "Config::fanout" children per node.
+    Tree,
+    // every node is a neighbor.
+    Total,
+}
+
+impl FromStr for TopologyStrategy {
+    type Err = Error;
+
+    fn from_str(name: &str) -> std::result::Result<Self, Error> {
+        match name {
+            "maelstrom" => Ok(TopologyStrategy::Maelstrom),
+            "tree" => Ok(TopologyStrategy::Tree),
+            "total" => Ok(TopologyStrategy::Total),
+            _ => Err(Error::InvalidConfig {
+                name: "topology".to_owned(),
+                value: name.to_owned(),
+            }),
+        }
+    }
+}
+
+// what a workload can be tuned with between runs, without recompiling: "GLOMERS_*" environment
+// variables (see "from_env") or the flags of "glomers". a workload's "create_node(config)"
+// builds its node with it, the runner gets it with "Runner::with_config".
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    // gossip is batched and sent every interval, none sends every value right away.
+    pub gossip_interval: Option<Duration>,
+    // how long output may wait to be written along with more, see "Runner::with_write_batching".
+    pub batch_window: Duration,
+    // the first retry of "Node::send_reliably", doubling from there.
+    pub retry_timeout: Duration,
+    // children per node of a "Tree" topology.
+    pub fanout: usize,
+    pub topology: TopologyStrategy,
+    // events kept for "dump_state", see "Node::enable_history". none keeps nothing.
+    pub history: Option<usize>,
+    // what the broadcast workload enables, set in code rather than from variables.
+    // requests remembered per client and peer, see "Node::enable_reply_cache".
+    pub reply_cache: usize,
+    // heartbeat interval and timeout, see "Node::enable_failure_detector".
+    pub failure_detector: Option<(Duration, Duration)>,
+    // messages held per unreachable peer, needs the failure detector.
+    pub hinted_handoff: Option<usize>,
+    // gossip sent a second, and burst, see "Node::enable_rate_limit".
+    pub rate_limit: Option<(f64, u32)>,
+    // bytes of state past which a warning is logged and requests older than a minute are
+    // dropped from the reply cache, see "Node::enable_memory_guard".
+    pub memory_limit: Option<usize>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            gossip_interval: None,
+            batch_window: Duration::ZERO,
+            retry_timeout: Duration::from_millis(100),
+            fanout: 4,
+            topology: TopologyStrategy::default(),
+            history: None,
+            reply_cache: 1024,
+            failure_detector: None,
+            hinted_handoff: None,
+            rate_limit: None,
+            memory_limit: Some(256 << 20),
+        }
+    }
+}
+
+impl Config {
+    // the defaults, overridden by any of "GLOMERS_GOSSIP_INTERVAL_MS", "GLOMERS_BATCH_WINDOW_MS",
+    // "GLOMERS_RETRY_TIMEOUT_MS", "GLOMERS_FANOUT", "GLOMERS_TOPOLOGY" (maelstrom, tree or
+    // total) and "GLOMERS_HISTORY" that is set. an interval (or a history) of 0 turns batched
+    // gossip (or the history) off.
+    pub fn from_env() -> Result<Self> {
+        Self::from_vars(env::vars())
+    }
+
+    pub fn from_vars(vars: impl IntoIterator<Item = (String, String)>) -> Result<Self> {
+        let vars: HashMap<String, String> = vars.into_iter().collect();
+        let mut config = Config::default();
+        if let Some(ms) = parse(&vars, "GLOMERS_GOSSIP_INTERVAL_MS")? {
+            config.gossip_interval = (ms > 0).then(|| Duration::from_millis(ms));
+        }
+        if let Some(ms) = parse(&vars, "GLOMERS_BATCH_WINDOW_MS")? {
+            config.batch_window = Duration::from_millis(ms);
+        }
+        if let Some(ms) = parse(&vars, "GLOMERS_RETRY_TIMEOUT_MS")? {
+            config.retry_timeout = Duration::from_millis(ms);
+        }
+        if let Some(fanout) = parse(&vars, "GLOMERS_FANOUT")? {
+            config.fanout = fanout;
+        }
+        if let Some(topology) = vars.get("GLOMERS_TOPOLOGY") {
+            config.topology = topology.parse()?;
+        }
+        if let Some(capacity) = parse(&vars, "GLOMERS_HISTORY")? {
+            config.history = (capacity > 0).then_some(capacity);
+        }
+        Ok(config)
+    }
+}
+
+fn parse<T: FromStr>(vars: &HashMap<String, String>, name: &str) -> Result<Option<T>> {
+    let Some(value) = vars.get(name) else {
+        return Ok(None);
+    };
+    match value.parse() {
+        Ok(parsed) => Ok(Some(parsed)),
+        Err(_) => Err(Box::new(Error::InvalidConfig {
+            name: name.to_owned(),
+            value: value.to_owned(),
+        })),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_config_from_vars() {
+        assert_eq!(Config::from_vars(vars(&[])).unwrap(), Config::default());
+
+        let config = Config::from_vars(vars(&[
+            ("GLOMERS_GOSSIP_INTERVAL_MS", "200"),
+            ("GLOMERS_RETRY_TIMEOUT_MS", "50"),
+            ("GLOMERS_FANOUT", "2"),
+            ("GLOMERS_TOPOLOGY", "tree"),
+            ("GLOMERS_HISTORY", "500"),
+            ("PATH", "/usr/bin"),
+        ]))
+        .unwrap();
+        assert_eq!(config.gossip_interval, Some(Duration::from_millis(200)));
+        assert_eq!(config.batch_window, Duration::ZERO);
+        assert_eq!(config.retry_timeout, Duration::from_millis(50));
+        assert_eq!(
+            (config.fanout, config.topology),
+            (2, TopologyStrategy::Tree)
+        );
+        assert_eq!(config.history, Some(500));
+
+        let error = Config::from_vars(vars(&[("GLOMERS_FANOUT", "two")])).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            r#"Invalid configuration, GLOMERS_FANOUT="two"."#
+        );
+        assert!(Config::from_vars(vars(&[("GLOMERS_TOPOLOGY", "star")])).is_err());
+    }
+}
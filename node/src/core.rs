@@ -1,25 +1,194 @@
-use std::collections::HashMap;
-use std::num::Wrapping;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::result;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use crate::helper::{Error, Result};
+use arc_swap::ArcSwap;
+
+use crate::clock::{Clock, SystemClock};
+use crate::config::{Config, TopologyStrategy};
+use crate::crdt::GSet;
+use crate::detector::{FailureDetector, Liveness};
+use crate::election::Elector;
+use crate::expect_body;
+use crate::flow::Flow;
+use crate::gossip::Gossip;
+use crate::handoff::Hints;
+use crate::helper::{Error, ErrorContext, Result};
+use crate::history::{EventHistory, Processed};
+use crate::kv::Kv;
+use crate::logs::Logs;
+use crate::memory::{Crossing, MemoryGuard, MemoryUsage};
+use crate::metrics::{MessageCounters, RttEstimator};
+use crate::outbox::Outbox;
+use crate::persist::Persistence;
+use crate::raft::RaftRpc;
+use crate::reply_cache::{Lookup, ReplyCache};
+use crate::retry::{Adaptive, Exponential, RetryPolicy};
+use crate::rpc::Rpcs;
+use crate::sequencer::{Seq, Sequencer};
+use crate::session::Sessions;
+use crate::storage::{SpawnedStorage, Storage};
+#[cfg(any(test, feature = "testing"))]
+use crate::testing::faults::{Fault, Faults};
+use crate::throttle::{Throttle, TokenBucket};
+use crate::topology::{diameter, generate};
+use crate::txn::{Op, Store};
+use crate::uid::{Snowflake, UidGenerator, UidSource};
+use serde::de::{DeserializeOwned, Deserializer, Error as _};
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use smallvec::SmallVec;
+use tracing::{debug, debug_span, error, info, trace_span, warn};
+
+pub use crate::node_id::NodeId;
+pub use smallvec::smallvec;
 
-pub type NodeId = String;
 pub type MessageId = u32;
 pub type CodeId = u32;
-pub type Handler = fn(&mut Node, Message) -> Result<Vec<Message>>;
+// most handlers answer with one or two messages, those don't allocate.
+pub type Replies = SmallVec<[Message; 2]>;
+// handlers put what they send into "out" as they go, instead of building a collection of
+// their own: a fan-out to every peer goes straight into the batch handed to the writer.
+pub type Handler = fn(&mut Node, Message, &mut dyn Sink) -> Result<()>;
+pub type ShutdownHook = fn(&mut Node);
+pub type TickHook = fn(&mut Node, Instant) -> Result<Replies>;
+pub type EvictionHook = fn(&mut Node, &MemoryUsage);
+pub type Callback = Box<dyn FnOnce(&mut Node, Message) -> Result<Replies> + Send>;
+// a handler of a workload's own messages, see "Node::register_body".
+pub type BodyHandler<B> = fn(&mut Node, Message<B>, &mut dyn Sink) -> Result<()>;
+type DecodingHandler = Arc<dyn Fn(&mut Node, Message, &mut dyn Sink) -> Result<()> + Send + Sync>;
 pub type BroadcastMessage = u64;
+pub type LogKey = String;
+pub type LogMessage = u64;
+pub type Offset = u64;
+pub type Timestamp = u64;
+pub type KvKey = Value;
+pub type KvValue = Value;
+
+pub const LIN_KV: &str = "lin-kv";
+pub const SEQ_KV: &str = "seq-kv";
+
+// the write-ahead log of new broadcast values, see "Node::enable_wal".
+const BROADCAST_WAL: &str = "broadcast";
+
+// retries of "Node::send_reliably" unless set with "Node::enable_outbox": "retry_timeout"
+// ("Config::retry_timeout", 100ms) doubling up to 5s, for as long as it takes.
+fn outbox_policy(retry_timeout: Duration) -> Exponential {
+    Exponential::new(retry_timeout, Duration::from_secs(5).max(retry_timeout))
+}
+
+// retries of "Node::rpc_with_retry" unless set with "Node::set_retry_policy".
+fn rpc_policy() -> impl RetryPolicy {
+    Adaptive::new(Duration::from_secs(1), Duration::from_secs(5)).max_attempts(3)
+}
+
+// error codes defined by the maelstrom protocol.
+pub mod code {
+    use super::CodeId;
+
+    pub const TIMEOUT: CodeId = 0;
+    pub const NODE_NOT_FOUND: CodeId = 1;
+    pub const NOT_SUPPORTED: CodeId = 10;
+    pub const TEMPORARILY_UNAVAILABLE: CodeId = 11;
+    pub const MALFORMED_REQUEST: CodeId = 12;
+    pub const CRASH: CodeId = 13;
+    pub const ABORT: CodeId = 14;
+    pub const KEY_DOES_NOT_EXIST: CodeId = 20;
+    pub const KEY_ALREADY_EXISTS: CodeId = 21;
+    pub const PRECONDITION_FAILED: CodeId = 22;
+    pub const TXN_CONFLICT: CodeId = 30;
+}
+
+// logical clock shared by the send and receive paths of a node, see Node::reply and Node::process.
+// it lives in a "Cell" so that stamping an outgoing message doesn't need "&mut Node".
+#[derive(Debug, Default)]
+pub struct LamportClock {
+    time: Cell<Timestamp>,
+}
+
+impl LamportClock {
+    pub fn time(&self) -> Timestamp {
+        self.time.get()
+    }
+
+    // local event or send.
+    pub fn tick(&self) -> Timestamp {
+        self.time.set(self.time.get() + 1);
+        self.time.get()
+    }
+
+    // receive: move past whatever the sender had seen.
+    pub fn merge(&self, received: Timestamp) -> Timestamp {
+        self.time.set(self.time.get().max(received) + 1);
+        self.time.get()
+    }
+}
+
+// the node's message ids, a counter shared with the handles "Node::msg_ids" gives out: a thread
+// holding one takes ids that never clash with the node's.
+#[derive(Debug, Default, Clone)]
+pub struct MsgIds(Arc<AtomicU32>);
+
+impl MsgIds {
+    pub fn next(&self) -> MessageId {
+        self.0.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    // the last id taken, 0 before the first.
+    pub fn last(&self) -> MessageId {
+        self.0.load(Ordering::Relaxed)
+    }
+}
 
 pub struct Node {
     node_id: Option<NodeId>,
     node_ids: Option<Vec<NodeId>>,
     handlers: HashMap<Type, Handler>,
+    // handlers of custom bodies, by their "type" tag.
+    bodies: HashMap<&'static str, DecodingHandler>,
 
-    msg_counter: u32,
-    uid_counter: Wrapping<u8>,
-    broadcast_messages: Vec<BroadcastMessage>,
+    msg_ids: MsgIds,
+    uid: Box<dyn UidGenerator>,
+    broadcast_messages: GSet<BroadcastMessage>,
+    // the values seen as of the last "read", see "broadcast_snapshot".
+    broadcast_snapshot: Arc<ArcSwap<Vec<BroadcastMessage>>>,
+    // fewest hops every broadcast value took to get here, with a hop limit only.
+    broadcast_hops: HashMap<BroadcastMessage, u32>,
+    max_hops: Option<u32>,
     neighbors: Vec<NodeId>,
+    logs: Logs,
+    store: Store,
+    kv: Kv,
+    sequencer: Sequencer,
+    // the rpcs waiting on a reply, with the round trip times of the answered ones.
+    rpcs: Rpcs,
+    lamport: LamportClock,
+    detector: Option<FailureDetector>,
+    elector: Option<Elector>,
+    shutdown_hooks: Vec<ShutdownHook>,
+    tick_hooks: Vec<TickHook>,
+    eviction_hooks: Vec<EvictionHook>,
+    memory_guard: Option<MemoryGuard>,
+    counters: Arc<MessageCounters>,
+    #[cfg(any(test, feature = "testing"))]
+    faults: Option<Faults>,
+    clock: Box<dyn Clock>,
+    persistence: Option<Persistence>,
+    wal: bool,
+    reply_cache: Option<ReplyCache>,
+    sessions: Option<Sessions>,
+    outbox: Option<Outbox>,
+    retry_policy: Box<dyn RetryPolicy>,
+    hints: Option<Hints>,
+    throttle: Option<Throttle>,
+    config: Config,
+    gossip: Option<Gossip>,
+    history: Option<EventHistory>,
 }
 
 impl Node {
@@ -27,23 +196,87 @@ impl Node {
         handlers
             .entry(Type::Init)
             .or_insert(Self::handler_init as Handler);
+        handlers
+            .entry(Type::Heartbeat)
+            .or_insert(Self::handler_heartbeat as Handler);
+        handlers
+            .entry(Type::DumpState)
+            .or_insert(Self::handler_dump_state as Handler);
+        handlers
+            .entry(Type::Hint)
+            .or_insert(Self::handler_hint as Handler);
+        handlers
+            .entry(Type::Join)
+            .or_insert(Self::handler_join as Handler);
+        handlers
+            .entry(Type::Leave)
+            .or_insert(Self::handler_leave as Handler);
         Self {
             handlers,
+            bodies: HashMap::new(),
             node_id: None,
             node_ids: None,
-            msg_counter: 0,
-            uid_counter: Wrapping::default(),
-            broadcast_messages: Vec::new(),
+            msg_ids: MsgIds::default(),
+            uid: Box::new(Snowflake::default()),
+            broadcast_messages: GSet::default(),
+            broadcast_snapshot: Arc::default(),
+            broadcast_hops: HashMap::new(),
+            max_hops: None,
             neighbors: Vec::new(),
+            logs: Logs::default(),
+            store: Store::default(),
+            kv: Kv::default(),
+            sequencer: Sequencer::default(),
+            rpcs: Rpcs::default(),
+            lamport: LamportClock::default(),
+            detector: None,
+            elector: None,
+            shutdown_hooks: Vec::new(),
+            tick_hooks: Vec::new(),
+            eviction_hooks: Vec::new(),
+            memory_guard: None,
+            counters: Arc::default(),
+            #[cfg(any(test, feature = "testing"))]
+            faults: None,
+            clock: Box::new(SystemClock),
+            persistence: None,
+            wal: false,
+            reply_cache: None,
+            sessions: None,
+            outbox: None,
+            retry_policy: Box::new(rpc_policy()),
+            hints: None,
+            throttle: None,
+            config: Config::default(),
+            gossip: None,
+            history: None,
         }
     }
 
-    pub fn gen_msg_id(&mut self) -> MessageId {
-        self.msg_counter += 1;
-        self.msg_counter
+    // "new", tuned with "config" from the start, see "configure". what workloads build their
+    // node with, in "create_node(config)".
+    pub fn with_config(handlers: HashMap<Type, Handler>, config: &Config) -> Self {
+        let mut node = Self::new(handlers);
+        node.configure(config);
+        node
+    }
+
+    pub fn gen_msg_id(&self) -> MessageId {
+        self.msg_ids.next()
+    }
+
+    // a handle on the node's message ids, for another thread: the ids it takes are unique
+    // with the node's own.
+    pub fn msg_ids(&self) -> MsgIds {
+        self.msg_ids.clone()
     }
 
-    pub fn reply(&self, dest: NodeId, body: Workload) -> Message {
+    // inter-node messages carrying a lamport timestamp get stamped on the way out.
+    // a handler that stamped one already (e.g. with the time its transaction ran at) keeps it.
+    pub fn reply(&self, dest: NodeId, mut body: Workload) -> Message {
+        if body.lamport() == Some(0) {
+            body.set_lamport(self.lamport.tick());
+        }
         Message {
             src: self.node_id(),
             dest,
@@ -51,293 +284,2989 @@ impl Node {
         }
     }
 
-    pub fn process(&mut self, message: Message) -> Result<Vec<Message>> {
-        message.body.key().and_then(|key| {
-            if !self.is_initialized() && key != Type::Init {
-                return Err(Box::new(Error::NotInitializedYet));
-            }
+    // replies to request "in_reply_to" from "dest". the body is built by "body" from the ids the
+    // reply carries, the request's and a fresh one, e.g. "Workload::broadcast_ok" as is, or
+    // "|in_reply_to, msg_id| Workload::echo_ok(in_reply_to, msg_id, echo)".
+    pub fn reply_to<F>(&mut self, (dest, in_reply_to): (NodeId, MessageId), body: F) -> Message
+    where
+        F: FnOnce(MessageId, MessageId) -> Workload,
+    {
+        let msg_id = self.gen_msg_id();
+        self.reply(dest, body(in_reply_to, msg_id))
+    }
+
+    // sends "body" to "dest" and runs "callback" once the reply (matched by "in_reply_to") arrives.
+    pub fn rpc<F>(&mut self, dest: NodeId, body: Workload, callback: F) -> Result<Message>
+    where
+        F: FnOnce(&mut Node, Message) -> Result<Replies> + Send + 'static,
+    {
+        self.register_rpc(dest, body, None, Box::new(callback))
+    }
 
-            // workaround to let the handler take "self".
-            match self.handlers.get(&key) {
-                Some(handler) => handler(self, message),
-                None if self.is_initialized() && key == Type::Init => {
-                    Err(Box::new(Error::AlreadyInitialized))
+    // same as "rpc", but the callback gets a "timeout" error if no reply arrives in time.
+    pub fn rpc_with_timeout<F>(
+        &mut self,
+        dest: NodeId,
+        body: Workload,
+        timeout: Duration,
+        callback: F,
+    ) -> Result<Message>
+    where
+        F: FnOnce(&mut Node, Message) -> Result<Replies> + Send + 'static,
+    {
+        let deadline = Some(self.now() + timeout);
+        self.register_rpc(dest, body, deadline, Box::new(callback))
+    }
+
+    // same as "rpc", but sent again (under a new msg_id) every time the retry policy's timeout
+    // runs out, see "set_retry_policy". the callback gets the reply to any of the attempts, or a
+    // "timeout" error once the policy gave up.
+    pub fn rpc_with_retry<F>(
+        &mut self,
+        dest: NodeId,
+        body: Workload,
+        callback: F,
+    ) -> Result<Message>
+    where
+        F: FnOnce(&mut Node, Message) -> Result<Replies> + Send + 'static,
+    {
+        match self.retry_policy.timeout(1, self.rtt(&dest).as_ref()) {
+            Some(timeout) => self.rpc_attempt(dest, body, 1, timeout, Box::new(callback)),
+            None => self.register_rpc(dest, body, None, Box::new(callback)),
+        }
+    }
+
+    fn rpc_attempt(
+        &mut self,
+        dest: NodeId,
+        body: Workload,
+        attempt: u32,
+        timeout: Duration,
+        callback: Callback,
+    ) -> Result<Message> {
+        let deadline = Some(self.now() + timeout);
+        let retry = (dest, body.clone());
+        let attempt_callback = move |node: &mut Node, reply: Message| {
+            let (dest, mut body) = retry;
+            let timed_out = matches!(
+                reply.body,
+                Workload::Error {
+                    code: code::TIMEOUT,
+                    ..
                 }
-                None => Err(Box::new(Error::HandlerNotFound { key })),
-            }
-        })
+            );
+            let next = match timed_out {
+                true => node
+                    .retry_policy
+                    .timeout(attempt + 1, node.rtt(&dest).as_ref()),
+                false => None,
+            };
+            let Some(timeout) = next else {
+                return callback(node, reply);
+            };
+            body.set_msg_id(node.gen_msg_id());
+            let request = node.rpc_attempt(dest, body, attempt + 1, timeout, callback)?;
+            Ok(smallvec![request])
+        };
+        self.register_rpc(dest, body, deadline, Box::new(attempt_callback))
     }
 
-    // will return empty node_id if node is not initialized.
-    pub fn node_id(&self) -> NodeId {
-        self.node_id.clone().unwrap_or(String::new())
-    }
-
-    pub fn gen_unique_id(&mut self) -> String {
-        let now = SystemTime::now();
-        let epoch = now
-            .duration_since(UNIX_EPOCH)
-            .expect("Unique id: should be able to get unix epoch.")
-            .as_millis() as u64;
-        let part1 = (epoch << 30) >> 7; // 48 bit epoch
-
-        let node_id_mask = 0x000000000000FF00;
-        let node_id: u64 = self
-            .node_id()
-            .chars()
-            .skip(1)
-            .collect::<String>()
-            .parse()
-            .unwrap();
-        let part2 = (node_id << 8) & node_id_mask; // 8 bit node id
+    // the policy of "rpc_with_retry": an adaptive timeout per peer, up to 3 attempts unless
+    // a workload sets its own.
+    pub fn set_retry_policy(&mut self, policy: impl RetryPolicy + 'static) {
+        self.retry_policy = Box::new(policy);
+    }
 
-        self.uid_counter += 1;
-        let part3 = self.uid_counter.0 as u64; // 8 bit unique id counter (wrapped)
+    // sends "body" until "dest" replies to it, with the same msg_id every time so that the
+    // receiving side can tell retries apart (see "enable_reply_cache"). any reply will do, it's
+    // consumed by the outbox rather than dispatched.
+    pub fn send_reliably(&mut self, dest: NodeId, body: Workload) -> Result<Message> {
+        let msg_id = body.msg_id().ok_or(Error::MissingMessageId)?;
+        let message = self.reply(dest, body);
+        let now = self.now();
+        let rtt = self.rtt(&message.dest);
+        self.outbox
+            .get_or_insert_with(|| Outbox::new(outbox_policy(self.config.retry_timeout)))
+            .push(msg_id, message.clone(), now, rtt.as_ref());
+        Ok(message)
+    }
 
-        let unique_id = part1 | part2 | part3;
-        unique_id.to_string()
+    // "send_reliably" with a callback: sent with the same msg_id until "dest" replies, and the
+    // reply goes to "callback" (after which the outbox lets go of it). the receiving side should
+    // tell retries apart, e.g. with "enable_reply_cache", if handling one twice matters.
+    pub fn rpc_reliably<F>(&mut self, dest: NodeId, body: Workload, callback: F) -> Result<Message>
+    where
+        F: FnOnce(&mut Node, Message) -> Result<Replies> + Send + 'static,
+    {
+        let message = self.send_reliably(dest, body)?;
+        let msg_id = message.body.msg_id().ok_or(Error::MissingMessageId)?;
+        let now = self.now();
+        self.rpcs
+            .register(msg_id, message.dest, now, None, Box::new(callback));
+        Ok(message)
     }
 
-    pub fn push_broadcast_message(&mut self, message: BroadcastMessage) {
-        self.broadcast_messages.push(message);
+    // how "send_reliably" retries. unless set here, it's "Config::retry_timeout" doubling up
+    // to 5s, for as long as it takes.
+    pub fn enable_outbox(&mut self, policy: impl RetryPolicy + 'static) {
+        self.outbox = Some(Outbox::new(policy));
     }
 
-    pub fn broadcast_messages(&self) -> Vec<BroadcastMessage> {
-        self.broadcast_messages.clone()
+    // messages of "send_reliably" not acknowledged yet.
+    pub fn outbox(&self) -> Option<&Outbox> {
+        self.outbox.as_ref()
     }
 
-    pub fn neighbors(&self) -> &Vec<NodeId> {
-        &self.neighbors
+    // rpcs still waiting for a reply (or their timeout).
+    pub fn pending_rpcs(&self) -> usize {
+        self.rpcs.len()
     }
 
-    pub fn set_neighbors(&mut self, neighbors: Vec<NodeId>) {
-        self.neighbors = neighbors;
+    // round trip times of the rpcs answered by "peer", none until one was.
+    pub fn rtt(&self, peer: &str) -> Option<RttEstimator> {
+        self.rpcs.rtt(peer)
     }
 
-    fn is_initialized(&self) -> bool {
-        self.node_id.is_some() && self.node_ids.is_some()
+    // a timeout for the next rpc to "peer" adapted to its round trip times,
+    // "fallback" until there's any.
+    pub fn rpc_timeout(&self, peer: &str, fallback: Duration) -> Duration {
+        self.rtt(peer).map_or(fallback, |rtt| rtt.timeout())
     }
 
-    // once initialized, remove "init" handler,
-    // so that node can log error on receiving "init" message again!
-    fn init(&mut self, node_id: NodeId, node_ids: Vec<NodeId>) {
-        self.node_id = Some(node_id);
-        self.node_ids = Some(node_ids);
-        self.handlers.remove(&Type::Init);
+    // messages received and sent so far, by type and by peer.
+    pub fn counters(&self) -> &MessageCounters {
+        &self.counters
     }
 
-    fn handler_init(node: &mut Node, message: Message) -> Result<Vec<Message>> {
-        match message.body {
-            Workload::Init {
-                msg_id,
-                node_id,
-                node_ids,
-            } => {
-                node.init(node_id, node_ids);
-                let reply = node.reply(message.src, Workload::init_ok(msg_id));
-                Ok(vec![reply])
-            }
-            _ => Err(Box::new(Error::ExpectedMessage {
-                found: message.body.key().unwrap_or(Type::Invalid),
-                expected: Type::Init,
-            })),
+    // a handle on the counters, for another thread, e.g. "Runner::with_counter_reports".
+    pub fn shared_counters(&self) -> Arc<MessageCounters> {
+        self.counters.clone()
+    }
+
+    // faults to inject in the dispatch of handlers, for tests. none until first asked for.
+    #[cfg(any(test, feature = "testing"))]
+    pub fn faults(&mut self) -> &mut Faults {
+        self.faults.get_or_insert_with(|| Faults::new(0))
+    }
+
+    // a snapshot of the node's internals for debugging, what "dump_state" answers with.
+    pub fn state(&self) -> Value {
+        let pending = self.rpcs.to_json(self.now());
+        json!({
+            "node_id": self.node_id,
+            "node_ids": self.node_ids,
+            "neighbors": self.neighbors,
+            "msg_counter": self.msg_ids.last(),
+            "lamport": self.lamport.time(),
+            "broadcast_messages": self.broadcast_messages.values(),
+            "delivered": self.sequencer.delivered(),
+            "pending_rpcs": pending,
+            "outbox": self.outbox.as_ref().map_or(0, Outbox::len),
+            "hints": self.hints.as_ref().map_or(0, Hints::len),
+            "throttled": self.throttle.as_ref().map_or(0, Throttle::len),
+            "gossip_queued": self.gossip.as_ref().map_or(0, Gossip::len),
+            "sessions": self.sessions.as_ref().map_or(0, Sessions::len),
+            "history": self.history.as_ref().map(EventHistory::to_json),
+            "suspects": self.suspects(),
+            "slow": self.slow_peers(),
+            "counters": self.counters.to_json(),
+            "memory": self.memory_usage(),
+            "srtt_us": self
+                .rpcs
+                .rtts()
+                .iter()
+                .map(|(peer, rtt)| (peer.to_string(), rtt.srtt().as_micros()))
+                .collect::<BTreeMap<_, _>>(),
+        })
+    }
+
+    // hooks run in registration order once the input is exhausted.
+    pub fn add_shutdown_hook(&mut self, hook: ShutdownHook) {
+        self.shutdown_hooks.push(hook);
+    }
+
+    pub fn shutdown(&mut self) {
+        for hook in std::mem::take(&mut self.shutdown_hooks) {
+            hook(self);
+        }
+        if let Err(error) = self.persist(self.clock.unix_millis()) {
+            warn!(%error, "state not persisted");
         }
     }
-}
 
-impl Default for Node {
-    fn default() -> Self {
-        let handlers = HashMap::new();
-        Node::new(handlers)
+    // messages of the types "B" has are handled by "handler", their bodies parsed into "B".
+    // a "Type::Custom" handler gets the custom bodies no registered type claims.
+    pub fn register_body<B: Body>(&mut self, handler: BodyHandler<B>) {
+        let decoding: DecodingHandler =
+            Arc::new(move |node, message, out| handler(node, message.decode()?, out));
+        for name in B::TYPES {
+            assert!(
+                Type::ALL.iter().all(|key| key.name() != *name),
+                "{name} should not be a type \"Workload\" has."
+            );
+            self.bodies.insert(name, Arc::clone(&decoding));
+        }
     }
-}
 
-#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
-pub struct Message {
-    pub src: NodeId,
-    pub dest: NodeId,
-    pub body: Workload,
-}
+    // periodic work (gossip, retries, ...), run on every tick once the node is initialized.
+    pub fn add_tick_hook(&mut self, hook: TickHook) {
+        self.tick_hooks.push(hook);
+    }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
-#[serde(tag = "type", rename_all = "snake_case")]
-pub enum Workload {
-    Init {
-        msg_id: MessageId,
-        node_id: NodeId,
-        node_ids: Vec<NodeId>,
-    },
-    InitOk {
-        in_reply_to: MessageId,
-    },
-    Error {
-        in_reply_to: MessageId,
-        code: CodeId,
-        text: String,
-    },
-    Echo {
-        msg_id: MessageId,
-        echo: String,
-    },
-    EchoOk {
-        in_reply_to: MessageId,
-        msg_id: MessageId,
-        echo: String,
-    },
-    Generate {
-        msg_id: MessageId,
-    },
-    GenerateOk {
-        in_reply_to: MessageId,
-        msg_id: MessageId,
-        id: String,
-    },
-    Broadcast {
-        msg_id: MessageId,
-        message: BroadcastMessage,
-    },
-    BroadcastOk {
-        in_reply_to: MessageId,
-        msg_id: MessageId,
-    },
-    Read {
-        msg_id: MessageId,
-    },
-    ReadOk {
-        in_reply_to: MessageId,
-        msg_id: MessageId,
-        messages: Vec<BroadcastMessage>,
-    },
-    Topology {
-        msg_id: MessageId,
-        topology: HashMap<NodeId, Vec<NodeId>>,
-    },
-    TopologyOk {
-        in_reply_to: MessageId,
-        msg_id: MessageId,
-    },
-}
+    // what the state that grows with the run takes, roughly, see "MemoryUsage".
+    pub fn memory_usage(&self) -> MemoryUsage {
+        MemoryUsage::new(
+            self.broadcast_messages.len(),
+            self.broadcast_hops.len(),
+            self.reply_cache.as_ref().map_or(0, ReplyCache::len),
+            self.outbox.as_ref().map_or(0, Outbox::len),
+        )
+    }
 
-impl Workload {
-    pub fn key(&self) -> Result<Type> {
-        match self {
-            Workload::Init { .. } => Ok(Type::Init),
-            Workload::Echo { .. } => Ok(Type::Echo),
-            Workload::Generate { .. } => Ok(Type::Generate),
-            Workload::Broadcast { .. } => Ok(Type::Broadcast),
-            Workload::Read { .. } => Ok(Type::Read),
-            Workload::Topology { .. } => Ok(Type::Topology),
-            _ => Err(Box::new(Error::KeyNotFound)),
+    // opt-in, checked every tick: once the state takes more than "threshold" bytes a warning
+    // is logged, and the eviction hooks run on every tick until it's back under.
+    pub fn enable_memory_guard(&mut self, threshold: usize) {
+        self.memory_guard = Some(MemoryGuard::new(threshold));
+    }
+
+    // frees what the node can do without, e.g. "prune_reply_cache". runs in registration order.
+    pub fn add_eviction_hook(&mut self, hook: EvictionHook) {
+        self.eviction_hooks.push(hook);
+    }
+
+    // forgets the requests first seen more than "older_than" ago, a retry of one of those is
+    // handled again. returns how many were forgotten.
+    pub fn prune_reply_cache(&mut self, older_than: Duration) -> usize {
+        let before = self.now().checked_sub(older_than);
+        match (self.reply_cache.as_mut(), before) {
+            (Some(cache), Some(before)) => cache.prune(before),
+            _ => 0,
         }
     }
 
-    pub fn echo_ok(in_reply_to: MessageId, msg_id: MessageId, echo: String) -> Workload {
-        Workload::EchoOk {
-            in_reply_to,
-            msg_id,
-            echo,
+    fn guard_memory(&mut self) {
+        let usage = self.memory_usage();
+        let Some(guard) = self.memory_guard.as_mut() else {
+            return;
+        };
+        match guard.check(&usage) {
+            Some(Crossing::Above) => warn!(
+                bytes = usage.total(),
+                threshold = guard.threshold(),
+                ?usage,
+                "memory over threshold"
+            ),
+            Some(Crossing::Below) => info!(bytes = usage.total(), "memory back under threshold"),
+            None => {}
+        }
+        if guard.is_over() {
+            for hook in self.eviction_hooks.clone() {
+                hook(self, &usage);
+            }
         }
     }
 
-    pub fn generate_ok(in_reply_to: MessageId, msg_id: MessageId, id: String) -> Workload {
-        Workload::GenerateOk {
-            in_reply_to,
-            msg_id,
-            id,
+    // the system clock unless a test replaced it, see "clock::ManualClock".
+    pub fn set_clock(&mut self, clock: impl Clock + 'static) {
+        self.clock = Box::new(clock);
+    }
+
+    // "uid::Snowflake" unless replaced, e.g. with "uid::Ulid" right after "Node::new".
+    pub fn set_uid_generator(&mut self, generator: impl UidGenerator + 'static) {
+        self.uid = Box::new(generator);
+    }
+
+    pub fn now(&self) -> Instant {
+        self.clock.now()
+    }
+
+    // opt-in, the state a restarted node shouldn't lose (the unique id generator, the seen
+    // broadcast values, the neighbors and the messages of "send_reliably" still unacknowledged)
+    // is saved to "<dir>/<node_id>.log" (a "storage::FileStorage") every "interval" and on
+    // shutdown, and restored on "init", the outbox sending its messages again. the uid generator
+    // saves ahead of itself on top of that, see "UidGenerator::save", so ids are never repeated
+    // however the node went down.
+    pub fn enable_persistence(&mut self, dir: impl Into<PathBuf>, interval: Duration) {
+        self.persistence = Some(Persistence::new(dir.into(), interval));
+    }
+
+    // same, kept in "storage" instead of a file of its own.
+    pub fn enable_persistence_with(&mut self, storage: impl Storage + 'static, interval: Duration) {
+        let storage = SpawnedStorage::spawn(storage);
+        self.persistence = Some(Persistence::with_storage(Box::new(storage), interval));
+    }
+
+    // opt-in on top of persistence: a new broadcast value is appended to a write-ahead log in
+    // the storage before the node goes on with it (and replies "broadcast_ok"), instead of
+    // waiting for the next save, and the log is replayed on "init". a value that can't be
+    // logged fails the handler and isn't kept, the client gets an error instead of an ok. every
+    // save covers the values logged so far, the log is emptied after it.
+    pub fn enable_wal(&mut self) {
+        self.wal = true;
+    }
+
+    // appends "record" to the write-ahead log "stream" (e.g. "counter" for deltas), for state of
+    // a workload's own. nothing without "enable_wal" and persistence.
+    pub fn wal_append(&mut self, stream: &str, record: &Value) -> Result<()> {
+        let (true, Some(node_id)) = (self.wal, self.node_id) else {
+            return Ok(());
+        };
+        match self.persistence.as_mut() {
+            Some(persistence) => persistence.append(&node_id, stream, record),
+            None => Ok(()),
         }
     }
 
-    pub fn broadcast_ok(in_reply_to: MessageId, msg_id: MessageId) -> Workload {
-        Workload::BroadcastOk {
-            in_reply_to,
-            msg_id,
+    // the records of "stream" logged so far, oldest first, to rebuild from after "init".
+    pub fn wal_records(&mut self, stream: &str) -> Result<Vec<Value>> {
+        let (true, Some(node_id)) = (self.wal, self.node_id) else {
+            return Ok(Vec::new());
+        };
+        match self.persistence.as_mut() {
+            Some(persistence) => persistence.records(&node_id, stream),
+            None => Ok(Vec::new()),
         }
     }
 
-    pub fn read_ok(
-        in_reply_to: MessageId,
-        msg_id: MessageId,
-        messages: Vec<BroadcastMessage>,
-    ) -> Workload {
-        Workload::ReadOk {
-            in_reply_to,
-            msg_id,
-            messages,
+    fn log_broadcast_message(&mut self, message: BroadcastMessage) -> Result<()> {
+        self.wal_append(BROADCAST_WAL, &json!(message))
+    }
+
+    // opt-in, a client or peer retrying a request (same "src" and "msg_id") gets the reply sent
+    // the first time instead of having it handled twice, e.g. a broadcast gossiped again. a retry
+    // arriving before the reply is dropped. the last "capacity" requests of every sender are kept.
+    pub fn enable_reply_cache(&mut self, capacity: usize) {
+        self.reply_cache = Some(ReplyCache::new(capacity));
+    }
+
+    // opt-in, every client gets a session: a request with a "msg_id" the client already went
+    // past is answered with an "abort" error instead of being handled (retries the reply cache
+    // recognizes aside). that's all a session keeps, reads are left to the store: a client talks
+    // to a single node, whose store never goes back to an older value, which gives it
+    // read-your-writes and monotonic reads.
+    pub fn enable_sessions(&mut self) {
+        self.sessions = Some(Sessions::default());
+    }
+
+    pub fn sessions_mut(&mut self) -> Option<&mut Sessions> {
+        self.sessions.as_mut()
+    }
+
+    fn persist(&mut self, unix_millis: u64) -> Result<()> {
+        let (Some(node_id), Some(_)) = (self.node_id, self.persistence.as_ref()) else {
+            return Ok(());
+        };
+        let outbox: Vec<&Message> = self.outbox.iter().flat_map(Outbox::messages).collect();
+        let state = json!({
+            "uid": self.uid.save(unix_millis),
+            "broadcast_messages": self.broadcast_messages(),
+            "outbox": outbox,
+            "neighbors": self.neighbors,
+            "max_hops": self.max_hops,
+        });
+        let now = self.clock.now();
+        let wal = self.wal;
+        match self.persistence.as_mut() {
+            Some(persistence) => {
+                persistence.save(&node_id, &state, now)?;
+                if wal {
+                    persistence.truncate(&node_id, BROADCAST_WAL)?;
+                }
+                Ok(())
+            }
+            None => Ok(()),
         }
     }
 
-    pub fn topology_ok(in_reply_to: MessageId, msg_id: MessageId) -> Workload {
-        Workload::TopologyOk {
-            in_reply_to,
-            msg_id,
+    fn restore(&mut self, node_id: &NodeId) -> Result<()> {
+        let Some(persistence) = self.persistence.as_mut() else {
+            return Ok(());
+        };
+        if self.wal {
+            // values logged after the last save, or before the first one.
+            for record in persistence.records(node_id, BROADCAST_WAL)? {
+                self.broadcast_messages
+                    .insert(serde_json::from_value(record)?);
+            }
+        }
+        let Some(state) = persistence.load(node_id)? else {
+            return Ok(());
+        };
+        self.uid.restore(&state["uid"]);
+        let messages: Vec<BroadcastMessage> =
+            serde_json::from_value(state["broadcast_messages"].clone()).unwrap_or_default();
+        for message in messages {
+            self.broadcast_messages.insert(message);
+        }
+        // the topology isn't sent again to a node that restarted, it would stop gossiping.
+        let neighbors: Vec<NodeId> =
+            serde_json::from_value(state["neighbors"].clone()).unwrap_or_default();
+        if !neighbors.is_empty() {
+            self.set_neighbors(neighbors);
+            self.max_hops = serde_json::from_value(state["max_hops"].clone()).unwrap_or_default();
+        }
+        // msg ids start over, the messages are sent again under new ones.
+        let outbox: Vec<Message> =
+            serde_json::from_value(state["outbox"].clone()).unwrap_or_default();
+        let now = self.now();
+        for mut message in outbox {
+            let msg_id = self.gen_msg_id();
+            message.body.set_msg_id(msg_id);
+            let rtt = self.rtt(&message.dest);
+            self.outbox
+                .get_or_insert_with(|| Outbox::new(outbox_policy(self.config.retry_timeout)))
+                .push(msg_id, message, now, rtt.as_ref());
         }
+        Ok(())
     }
 
-    fn init_ok(in_reply_to: MessageId) -> Workload {
-        Workload::InitOk { in_reply_to }
+    // opt-in, heartbeats are sent from "tick" and any message received counts as liveness. the
+    // pace of the heartbeats alone is what silences are judged against.
+    pub fn enable_failure_detector(&mut self, interval: Duration, timeout: Duration) {
+        let mut detector = FailureDetector::new(interval, timeout);
+        detector.set_peers(&self.peers(), self.now());
+        self.detector = Some(detector);
     }
-}
 
-#[derive(Eq, PartialEq, Hash, Debug, Clone)]
-pub enum Type {
-    Init,
-    Echo,
-    Generate,
-    Broadcast,
-    Read,
-    Topology,
+    // peers that didn't show any sign of life lately, empty unless the detector is enabled.
+    pub fn suspects(&self) -> Vec<NodeId> {
+        self.detector
+            .as_ref()
+            .map(FailureDetector::suspects)
+            .unwrap_or_default()
+    }
 
-    Invalid, // received key is either not listed or missing in the message.
-}
+    // graded, "Liveness::Alive" unless the detector is enabled.
+    pub fn liveness(&self, peer: &NodeId) -> Liveness {
+        self.detector
+            .as_ref()
+            .map_or(Liveness::Alive, |detector| detector.liveness(peer))
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    // peers that are late but not suspected, their retries wait, see "Outbox::due".
+    pub fn slow_peers(&self) -> Vec<NodeId> {
+        self.detector
+            .as_ref()
+            .map(FailureDetector::slow)
+            .unwrap_or_default()
+    }
 
-    #[test]
-    fn test_node_init() {
-        let mut node = Node::default();
-        let message = serde_json::from_str::<Message>(
-            r#"{"src":"c1","dest":"n1","body":{"type":"init","msg_id":1,"node_id":"n1","node_ids":["n1","n2","n3"]}}"#,
-        )
-        .unwrap();
-        let reply = node.process(message);
-        assert!(reply.is_ok());
+    pub fn detector_mut(&mut self) -> Option<&mut FailureDetector> {
+        self.detector.as_mut()
+    }
 
-        let reply = serde_json::to_string(&reply.unwrap().first().unwrap()).unwrap();
-        assert_eq!(
-            reply,
-            r#"{"src":"n1","dest":"c1","body":{"type":"init_ok","in_reply_to":1}}"#
+    // opt-in, a coordinator for workloads that need a single one, see "election::Elector":
+    // heartbeats go to every peer each "heartbeat_interval" from "tick", and the leader is the
+    // lowest node id heard from within "lease". the failure detector's heartbeats count too.
+    pub fn enable_election(&mut self, lease: Duration, heartbeat_interval: Duration) {
+        let now = self.now();
+        let elector = Elector::new(
+            self.node_id(),
+            self.node_ids(),
+            lease,
+            heartbeat_interval,
+            now,
         );
+        self.elector = Some(elector);
     }
 
-    #[test]
-    fn test_node_not_init() {
-        let mut node = Node::default();
-        let json =
-            r#"{"src":"c1","dest":"n2","body":{"type":"echo","echo":"Hello, World!","msg_id":1}}"#;
-        let message = serde_json::from_str::<Message>(json).unwrap();
-        let reply = node.process(message); // received valid message but node is not initialized yet.
-        assert!(reply.is_err());
-        assert_eq!(
-            reply.err().unwrap().to_string(),
-            Box::new(Error::NotInitializedYet).to_string()
-        );
+    // the current leader, none unless the election is enabled.
+    pub fn leader(&self) -> Option<NodeId> {
+        let now = self.now();
+        self.elector.as_ref().map(|elector| *elector.leader(now))
     }
 
-    #[test]
-    fn test_node_fail_reini() {
-        let mut node = Node::default();
-        let json = r#"{"src":"c1","dest":"n1","body":{"type":"init","msg_id":1,"node_id":"n1","node_ids":["n1","n2","n3"]}}"#;
-        let message = serde_json::from_str::<Message>(json).unwrap();
+    pub fn is_leader(&self) -> bool {
+        self.leader() == Some(self.node_id())
+    }
 
-        let _ = node.process(message.clone()); // initialized.
-        let reply = node.process(message); // receiving "init" again!
-        assert!(reply.is_err());
-        assert_eq!(
-            reply.err().unwrap().to_string(),
-            Box::new(Error::AlreadyInitialized).to_string()
-        );
+    // tunes the node with "config", see "Config". the outbox retries from "retry_timeout" on
+    // unless set with "enable_outbox", gossip is batched with a "gossip_interval", and a
+    // "history" capacity keeps one.
+    pub fn configure(&mut self, config: &Config) {
+        self.gossip = config.gossip_interval.map(Gossip::new);
+        self.history = config.history.map(EventHistory::new);
+        self.config = config.clone();
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    // opt-in, the last "capacity" messages handled (and ticks that sent something), with
+    // what the node sent in response and when, see "EventHistory". it's part of "state" too,
+    // which a "dump_state" request returns.
+    pub fn enable_history(&mut self, capacity: usize) {
+        self.history = Some(EventHistory::new(capacity));
+    }
+
+    pub fn history(&self) -> Option<&EventHistory> {
+        self.history.as_ref()
+    }
+
+    // opt-in, on top of the failure detector. a message of "send_reliably" to a suspected peer
+    // is handed off once to a peer that isn't, which holds it until the destination is no
+    // longer suspected (by the holder) and delivers it: a partition between two nodes only
+    // delays their messages as long as a third one reaches both. the sender keeps retrying
+    // as usual, the reply cache of the destination (see "enable_reply_cache") tells the copies
+    // apart. at most "capacity" messages are held per destination.
+    pub fn enable_hinted_handoff(&mut self, capacity: usize) {
+        self.hints = Some(Hints::new(capacity));
+    }
+
+    // opt-in, requests to other nodes (gossip, retries of the outbox, rpcs) go out at most
+    // "rate" a second, with bursts of up to "burst". the rest waits in a queue, where a message
+    // with the same payload as one already waiting for the same peer is dropped (an rpc never
+    // is): retries piling up behind a partition go out once it heals, not all at once.
+    // replies, heartbeats and messages to clients and services are never held back.
+    pub fn enable_rate_limit(&mut self, rate: f64, burst: u32) {
+        self.throttle = Some(Throttle::new(TokenBucket::new(rate, burst)));
+    }
+
+    // what the rate limit lets through of "replies", and of those held back earlier. a message
+    // dropped as a duplicate needs no retries of its own, nor a callback.
+    fn throttled(&mut self, replies: Result<Replies>, now: Instant) -> Result<Replies> {
+        let Some(throttle) = self.throttle.as_mut() else {
+            return replies;
+        };
+        let rpcs = &self.rpcs;
+        let (admitted, coalesced) = throttle.admit(replies?, now, |msg_id| rpcs.is_pending(msg_id));
+        for msg_id in coalesced {
+            if let Some(outbox) = self.outbox.as_mut() {
+                outbox.remove(msg_id);
+            }
+            self.rpcs.cancel(msg_id);
+        }
+        Ok(admitted)
+    }
+
+    // hands off what's waiting on suspected peers, and delivers the hints held for peers that
+    // are back.
+    fn hand_off(&mut self) -> Result<Replies> {
+        let mut replies = Replies::new();
+        let suspects = self.suspects();
+        let held = self
+            .hints
+            .as_mut()
+            .map(|hints| hints.deliverable(&suspects))
+            .unwrap_or_default();
+        for message in held {
+            let body = Workload::Hint {
+                msg_id: self.gen_msg_id(),
+                message: Box::new(message.clone()),
+            };
+            replies.push(self.send_reliably(message.dest, body)?);
+        }
+
+        // slow peers only hold hints when no peer is keeping up.
+        let (alive, slow): (Vec<NodeId>, Vec<NodeId>) = self
+            .peers()
+            .into_iter()
+            .filter(|peer| !suspects.contains(peer))
+            .partition(|peer| self.liveness(peer) == Liveness::Alive);
+        let holders = if alive.is_empty() { slow } else { alive };
+        if holders.is_empty() {
+            return Ok(replies);
+        }
+        let unhinted = self
+            .outbox
+            .as_mut()
+            .map(|outbox| outbox.unhinted(&suspects))
+            .unwrap_or_default();
+        for message in unhinted {
+            // spread over the holders by msg_id.
+            let msg_id = message.body.msg_id().unwrap_or_default();
+            let holder = holders[msg_id as usize % holders.len()];
+            let body = Workload::Hint {
+                msg_id: self.gen_msg_id(),
+                message: Box::new(message),
+            };
+            replies.push(self.send_reliably(holder, body)?);
+        }
+        Ok(replies)
+    }
+
+    // time based housekeeping: heartbeats, overdue rpcs and tick hooks.
+    pub fn tick(&mut self, now: Instant) -> Result<Replies> {
+        let _span = trace_span!("tick").entered();
+        let replies = self.housekeep(now);
+        let replies = self.throttled(replies, now);
+        self.outcome(&replies);
+        // a tick that did nothing isn't worth the room.
+        if !matches!(&replies, Ok(sent) if sent.is_empty()) {
+            self.remember(None, "tick".to_owned(), &replies);
+        }
+        replies
+    }
+
+    fn housekeep(&mut self, now: Instant) -> Result<Replies> {
+        let mut heartbeats = self
+            .detector
+            .as_mut()
+            .map(|detector| detector.tick(now))
+            .unwrap_or_default();
+        if let Some(elector) = self.elector.as_mut() {
+            // one heartbeat per peer, whoever it's due for.
+            for peer in elector.tick(now) {
+                if !heartbeats.contains(&peer) {
+                    heartbeats.push(peer);
+                }
+            }
+        }
+        let expired = self.rpcs.expired(now);
+
+        let mut replies = Replies::new();
+        #[cfg(any(test, feature = "testing"))]
+        if let Some(faults) = self.faults.as_mut() {
+            replies.extend(faults.release(now));
+        }
+        let slow = self.slow_peers();
+        if let Some(outbox) = self.outbox.as_mut() {
+            replies.extend(outbox.due(now, &self.rpcs.rtts(), &slow));
+        }
+        if self.hints.is_some() {
+            replies.extend(self.hand_off()?);
+        }
+        for peer in heartbeats {
+            let body = Workload::Heartbeat {
+                msg_id: self.gen_msg_id(),
+                lamport: 0,
+            };
+            replies.push(self.reply(peer, body));
+        }
+        for (msg_id, dest, callback) in expired {
+            let timeout = Message {
+                src: dest,
+                dest: self.node_id(),
+                body: Workload::error(msg_id, code::TIMEOUT, "rpc timed out".to_owned()),
+            };
+            replies.extend(callback(self, timeout)?);
+        }
+        if self.node_id.is_some() {
+            for hook in self.tick_hooks.clone() {
+                replies.extend(hook(self, now)?);
+            }
+        }
+        if self.persistence.as_ref().is_some_and(|p| p.due(now)) {
+            self.persist(self.clock.unix_millis())?;
+        }
+        self.guard_memory();
+        Ok(replies)
+    }
+
+    // every message is handled in its own span, replies and errors are traced within it.
+    pub fn process(&mut self, message: Message) -> Result<Replies> {
+        let _span = debug_span!(
+            "message",
+            r#type = message.body.name(),
+            src = %message.src,
+            msg_id = message.body.msg_id(),
+            in_reply_to = message.body.in_reply_to(),
+        )
+        .entered();
+        let started = Instant::now();
+        debug!(flow = %Flow(&message), "received");
+        self.observe(&message);
+        let received = self.history.is_some().then(|| {
+            let handler = match message.body.in_reply_to() {
+                Some(in_reply_to) if self.rpcs.is_pending(in_reply_to) => "callback",
+                _ => message.body.name(),
+            };
+            (message.clone(), handler.to_owned())
+        });
+        let replies = self.dispatch(message);
+        let replies = self.throttled(replies, self.now());
+        self.outcome(&replies);
+        if let Some((received, handler)) = received {
+            self.remember(Some(received), handler, &replies);
+        }
+        debug!(latency_us = started.elapsed().as_micros() as u64, "handled");
+        replies
+    }
+
+    // keeps the event in the history, if there's one.
+    fn remember(&mut self, received: Option<Message>, handler: String, replies: &Result<Replies>) {
+        let Some(history) = self.history.as_mut() else {
+            return;
+        };
+        let (sent, error) = match replies {
+            Ok(replies) => (replies.to_vec(), None),
+            Err(e) => (Vec::new(), Some(e.to_string())),
+        };
+        history.push(Processed {
+            at: self.clock.unix_millis(),
+            received,
+            handler,
+            sent,
+            error,
+        });
+    }
+
+    // counts and traces what the node sends. errors are returned to the caller as well,
+    // the event only makes them visible.
+    fn outcome(&mut self, replies: &Result<Replies>) {
+        match replies {
+            Ok(replies) => {
+                for reply in replies {
+                    self.sent(reply);
+                }
+            }
+            Err(e) => warn!(error = %e, "failed"),
+        }
+    }
+
+    // what every received message goes through before it's dispatched, by "process" or
+    // by the async runner.
+    pub(crate) fn observe(&mut self, message: &Message) {
+        self.counters.received(message);
+        if let Some(timestamp) = message.body.lamport() {
+            self.lamport.merge(timestamp);
+        }
+        if let Some(detector) = self.detector.as_mut() {
+            match message.body {
+                Workload::Heartbeat { .. } => detector.heartbeat(&message.src, self.clock.now()),
+                _ => detector.heard_from(&message.src, self.clock.now()),
+            }
+        }
+        if let (Some(elector), Workload::Heartbeat { .. }) = (self.elector.as_mut(), &message.body)
+        {
+            elector.on_heartbeat(self.clock.now(), &message.src);
+        }
+    }
+
+    pub(crate) fn sent(&mut self, reply: &Message) {
+        self.counters.sent(reply);
+        if let Some(cache) = self.reply_cache.as_mut() {
+            cache.replied(reply);
+        }
+        debug!(
+            flow = %Flow(reply),
+            dest = %reply.dest,
+            r#type = reply.body.name(),
+            msg_id = reply.body.msg_id(),
+            in_reply_to = reply.body.in_reply_to(),
+            "reply"
+        );
+    }
+
+    fn dispatch(&mut self, message: Message) -> Result<Replies> {
+        let now = self.now();
+        let callback = message
+            .body
+            .in_reply_to()
+            .and_then(|in_reply_to| self.rpcs.complete(in_reply_to, now));
+        if let Some(callback) = callback {
+            // the reply to an rpc sent reliably acknowledges it as well.
+            if let (Some(outbox), Some(in_reply_to)) =
+                (self.outbox.as_mut(), message.body.in_reply_to())
+            {
+                outbox.ack(&message.src, in_reply_to);
+            }
+            return self.isolated(message, callback);
+        }
+        if let (Some(outbox), Some(in_reply_to)) =
+            (self.outbox.as_mut(), message.body.in_reply_to())
+        {
+            if outbox.ack(&message.src, in_reply_to) {
+                return Ok(Replies::new());
+            }
+        }
+
+        let key = message.body.key();
+        if !self.is_initialized() && key != Type::Init {
+            return Err(Box::new(Error::NotInitializedYet));
+        }
+
+        let request = match (message.body.msg_id(), message.body.in_reply_to()) {
+            (Some(msg_id), None) if !message.src.is_service() && key != Type::Init => {
+                Some((message.src, msg_id))
+            }
+            _ => None,
+        };
+        let now = self.now();
+        if let (Some(cache), Some((src, msg_id))) = (self.reply_cache.as_mut(), &request) {
+            match cache.lookup(src, *msg_id, &message.body, now) {
+                Lookup::New => {}
+                Lookup::InFlight => return Ok(Replies::new()),
+                Lookup::Replay(reply) => return Ok(smallvec![reply]),
+            }
+        }
+        if let (Some(sessions), Some((src, msg_id))) = (self.sessions.as_mut(), &request) {
+            if src.is_client() {
+                if let Err((code, text)) = sessions.admit(src, *msg_id) {
+                    let body = Workload::error(*msg_id, code, text);
+                    return Ok(smallvec![self.reply(*src, body)]);
+                }
+            }
+        }
+
+        // workaround to let the handler take "self".
+        let body = message
+            .body
+            .custom_type()
+            .and_then(|name| self.bodies.get(name))
+            .cloned();
+        let replies: Result<Replies> = match (body, self.handlers.get(&key)) {
+            (Some(handler), _) => self.handle(message, &*handler),
+            (None, Some(&handler)) => self.handle(message, handler),
+            (None, None) if self.is_initialized() && key == Type::Init => {
+                Err(Box::new(Error::AlreadyInitialized))
+            }
+            (None, None) => Err(Box::new(Error::HandlerNotFound { key })),
+        };
+        if let (Some(cache), Some((src, msg_id)), Err(_)) =
+            (self.reply_cache.as_mut(), &request, &replies)
+        {
+            cache.forget(src, *msg_id);
+        }
+        replies
+    }
+
+    // injected faults come first, in builds that have them (see "testing::faults").
+    fn handle<H>(&mut self, message: Message, handler: H) -> Result<Replies>
+    where
+        H: Fn(&mut Node, Message, &mut dyn Sink) -> Result<()>,
+    {
+        #[cfg(any(test, feature = "testing"))]
+        if let Some(faults) = self.faults.as_mut() {
+            if let Some(fault) = faults.draw(&message.body.key()) {
+                return self.faulty(message, handler, fault);
+            }
+        }
+        self.isolated(message, collected(handler))
+    }
+
+    #[cfg(any(test, feature = "testing"))]
+    fn faulty<H>(&mut self, message: Message, handler: H, fault: Fault) -> Result<Replies>
+    where
+        H: Fn(&mut Node, Message, &mut dyn Sink) -> Result<()>,
+    {
+        debug!(?fault, "injected fault");
+        let delay = match fault {
+            Fault::Fail(code) => {
+                let text = "injected fault".to_owned();
+                return match (message.body.msg_id(), message.body.in_reply_to()) {
+                    (Some(msg_id), None) => {
+                        let body = Workload::error(msg_id, code, text);
+                        Ok(smallvec![self.reply(message.src, body)])
+                    }
+                    _ => Err(Box::new(Error::Rpc { code, text })),
+                };
+            }
+            Fault::Delay(delay) => Some(delay),
+            Fault::Drop => None,
+        };
+        let replies = self.isolated(message, collected(handler))?;
+        if let (Some(delay), Some(faults)) = (delay, self.faults.as_mut()) {
+            faults.hold(delay, replies);
+        }
+        Ok(Replies::new())
+    }
+
+    // a panicking handler (or callback) doesn't take the node down: the panic is logged along
+    // with the message, and a request gets a "crash" error reply instead of its answer.
+    // errors get the message attached, see "ErrorContext".
+    fn isolated<F>(&mut self, message: Message, handler: F) -> Result<Replies>
+    where
+        F: FnOnce(&mut Node, Message) -> Result<Replies>,
+    {
+        // the handler takes the message, only what an error needs is kept. messages can be
+        // large (broadcast batches, txns), and most are handled without one.
+        let src = message.src;
+        let key = message.body.key();
+        let (msg_id, in_reply_to) = (message.body.msg_id(), message.body.in_reply_to());
+        let input = || {
+            json!({
+                "src": src,
+                "body": {"type": key.name(), "msg_id": msg_id, "in_reply_to": in_reply_to},
+            })
+            .to_string()
+        };
+        let payload = match panic::catch_unwind(AssertUnwindSafe(|| handler(self, message))) {
+            Ok(Ok(replies)) => return Ok(replies),
+            Ok(Err(e)) => {
+                let input = input();
+                return Err(ErrorContext::wrap(e, Some(key), input));
+            }
+            Err(payload) => payload,
+        };
+        let reason = payload
+            .downcast_ref::<&str>()
+            .map(|reason| reason.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_owned());
+        error!(panic = %reason, input = %input(), "handler panicked");
+        match (msg_id, in_reply_to) {
+            (Some(msg_id), None) => {
+                let body =
+                    Workload::error(msg_id, code::CRASH, format!("handler panicked: {reason}"));
+                Ok(smallvec![self.reply(src, body)])
+            }
+            _ => Err(Box::new(Error::Panicked { reason })),
+        }
+    }
+
+    // will return empty node_id if node is not initialized.
+    pub fn node_id(&self) -> NodeId {
+        self.node_id.unwrap_or_default()
+    }
+
+    // will return empty node_ids if node is not initialized.
+    pub fn node_ids(&self) -> &[NodeId] {
+        self.node_ids.as_deref().unwrap_or(&[])
+    }
+
+    // "node_id" joins the cluster after init, appended to "node_ids". see "membership_changed".
+    pub fn join(&mut self, node_id: NodeId) {
+        let node_ids = self.node_ids.get_or_insert_with(Vec::new);
+        if !node_ids.contains(&node_id) {
+            node_ids.push(node_id);
+            self.membership_changed();
+        }
+    }
+
+    // "node_id" leaves the cluster, what's still to be sent to it is dropped: the outbox, the
+    // rate limit's queue, queued gossip and hints. the callbacks of rpcs waiting on it get a
+    // "node-not-found" error, what they reply is returned.
+    pub fn leave(&mut self, node_id: &NodeId) -> Result<Replies> {
+        let Some(node_ids) = self.node_ids.as_mut() else {
+            return Ok(Replies::new());
+        };
+        let before = node_ids.len();
+        node_ids.retain(|n| n != node_id);
+        if node_ids.len() == before {
+            return Ok(Replies::new());
+        }
+        if let Some(outbox) = self.outbox.as_mut() {
+            outbox.remove_to(node_id);
+        }
+        if let Some(throttle) = self.throttle.as_mut() {
+            throttle.remove_to(node_id);
+        }
+        if let Some(gossip) = self.gossip.as_ref() {
+            gossip.remove(*node_id);
+        }
+        if let Some(hints) = self.hints.as_mut() {
+            hints.remove_to(node_id);
+        }
+        let orphaned = self.rpcs.forget(*node_id);
+        self.membership_changed();
+        let mut replies = Replies::new();
+        for (msg_id, callback) in orphaned {
+            let text = "node left the cluster".to_owned();
+            let error = Message {
+                src: *node_id,
+                dest: self.node_id(),
+                body: Workload::error(msg_id, code::NODE_NOT_FOUND, text),
+            };
+            replies.extend(callback(self, error)?);
+        }
+        Ok(replies)
+    }
+
+    // the failure detector watches the new peers, and the neighbors are generated again from
+    // the new "node_ids" with the configured strategy, a tree if that's maelstrom's: the topology
+    // it sent doesn't cover a node that joined since. a hop limit follows the new diameter.
+    fn membership_changed(&mut self) {
+        let peers = self.peers();
+        if let Some(detector) = self.detector.as_mut() {
+            detector.set_peers(&peers, self.clock.now());
+        }
+        self.elect_among_members();
+        let strategy = match self.config.topology {
+            TopologyStrategy::Maelstrom => TopologyStrategy::Tree,
+            strategy => strategy,
+        };
+        let node_ids = self.node_ids().to_vec();
+        let Some(mut topology) = generate(strategy, &node_ids, self.config.fanout) else {
+            return;
+        };
+        if self.max_hops.is_some() {
+            self.max_hops = Some(diameter(&topology));
+        }
+        let node_id = self.node_id();
+        self.neighbors = topology.remove(&node_id).unwrap_or_default();
+    }
+
+    // every other node of the cluster.
+    pub fn peers(&self) -> Vec<NodeId> {
+        let node_id = self.node_id();
+        self.node_ids()
+            .iter()
+            .filter(|n| **n != node_id)
+            .cloned()
+            .collect()
+    }
+
+    // unique across the cluster, in the scheme picked with "set_uid_generator". fails when the
+    // node isn't initialized, or when the id lease it's about to extend can't be persisted.
+    pub fn gen_unique_id(&mut self) -> Result<String> {
+        Ok(self.gen_unique_ids(1)?.remove(0))
+    }
+
+    // "n" ids in one go, on one reading of the clock.
+    pub fn gen_unique_ids(&mut self, n: usize) -> Result<Vec<String>> {
+        let node_id = self.node_id();
+        let index = self
+            .node_ids()
+            .iter()
+            .position(|id| *id == node_id)
+            .ok_or(Error::NotInitializedYet)? as u64;
+        let source = UidSource {
+            node_id: &node_id,
+            index,
+            unix_millis: self.clock.unix_millis(),
+        };
+        let mut ids = Vec::with_capacity(n);
+        for _ in 0..n {
+            if self.persistence.is_some() && self.uid.must_save(&source) {
+                self.persist(source.unix_millis)?;
+            }
+            ids.push(self.uid.generate(&source));
+        }
+        Ok(ids)
+    }
+
+    // returns false if the message was already seen. with the write-ahead log, a new value is
+    // logged before it's kept, and one that can't be is an error.
+    pub fn push_broadcast_message(&mut self, message: BroadcastMessage) -> Result<bool> {
+        if self.broadcast_messages.contains(&message) {
+            return Ok(false);
+        }
+        self.log_broadcast_message(message)?;
+        Ok(self.broadcast_messages.insert(message))
+    }
+
+    // borrowed, a "read" reply is built straight from the node's state.
+    pub fn broadcast_messages(&self) -> &[BroadcastMessage] {
+        self.broadcast_messages.values()
+    }
+
+    // the values seen, as an immutable copy shared by every reader until more arrive. a copy is
+    // only made when asked for and values were added since the last one, not as they arrive, so
+    // back to back reads share one.
+    pub fn broadcast_snapshot(&self) -> Arc<Vec<BroadcastMessage>> {
+        self.publish_broadcast_snapshot()
+    }
+
+    // a handle on the snapshots, for readers that don't hold the node (e.g. another thread).
+    // what they load is as of the last "broadcast_snapshot", loading it takes no lock.
+    pub fn broadcast_snapshots(&self) -> Arc<ArcSwap<Vec<BroadcastMessage>>> {
+        self.broadcast_snapshot.clone()
+    }
+
+    // values are only ever added, a snapshot as long as the set is current.
+    fn publish_broadcast_snapshot(&self) -> Arc<Vec<BroadcastMessage>> {
+        let snapshot = self.broadcast_snapshot.load_full();
+        if snapshot.len() == self.broadcast_messages.len() {
+            return snapshot;
+        }
+        let snapshot = Arc::new(self.broadcast_messages.values().to_vec());
+        self.broadcast_snapshot.store(snapshot.clone());
+        snapshot
+    }
+
+    pub fn neighbors(&self) -> &Vec<NodeId> {
+        &self.neighbors
+    }
+
+    pub fn set_neighbors(&mut self, neighbors: Vec<NodeId>) {
+        self.neighbors = neighbors;
+    }
+
+    // gossip to "neighbor" waits for the next batch when "Config::gossip_interval" is set,
+    // false if it isn't and the gossip is to be sent right away.
+    pub fn queue_gossip(&mut self, neighbor: NodeId, message: BroadcastMessage, hops: u32) -> bool {
+        let Some(gossip) = self.gossip.as_ref() else {
+            return false;
+        };
+        gossip.push(neighbor, message, hops);
+        true
+    }
+
+    // the gossip queued for every neighbor, with the fewest hops any of it took, once per
+    // "Config::gossip_interval".
+    pub fn due_gossip(&mut self, now: Instant) -> Vec<(NodeId, Vec<BroadcastMessage>, u32)> {
+        self.gossip
+            .as_ref()
+            .map_or(Vec::new(), |gossip| gossip.due(now))
+    }
+
+    // how far a broadcast value is gossiped from the node a client gave it to, typically the
+    // diameter of the topology ("topology::diameter"). none by default.
+    pub fn set_max_hops(&mut self, max_hops: Option<u32>) {
+        self.max_hops = max_hops;
+    }
+
+    pub fn max_hops(&self) -> Option<u32> {
+        self.max_hops
+    }
+
+    // "push_broadcast_message" for a value that took "hops" hops to get here, true if it's to
+    // be forwarded to the neighbors: it's new, and without a hop limit that's all. with one,
+    // the copies sent on must stay within it, and a value that came in fewer hops than before
+    // is forwarded again, the copies sent then may have stopped short of some nodes.
+    pub fn push_broadcast_message_hops(
+        &mut self,
+        message: BroadcastMessage,
+        hops: u32,
+    ) -> Result<bool> {
+        let new = self.push_broadcast_message(message)?;
+        let Some(max_hops) = self.max_hops else {
+            return Ok(new);
+        };
+        let fewer = self
+            .broadcast_hops
+            .get(&message)
+            .is_none_or(|fewest| hops < *fewest);
+        if fewer {
+            self.broadcast_hops.insert(message, hops);
+        }
+        Ok(fewer && hops < max_hops)
+    }
+
+    pub fn lamport(&self) -> &LamportClock {
+        &self.lamport
+    }
+
+    pub fn logs(&self) -> &Logs {
+        &self.logs
+    }
+
+    pub fn logs_mut(&mut self) -> &mut Logs {
+        &mut self.logs
+    }
+
+    // the logs are written through to "storage" and start out as what it already holds, see
+    // "Logs::open". replaces whatever was logged before.
+    pub fn set_log_storage(&mut self, storage: impl Storage + 'static) -> Result<()> {
+        self.logs = Logs::open(SpawnedStorage::spawn(storage))?;
+        Ok(())
+    }
+
+    pub fn store_mut(&mut self) -> &mut Store {
+        &mut self.store
+    }
+
+    pub fn kv(&self) -> &Kv {
+        &self.kv
+    }
+
+    pub fn kv_mut(&mut self) -> &mut Kv {
+        &mut self.kv
+    }
+
+    pub fn sequencer(&self) -> &Sequencer {
+        &self.sequencer
+    }
+
+    pub fn sequencer_mut(&mut self) -> &mut Sequencer {
+        &mut self.sequencer
+    }
+
+    fn register_rpc(
+        &mut self,
+        dest: NodeId,
+        body: Workload,
+        deadline: Option<Instant>,
+        callback: Callback,
+    ) -> Result<Message> {
+        let msg_id = body.msg_id().ok_or(Error::MissingMessageId)?;
+        let now = self.now();
+        self.rpcs.register(msg_id, dest, now, deadline, callback);
+        Ok(self.reply(dest, body))
+    }
+
+    pub(crate) fn is_initialized(&self) -> bool {
+        self.node_id.is_some() && self.node_ids.is_some()
+    }
+
+    // once initialized, remove "init" handler,
+    // so that node can log error on receiving "init" message again!
+    fn init(&mut self, node_id: NodeId, node_ids: Vec<NodeId>) {
+        self.node_id = Some(node_id);
+        self.node_ids = Some(node_ids);
+        self.handlers.remove(&Type::Init);
+        let peers = self.peers();
+        if let Some(detector) = self.detector.as_mut() {
+            detector.set_peers(&peers, self.clock.now());
+        }
+        self.elect_among_members();
+    }
+
+    fn elect_among_members(&mut self) {
+        let (node_id, now) = (self.node_id(), self.clock.now());
+        if let (Some(elector), Some(node_ids)) = (self.elector.as_mut(), self.node_ids.as_ref()) {
+            elector.set_members(node_id, node_ids, now);
+        }
+    }
+
+    // liveness is recorded for every message in "process", nothing left to do.
+    fn handler_heartbeat(_: &mut Node, _: Message, _: &mut dyn Sink) -> Result<()> {
+        Ok(())
+    }
+
+    // a binary keeping state outside of the node registers its own handler for "dump_state",
+    // typically extending "Node::state".
+    fn handler_dump_state(node: &mut Node, message: Message, out: &mut dyn Sink) -> Result<()> {
+        expect_body!(message, DumpState { msg_id });
+        let state = node.state();
+        out.send(node.reply_to((message.src, msg_id), |in_reply_to, msg_id| {
+            Workload::dump_state_ok(in_reply_to, msg_id, state)
+        }));
+        Ok(())
+    }
+
+    // the cluster changed after init. every node of it is told, the one joining gets "init" with
+    // the whole cluster instead. a node told it's leaving is left with no peers.
+    fn handler_join(node: &mut Node, message: Message, out: &mut dyn Sink) -> Result<()> {
+        expect_body!(message, Join { msg_id, node_id });
+        node.join(node_id);
+        out.send(node.reply_to((message.src, msg_id), Workload::join_ok));
+        Ok(())
+    }
+
+    fn handler_leave(node: &mut Node, message: Message, out: &mut dyn Sink) -> Result<()> {
+        expect_body!(message, Leave { msg_id, node_id });
+        if node_id == node.node_id() {
+            let node_ids = vec![node_id];
+            node.node_ids = Some(node_ids);
+            node.membership_changed();
+        } else {
+            for reply in node.leave(&node_id)? {
+                out.send(reply);
+            }
+        }
+        out.send(node.reply_to((message.src, msg_id), Workload::leave_ok));
+        Ok(())
+    }
+
+    // a hint for another node is held for it, one for this node is handled as if it came
+    // straight from its sender, who gets the replies. either way it's acknowledged, a node
+    // without hinted handoff refuses to hold hints.
+    fn handler_hint(node: &mut Node, message: Message, out: &mut dyn Sink) -> Result<()> {
+        expect_body!(
+            message,
+            Hint {
+                msg_id,
+                message: hinted
+            }
+        );
+        if hinted.dest == node.node_id() {
+            match node.dispatch(*hinted) {
+                Ok(replies) => out.extend(replies),
+                Err(error) => warn!(%error, "hint not handled"),
+            }
+        } else if let Some(hints) = node.hints.as_mut() {
+            hints.hold(*hinted);
+        } else {
+            let text = "hinted handoff not enabled".to_owned();
+            let body = Workload::error(msg_id, code::NOT_SUPPORTED, text);
+            out.send(node.reply(message.src, body));
+            return Ok(());
+        }
+        out.send(node.reply_to((message.src, msg_id), Workload::hint_ok));
+        Ok(())
+    }
+
+    fn handler_init(node: &mut Node, message: Message, out: &mut dyn Sink) -> Result<()> {
+        expect_body!(
+            message,
+            Init {
+                msg_id,
+                node_id,
+                node_ids
+            }
+        );
+        node.restore(&node_id)?;
+        node.init(node_id, node_ids);
+        out.send(node.reply(message.src, Workload::init_ok(msg_id)));
+        Ok(())
+    }
+}
+
+// where a handler's messages go, see "Handler".
+pub trait Sink {
+    fn send(&mut self, message: Message);
+}
+
+impl Sink for Replies {
+    fn send(&mut self, message: Message) {
+        self.push(message);
+    }
+}
+
+impl Extend<Message> for dyn Sink + '_ {
+    fn extend<I: IntoIterator<Item = Message>>(&mut self, messages: I) {
+        for message in messages {
+            self.send(message);
+        }
+    }
+}
+
+// a handler with its output collected, the way "isolated" runs callbacks.
+fn collected<H>(handler: H) -> impl FnOnce(&mut Node, Message) -> Result<Replies>
+where
+    H: Fn(&mut Node, Message, &mut dyn Sink) -> Result<()>,
+{
+    move |node, message| {
+        let mut replies = Replies::new();
+        handler(node, message, &mut replies)?;
+        Ok(replies)
+    }
+}
+
+impl Default for Node {
+    fn default() -> Self {
+        let handlers = HashMap::new();
+        Node::new(handlers)
+    }
+}
+
+// the bodies of a workload's own messages, an enum tagged by "type" the way "Workload" is.
+// "TYPES" are its tags, none of them one "Workload" has, see "Node::register_body".
+pub trait Body: Serialize + DeserializeOwned + 'static {
+    const TYPES: &'static [&'static str];
+}
+
+// custom bodies arrive as "Workload::Custom": "Message::decode" reads one into the workload's
+// own body, "Workload::custom" writes one.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct Message<B = Workload> {
+    pub src: NodeId,
+    pub dest: NodeId,
+    pub body: B,
+}
+
+impl Message {
+    pub fn decode<B: DeserializeOwned>(self) -> Result<Message<B>> {
+        let Workload::Custom(body) = self.body else {
+            return Err(Box::new(Error::ExpectedMessage {
+                found: self.body.key(),
+                expected: Type::Custom,
+            }));
+        };
+        Ok(Message {
+            src: self.src,
+            dest: self.dest,
+            body: serde_json::from_value(body)?,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Workload {
+    Init {
+        msg_id: MessageId,
+        node_id: NodeId,
+        node_ids: Vec<NodeId>,
+    },
+    InitOk {
+        in_reply_to: MessageId,
+    },
+    Error {
+        in_reply_to: MessageId,
+        code: CodeId,
+        #[serde(default)]
+        text: String,
+    },
+    Echo {
+        msg_id: MessageId,
+        echo: String,
+    },
+    EchoOk {
+        in_reply_to: MessageId,
+        msg_id: MessageId,
+        echo: String,
+    },
+    // "n" asks for a batch of ids, answered with "ids" instead of "id".
+    Generate {
+        msg_id: MessageId,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        n: Option<usize>,
+    },
+    GenerateOk {
+        in_reply_to: MessageId,
+        msg_id: MessageId,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        ids: Option<Vec<String>>,
+    },
+    Broadcast {
+        msg_id: MessageId,
+        message: BroadcastMessage,
+        // hops from the node a client gave the value to, when gossiped, see "Node::max_hops".
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        hops: Option<u32>,
+    },
+    BroadcastOk {
+        in_reply_to: MessageId,
+        msg_id: MessageId,
+    },
+    // "read" is shared by the broadcast workload and the kv services, only the latter sends "key".
+    Read {
+        msg_id: MessageId,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        key: Option<KvKey>,
+    },
+    // kv services reply without "msg_id", hence the defaults.
+    ReadOk {
+        in_reply_to: MessageId,
+        #[serde(default)]
+        msg_id: MessageId,
+        // shared with the node's snapshot, a reply doesn't copy the values.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        messages: Option<Arc<Vec<BroadcastMessage>>>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        value: Option<KvValue>,
+    },
+    Write {
+        msg_id: MessageId,
+        key: KvKey,
+        value: KvValue,
+    },
+    WriteOk {
+        in_reply_to: MessageId,
+        #[serde(default)]
+        msg_id: MessageId,
+    },
+    Cas {
+        msg_id: MessageId,
+        key: KvKey,
+        from: KvValue,
+        to: KvValue,
+        #[serde(default)]
+        create_if_not_exists: bool,
+    },
+    CasOk {
+        in_reply_to: MessageId,
+        #[serde(default)]
+        msg_id: MessageId,
+    },
+    Topology {
+        msg_id: MessageId,
+        topology: HashMap<NodeId, Vec<NodeId>>,
+    },
+    TopologyOk {
+        in_reply_to: MessageId,
+        msg_id: MessageId,
+    },
+    Send {
+        msg_id: MessageId,
+        key: LogKey,
+        msg: LogMessage,
+    },
+    SendOk {
+        in_reply_to: MessageId,
+        msg_id: MessageId,
+        offset: Offset,
+    },
+    Poll {
+        msg_id: MessageId,
+        offsets: HashMap<LogKey, Offset>,
+    },
+    PollOk {
+        in_reply_to: MessageId,
+        msg_id: MessageId,
+        msgs: HashMap<LogKey, Vec<(Offset, LogMessage)>>,
+    },
+    CommitOffsets {
+        msg_id: MessageId,
+        offsets: HashMap<LogKey, Offset>,
+    },
+    CommitOffsetsOk {
+        in_reply_to: MessageId,
+        msg_id: MessageId,
+    },
+    ListCommittedOffsets {
+        msg_id: MessageId,
+        keys: Vec<LogKey>,
+    },
+    ListCommittedOffsetsOk {
+        in_reply_to: MessageId,
+        msg_id: MessageId,
+        offsets: HashMap<LogKey, Offset>,
+    },
+    Txn {
+        msg_id: MessageId,
+        txn: Vec<Op>,
+    },
+    TxnOk {
+        in_reply_to: MessageId,
+        msg_id: MessageId,
+        txn: Vec<Op>,
+    },
+    Raft {
+        msg_id: MessageId,
+        rpc: RaftRpc,
+    },
+    // asks the sequencer to order a broadcast message, answered with the "seq" it got.
+    Sequence {
+        msg_id: MessageId,
+        message: BroadcastMessage,
+    },
+    SequenceOk {
+        in_reply_to: MessageId,
+        msg_id: MessageId,
+        seq: Seq,
+    },
+    // a sequenced message, delivered in "seq" order by every node, acknowledged once received.
+    Deliver {
+        msg_id: MessageId,
+        seq: Seq,
+        message: BroadcastMessage,
+    },
+    DeliverOk {
+        in_reply_to: MessageId,
+        msg_id: MessageId,
+    },
+    // debugging aid, answered with a snapshot of the node's state.
+    DumpState {
+        msg_id: MessageId,
+    },
+    DumpStateOk {
+        in_reply_to: MessageId,
+        msg_id: MessageId,
+        state: Value,
+    },
+    // liveness signal between nodes, never replied to.
+    Heartbeat {
+        msg_id: MessageId,
+        #[serde(default)]
+        lamport: Timestamp,
+    },
+    // a message for a peer its sender can't reach, held by the receiver until it can deliver
+    // it (in another hint), see "Node::enable_hinted_handoff".
+    Hint {
+        msg_id: MessageId,
+        message: Box<Message>,
+    },
+    HintOk {
+        in_reply_to: MessageId,
+        msg_id: MessageId,
+    },
+    // a node joining or leaving the cluster after init, see "Node::join".
+    Join {
+        msg_id: MessageId,
+        node_id: NodeId,
+    },
+    JoinOk {
+        in_reply_to: MessageId,
+        msg_id: MessageId,
+    },
+    Leave {
+        msg_id: MessageId,
+        node_id: NodeId,
+    },
+    LeaveOk {
+        in_reply_to: MessageId,
+        msg_id: MessageId,
+    },
+    // a body of a type the variants above don't know, "type" tag included.
+    #[serde(untagged, deserialize_with = "custom_body")]
+    Custom(Value),
+}
+
+// the body of a known type that failed to parse is malformed, not custom.
+fn custom_body<'de, D: Deserializer<'de>>(deserializer: D) -> result::Result<Value, D::Error> {
+    let body = Value::deserialize(deserializer)?;
+    match body.get("type") {
+        Some(Value::String(name)) if serde_json::from_value::<Type>(json!(name)).is_err() => {
+            Ok(body)
+        }
+        Some(Value::String(name)) => Err(D::Error::custom(format!("malformed {name} body"))),
+        _ => Err(D::Error::custom("body without a type")),
+    }
+}
+
+impl Workload {
+    pub fn msg_id(&self) -> Option<MessageId> {
+        match self {
+            Workload::Init { msg_id, .. }
+            | Workload::Echo { msg_id, .. }
+            | Workload::EchoOk { msg_id, .. }
+            | Workload::Generate { msg_id, .. }
+            | Workload::GenerateOk { msg_id, .. }
+            | Workload::Broadcast { msg_id, .. }
+            | Workload::BroadcastOk { msg_id, .. }
+            | Workload::Read { msg_id, .. }
+            | Workload::ReadOk { msg_id, .. }
+            | Workload::Write { msg_id, .. }
+            | Workload::WriteOk { msg_id, .. }
+            | Workload::Cas { msg_id, .. }
+            | Workload::CasOk { msg_id, .. }
+            | Workload::Topology { msg_id, .. }
+            | Workload::TopologyOk { msg_id, .. }
+            | Workload::Send { msg_id, .. }
+            | Workload::SendOk { msg_id, .. }
+            | Workload::Poll { msg_id, .. }
+            | Workload::PollOk { msg_id, .. }
+            | Workload::CommitOffsets { msg_id, .. }
+            | Workload::CommitOffsetsOk { msg_id, .. }
+            | Workload::ListCommittedOffsets { msg_id, .. }
+            | Workload::ListCommittedOffsetsOk { msg_id, .. }
+            | Workload::Txn { msg_id, .. }
+            | Workload::TxnOk { msg_id, .. }
+            | Workload::Raft { msg_id, .. }
+            | Workload::Heartbeat { msg_id, .. }
+            | Workload::DumpState { msg_id }
+            | Workload::DumpStateOk { msg_id, .. }
+            | Workload::Sequence { msg_id, .. }
+            | Workload::SequenceOk { msg_id, .. }
+            | Workload::Deliver { msg_id, .. }
+            | Workload::DeliverOk { msg_id, .. }
+            | Workload::Hint { msg_id, .. }
+            | Workload::HintOk { msg_id, .. }
+            | Workload::Join { msg_id, .. }
+            | Workload::JoinOk { msg_id, .. }
+            | Workload::Leave { msg_id, .. }
+            | Workload::LeaveOk { msg_id, .. } => Some(*msg_id),
+            Workload::Custom(body) => serde_json::from_value(body["msg_id"].clone()).ok(),
+            Workload::InitOk { .. } | Workload::Error { .. } => None,
+        }
+    }
+
+    pub fn msg_id_mut(&mut self) -> Option<&mut MessageId> {
+        match self {
+            Workload::Init { msg_id, .. }
+            | Workload::Echo { msg_id, .. }
+            | Workload::EchoOk { msg_id, .. }
+            | Workload::Generate { msg_id, .. }
+            | Workload::GenerateOk { msg_id, .. }
+            | Workload::Broadcast { msg_id, .. }
+            | Workload::BroadcastOk { msg_id, .. }
+            | Workload::Read { msg_id, .. }
+            | Workload::ReadOk { msg_id, .. }
+            | Workload::Write { msg_id, .. }
+            | Workload::WriteOk { msg_id, .. }
+            | Workload::Cas { msg_id, .. }
+            | Workload::CasOk { msg_id, .. }
+            | Workload::Topology { msg_id, .. }
+            | Workload::TopologyOk { msg_id, .. }
+            | Workload::Send { msg_id, .. }
+            | Workload::SendOk { msg_id, .. }
+            | Workload::Poll { msg_id, .. }
+            | Workload::PollOk { msg_id, .. }
+            | Workload::CommitOffsets { msg_id, .. }
+            | Workload::CommitOffsetsOk { msg_id, .. }
+            | Workload::ListCommittedOffsets { msg_id, .. }
+            | Workload::ListCommittedOffsetsOk { msg_id, .. }
+            | Workload::Txn { msg_id, .. }
+            | Workload::TxnOk { msg_id, .. }
+            | Workload::Raft { msg_id, .. }
+            | Workload::Heartbeat { msg_id, .. }
+            | Workload::DumpState { msg_id }
+            | Workload::DumpStateOk { msg_id, .. }
+            | Workload::Sequence { msg_id, .. }
+            | Workload::SequenceOk { msg_id, .. }
+            | Workload::Deliver { msg_id, .. }
+            | Workload::DeliverOk { msg_id, .. }
+            | Workload::Hint { msg_id, .. }
+            | Workload::HintOk { msg_id, .. }
+            | Workload::Join { msg_id, .. }
+            | Workload::JoinOk { msg_id, .. }
+            | Workload::Leave { msg_id, .. }
+            | Workload::LeaveOk { msg_id, .. } => Some(msg_id),
+            Workload::InitOk { .. } | Workload::Error { .. } | Workload::Custom(_) => None,
+        }
+    }
+
+    // like "msg_id_mut", custom bodies included. false if the body has no "msg_id".
+    pub fn set_msg_id(&mut self, msg_id: MessageId) -> bool {
+        match self {
+            Workload::Custom(body) if body.get("msg_id").is_some() => {
+                body["msg_id"] = msg_id.into();
+                true
+            }
+            body => body.msg_id_mut().map(|id| *id = msg_id).is_some(),
+        }
+    }
+
+    // custom bodies carry theirs in "lamport", if any.
+    pub fn lamport(&self) -> Option<Timestamp> {
+        match self {
+            Workload::Heartbeat { lamport, .. } => Some(*lamport),
+            Workload::Custom(body) => body.get("lamport")?.as_u64(),
+            _ => None,
+        }
+    }
+
+    // false if the body carries no lamport timestamp.
+    pub(crate) fn set_lamport(&mut self, timestamp: Timestamp) -> bool {
+        match self {
+            Workload::Heartbeat { lamport, .. } => *lamport = timestamp,
+            Workload::Custom(body) if body.get("lamport").is_some() => {
+                body["lamport"] = timestamp.into();
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn in_reply_to(&self) -> Option<MessageId> {
+        match self {
+            Workload::InitOk { in_reply_to }
+            | Workload::Error { in_reply_to, .. }
+            | Workload::EchoOk { in_reply_to, .. }
+            | Workload::GenerateOk { in_reply_to, .. }
+            | Workload::BroadcastOk { in_reply_to, .. }
+            | Workload::ReadOk { in_reply_to, .. }
+            | Workload::WriteOk { in_reply_to, .. }
+            | Workload::CasOk { in_reply_to, .. }
+            | Workload::TopologyOk { in_reply_to, .. }
+            | Workload::SendOk { in_reply_to, .. }
+            | Workload::PollOk { in_reply_to, .. }
+            | Workload::CommitOffsetsOk { in_reply_to, .. }
+            | Workload::ListCommittedOffsetsOk { in_reply_to, .. }
+            | Workload::TxnOk { in_reply_to, .. }
+            | Workload::DumpStateOk { in_reply_to, .. }
+            | Workload::HintOk { in_reply_to, .. }
+            | Workload::DeliverOk { in_reply_to, .. }
+            | Workload::SequenceOk { in_reply_to, .. }
+            | Workload::JoinOk { in_reply_to, .. }
+            | Workload::LeaveOk { in_reply_to, .. } => Some(*in_reply_to),
+            Workload::Custom(body) => serde_json::from_value(body["in_reply_to"].clone()).ok(),
+            _ => None,
+        }
+    }
+
+    pub fn echo_ok(in_reply_to: MessageId, msg_id: MessageId, echo: String) -> Workload {
+        Workload::EchoOk {
+            in_reply_to,
+            msg_id,
+            echo,
+        }
+    }
+
+    pub fn generate_ok(in_reply_to: MessageId, msg_id: MessageId, id: String) -> Workload {
+        Workload::GenerateOk {
+            in_reply_to,
+            msg_id,
+            id: Some(id),
+            ids: None,
+        }
+    }
+
+    pub fn generate_batch_ok(
+        in_reply_to: MessageId,
+        msg_id: MessageId,
+        ids: Vec<String>,
+    ) -> Workload {
+        Workload::GenerateOk {
+            in_reply_to,
+            msg_id,
+            id: None,
+            ids: Some(ids),
+        }
+    }
+
+    pub fn broadcast_ok(in_reply_to: MessageId, msg_id: MessageId) -> Workload {
+        Workload::BroadcastOk {
+            in_reply_to,
+            msg_id,
+        }
+    }
+
+    pub fn read_ok(
+        in_reply_to: MessageId,
+        msg_id: MessageId,
+        messages: Arc<Vec<BroadcastMessage>>,
+    ) -> Workload {
+        Workload::ReadOk {
+            in_reply_to,
+            msg_id,
+            messages: Some(messages),
+            value: None,
+        }
+    }
+
+    pub fn txn_ok(in_reply_to: MessageId, msg_id: MessageId, txn: Vec<Op>) -> Workload {
+        Workload::TxnOk {
+            in_reply_to,
+            msg_id,
+            txn,
+        }
+    }
+
+    pub fn kv_read_ok(in_reply_to: MessageId, msg_id: MessageId, value: KvValue) -> Workload {
+        Workload::ReadOk {
+            in_reply_to,
+            msg_id,
+            messages: None,
+            value: Some(value),
+        }
+    }
+
+    pub fn write_ok(in_reply_to: MessageId, msg_id: MessageId) -> Workload {
+        Workload::WriteOk {
+            in_reply_to,
+            msg_id,
+        }
+    }
+
+    pub fn cas_ok(in_reply_to: MessageId, msg_id: MessageId) -> Workload {
+        Workload::CasOk {
+            in_reply_to,
+            msg_id,
+        }
+    }
+
+    pub fn read(msg_id: MessageId, key: KvKey) -> Workload {
+        Workload::Read {
+            msg_id,
+            key: Some(key),
+        }
+    }
+
+    pub fn write(msg_id: MessageId, key: KvKey, value: KvValue) -> Workload {
+        Workload::Write { msg_id, key, value }
+    }
+
+    pub fn cas(
+        msg_id: MessageId,
+        key: KvKey,
+        from: KvValue,
+        to: KvValue,
+        create_if_not_exists: bool,
+    ) -> Workload {
+        Workload::Cas {
+            msg_id,
+            key,
+            from,
+            to,
+            create_if_not_exists,
+        }
+    }
+
+    pub fn error(in_reply_to: MessageId, code: CodeId, text: String) -> Workload {
+        Workload::Error {
+            in_reply_to,
+            code,
+            text,
+        }
+    }
+
+    pub fn topology_ok(in_reply_to: MessageId, msg_id: MessageId) -> Workload {
+        Workload::TopologyOk {
+            in_reply_to,
+            msg_id,
+        }
+    }
+
+    pub fn send_ok(in_reply_to: MessageId, msg_id: MessageId, offset: Offset) -> Workload {
+        Workload::SendOk {
+            in_reply_to,
+            msg_id,
+            offset,
+        }
+    }
+
+    pub fn poll_ok(
+        in_reply_to: MessageId,
+        msg_id: MessageId,
+        msgs: HashMap<LogKey, Vec<(Offset, LogMessage)>>,
+    ) -> Workload {
+        Workload::PollOk {
+            in_reply_to,
+            msg_id,
+            msgs,
+        }
+    }
+
+    pub fn commit_offsets_ok(in_reply_to: MessageId, msg_id: MessageId) -> Workload {
+        Workload::CommitOffsetsOk {
+            in_reply_to,
+            msg_id,
+        }
+    }
+
+    pub fn list_committed_offsets_ok(
+        in_reply_to: MessageId,
+        msg_id: MessageId,
+        offsets: HashMap<LogKey, Offset>,
+    ) -> Workload {
+        Workload::ListCommittedOffsetsOk {
+            in_reply_to,
+            msg_id,
+            offsets,
+        }
+    }
+
+    pub fn dump_state_ok(in_reply_to: MessageId, msg_id: MessageId, state: Value) -> Workload {
+        Workload::DumpStateOk {
+            in_reply_to,
+            msg_id,
+            state,
+        }
+    }
+
+    pub fn sequence_ok(in_reply_to: MessageId, msg_id: MessageId, seq: Seq) -> Workload {
+        Workload::SequenceOk {
+            in_reply_to,
+            msg_id,
+            seq,
+        }
+    }
+
+    pub fn deliver_ok(in_reply_to: MessageId, msg_id: MessageId) -> Workload {
+        Workload::DeliverOk {
+            in_reply_to,
+            msg_id,
+        }
+    }
+
+    pub fn hint_ok(in_reply_to: MessageId, msg_id: MessageId) -> Workload {
+        Workload::HintOk {
+            in_reply_to,
+            msg_id,
+        }
+    }
+
+    pub fn join_ok(in_reply_to: MessageId, msg_id: MessageId) -> Workload {
+        Workload::JoinOk {
+            in_reply_to,
+            msg_id,
+        }
+    }
+
+    pub fn leave_ok(in_reply_to: MessageId, msg_id: MessageId) -> Workload {
+        Workload::LeaveOk {
+            in_reply_to,
+            msg_id,
+        }
+    }
+
+    // the body of a binary's own message, see "Body". one whose "type" is a known one belongs
+    // in that variant, and is rejected.
+    pub fn custom<B: Serialize>(body: &B) -> Result<Workload> {
+        let body = serde_json::to_value(body)?;
+        match body.get("type").and_then(Value::as_str) {
+            Some(name) if Type::ALL.iter().all(|key| key.name() != name) => {
+                Ok(Workload::Custom(body))
+            }
+            Some(name) => Err(format!("custom body of known type {name}").into()),
+            None => Err("custom body without a type".into()),
+        }
+    }
+
+    // the "type" tag of a custom body, what "Node::register_body" routes it by.
+    pub fn custom_type(&self) -> Option<&str> {
+        match self {
+            Workload::Custom(body) => body.get("type")?.as_str(),
+            _ => None,
+        }
+    }
+
+    fn init_ok(in_reply_to: MessageId) -> Workload {
+        Workload::InitOk { in_reply_to }
+    }
+}
+
+// "Type" has a variant for every "Workload" variant, and "Workload::key" maps one to the other.
+// both are generated from this list: a variant missing from it fails to compile.
+macro_rules! types {
+    ($($(#[$meta:meta])* $variant:ident = $name:literal),* $(,)?) => {
+        // what handlers are registered by, a message's "type" tag.
+        #[derive(Eq, PartialEq, Hash, Debug, Clone, Deserialize)]
+        #[serde(rename_all = "snake_case")]
+        pub enum Type {
+            $($(#[$meta])* $variant,)*
+        }
+
+        impl Type {
+            // every type, in declaration order.
+            pub const ALL: &'static [Type] = &[$(Type::$variant,)*];
+
+            // the "type" tag, e.g. "broadcast_ok".
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $(Type::$variant => $name,)*
+                }
+            }
+        }
+
+        impl Workload {
+            pub fn key(&self) -> Type {
+                match self {
+                    $(Workload::$variant { .. } => Type::$variant,)*
+                }
+            }
+
+            // the "type" tag on the wire, replies included.
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $(Workload::$variant { .. } => $name,)*
+                }
+            }
+        }
+    };
+}
+
+types!(
+    Init = "init",
+    InitOk = "init_ok",
+    Error = "error",
+    Echo = "echo",
+    EchoOk = "echo_ok",
+    Generate = "generate",
+    GenerateOk = "generate_ok",
+    Broadcast = "broadcast",
+    BroadcastOk = "broadcast_ok",
+    Read = "read",
+    ReadOk = "read_ok",
+    Write = "write",
+    WriteOk = "write_ok",
+    Cas = "cas",
+    CasOk = "cas_ok",
+    Topology = "topology",
+    TopologyOk = "topology_ok",
+    Send = "send",
+    SendOk = "send_ok",
+    Poll = "poll",
+    PollOk = "poll_ok",
+    CommitOffsets = "commit_offsets",
+    CommitOffsetsOk = "commit_offsets_ok",
+    ListCommittedOffsets = "list_committed_offsets",
+    ListCommittedOffsetsOk = "list_committed_offsets_ok",
+    Txn = "txn",
+    TxnOk = "txn_ok",
+    Raft = "raft",
+    Sequence = "sequence",
+    SequenceOk = "sequence_ok",
+    Deliver = "deliver",
+    DeliverOk = "deliver_ok",
+    DumpState = "dump_state",
+    DumpStateOk = "dump_state_ok",
+    Heartbeat = "heartbeat",
+    Hint = "hint",
+    HintOk = "hint_ok",
+    Join = "join",
+    JoinOk = "join_ok",
+    Leave = "leave",
+    LeaveOk = "leave_ok",
+    // any body "Workload" has no variant for, see "Message::decode".
+    #[serde(skip)]
+    Custom = "custom",
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    fn initialized(node_id: &str, nodes: usize) -> Node {
+        let mut node = Node::default();
+        let node_ids: Vec<NodeId> = (1..=nodes).map(|i| format!("n{i}").into()).collect();
+        node.init(node_id.into(), node_ids);
+        node
+    }
+
+    #[test]
+    fn test_type_names() {
+        // the names given to "types!" are the tags serde reads.
+        for key in Type::ALL.iter().filter(|key| **key != Type::Custom) {
+            let parsed: Type = serde_json::from_value(json!(key.name())).unwrap();
+            assert_eq!(parsed, *key);
+        }
+        assert_eq!(Workload::broadcast_ok(1, 2).name(), "broadcast_ok");
+        assert_eq!(Workload::Custom(json!({"type": "add"})).name(), "custom");
+    }
+
+    #[test]
+    fn test_node_init() {
+        let mut node = Node::default();
+        let message = serde_json::from_str::<Message>(
+            r#"{"src":"c1","dest":"n1","body":{"type":"init","msg_id":1,"node_id":"n1","node_ids":["n1","n2","n3"]}}"#,
+        )
+        .unwrap();
+        let reply = node.process(message);
+        assert!(reply.is_ok());
+
+        let reply = serde_json::to_string(&reply.unwrap().first().unwrap()).unwrap();
+        assert_eq!(
+            reply,
+            r#"{"src":"n1","dest":"c1","body":{"type":"init_ok","in_reply_to":1}}"#
+        );
+    }
+
+    #[test]
+    fn test_node_not_init() {
+        let mut node = Node::default();
+        let json =
+            r#"{"src":"c1","dest":"n2","body":{"type":"echo","echo":"Hello, World!","msg_id":1}}"#;
+        let message = serde_json::from_str::<Message>(json).unwrap();
+        let reply = node.process(message); // received valid message but node is not initialized yet.
+        assert!(reply.is_err());
+        assert_eq!(
+            reply.err().unwrap().to_string(),
+            Box::new(Error::NotInitializedYet).to_string()
+        );
+    }
+
+    #[test]
+    fn test_reply_to() {
+        let mut node = initialized("n1", 1);
+        node.gen_msg_id();
+        let reply = node.reply_to(("c1".into(), 7), Workload::broadcast_ok);
+        assert_eq!(reply.src, "n1");
+        assert_eq!(reply.dest, "c1");
+        assert_eq!(reply.body, Workload::broadcast_ok(7, 2));
+    }
+
+    #[test]
+    fn test_replies_have_keys() {
+        let mut node = initialized("n1", 1);
+        // a reply nobody waits for is a message without handler, like any other.
+        let message = crate::testing::message::msg().body(Workload::write_ok(1, 2));
+        assert_eq!(message.body.key(), Type::WriteOk);
+        let error = node.process(message).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            Error::HandlerNotFound { key: Type::WriteOk }.to_string()
+        );
+    }
+
+    #[test]
+    fn test_handler_unexpected_body() {
+        let mut node = initialized("n1", 1);
+        let message = crate::testing::message::msg().echo("hi");
+        let error = Node::handler_dump_state(&mut node, message, &mut Replies::new()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            Error::ExpectedMessage {
+                found: Type::Echo,
+                expected: Type::DumpState,
+            }
+            .to_string()
+        );
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(tag = "type", rename_all = "snake_case")]
+    enum Gossip {
+        Gossip { msg_id: MessageId, seen: Vec<u64> },
+        GossipOk { in_reply_to: MessageId },
+    }
+
+    impl Body for Gossip {
+        const TYPES: &'static [&'static str] = &["gossip", "gossip_ok"];
+    }
+
+    fn handler_gossip(node: &mut Node, message: Message<Gossip>, out: &mut dyn Sink) -> Result<()> {
+        let Gossip::Gossip { msg_id, .. } = message.body else {
+            return Ok(());
+        };
+        let reply = Gossip::GossipOk {
+            in_reply_to: msg_id,
+        };
+        out.send(node.reply(message.src, Workload::custom(&reply)?));
+        Ok(())
+    }
+
+    #[test]
+    fn test_custom_bodies() {
+        let mut node = Node::new(HashMap::from([(Type::Custom, handler_other as Handler)]));
+        node.register_body(handler_gossip);
+        node.init("n1".into(), vec!["n1".into(), "n2".into()]);
+        let json = r#"{"src":"n2","dest":"n1","body":{"type":"gossip","msg_id":3,"seen":[1,2]}}"#;
+        let message = serde_json::from_str::<Message>(json).unwrap();
+        assert_eq!(message.body.key(), Type::Custom);
+        assert_eq!(message.body.msg_id(), Some(3));
+
+        let replies = node.process(message).unwrap();
+        assert_eq!(replies[0].body.in_reply_to(), Some(3));
+        let reply = replies[0].clone().decode::<Gossip>().unwrap();
+        assert_eq!(reply.body, Gossip::GossipOk { in_reply_to: 3 });
+        assert_eq!(
+            serde_json::to_string(&replies[0]).unwrap(),
+            r#"{"src":"n1","dest":"n2","body":{"in_reply_to":3,"type":"gossip_ok"}}"#
+        );
+
+        // a type nothing registered goes to the "Type::Custom" handler.
+        let json = r#"{"src":"n2","dest":"n1","body":{"type":"rumor","msg_id":4}}"#;
+        let replies = node.process(serde_json::from_str(json).unwrap()).unwrap();
+        assert_eq!(replies[0].body.custom_type(), Some("other_ok"));
+
+        // a known type stays malformed, it isn't taken for a custom body.
+        let json = r#"{"src":"c1","dest":"n1","body":{"type":"echo","msg_id":"3"}}"#;
+        assert!(serde_json::from_str::<Message>(json).is_err());
+        let body = Workload::custom(&json!({"type": "echo", "msg_id": 1, "echo": "hi"}));
+        assert!(body.is_err());
+        let echo = crate::testing::message::msg().echo("hi");
+        assert!(echo.decode::<Gossip>().is_err());
+    }
+
+    fn handler_other(node: &mut Node, message: Message, out: &mut dyn Sink) -> Result<()> {
+        let body = json!({"type": "other_ok", "in_reply_to": message.body.msg_id()});
+        out.send(node.reply(message.src, Workload::custom(&body)?));
+        Ok(())
+    }
+
+    #[test]
+    fn test_node_fail_reini() {
+        let mut node = Node::default();
+        let json = r#"{"src":"c1","dest":"n1","body":{"type":"init","msg_id":1,"node_id":"n1","node_ids":["n1","n2","n3"]}}"#;
+        let message = serde_json::from_str::<Message>(json).unwrap();
+
+        let _ = node.process(message.clone()); // initialized.
+        let reply = node.process(message); // receiving "init" again!
+        assert!(reply.is_err());
+        assert_eq!(
+            reply.err().unwrap().to_string(),
+            Box::new(Error::AlreadyInitialized).to_string()
+        );
+    }
+
+    #[test]
+    fn test_node_rpc_callback() {
+        let mut node = Node::default();
+        let json = r#"{"src":"c1","dest":"n1","body":{"type":"init","msg_id":1,"node_id":"n1","node_ids":["n1"]}}"#;
+        let _ = node.process(serde_json::from_str::<Message>(json).unwrap());
+
+        let msg_id = node.gen_msg_id();
+        let request = node
+            .rpc(
+                LIN_KV.into(),
+                Workload::read(msg_id, "k".into()),
+                |node, reply| {
+                    let body = Workload::error(0, code::CRASH, format!("{:?}", reply.body));
+                    Ok(smallvec![node.reply("c1".into(), body)])
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            serde_json::to_string(&request).unwrap(),
+            r#"{"src":"n1","dest":"lin-kv","body":{"type":"read","msg_id":1,"key":"k"}}"#
+        );
+
+        let fallback = Duration::from_secs(1);
+        assert_eq!(node.rpc_timeout(LIN_KV, fallback), fallback);
+
+        // kv services don't send "msg_id" in their replies.
+        let json =
+            r#"{"src":"lin-kv","dest":"n1","body":{"type":"read_ok","in_reply_to":1,"value":7}}"#;
+        let replies = node
+            .process(serde_json::from_str::<Message>(json).unwrap())
+            .unwrap();
+        assert_eq!(replies.len(), 1);
+        assert_eq!(replies[0].dest, "c1");
+        // the reply gave a first round trip time.
+        let rtt = node.rtt(LIN_KV).unwrap();
+        assert_eq!(rtt.samples(), 1);
+        assert_eq!(node.rpc_timeout(LIN_KV, fallback), rtt.timeout());
+
+        // the callback is consumed, a duplicate reply is not routed again.
+        let replies = node.process(serde_json::from_str::<Message>(json).unwrap());
+        assert!(replies.is_err());
+    }
+
+    #[test]
+    fn test_node_rpc_timeout() {
+        let mut node = Node::default();
+        let json = r#"{"src":"c1","dest":"n1","body":{"type":"init","msg_id":1,"node_id":"n1","node_ids":["n1","n2"]}}"#;
+        let _ = node.process(serde_json::from_str::<Message>(json).unwrap());
+
+        let body = Workload::Heartbeat {
+            msg_id: node.gen_msg_id(),
+            lamport: 0,
+        };
+        let timeout = Duration::from_millis(100);
+        let _ = node
+            .rpc_with_timeout("n2".into(), body, timeout, |_, reply| {
+                assert!(matches!(
+                    reply.body,
+                    Workload::Error {
+                        code: code::TIMEOUT,
+                        ..
+                    }
+                ));
+                Ok(smallvec![reply])
+            })
+            .unwrap();
+
+        assert!(node.tick(Instant::now()).unwrap().is_empty());
+        let replies = node.tick(Instant::now() + timeout).unwrap();
+        assert_eq!(replies.len(), 1);
+        assert_eq!(replies[0].src, "n2");
+        assert!(node.tick(Instant::now() + timeout).unwrap().is_empty());
+        // timeouts aren't round trips.
+        assert!(node.rtt("n2").is_none());
+    }
+
+    #[test]
+    fn test_node_uid_generator() {
+        let mut node = initialized("n2", 2);
+        node.set_uid_generator(crate::uid::Counter::default());
+        assert_eq!(node.gen_unique_id().unwrap(), "n2-1");
+        assert_eq!(node.gen_unique_id().unwrap(), "n2-2");
+        // not before "init".
+        assert!(Node::new(HashMap::new()).gen_unique_id().is_err());
+    }
+
+    #[test]
+    fn test_node_persistence() {
+        let dir = std::env::temp_dir().join(format!("node-persistence-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let clock = ManualClock::new(1_700_000_000_000);
+        let start = |uid: Box<dyn UidGenerator>, clock: &ManualClock| {
+            let mut node = Node::default();
+            node.set_clock(clock.clone());
+            node.uid = uid;
+            node.enable_persistence(&dir, Duration::from_secs(1));
+            crate::testing::message::init(&mut node, "n1", &["n1", "n2"]);
+            node
+        };
+
+        let mut node = start(Box::<crate::uid::Counter>::default(), &clock);
+        assert_eq!(node.gen_unique_id().unwrap(), "n1-1");
+        node.push_broadcast_message(7).unwrap();
+        clock.advance(Duration::from_secs(1));
+        node.tick(node.now()).unwrap();
+        node.gen_unique_id().unwrap();
+
+        // a crash loses nothing the lease doesn't cover.
+        let mut node = start(Box::<crate::uid::Counter>::default(), &clock);
+        assert_eq!(node.broadcast_messages(), &[7]);
+        assert_eq!(node.gen_unique_id().unwrap(), "n1-10002");
+
+        // nor does the clock stepping back over a restart.
+        let mut node = start(Box::<Snowflake>::default(), &clock);
+        let before = node.gen_unique_id().unwrap();
+        node.shutdown();
+        let mut node = start(
+            Box::<Snowflake>::default(),
+            &ManualClock::new(1_600_000_000_000),
+        );
+        assert!(node.gen_unique_id().unwrap() > before);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_node_shared_ids_and_counters() {
+        let mut node = Node::default();
+        crate::testing::message::init(&mut node, "n1", &["n1", "n2"]);
+        let (ids, counters) = (node.msg_ids(), node.shared_counters());
+        let other = std::thread::spawn(move || (0..100).map(|_| ids.next()).collect::<Vec<_>>());
+        let mut taken: Vec<_> = (0..100).map(|_| node.gen_msg_id()).collect();
+        taken.extend(other.join().unwrap());
+        taken.sort();
+        taken.dedup();
+        assert_eq!(taken.len(), 200);
+
+        let json = r#"{"src":"n2","dest":"n1","body":{"type":"heartbeat","msg_id":1}}"#;
+        node.process(serde_json::from_str::<Message>(json).unwrap())
+            .unwrap();
+        let reader = std::thread::spawn(move || counters.received_count("heartbeat"));
+        assert_eq!(reader.join().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_node_broadcast_snapshot() {
+        let mut node = Node::default();
+        crate::testing::message::init(&mut node, "n1", &["n1", "n2"]);
+        let snapshots = node.broadcast_snapshots();
+        node.push_broadcast_message(7).unwrap();
+        node.push_broadcast_message(8).unwrap();
+        // published when asked for, not as they arrive or on a tick.
+        node.tick(Instant::now()).unwrap();
+        assert!(snapshots.load().is_empty());
+        node.broadcast_snapshot();
+        let reader = std::thread::spawn(move || snapshots.load().to_vec());
+        assert_eq!(reader.join().unwrap(), vec![7, 8]);
+
+        // shared until a value is added, then a new copy.
+        let snapshot = node.broadcast_snapshot();
+        assert!(Arc::ptr_eq(&snapshot, &node.broadcast_snapshot()));
+        // and by the replies built from it.
+        let Workload::ReadOk {
+            messages: Some(messages),
+            ..
+        } = Workload::read_ok(1, 2, node.broadcast_snapshot())
+        else {
+            unreachable!()
+        };
+        assert!(Arc::ptr_eq(&snapshot, &messages));
+        node.push_broadcast_message(9).unwrap();
+        assert_eq!(*node.broadcast_snapshot(), vec![7, 8, 9]);
+        assert_eq!(*snapshot, vec![7, 8]);
+    }
+
+    #[test]
+    fn test_node_wal() {
+        use crate::storage::{MemoryStorage, Storage};
+        use std::sync::Mutex;
+
+        let disk = Arc::new(Mutex::new(MemoryStorage::default()));
+        let start = |wal: bool| {
+            let mut node = Node::default();
+            node.enable_persistence_with(disk.clone(), Duration::from_secs(3600));
+            if wal {
+                node.enable_wal();
+            }
+            crate::testing::message::init(&mut node, "n1", &["n1"]);
+            node
+        };
+
+        // nothing was saved yet when the node crashes, the log has it all.
+        let mut node = start(true);
+        node.push_broadcast_message(7).unwrap();
+        node.push_broadcast_message_hops(8, 1).unwrap();
+        node.push_broadcast_message(7).unwrap();
+        node.wal_append("counter", &json!(3)).unwrap();
+        drop(node);
+        let mut node = start(true);
+        assert_eq!(node.broadcast_messages(), &[7, 8]);
+        assert_eq!(node.wal_records("counter").unwrap(), vec![json!(3)]);
+
+        // without the log, only the last save counts.
+        drop(node);
+        let mut node = start(false);
+        assert!(node.broadcast_messages().is_empty());
+        node.push_broadcast_message(9).unwrap();
+        assert_eq!(disk.scan("wal/broadcast").unwrap().len(), 2);
+
+        // a save covers what was logged, the log starts over.
+        drop(node);
+        let mut node = start(true);
+        node.push_broadcast_message(10).unwrap();
+        node.shutdown();
+        assert!(disk.scan("wal/broadcast").unwrap().is_empty());
+        assert_eq!(start(true).broadcast_messages(), &[7, 8, 10]);
+    }
+
+    #[test]
+    fn test_node_reply_cache() {
+        use crate::testing::message::{init, msg};
+
+        fn handler_broadcast(node: &mut Node, msg: Message, out: &mut dyn Sink) -> Result<()> {
+            expect_body!(
+                msg,
+                Broadcast {
+                    msg_id,
+                    message,
+                    ..
+                }
+            );
+            node.push_broadcast_message(message)?;
+            let body = Workload::Broadcast {
+                msg_id: node.gen_msg_id(),
+                message,
+                hops: None,
+            };
+            out.send(node.reply("n2".into(), body));
+            out.send(node.reply_to((msg.src, msg_id), Workload::broadcast_ok));
+            Ok(())
+        }
+        fn handler_read(node: &mut Node, msg: Message, out: &mut dyn Sink) -> Result<()> {
+            expect_body!(msg, Read { msg_id, .. });
+            let body = Workload::read(node.gen_msg_id(), json!("k"));
+            let src = msg.src;
+            out.send(node.rpc(LIN_KV.into(), body, move |node, _| {
+                Ok(smallvec![node
+                    .reply_to((src, msg_id), |in_reply_to, msg_id| {
+                        Workload::kv_read_ok(in_reply_to, msg_id, json!(1))
+                    })])
+            })?);
+            Ok(())
+        }
+        let mut handlers: HashMap<Type, Handler> = HashMap::new();
+        handlers.insert(Type::Broadcast, handler_broadcast);
+        handlers.insert(Type::Read, handler_read);
+        let mut node = Node::new(handlers);
+        node.enable_reply_cache(16);
+        init(&mut node, "n1", &["n1", "n2"]);
+
+        // a retried broadcast is answered, not gossiped, again.
+        let replies = node.process(msg().id(2).broadcast(5)).unwrap();
+        assert_eq!(replies.len(), 2);
+        let retried = node.process(msg().id(2).broadcast(5)).unwrap();
+        assert_eq!(retried[..], replies[1..]);
+        // the same msg_id with another body is another request.
+        assert_eq!(node.process(msg().id(2).broadcast(6)).unwrap().len(), 2);
+        assert_eq!(
+            node.process(msg().from("c2").id(2).broadcast(5))
+                .unwrap()
+                .len(),
+            2
+        );
+
+        // a retry is dropped while the reply is pending, and answered once it was sent.
+        let request = node.process(msg().id(3).read()).unwrap().remove(0);
+        assert!(node.process(msg().id(3).read()).unwrap().is_empty());
+        let reply = Message {
+            src: LIN_KV.into(),
+            dest: "n1".into(),
+            body: Workload::kv_read_ok(request.body.msg_id().unwrap(), 0, json!(1)),
+        };
+        let replies = node.process(reply).unwrap();
+        assert_eq!(node.process(msg().id(3).read()).unwrap(), replies);
+    }
+
+    #[test]
+    fn test_node_rpc_with_retry() {
+        use crate::retry::{Fixed, RetryPolicy};
+
+        let clock = ManualClock::new(1_700_000_000_000);
+        let mut node = initialized("n1", 2);
+        node.set_clock(clock.clone());
+        node.set_retry_policy(Fixed(Duration::from_millis(100)).max_attempts(2));
+        let ms = Duration::from_millis;
+
+        // sent again under a new msg_id once the timeout runs out, the reply to it is the one.
+        let body = Workload::read(node.gen_msg_id(), json!("k"));
+        let request = node
+            .rpc_with_retry("n2".into(), body, |_, reply| Ok(smallvec![reply]))
+            .unwrap();
+        clock.advance(ms(100));
+        let retried = node.tick(node.now()).unwrap().remove(0);
+        assert_eq!(retried.body.msg_id(), Some(2));
+        assert_eq!(retried.body.key(), request.body.key());
+        let reply = Message {
+            src: "n2".into(),
+            dest: "n1".into(),
+            body: Workload::kv_read_ok(2, 1, json!(1)),
+        };
+        assert_eq!(node.process(reply.clone()).unwrap()[..], [reply]);
+
+        // the callback gets the timeout once the policy gives up.
+        let body = Workload::read(node.gen_msg_id(), json!("k"));
+        node.rpc_with_retry("n2".into(), body, |_, reply| Ok(smallvec![reply]))
+            .unwrap();
+        clock.advance(ms(100));
+        assert_eq!(node.tick(node.now()).unwrap()[0].dest, "n2");
+        clock.advance(ms(100));
+        let replies = node.tick(node.now()).unwrap();
+        assert!(matches!(
+            replies[0].body,
+            Workload::Error {
+                code: code::TIMEOUT,
+                ..
+            }
+        ));
+        assert_eq!(node.pending_rpcs(), 0);
+    }
+
+    #[test]
+    fn test_node_rpc_reliably() {
+        let clock = ManualClock::new(1_700_000_000_000);
+        let mut node = initialized("n1", 2);
+        node.set_clock(clock.clone());
+
+        // sent again under the same msg_id, until the reply runs the callback.
+        let body = Workload::read(node.gen_msg_id(), json!("k"));
+        node.rpc_reliably("n2".into(), body, |_, reply| Ok(smallvec![reply]))
+            .unwrap();
+        clock.advance(Duration::from_secs(10));
+        let retried = node.tick(node.now()).unwrap().remove(0);
+        assert_eq!(retried.body.msg_id(), Some(1));
+        let reply = Message {
+            src: "n2".into(),
+            dest: "n1".into(),
+            body: Workload::kv_read_ok(1, 1, json!(1)),
+        };
+        assert_eq!(node.process(reply.clone()).unwrap()[..], [reply]);
+        assert!(node.outbox().unwrap().is_empty());
+        assert_eq!(node.pending_rpcs(), 0);
+    }
+
+    #[test]
+    fn test_node_manual_clock() {
+        let clock = ManualClock::new(1_700_000_000_000);
+        let mut node = Node::default();
+        node.set_clock(clock.clone());
+        node.init("n1".into(), vec!["n1".into(), "n2".into()]);
+        assert_eq!(
+            node.gen_unique_id().unwrap(),
+            "018bcfe5680000000000000000000000"
+        );
+
+        let body = Workload::read(node.gen_msg_id(), json!("k"));
+        let timeout = Duration::from_millis(100);
+        node.rpc_with_timeout("n2".into(), body, timeout, |_, reply| Ok(smallvec![reply]))
+            .unwrap();
+        clock.advance(Duration::from_millis(99));
+        assert!(node.tick(node.now()).unwrap().is_empty());
+        clock.advance(Duration::from_millis(1));
+        assert_eq!(node.tick(node.now()).unwrap().len(), 1);
+
+        // round trips are measured on the clock as well.
+        let body = Workload::read(node.gen_msg_id(), json!("k"));
+        node.rpc("n2".into(), body, |_, _| Ok(Replies::new()))
+            .unwrap();
+        clock.advance(Duration::from_millis(30));
+        let reply = Message {
+            src: "n2".into(),
+            dest: "n1".into(),
+            body: Workload::kv_read_ok(2, 1, json!(1)),
+        };
+        node.process(reply).unwrap();
+        assert_eq!(node.rtt("n2").unwrap().srtt(), Duration::from_millis(30));
+    }
+
+    #[test]
+    fn test_node_dump_state() {
+        let mut node = Node::default();
+        let json = r#"{"src":"c1","dest":"n1","body":{"type":"init","msg_id":1,"node_id":"n1","node_ids":["n1","n2"]}}"#;
+        let _ = node.process(serde_json::from_str::<Message>(json).unwrap());
+        node.push_broadcast_message(7).unwrap();
+        let body = Workload::Heartbeat {
+            msg_id: node.gen_msg_id(),
+            lamport: 0,
+        };
+        let _ = node
+            .rpc("n2".into(), body, |_, _| Ok(Replies::new()))
+            .unwrap();
+
+        let json = r#"{"src":"c1","dest":"n1","body":{"type":"dump_state","msg_id":2}}"#;
+        let replies = node
+            .process(serde_json::from_str::<Message>(json).unwrap())
+            .unwrap();
+        let Workload::DumpStateOk {
+            in_reply_to: 2,
+            state,
+            ..
+        } = &replies[0].body
+        else {
+            panic!("expected dump_state_ok, got {:?}", replies[0].body);
+        };
+        assert_eq!(state["node_id"], "n1");
+        assert_eq!(state["broadcast_messages"], json!([7]));
+        assert_eq!(
+            state["pending_rpcs"],
+            json!([{"msg_id": 1, "dest": "n2", "deadline_ms": null}])
+        );
+    }
+
+    #[test]
+    fn test_node_panic_isolation() {
+        fn handler_panic(_: &mut Node, _: Message, _: &mut dyn Sink) -> Result<()> {
+            panic!("boom");
+        }
+        let mut handlers: HashMap<Type, Handler> = HashMap::new();
+        handlers.insert(Type::Echo, handler_panic);
+        let mut node = Node::new(handlers);
+        let json = r#"{"src":"c1","dest":"n1","body":{"type":"init","msg_id":1,"node_id":"n1","node_ids":["n1"]}}"#;
+        let _ = node.process(serde_json::from_str::<Message>(json).unwrap());
+
+        let json = r#"{"src":"c1","dest":"n1","body":{"type":"echo","echo":"hi","msg_id":2}}"#;
+        let replies = node
+            .process(serde_json::from_str::<Message>(json).unwrap())
+            .unwrap();
+        assert_eq!(
+            serde_json::to_string(&replies[0]).unwrap(),
+            r#"{"src":"n1","dest":"c1","body":{"type":"error","in_reply_to":2,"code":13,"text":"handler panicked: boom"}}"#
+        );
+
+        // the node keeps serving.
+        let json = r#"{"src":"c1","dest":"n1","body":{"type":"dump_state","msg_id":3}}"#;
+        assert!(node
+            .process(serde_json::from_str::<Message>(json).unwrap())
+            .is_ok());
+    }
+
+    #[test]
+    fn test_node_error_context() {
+        fn handler_fail(_: &mut Node, _: Message, _: &mut dyn Sink) -> Result<()> {
+            Err(Box::new(Error::KeyNotFound))
+        }
+        let mut handlers: HashMap<Type, Handler> = HashMap::new();
+        handlers.insert(Type::Read, handler_fail);
+        let mut node = Node::new(handlers);
+        let json = r#"{"src":"c1","dest":"n1","body":{"type":"init","msg_id":1,"node_id":"n1","node_ids":["n1"]}}"#;
+        let _ = node.process(serde_json::from_str::<Message>(json).unwrap());
+
+        let json = r#"{"src":"c1","dest":"n1","body":{"type":"read","msg_id":2}}"#;
+        let error = node
+            .process(serde_json::from_str::<Message>(json).unwrap())
+            .unwrap_err();
+        // what identifies the message, not the whole of it.
+        let input = r#"{"body":{"in_reply_to":null,"msg_id":2,"type":"read"},"src":"c1"}"#;
+        assert_eq!(
+            error.to_string(),
+            format!(r#"error="Key not found." type=Read input={input}"#)
+        );
+        assert_eq!(
+            error.source().unwrap().to_string(),
+            Error::KeyNotFound.to_string()
+        );
+    }
+
+    #[test]
+    fn test_node_failure_detector() {
+        let mut node = Node::default();
+        let interval = Duration::from_millis(100);
+        node.enable_failure_detector(interval, interval * 5);
+        let json = r#"{"src":"c1","dest":"n1","body":{"type":"init","msg_id":1,"node_id":"n1","node_ids":["n1","n2"]}}"#;
+        let _ = node.process(serde_json::from_str::<Message>(json).unwrap());
+
+        let heartbeats = node.tick(Instant::now()).unwrap();
+        assert_eq!(heartbeats.len(), 1);
+        assert_eq!(heartbeats[0].dest, "n2");
+
+        // late, not dead yet.
+        node.tick(Instant::now() + interval * 2).unwrap();
+        assert_eq!(node.liveness(&"n2".into()), Liveness::Slow);
+        assert!(node.suspects().is_empty());
+
+        let later = Instant::now() + interval * 10;
+        node.tick(later).unwrap();
+        assert_eq!(node.suspects(), vec!["n2"]);
+        assert_eq!(node.liveness(&"n2".into()), Liveness::Dead);
+        assert!(node.slow_peers().is_empty());
+
+        let json = r#"{"src":"n2","dest":"n1","body":{"type":"heartbeat","msg_id":1}}"#;
+        let replies = node.process(serde_json::from_str::<Message>(json).unwrap());
+        assert!(replies.unwrap().is_empty());
+        assert!(node.suspects().is_empty());
+    }
+
+    #[test]
+    fn test_node_election() {
+        let clock = ManualClock::new(0);
+        let mut node = Node::default();
+        node.set_clock(clock.clone());
+        node.enable_election(Duration::from_millis(500), Duration::from_millis(100));
+        let json = r#"{"src":"c1","dest":"n2","body":{"type":"init","msg_id":1,"node_id":"n2","node_ids":["n1","n2","n3"]}}"#;
+        let _ = node.process(serde_json::from_str::<Message>(json).unwrap());
+        assert_eq!(node.leader(), Some("n1".into()));
+
+        let mut heartbeats = node.tick(node.now()).unwrap();
+        heartbeats.sort_by_key(|heartbeat| heartbeat.dest);
+        let dests: Vec<&str> = heartbeats.iter().map(|h| h.dest.as_str()).collect();
+        assert_eq!(dests, ["n1", "n3"]);
+        assert!(matches!(heartbeats[0].body, Workload::Heartbeat { .. }));
+
+        // n1 goes quiet, n3 doesn't: n2 takes over.
+        clock.advance(Duration::from_millis(600));
+        let json = r#"{"src":"n3","dest":"n2","body":{"type":"heartbeat","msg_id":1}}"#;
+        let _ = node.process(serde_json::from_str::<Message>(json).unwrap());
+        assert!(node.is_leader());
+        let json = r#"{"src":"n1","dest":"n2","body":{"type":"heartbeat","msg_id":2}}"#;
+        let _ = node.process(serde_json::from_str::<Message>(json).unwrap());
+        assert_eq!(node.leader(), Some("n1".into()));
+        // and once it leaves, for good.
+        node.leave(&"n1".into()).unwrap();
+        assert!(node.is_leader());
+    }
+
+    #[test]
+    fn test_node_join_and_leave() {
+        use crate::testing::message::msg;
+
+        let mut node = initialized("n1", 3);
+        let config = Config {
+            topology: TopologyStrategy::Total,
+            ..Config::default()
+        };
+        node.configure(&config);
+        node.set_max_hops(Some(1));
+        let gossip = Workload::Broadcast {
+            msg_id: 5,
+            message: 7,
+            hops: None,
+        };
+        node.send_reliably("n3".into(), gossip).unwrap();
+
+        let join = msg().from("c1").id(1).body(Workload::Join {
+            msg_id: 1,
+            node_id: "n4".into(),
+        });
+        let replies = node.process(join).unwrap();
+        assert_eq!(replies[0].body, Workload::join_ok(1, 1));
+        assert_eq!(node.node_ids(), &["n1", "n2", "n3", "n4"]);
+        assert_eq!(node.neighbors(), &["n2", "n3", "n4"]);
+
+        // what's left for a node that leaves is dropped.
+        let leave = |node_id: &str| {
+            msg().from("c1").id(2).body(Workload::Leave {
+                msg_id: 2,
+                node_id: node_id.into(),
+            })
+        };
+        node.process(leave("n3")).unwrap();
+        assert_eq!(node.neighbors(), &["n2", "n4"]);
+        assert!(node.outbox().unwrap().is_empty());
+        assert_eq!(node.max_hops(), Some(1));
+
+        // with a tree, the hop limit follows the diameter.
+        node.configure(&Config {
+            topology: TopologyStrategy::Tree,
+            fanout: 1,
+            ..Config::default()
+        });
+        node.process(leave("n5")).unwrap(); // not a member, nothing changes.
+        assert_eq!(node.max_hops(), Some(1));
+        node.join("n5".into());
+        assert_eq!(node.max_hops(), Some(3));
+        node.process(leave("n1")).unwrap();
+        assert!(node.neighbors().is_empty());
+        assert!(node.peers().is_empty());
+    }
+
+    #[test]
+    fn test_node_leave_purges() {
+        let mut node = initialized("n1", 3);
+        node.configure(&Config {
+            gossip_interval: Some(Duration::from_secs(1)),
+            ..Config::default()
+        });
+        node.enable_rate_limit(1.0, 1);
+        node.enable_hinted_handoff(8);
+        let now = node.now();
+        let broadcast = |msg_id, message| Workload::Broadcast {
+            msg_id,
+            message,
+            hops: None,
+        };
+
+        // gossip, two requests behind the rate limit, a hint and an rpc, all for n3.
+        assert!(node.queue_gossip("n3".into(), 7, 1));
+        let queued = smallvec![
+            node.reply("n2".into(), broadcast(1, 1)),
+            node.reply("n3".into(), broadcast(2, 2)),
+            node.reply("n3".into(), broadcast(3, 3)),
+        ];
+        assert_eq!(node.throttled(Ok(queued), now).unwrap().len(), 1);
+        assert_eq!(node.state()["throttled"], 2);
+        let hint = node.reply("n3".into(), broadcast(4, 4));
+        node.hints.as_mut().unwrap().hold(hint);
+        node.rpc("n3".into(), broadcast(5, 5), |_, reply| {
+            Ok(smallvec![reply])
+        })
+        .unwrap();
+
+        let replies = node.leave(&"n3".into()).unwrap();
+        assert_eq!(replies.len(), 1);
+        assert!(matches!(
+            replies[0].body,
+            Workload::Error {
+                in_reply_to: 5,
+                code: code::NODE_NOT_FOUND,
+                ..
+            }
+        ));
+        let state = node.state();
+        assert_eq!(state["gossip_queued"], 0);
+        assert_eq!(state["throttled"], 0);
+        assert_eq!(state["hints"], 0);
+        assert_eq!(node.pending_rpcs(), 0);
+    }
+
+    #[test]
+    fn test_node_shutdown_hooks() {
+        let mut node = Node::default();
+        node.add_shutdown_hook(|node| {
+            node.push_broadcast_message(1).unwrap();
+        });
+        node.add_shutdown_hook(|node| {
+            node.push_broadcast_message(2).unwrap();
+        });
+        node.shutdown();
+        node.shutdown(); // hooks only run once.
+        assert_eq!(node.broadcast_messages(), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_node_tick_hooks() {
+        let mut node = Node::default();
+        node.add_tick_hook(|node, _| {
+            let body = Workload::Heartbeat {
+                msg_id: node.gen_msg_id(),
+                lamport: 0,
+            };
+            Ok(smallvec![node.reply("n2".into(), body)])
+        });
+        assert!(node.tick(Instant::now()).unwrap().is_empty()); // not initialized yet.
+
+        let json = r#"{"src":"c1","dest":"n1","body":{"type":"init","msg_id":1,"node_id":"n1","node_ids":["n1","n2"]}}"#;
+        node.process(serde_json::from_str::<Message>(json).unwrap())
+            .unwrap();
+        let replies = node.tick(Instant::now()).unwrap();
+        assert_eq!(replies.len(), 1);
+        assert_eq!(replies[0].dest, "n2");
+    }
+
+    #[test]
+    fn test_node_memory_guard() {
+        use crate::testing::message::{init, msg};
+
+        let clock = ManualClock::new(1_700_000_000_000);
+        let mut node = Node::new(HashMap::from([(
+            Type::Read,
+            (|node: &mut Node, msg: Message, out: &mut dyn Sink| {
+                let msg_id = msg.body.msg_id().unwrap_or_default();
+                out.send(node.reply_to((msg.src, msg_id), |in_reply_to, msg_id| {
+                    Workload::read_ok(in_reply_to, msg_id, Arc::default())
+                }));
+                Ok(())
+            }) as Handler,
+        )]));
+        node.set_clock(clock.clone());
+        node.enable_reply_cache(1_000);
+        init(&mut node, "n1", &["n1"]);
+        for value in 0..100 {
+            node.push_broadcast_message(value).unwrap();
+        }
+        node.add_eviction_hook(|node, _| {
+            node.prune_reply_cache(Duration::from_secs(60));
+        });
+        for msg_id in 1..=10 {
+            node.process(msg().from("c1").id(msg_id).read()).unwrap();
+        }
+        clock.advance(Duration::from_secs(120));
+        for msg_id in 11..=12 {
+            node.process(msg().from("c1").id(msg_id).read()).unwrap();
+        }
+        let usage = node.memory_usage();
+        assert!(usage.seen > 0);
+        assert_eq!(node.state()["memory"]["seen"], json!(usage.seen));
+
+        // no guard, no eviction.
+        node.tick(node.now()).unwrap();
+        assert_eq!(node.memory_usage(), usage);
+
+        // over the threshold, the requests older than a minute go, and it's back under.
+        let request = usage.reply_cache / 12;
+        node.enable_memory_guard(usage.seen + request * 5);
+        node.tick(node.now()).unwrap();
+        assert_eq!(node.memory_usage().reply_cache, request * 2);
+        assert!(node.memory_guard.as_ref().unwrap().is_over());
+        node.tick(node.now()).unwrap();
+        assert!(!node.memory_guard.as_ref().unwrap().is_over());
+        assert_eq!(node.prune_reply_cache(Duration::from_secs(60)), 0);
+    }
+
+    #[test]
+    fn test_lamport_clock() {
+        let clock = LamportClock::default();
+        assert_eq!(clock.tick(), 1);
+        assert_eq!(clock.merge(5), 6);
+        assert_eq!(clock.merge(2), 7);
+    }
+
+    #[test]
+    fn test_node_lamport_stamping() {
+        let mut node = Node::new(HashMap::from([(
+            Type::Heartbeat,
+            (|_, _, _| Ok(())) as Handler,
+        )]));
+        let json = r#"{"src":"c1","dest":"n1","body":{"type":"init","msg_id":1,"node_id":"n1","node_ids":["n1","n2"]}}"#;
+        let _ = node.process(serde_json::from_str::<Message>(json).unwrap());
+
+        let json =
+            r#"{"src":"n2","dest":"n1","body":{"type":"heartbeat","msg_id":1,"lamport":10}}"#;
+        let _ = node.process(serde_json::from_str::<Message>(json).unwrap());
+        assert_eq!(node.lamport().time(), 11);
+
+        let body = Workload::Heartbeat {
+            msg_id: node.gen_msg_id(),
+            lamport: 0,
+        };
+        let message = node.reply("n2".into(), body);
+        assert_eq!(message.body.lamport(), Some(12));
     }
 
     // TODO test unique id generator
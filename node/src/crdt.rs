@@ -0,0 +1,334 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::hash::Hash;
+
+use serde::{Deserialize, Serialize};
+
+use crate::core::{NodeId, Timestamp};
+
+// state-based CRDT: merging is commutative, associative and idempotent,
+// so replicas converge no matter how often or in which order states are exchanged.
+pub trait Merge {
+    fn merge(&mut self, other: &Self);
+}
+
+// grow-only set, keeps insertion order so reads are stable. two sets are equal when they hold
+// the same values, whatever order they were inserted in.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "Vec<T>", into = "Vec<T>")]
+#[serde(bound(
+    serialize = "T: Serialize + Clone",
+    deserialize = "T: Deserialize<'de> + Hash + Eq + Clone"
+))]
+pub struct GSet<T: Hash + Eq> {
+    values: Vec<T>,
+    index: HashSet<T>,
+}
+
+impl<T: Hash + Eq + Clone> GSet<T> {
+    // returns false if the value was already present.
+    pub fn insert(&mut self, value: T) -> bool {
+        if !self.index.insert(value.clone()) {
+            return false;
+        }
+        self.values.push(value);
+        true
+    }
+
+    pub fn contains(&self, value: &T) -> bool {
+        self.index.contains(value)
+    }
+
+    pub fn values(&self) -> &[T] {
+        &self.values
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+impl<T: Hash + Eq> PartialEq for GSet<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index
+    }
+}
+
+impl<T: Hash + Eq> Eq for GSet<T> {}
+
+impl<T: Hash + Eq> Default for GSet<T> {
+    fn default() -> Self {
+        Self {
+            values: Vec::new(),
+            index: HashSet::new(),
+        }
+    }
+}
+
+impl<T: Hash + Eq + Clone> Merge for GSet<T> {
+    fn merge(&mut self, other: &Self) {
+        for value in &other.values {
+            self.insert(value.clone());
+        }
+    }
+}
+
+impl<T: Hash + Eq + Clone> From<Vec<T>> for GSet<T> {
+    fn from(values: Vec<T>) -> Self {
+        let mut set = GSet::default();
+        values.into_iter().for_each(|value| {
+            set.insert(value);
+        });
+        set
+    }
+}
+
+impl<T: Hash + Eq> From<GSet<T>> for Vec<T> {
+    fn from(set: GSet<T>) -> Self {
+        set.values
+    }
+}
+
+// unique tag of an add operation: the replica and its local add counter.
+pub type Dot = (NodeId, u64);
+
+// observed-remove set: a remove only cancels the adds it has seen, concurrent adds win.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(bound(serialize = "T: Serialize", deserialize = "T: Deserialize<'de> + Ord"))]
+pub struct ORSet<T: Ord> {
+    adds: BTreeSet<(T, Dot)>,
+    tombstones: BTreeSet<Dot>,
+}
+
+impl<T: Ord + Clone> ORSet<T> {
+    pub fn insert(&mut self, node_id: &NodeId, value: T) {
+        let counter = self
+            .adds
+            .iter()
+            .map(|(_, dot)| dot)
+            .chain(self.tombstones.iter())
+            .filter(|(n, _)| n == node_id)
+            .map(|(_, counter)| *counter)
+            .max()
+            .unwrap_or(0);
+        self.adds.insert((value, (*node_id, counter + 1)));
+    }
+
+    pub fn remove(&mut self, value: &T) {
+        let observed: Vec<(T, Dot)> = self
+            .adds
+            .iter()
+            .filter(|(v, _)| v == value)
+            .cloned()
+            .collect();
+        for add in observed {
+            self.adds.remove(&add);
+            self.tombstones.insert(add.1);
+        }
+    }
+
+    pub fn contains(&self, value: &T) -> bool {
+        self.adds.iter().any(|(v, _)| v == value)
+    }
+
+    pub fn values(&self) -> BTreeSet<T> {
+        self.adds.iter().map(|(value, _)| value.clone()).collect()
+    }
+}
+
+impl<T: Ord> Default for ORSet<T> {
+    fn default() -> Self {
+        Self {
+            adds: BTreeSet::new(),
+            tombstones: BTreeSet::new(),
+        }
+    }
+}
+
+impl<T: Ord + Clone> Merge for ORSet<T> {
+    fn merge(&mut self, other: &Self) {
+        self.tombstones.extend(other.tombstones.iter().cloned());
+        self.adds.extend(other.adds.iter().cloned());
+        let tombstones = &self.tombstones;
+        self.adds.retain(|(_, dot)| !tombstones.contains(dot));
+    }
+}
+
+// last-writer-wins register, ties on the timestamp are broken by node id.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LwwRegister<T> {
+    value: Option<T>,
+    timestamp: Timestamp,
+    node_id: NodeId,
+}
+
+impl<T: Clone> LwwRegister<T> {
+    pub fn set(&mut self, node_id: &NodeId, timestamp: Timestamp, value: T) {
+        if (timestamp, node_id) > (self.timestamp, &self.node_id) {
+            self.value = Some(value);
+            self.timestamp = timestamp;
+            self.node_id = *node_id;
+        }
+    }
+
+    pub fn get(&self) -> Option<&T> {
+        self.value.as_ref()
+    }
+
+    pub fn timestamp(&self) -> Timestamp {
+        self.timestamp
+    }
+}
+
+impl<T> Default for LwwRegister<T> {
+    fn default() -> Self {
+        Self {
+            value: None,
+            timestamp: 0,
+            node_id: NodeId::default(),
+        }
+    }
+}
+
+impl<T: Clone> Merge for LwwRegister<T> {
+    fn merge(&mut self, other: &Self) {
+        if let Some(value) = &other.value {
+            self.set(&other.node_id, other.timestamp, value.clone());
+        }
+    }
+}
+
+// grow-only counter, every replica only increments its own slot.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct GCounter {
+    counts: BTreeMap<NodeId, u64>,
+}
+
+impl GCounter {
+    pub fn increment(&mut self, node_id: &NodeId, delta: u64) {
+        *self.counts.entry(*node_id).or_insert(0) += delta;
+    }
+
+    pub fn value(&self) -> u64 {
+        self.counts.values().sum()
+    }
+}
+
+impl Merge for GCounter {
+    fn merge(&mut self, other: &Self) {
+        for (node_id, count) in &other.counts {
+            let entry = self.counts.entry(*node_id).or_insert(0);
+            *entry = (*entry).max(*count);
+        }
+    }
+}
+
+// counter supporting decrements, as a pair of grow-only counters.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PNCounter {
+    p: GCounter,
+    n: GCounter,
+}
+
+impl PNCounter {
+    pub fn add(&mut self, node_id: &NodeId, delta: i64) {
+        if delta >= 0 {
+            self.p.increment(node_id, delta as u64);
+        } else {
+            self.n.increment(node_id, delta.unsigned_abs());
+        }
+    }
+
+    pub fn value(&self) -> i64 {
+        self.p.value() as i64 - self.n.value() as i64
+    }
+}
+
+impl Merge for PNCounter {
+    fn merge(&mut self, other: &Self) {
+        self.p.merge(&other.p);
+        self.n.merge(&other.n);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gset() {
+        let mut a = GSet::from(vec![3, 1]);
+        let b = GSet::from(vec![2, 3]);
+        assert!(!a.insert(1));
+        a.merge(&b);
+        a.merge(&b); // idempotent.
+        assert_eq!(a.values(), &[3, 1, 2]);
+        assert_eq!(serde_json::to_string(&a).unwrap(), "[3,1,2]");
+        assert_eq!(serde_json::from_str::<GSet<u64>>("[3,1,2]").unwrap(), a);
+        // the same values, merged the other way around.
+        let mut c = b.clone();
+        c.merge(&GSet::from(vec![3, 1]));
+        assert_eq!(c.values(), &[2, 3, 1]);
+        assert_eq!(c, a);
+        assert_ne!(b, a);
+    }
+
+    #[test]
+    fn test_orset_add_wins() {
+        let (n1, n2) = (NodeId::from("n1"), NodeId::from("n2"));
+        let mut a = ORSet::default();
+        a.insert(&n1, "x");
+        let mut b = a.clone();
+
+        // n1 removes "x" while n2 concurrently re-adds it.
+        a.remove(&"x");
+        b.insert(&n2, "x");
+        a.merge(&b);
+        b.merge(&a);
+        assert!(a.contains(&"x"));
+        assert_eq!(a, b);
+
+        a.remove(&"x");
+        b.merge(&a);
+        assert!(!b.contains(&"x"));
+    }
+
+    #[test]
+    fn test_lww_register() {
+        let (n1, n2) = (NodeId::from("n1"), NodeId::from("n2"));
+        let mut a = LwwRegister::default();
+        let mut b = LwwRegister::default();
+        a.set(&n1, 2, "a");
+        b.set(&n2, 2, "b"); // same timestamp, n2 wins the tie.
+        a.merge(&b);
+        b.merge(&a);
+        assert_eq!(a.get(), Some(&"b"));
+        assert_eq!(a, b);
+
+        a.set(&n1, 1, "stale");
+        assert_eq!(a.get(), Some(&"b"));
+    }
+
+    #[test]
+    fn test_counters() {
+        let (n1, n2) = (NodeId::from("n1"), NodeId::from("n2"));
+        let mut a = PNCounter::default();
+        let mut b = PNCounter::default();
+        a.add(&n1, 5);
+        b.add(&n2, 3);
+        b.add(&n2, -1);
+        a.merge(&b);
+        b.merge(&a);
+        a.merge(&b);
+        assert_eq!(a.value(), 7);
+        assert_eq!(b.value(), 7);
+
+        let mut g = GCounter::default();
+        g.increment(&n1, 2);
+        assert_eq!(serde_json::to_string(&g).unwrap(), r#"{"n1":2}"#);
+    }
+}
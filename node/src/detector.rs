@@ -0,0 +1,279 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant};
+
+use crate::core::NodeId;
+
+// intervals between messages remembered per peer.
+const WINDOW: usize = 100;
+// phi past which a peer is slow: about a 1 in 10 chance it's on time.
+const SLOW_PHI: f64 = 1.0;
+// and dead: 1 in 10^8.
+const DEAD_PHI: f64 = 8.0;
+
+// how a peer looks from here, see "FailureDetector::liveness".
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Liveness {
+    Alive,
+    // quiet for longer than its messages usually are apart.
+    Slow,
+    // quiet for so long it's not coming back any time soon, see "FailureDetector::suspects".
+    Dead,
+}
+
+// when a peer was last heard from, and how far apart its heartbeats were lately.
+#[derive(Debug, Clone)]
+struct Arrivals {
+    last: Instant,
+    last_heartbeat: Instant,
+    intervals: VecDeque<f64>,
+}
+
+// phi accrual failure detector (hayashibara et al.): rather than suspected or not, a peer gets
+// a suspicion level "phi" that grows the longer it's quiet, measured against the intervals
+// between its heartbeats so far, assumed normally distributed. phi of 1 means a 10% chance the
+// next message is still to come, 2 means 1%, and so on. a peer with jittery latency takes
+// longer to look dead than one that's always on time. any message proves the peer alive, but
+// only heartbeats, sent at a steady pace, are measured: a burst of gossip would make the
+// peer look dead as soon as it pauses. a peer is slow once phi reaches 1, and dead (suspected) once it reaches 8
+// after "timeout" at least.
+#[derive(Debug, Clone)]
+pub struct FailureDetector {
+    interval: Duration,
+    timeout: Duration,
+    next_heartbeat: Option<Instant>,
+    arrivals: HashMap<NodeId, Arrivals>,
+    slow: HashSet<NodeId>,
+    suspected: HashSet<NodeId>,
+    recovered: Vec<NodeId>,
+}
+
+impl FailureDetector {
+    pub fn new(interval: Duration, timeout: Duration) -> Self {
+        Self {
+            interval,
+            timeout,
+            next_heartbeat: None,
+            arrivals: HashMap::new(),
+            slow: HashSet::new(),
+            suspected: HashSet::new(),
+            recovered: Vec::new(),
+        }
+    }
+
+    // peers start out trusted, as if heard from just now. the ones kept keep their intervals.
+    pub fn set_peers(&mut self, peers: &[NodeId], now: Instant) {
+        let interval = self.interval.as_secs_f64() * 1000.0;
+        let mut arrivals = std::mem::take(&mut self.arrivals);
+        for peer in peers {
+            // until the first interval is measured, the heartbeats' is expected.
+            let mut kept = arrivals.remove(peer).unwrap_or_else(|| Arrivals {
+                last: now,
+                last_heartbeat: now,
+                intervals: VecDeque::from([interval]),
+            });
+            kept.last = now;
+            kept.last_heartbeat = now;
+            self.arrivals.insert(*peer, kept);
+        }
+        self.slow.clear();
+        self.suspected.clear();
+    }
+
+    // any message from "peer", it's alive.
+    pub fn heard_from(&mut self, peer: &NodeId, now: Instant) {
+        let Some(arrivals) = self.arrivals.get_mut(peer) else {
+            return;
+        };
+        arrivals.last = now;
+        self.slow.remove(peer);
+        if self.suspected.remove(peer) {
+            self.recovered.push(*peer);
+        }
+    }
+
+    // a heartbeat from "peer", its distance to the previous one is what phi is measured against.
+    pub fn heartbeat(&mut self, peer: &NodeId, now: Instant) {
+        let Some(arrivals) = self.arrivals.get_mut(peer) else {
+            return;
+        };
+        let interval = now.saturating_duration_since(arrivals.last_heartbeat);
+        arrivals.last_heartbeat = now;
+        if arrivals.intervals.len() == WINDOW {
+            arrivals.intervals.pop_front();
+        }
+        arrivals
+            .intervals
+            .push_back(interval.as_secs_f64() * 1000.0);
+        self.heard_from(peer, now);
+    }
+
+    // the suspicion level of "peer" at "now", none for a peer it doesn't watch.
+    pub fn phi(&self, peer: &NodeId, now: Instant) -> Option<f64> {
+        let arrivals = self.arrivals.get(peer)?;
+        let count = arrivals.intervals.len() as f64;
+        let mean = arrivals.intervals.iter().sum::<f64>() / count;
+        let variance = arrivals
+            .intervals
+            .iter()
+            .map(|interval| (interval - mean).powi(2))
+            .sum::<f64>()
+            / count;
+        // a peer that was always on time isn't dead the first time it's a little late.
+        let std_dev = variance
+            .sqrt()
+            .max(self.interval.as_secs_f64() * 1000.0 / 4.0);
+        let elapsed = now.saturating_duration_since(arrivals.last).as_secs_f64() * 1000.0;
+        Some(phi(elapsed, mean, std_dev))
+    }
+
+    // refreshes suspicions and returns the peers to heartbeat, if a heartbeat is due.
+    pub fn tick(&mut self, now: Instant) -> Vec<NodeId> {
+        let mut peers: Vec<NodeId> = self.arrivals.keys().cloned().collect();
+        peers.sort();
+        for peer in &peers {
+            let phi = self.phi(peer, now).unwrap_or_default();
+            let quiet = now.saturating_duration_since(self.arrivals[peer].last);
+            if phi >= DEAD_PHI && quiet >= self.timeout {
+                self.slow.remove(peer);
+                self.suspected.insert(*peer);
+            } else if phi >= SLOW_PHI && !self.suspected.contains(peer) {
+                self.slow.insert(*peer);
+            }
+        }
+
+        if self.next_heartbeat.is_some_and(|next| now < next) {
+            return Vec::new();
+        }
+        self.next_heartbeat = Some(now + self.interval);
+        peers
+    }
+
+    // as of the last "tick". a peer it doesn't watch is alive.
+    pub fn liveness(&self, peer: &NodeId) -> Liveness {
+        if self.suspected.contains(peer) {
+            Liveness::Dead
+        } else if self.slow.contains(peer) {
+            Liveness::Slow
+        } else {
+            Liveness::Alive
+        }
+    }
+
+    pub fn is_suspected(&self, peer: &NodeId) -> bool {
+        self.suspected.contains(peer)
+    }
+
+    // the dead peers.
+    pub fn suspects(&self) -> Vec<NodeId> {
+        let mut suspects: Vec<NodeId> = self.suspected.iter().cloned().collect();
+        suspects.sort();
+        suspects
+    }
+
+    // the slow peers, dead ones aside.
+    pub fn slow(&self) -> Vec<NodeId> {
+        let mut slow: Vec<NodeId> = self.slow.iter().cloned().collect();
+        slow.sort();
+        slow
+    }
+
+    // peers that became reachable again since the last call, worth a resync.
+    pub fn take_recovered(&mut self) -> Vec<NodeId> {
+        std::mem::take(&mut self.recovered)
+    }
+}
+
+// -log10 of the chance the next message comes after "elapsed", with the logistic approximation
+// of the normal distribution's cdf that akka uses.
+fn phi(elapsed: f64, mean: f64, std_dev: f64) -> f64 {
+    let y = (elapsed - mean) / std_dev;
+    let e = (-y * (1.5976 + 0.070566 * y * y)).exp();
+    let later = if elapsed > mean {
+        e / (1.0 + e)
+    } else {
+        1.0 - 1.0 / (1.0 + e)
+    };
+    // past what an f64 can tell apart from 0, phi is as good as infinite.
+    -later.max(f64::MIN_POSITIVE).log10()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detector_suspects_and_recovers() {
+        let now = Instant::now();
+        let mut detector =
+            FailureDetector::new(Duration::from_millis(100), Duration::from_millis(500));
+        detector.set_peers(&["n2".into(), "n3".into()], now);
+        assert_eq!(detector.tick(now), vec!["n2", "n3"]);
+        assert!(detector.tick(now + Duration::from_millis(50)).is_empty());
+
+        let later = now + Duration::from_millis(600);
+        detector.heard_from(&"n2".into(), later);
+        detector.tick(later);
+        assert_eq!(detector.suspects(), vec!["n3"]);
+
+        detector.heard_from(&"n3".into(), later);
+        assert!(!detector.is_suspected(&"n3".into()));
+        assert_eq!(detector.take_recovered(), vec!["n3"]);
+        assert!(detector.take_recovered().is_empty());
+    }
+
+    #[test]
+    fn test_detector_phi() {
+        let now = Instant::now();
+        let at = |ms| now + Duration::from_millis(ms);
+        let interval = Duration::from_millis(100);
+        let mut detector = FailureDetector::new(interval, interval * 3);
+        detector.set_peers(&["n2".into(), "n3".into()], now);
+        // n2 is always on time, n3's messages come 20 to 180ms apart.
+        let mut jittery = 0;
+        for i in 1..=20 {
+            detector.heartbeat(&"n2".into(), at(i * 100));
+            jittery += if i % 2 == 0 { 20 } else { 180 };
+            detector.heartbeat(&"n3".into(), at(jittery));
+        }
+        let (n2, n3) = ("n2".into(), "n3".into());
+        let phi = |detector: &FailureDetector, peer, ms| detector.phi(peer, at(ms)).unwrap();
+        assert!(phi(&detector, &n2, 2_050) < SLOW_PHI);
+        // quiet for as long, n2 looks more dead than n3.
+        assert!(phi(&detector, &n2, 2_400) > phi(&detector, &n3, 2_400));
+        assert!(phi(&detector, &n2, 2_200) < phi(&detector, &n2, 2_300));
+
+        detector.tick(at(2_150));
+        assert_eq!(detector.liveness(&n2), Liveness::Slow);
+        assert_eq!(detector.liveness(&n3), Liveness::Alive);
+        assert_eq!(detector.slow(), vec!["n2"]);
+        // not dead before "timeout".
+        detector.tick(at(2_290));
+        assert!(detector.suspects().is_empty());
+        detector.tick(at(2_500));
+        assert_eq!(detector.liveness(&n2), Liveness::Dead);
+        assert_eq!(detector.liveness(&n3), Liveness::Slow);
+        assert!(detector.slow().iter().all(|peer| *peer != n2));
+        assert!(detector.phi(&"n4".into(), at(2_500)).is_none());
+    }
+
+    #[test]
+    fn test_detector_measures_heartbeats_only() {
+        let now = Instant::now();
+        let at = |ms| now + Duration::from_millis(ms);
+        let interval = Duration::from_millis(100);
+        let mut detector = FailureDetector::new(interval, interval * 3);
+        let n2: NodeId = "n2".into();
+        detector.set_peers(std::slice::from_ref(&n2), now);
+        for i in 1..=10 {
+            detector.heartbeat(&n2, at(i * 100));
+            // a burst of gossip in between.
+            for j in 1..=9 {
+                detector.heard_from(&n2, at(i * 100 + j));
+            }
+        }
+        // the gossip didn't shrink the expected interval, a pause as long isn't suspicious.
+        assert!(detector.phi(&n2, at(1_100)).unwrap() < SLOW_PHI);
+        detector.tick(at(1_100));
+        assert_eq!(detector.liveness(&n2), Liveness::Alive);
+    }
+}
@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::core::NodeId;
+
+// lease-based bully election: the lowest node id heard from within the lease is the leader.
+// every node heartbeats its peers, nothing else needs to be exchanged.
+pub struct Elector {
+    id: NodeId,
+    lease: Duration,
+    heartbeat_interval: Duration,
+    next_heartbeat: Instant,
+    last_seen: HashMap<NodeId, Instant>,
+}
+
+impl Elector {
+    // peers get a full lease at startup, so nobody claims leadership before hearing from the others.
+    pub fn new(
+        id: NodeId,
+        node_ids: &[NodeId],
+        lease: Duration,
+        heartbeat_interval: Duration,
+        now: Instant,
+    ) -> Self {
+        let mut elector = Self {
+            id,
+            lease,
+            heartbeat_interval,
+            next_heartbeat: now,
+            last_seen: HashMap::new(),
+        };
+        elector.set_members(id, node_ids, now);
+        elector
+    }
+
+    // the cluster changed (or the node learnt its id on "init"): peers that left are forgotten,
+    // new ones get a full lease like at startup.
+    pub fn set_members(&mut self, id: NodeId, node_ids: &[NodeId], now: Instant) {
+        self.id = id;
+        self.last_seen
+            .retain(|peer, _| *peer != id && node_ids.contains(peer));
+        for peer in node_ids.iter().filter(|n| **n != id) {
+            self.last_seen.entry(*peer).or_insert(now);
+        }
+    }
+
+    // returns the peers to send a heartbeat to, if one is due.
+    pub fn tick(&mut self, now: Instant) -> Vec<NodeId> {
+        if now < self.next_heartbeat {
+            return Vec::new();
+        }
+        self.next_heartbeat = now + self.heartbeat_interval;
+        self.last_seen.keys().cloned().collect()
+    }
+
+    pub fn on_heartbeat(&mut self, now: Instant, src: &NodeId) {
+        if let Some(seen) = self.last_seen.get_mut(src) {
+            *seen = now;
+        }
+    }
+
+    pub fn leader(&self, now: Instant) -> &NodeId {
+        self.last_seen
+            .iter()
+            .filter(|(_, seen)| now.duration_since(**seen) <= self.lease)
+            .map(|(peer, _)| peer)
+            .chain(std::iter::once(&self.id))
+            .min_by(|a, b| by_index(a).cmp(&by_index(b)))
+            .unwrap_or(&self.id)
+    }
+
+    pub fn is_leader(&self, now: Instant) -> bool {
+        *self.leader(now) == self.id
+    }
+}
+
+// orders "n2" before "n10", falling back to plain string order for other ids.
+fn by_index(id: &NodeId) -> (Option<u64>, &NodeId) {
+    (id.get(1..).and_then(|n| n.parse().ok()), id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn elector(id: &str, now: Instant) -> Elector {
+        let ids: Vec<NodeId> = ["n1", "n2", "n10"].map(NodeId::from).to_vec();
+        let lease = Duration::from_millis(500);
+        Elector::new(id.into(), &ids, lease, Duration::from_millis(100), now)
+    }
+
+    #[test]
+    fn test_elector_lowest_alive_wins() {
+        let now = Instant::now();
+        let mut elector = elector("n2", now);
+        assert_eq!(elector.leader(now), "n1");
+        assert!(!elector.is_leader(now));
+
+        // n1 went quiet, n10 kept heartbeating.
+        let later = now + Duration::from_secs(1);
+        elector.on_heartbeat(later, &"n10".into());
+        assert!(elector.is_leader(later));
+
+        elector.on_heartbeat(later, &"n1".into());
+        assert_eq!(elector.leader(later), "n1");
+
+        // n1 left, n3 joined and gets a lease of its own.
+        let ids: Vec<NodeId> = ["n2", "n3", "n10"].map(NodeId::from).to_vec();
+        elector.set_members("n2".into(), &ids, later);
+        assert_eq!(elector.leader(later), "n2");
+        let mut peers = elector.tick(later);
+        peers.sort();
+        assert_eq!(peers, vec!["n10", "n3"]);
+    }
+
+    #[test]
+    fn test_elector_heartbeat_interval() {
+        let now = Instant::now();
+        let mut elector = elector("n1", now);
+        let mut peers = elector.tick(now);
+        peers.sort();
+        assert_eq!(peers, vec!["n10", "n2"]);
+        assert!(elector.tick(now + Duration::from_millis(50)).is_empty());
+        assert_eq!(elector.tick(now + Duration::from_millis(100)).len(), 2);
+    }
+}
@@ -0,0 +1,83 @@
+use std::fmt::{Display, Formatter};
+
+use serde_json::Value;
+
+use crate::core::Message;
+
+// renders a message as one line of a message flow, e.g.
+// "c1 → n1: broadcast(1000) [msg_id=5]" or "n1 → n2: send(key=\"k1\", msg=7) [msg_id=3]".
+// a body with a single field shows the value alone, otherwise fields are named.
+pub struct Flow<'a>(pub &'a Message);
+
+impl Display for Flow<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let Message { src, dest, body } = self.0;
+        let mut fields = match serde_json::to_value(body) {
+            Ok(Value::Object(fields)) => fields,
+            _ => Default::default(),
+        };
+        for key in ["type", "msg_id", "in_reply_to"] {
+            fields.remove(key);
+        }
+        let arguments = match fields.len() {
+            1 => fields.values().map(Value::to_string).collect::<Vec<_>>(),
+            _ => fields
+                .iter()
+                .map(|(key, value)| format!("{key}={value}"))
+                .collect(),
+        };
+        write!(
+            f,
+            "{src} → {dest}: {}({})",
+            body.name(),
+            arguments.join(", ")
+        )?;
+
+        let ids: Vec<String> = [
+            ("msg_id", body.msg_id()),
+            ("in_reply_to", body.in_reply_to()),
+        ]
+        .into_iter()
+        .filter_map(|(key, id)| id.map(|id| format!("{key}={id}")))
+        .collect();
+        if !ids.is_empty() {
+            write!(f, " [{}]", ids.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flow(json: &str) -> String {
+        Flow(&serde_json::from_str::<Message>(json).unwrap()).to_string()
+    }
+
+    #[test]
+    fn test_flow() {
+        assert_eq!(
+            flow(
+                r#"{"src":"c1","dest":"n1","body":{"type":"broadcast","message":1000,"msg_id":5}}"#
+            ),
+            "c1 → n1: broadcast(1000) [msg_id=5]"
+        );
+        assert_eq!(
+            flow(
+                r#"{"src":"n1","dest":"c1","body":{"type":"send_ok","in_reply_to":3,"msg_id":4,"offset":0}}"#
+            ),
+            "n1 → c1: send_ok(0) [msg_id=4, in_reply_to=3]"
+        );
+        assert_eq!(
+            flow(
+                r#"{"src":"c1","dest":"n1","body":{"type":"send","key":"k1","msg":7,"msg_id":3}}"#
+            ),
+            r#"c1 → n1: send(key="k1", msg=7) [msg_id=3]"#
+        );
+        assert_eq!(
+            flow(r#"{"src":"n1","dest":"c1","body":{"type":"init_ok","in_reply_to":1}}"#),
+            "n1 → c1: init_ok() [in_reply_to=1]"
+        );
+    }
+}
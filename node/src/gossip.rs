@@ -0,0 +1,166 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use crate::actor::{Actor, Component, Reply};
+use crate::core::BroadcastMessage;
+use crate::node_id::NodeId;
+
+// broadcast values waiting to be gossiped, by neighbor, with the fewest hops each took. they
+// go out together once every "interval", see "Config::gossip_interval".
+pub(crate) struct GossipQueue {
+    interval: Duration,
+    next_at: Option<Instant>,
+    pending: BTreeMap<NodeId, BTreeMap<BroadcastMessage, u32>>,
+}
+
+impl GossipQueue {
+    pub(crate) fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            next_at: None,
+            pending: BTreeMap::new(),
+        }
+    }
+
+    pub(crate) fn push(&mut self, neighbor: NodeId, message: BroadcastMessage, hops: u32) {
+        let fewest = self
+            .pending
+            .entry(neighbor)
+            .or_default()
+            .entry(message)
+            .or_insert(hops);
+        *fewest = hops.min(*fewest);
+    }
+
+    // every neighbor's values and the fewest hops among them, once "interval" passed since the
+    // last batch. the first goes out right away.
+    pub(crate) fn due(&mut self, now: Instant) -> Vec<(NodeId, Vec<BroadcastMessage>, u32)> {
+        if self.next_at.is_some_and(|next_at| now < next_at) || self.pending.is_empty() {
+            return Vec::new();
+        }
+        self.next_at = Some(now + self.interval);
+        std::mem::take(&mut self.pending)
+            .into_iter()
+            .map(|(neighbor, pending)| {
+                let hops = pending.values().copied().min().unwrap_or(0);
+                (neighbor, pending.into_keys().collect(), hops)
+            })
+            .collect()
+    }
+
+    // "neighbor" left the cluster, its values aren't sent.
+    pub(crate) fn remove(&mut self, neighbor: &NodeId) {
+        self.pending.remove(neighbor);
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.pending.values().map(BTreeMap::len).sum()
+    }
+}
+
+pub(crate) enum GossipRequest {
+    Push {
+        neighbor: NodeId,
+        message: BroadcastMessage,
+        hops: u32,
+    },
+    Due {
+        now: Instant,
+        reply: Reply<Vec<(NodeId, Vec<BroadcastMessage>, u32)>>,
+    },
+    Remove {
+        neighbor: NodeId,
+    },
+    Len {
+        reply: Reply<usize>,
+    },
+}
+
+impl Component for GossipQueue {
+    type Request = GossipRequest;
+
+    fn handle(&mut self, request: GossipRequest) {
+        match request {
+            GossipRequest::Push {
+                neighbor,
+                message,
+                hops,
+            } => self.push(neighbor, message, hops),
+            GossipRequest::Due { now, reply } => {
+                let _ = reply.send(self.due(now));
+            }
+            GossipRequest::Remove { neighbor } => self.remove(&neighbor),
+            GossipRequest::Len { reply } => {
+                let _ = reply.send(self.len());
+            }
+        }
+    }
+}
+
+// the node's handle on its "GossipQueue", running on a thread of its own.
+pub(crate) struct Gossip(Actor<GossipQueue>);
+
+impl Gossip {
+    pub(crate) fn new(interval: Duration) -> Self {
+        Gossip(Actor::spawn("gossip", GossipQueue::new(interval)))
+    }
+
+    pub(crate) fn push(&self, neighbor: NodeId, message: BroadcastMessage, hops: u32) {
+        self.0.tell(GossipRequest::Push {
+            neighbor,
+            message,
+            hops,
+        });
+    }
+
+    pub(crate) fn due(&self, now: Instant) -> Vec<(NodeId, Vec<BroadcastMessage>, u32)> {
+        self.0.ask(|reply| GossipRequest::Due { now, reply })
+    }
+
+    pub(crate) fn remove(&self, neighbor: NodeId) {
+        self.0.tell(GossipRequest::Remove { neighbor });
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.0.ask(|reply| GossipRequest::Len { reply })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gossip_queue_batches() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut queue = GossipQueue::new(Duration::from_millis(200));
+        assert!(queue.due(at(0)).is_empty());
+
+        queue.push("n2".into(), 1, 1);
+        assert_eq!(queue.due(at(0)), vec![("n2".into(), vec![1], 1)]);
+
+        queue.push("n2".into(), 3, 2);
+        queue.push("n2".into(), 2, 1);
+        queue.push("n2".into(), 3, 1);
+        queue.push("n3".into(), 3, 4);
+        assert_eq!(queue.len(), 3);
+        assert!(queue.due(at(100)).is_empty());
+        assert_eq!(
+            queue.due(at(200)),
+            vec![("n2".into(), vec![2, 3], 1), ("n3".into(), vec![3], 4)]
+        );
+        assert_eq!(queue.len(), 0);
+    }
+
+    #[test]
+    fn test_gossip_handle() {
+        let start = Instant::now();
+        let gossip = Gossip::new(Duration::from_millis(200));
+        gossip.push("n2".into(), 1, 2);
+        gossip.push("n3".into(), 1, 2);
+        gossip.remove("n3".into());
+        assert_eq!(gossip.len(), 1);
+        assert_eq!(gossip.due(start), vec![("n2".into(), vec![1], 2)]);
+    }
+}
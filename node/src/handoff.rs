@@ -0,0 +1,88 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::core::Message;
+use crate::node_id::NodeId;
+
+// messages held on behalf of peers that couldn't reach their destination, by destination, see
+// "Node::enable_hinted_handoff". past "capacity" per destination the oldest are dropped, their
+// sender still has them in its outbox.
+pub(crate) struct Hints {
+    capacity: usize,
+    held: BTreeMap<NodeId, VecDeque<Message>>,
+}
+
+impl Hints {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            held: BTreeMap::new(),
+        }
+    }
+
+    pub(crate) fn hold(&mut self, message: Message) {
+        let held = self.held.entry(message.dest).or_default();
+        if held.len() == self.capacity {
+            held.pop_front();
+        }
+        held.push_back(message);
+    }
+
+    // the messages for every destination not in "unreachable", which are no longer held.
+    pub(crate) fn deliverable(&mut self, unreachable: &[NodeId]) -> Vec<Message> {
+        let mut deliverable = Vec::new();
+        self.held.retain(|dest, held| {
+            if unreachable.contains(dest) {
+                return true;
+            }
+            deliverable.extend(held.drain(..));
+            false
+        });
+        deliverable
+    }
+
+    // "dest" left the cluster, there's no one to deliver its messages to.
+    pub(crate) fn remove_to(&mut self, dest: &NodeId) {
+        self.held.remove(dest);
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.held.values().map(VecDeque::len).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::Workload;
+
+    fn broadcast(dest: &str, message: u64) -> Message {
+        Message {
+            src: "n1".into(),
+            dest: dest.into(),
+            body: Workload::Broadcast {
+                msg_id: message as u32,
+                message,
+                hops: None,
+            },
+        }
+    }
+
+    #[test]
+    fn test_hints_held_until_reachable() {
+        let mut hints = Hints::new(2);
+        for message in 1..=3 {
+            hints.hold(broadcast("n2", message));
+        }
+        hints.hold(broadcast("n3", 4));
+        assert_eq!(hints.len(), 3);
+
+        // n2 is still down, only n3 gets its hint. the oldest hint of n2 didn't fit.
+        let unreachable = vec!["n2".into()];
+        assert_eq!(hints.deliverable(&unreachable), vec![broadcast("n3", 4)]);
+        assert_eq!(
+            hints.deliverable(&[]),
+            vec![broadcast("n2", 2), broadcast("n2", 3)]
+        );
+        assert_eq!(hints.len(), 0);
+    }
+}
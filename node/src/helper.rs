@@ -1,4 +1,4 @@
-use crate::core::Type;
+use crate::core::{CodeId, Offset, Type};
 use std::fmt::{Debug, Display, Formatter};
 use std::{error, result};
 
@@ -11,6 +11,12 @@ pub enum Error {
     ExpectedMessage { found: Type, expected: Type },
     NotInitializedYet,
     AlreadyInitialized,
+    MissingMessageId,
+    Rpc { code: CodeId, text: String },
+    QuorumNotReached { got: usize, needed: usize },
+    Panicked { reason: String },
+    InvalidConfig { name: String, value: String },
+    OffsetGap { next: Offset, offset: Offset },
 }
 
 impl Display for Error {
@@ -26,9 +32,68 @@ impl Display for Error {
             ),
             Error::NotInitializedYet => "Node is not initialized yet.".to_owned(),
             Error::AlreadyInitialized => "Node is already initialized.".to_owned(),
+            Error::MissingMessageId => "RPC request must carry a msg_id.".to_owned(),
+            Error::Rpc { code, text } => format!(r#"RPC failed with code {code}: "{text}"."#),
+            Error::QuorumNotReached { got, needed } => {
+                format!("Quorum not reached, got {got} of {needed} replies.")
+            }
+            Error::Panicked { reason } => format!(r#"Handler panicked: "{reason}"."#),
+            Error::InvalidConfig { name, value } => {
+                format!(r#"Invalid configuration, {name}="{value}"."#)
+            }
+            Error::OffsetGap { next, offset } => {
+                format!("Offset {offset} would leave a gap, the next one is {next}.")
+            }
         };
         write!(f, "{error}")
     }
 }
 
 impl error::Error for Error {}
+
+// an error along with what caused it: the input (a raw line, or the message as JSON) and,
+// once parsed, the type of the message, so a log line can be traced back to its message.
+#[derive(Debug)]
+pub struct ErrorContext {
+    pub error: Box<dyn error::Error>,
+    pub key: Option<Type>,
+    pub input: String,
+}
+
+impl ErrorContext {
+    pub fn wrap(error: Box<dyn error::Error>, key: Option<Type>, input: String) -> Box<Self> {
+        Box::new(Self { error, key, input })
+    }
+}
+
+// one line of "key=value" pairs, e.g. error="..." type=Broadcast input={"src":"c1",...}.
+impl Display for ErrorContext {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "error={:?}", self.error.to_string())?;
+        if let Some(key) = &self.key {
+            write!(f, " type={key:?}")?;
+        }
+        write!(f, " input={}", self.input)
+    }
+}
+
+impl error::Error for ErrorContext {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(self.error.as_ref())
+    }
+}
+
+// destructures the body of a message as the given "Workload" variant, binding its fields in
+// the enclosing handler, or returns the "ExpectedMessage" error for any other body, e.g.
+// "expect_body!(msg, Broadcast { msg_id, message, .. });". the rest of the message stays usable.
+#[macro_export]
+macro_rules! expect_body {
+    ($msg:expr, $variant:ident { $($fields:tt)* }) => {
+        let $crate::core::Workload::$variant { $($fields)* } = $msg.body else {
+            return Err(Box::new($crate::helper::Error::ExpectedMessage {
+                found: $msg.body.key(),
+                expected: $crate::core::Type::$variant,
+            }));
+        };
+    };
+}
@@ -0,0 +1,149 @@
+use std::collections::VecDeque;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::core::Message;
+
+// one message handled (or one tick that sent something), with what came of it. "at" is the
+// node's wall clock, in milliseconds since the unix epoch.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Processed {
+    pub at: u64,
+    // none for a tick.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub received: Option<Message>,
+    // the message type, "callback" for a reply to an rpc, or "tick".
+    pub handler: String,
+    pub sent: Vec<Message>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Processed {
+    // messages received and sent, in that order.
+    pub fn messages(&self) -> impl Iterator<Item = &Message> {
+        self.received.iter().chain(&self.sent)
+    }
+}
+
+// the last "capacity" events of a node, oldest first, see "Node::enable_history". enough to
+// answer "why didn't n7 learn 42" after the fact: "mentioning(&json!(42))" on n7 and on its
+// neighbors shows who had it, who sent it where, and what came back.
+#[derive(Debug)]
+pub struct EventHistory {
+    capacity: usize,
+    events: VecDeque<Processed>,
+}
+
+impl EventHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            events: VecDeque::new(),
+        }
+    }
+
+    pub(crate) fn push(&mut self, event: Processed) {
+        if self.events.len() == self.capacity {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+
+    pub fn events(&self) -> impl Iterator<Item = &Processed> {
+        self.events.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    // events a message of which carries "value" in its body, ids aside.
+    pub fn mentioning(&self, value: &Value) -> Vec<&Processed> {
+        self.filter(|message| {
+            let Ok(Value::Object(mut body)) = serde_json::to_value(&message.body) else {
+                return false;
+            };
+            body.remove("msg_id");
+            body.remove("in_reply_to");
+            body.values().any(|field| contains(field, value))
+        })
+    }
+
+    // events with a message from or to "peer".
+    pub fn with_peer(&self, peer: &str) -> Vec<&Processed> {
+        self.filter(|message| message.src.as_str() == peer || message.dest.as_str() == peer)
+    }
+
+    pub fn to_json(&self) -> Value {
+        serde_json::to_value(&self.events).expect("History should serialize.")
+    }
+
+    fn filter(&self, matches: impl Fn(&Message) -> bool) -> Vec<&Processed> {
+        self.events
+            .iter()
+            .filter(|event| event.messages().any(&matches))
+            .collect()
+    }
+}
+
+fn contains(field: &Value, value: &Value) -> bool {
+    match field {
+        Value::Array(items) => items.iter().any(|item| contains(item, value)),
+        Value::Object(fields) => fields.values().any(|item| contains(item, value)),
+        field => field == value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::Workload;
+    use crate::testing::message::msg;
+    use serde_json::json;
+
+    fn event(at: u64, received: Message, sent: Vec<Message>) -> Processed {
+        Processed {
+            at,
+            handler: received.body.name().to_owned(),
+            received: Some(received),
+            sent,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_event_history() {
+        let mut history = EventHistory::new(2);
+        let gossip = Workload::Broadcast {
+            msg_id: 42,
+            message: 7,
+            hops: Some(1),
+        };
+        history.push(event(1, msg().id(42).broadcast(1), vec![]));
+        history.push(event(2, msg().to("n1").broadcast(42), vec![]));
+        history.push(event(3, msg().to("n1").read(), vec![]));
+        history.push(event(
+            4,
+            msg().from("n2").id(3).body(gossip),
+            vec![msg().from("n1").to("n3").broadcast(7)],
+        ));
+
+        // the oldest are gone.
+        assert_eq!(history.len(), 2);
+        let at: Vec<u64> = history.events().map(|event| event.at).collect();
+        assert_eq!(at, vec![3, 4]);
+        // a msg_id of 42 isn't a mention of 42.
+        assert!(history.mentioning(&json!(42)).is_empty());
+        assert_eq!(history.mentioning(&json!(7))[0].at, 4);
+        assert_eq!(history.with_peer("n3").len(), 1);
+        assert_eq!(history.with_peer("c1").len(), 1);
+        assert_eq!(history.to_json()[0]["handler"], "read");
+        assert!(history.to_json()[0].get("error").is_none());
+    }
+}
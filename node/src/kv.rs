@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+
+use crate::core::{code, CodeId, KvKey, KvValue};
+
+// json values aren't hashable, so keys are stored by their json encoding.
+#[derive(Debug, Default, Clone)]
+pub struct Kv {
+    data: HashMap<String, KvValue>,
+}
+
+impl Kv {
+    pub fn read(&self, key: &KvKey) -> Result<KvValue, (CodeId, String)> {
+        self.data.get(&key.to_string()).cloned().ok_or_else(|| {
+            (
+                code::KEY_DOES_NOT_EXIST,
+                format!("key {key} does not exist"),
+            )
+        })
+    }
+
+    pub fn write(&mut self, key: &KvKey, value: KvValue) {
+        self.data.insert(key.to_string(), value);
+    }
+
+    pub fn cas(
+        &mut self,
+        key: &KvKey,
+        from: &KvValue,
+        to: KvValue,
+        create_if_not_exists: bool,
+    ) -> Result<(), (CodeId, String)> {
+        match self.data.get_mut(&key.to_string()) {
+            Some(current) if current == from => {
+                *current = to;
+                Ok(())
+            }
+            Some(current) => Err((
+                code::PRECONDITION_FAILED,
+                format!("expected {from}, but had {current}"),
+            )),
+            None if create_if_not_exists => {
+                self.write(key, to);
+                Ok(())
+            }
+            None => Err((
+                code::KEY_DOES_NOT_EXIST,
+                format!("key {key} does not exist"),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kv_read_write() {
+        let mut kv = Kv::default();
+        assert_eq!(kv.read(&1.into()).unwrap_err().0, code::KEY_DOES_NOT_EXIST);
+        kv.write(&1.into(), 5.into());
+        assert_eq!(kv.read(&1.into()), Ok(5.into()));
+        // "1" and 1 are different keys.
+        assert!(kv.read(&"1".into()).is_err());
+    }
+
+    #[test]
+    fn test_kv_cas() {
+        let mut kv = Kv::default();
+        let key = 1.into();
+        assert_eq!(
+            kv.cas(&key, &0.into(), 1.into(), false).unwrap_err().0,
+            code::KEY_DOES_NOT_EXIST
+        );
+        assert!(kv.cas(&key, &0.into(), 1.into(), true).is_ok());
+        assert_eq!(
+            kv.cas(&key, &0.into(), 2.into(), false).unwrap_err().0,
+            code::PRECONDITION_FAILED
+        );
+        assert!(kv.cas(&key, &1.into(), 2.into(), false).is_ok());
+        assert_eq!(kv.read(&key), Ok(2.into()));
+    }
+}
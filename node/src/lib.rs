@@ -1,44 +1,1029 @@
-use crate::core::{Message, Node};
-use std::io::{stdin, stdout, Stdin, Stdout, Write};
+use crate::config::Config;
+use crate::core::{Message, Node, Replies, Type};
+use crate::helper::{ErrorContext, Result};
+use crate::logging::{LogFormat, Verbosity};
+use crate::metrics::{HandlerLatencies, Profile, QueueDepth};
+use crate::outbox::Outbox;
+use crate::record::Recorder;
+use crate::shards::shard;
+use crate::split::{LineSplitter, READ_CHUNK};
+use crate::viz::{GossipTrace, TraceFormat};
+use signal_hook::consts::{SIGINT, SIGTERM};
+use std::io::{stdin, stdout, BufRead, BufReader, Stdin, Stdout, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
 
+#[cfg(any(test, feature = "async"))]
+pub mod async_runner;
+
+pub(crate) mod actor;
+pub mod clock;
+pub mod config;
 pub mod core;
+pub mod crdt;
+pub mod detector;
+pub mod election;
+pub(crate) mod flow;
+pub(crate) mod gossip;
+pub(crate) mod handoff;
 pub mod helper;
+pub mod history;
+pub mod kv;
+pub mod logging;
+pub mod logs;
+pub mod memory;
+pub mod metrics;
+pub mod node_id;
+pub mod outbox;
+pub(crate) mod persist;
+pub mod prelude;
+pub mod quorum;
+pub mod raft;
+pub mod record;
+pub(crate) mod reply_cache;
+pub mod retry;
+pub mod rng;
+pub(crate) mod rpc;
+pub mod sequencer;
+pub mod session;
+pub(crate) mod shards;
+pub(crate) mod split;
+pub mod storage;
+pub mod tcp;
+#[cfg(feature = "bench")]
+pub mod template;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod throttle;
+pub mod topology;
+pub mod txn;
+pub mod uid;
+pub mod vclock;
+pub mod viz;
+
+// parses one line of input (trailing newline included), shared by the runners.
+// a failure carries the line along, see "ErrorContext".
+pub(crate) fn parse_line(line: &mut [u8]) -> Result<Message> {
+    parse(line).map_err(|e| {
+        let input = String::from_utf8_lossy(line).trim_end().to_owned();
+        ErrorContext::wrap(e, None, input) as Box<dyn std::error::Error>
+    })
+}
 
-pub struct Runner {
+#[cfg(not(feature = "simd-json"))]
+fn parse(line: &mut [u8]) -> Result<Message> {
+    Ok(serde_json::from_slice::<Message>(line)?)
+}
+
+// simd-json parses in place, a line it failed on may come out partly rewritten.
+#[cfg(feature = "simd-json")]
+fn parse(line: &mut [u8]) -> Result<Message> {
+    Ok(simd_json::serde::from_slice::<Message>(line)?)
+}
+
+// writes one reply (without the newline), shared by the runners.
+#[cfg(not(feature = "bench"))]
+pub(crate) fn write_message(output: &mut Vec<u8>, message: &Message) -> serde_json::Result<()> {
+    serde_json::to_writer(output, message)
+}
+
+#[cfg(feature = "bench")]
+pub(crate) use template::write_message;
+
+// input is read on a dedicated thread and output written on another one, connected to the
+// processing stage (which owns the node) by channels. a slow handler doesn't stall reading,
+// and all writes are serialized by the writer thread.
+// STDIN/STDOUT by default, any newline-delimited reader/writer pair works (pipes, files, ...).
+// the node is ticked every "tick_interval", whether input arrives or not.
+// both channels hold at most "queue_capacity" messages. when processing falls behind, the
+// reader blocks and stops draining the input, pushing back on whoever writes to it (maelstrom
+// waits on the pipe). likewise a slow output blocks the processing stage.
+pub struct Runner<R = BufReader<Stdin>, W = Stdout> {
     node: Node,
-    stdin: Stdin,
-    stdout: Stdout,
+    io: Option<(R, W)>,
+    tick_interval: Duration,
+    queue_capacity: usize,
+    inbound: Arc<QueueDepth>,
+    outbound: Arc<QueueDepth>,
+    recorder: Option<Recorder>,
+    // gossip sent, with when the trace started.
+    gossip_trace: Option<(GossipTrace, Instant)>,
+    stop: Arc<AtomicBool>,
+    drain_timeout: Duration,
+    batch_size: usize,
+    batch_delay: Duration,
+    profile: Option<Profile>,
+    latencies: Option<HandlerLatencies>,
+    // how often the message counters are logged, from a thread of their own.
+    counter_reports: Option<Duration>,
+    verbosity: Verbosity,
+    log_format: LogFormat,
+    // worker threads handling the input by source, see "with_source_shards".
+    shards: Option<usize>,
 }
 
 impl Runner {
     pub fn new(node: Node) -> Self {
+        Runner::with_io(
+            node,
+            BufReader::with_capacity(READ_CHUNK, stdin()),
+            stdout(),
+        )
+    }
+}
+
+impl<R, W> Runner<R, W>
+where
+    R: BufRead + Send + 'static,
+    W: Write + Send + 'static,
+{
+    pub fn with_io(node: Node, reader: R, writer: W) -> Self {
         Self {
             node,
-            stdin: stdin(),
-            stdout: stdout(),
+            io: Some((reader, writer)),
+            tick_interval: Duration::from_millis(100),
+            queue_capacity: 1024,
+            inbound: Arc::default(),
+            outbound: Arc::default(),
+            recorder: None,
+            gossip_trace: None,
+            stop: Arc::default(),
+            drain_timeout: Duration::from_secs(1),
+            batch_size: 64,
+            batch_delay: Duration::ZERO,
+            profile: None,
+            latencies: None,
+            counter_reports: None,
+            verbosity: Verbosity::default(),
+            log_format: LogFormat::default(),
+            shards: None,
         }
     }
 
+    // what gets logged to STDERR, unless "RUST_LOG" says otherwise.
+    pub fn with_verbosity(mut self, verbosity: Verbosity) -> Self {
+        self.verbosity = verbosity;
+        self
+    }
+
+    // JSON lines instead of text on STDERR, see "LogFormat".
+    pub fn with_log_format(mut self, log_format: LogFormat) -> Self {
+        self.log_format = log_format;
+        self
+    }
+
+    // measures how long each message takes to process, and logs throughput and latency
+    // every "interval".
+    pub fn with_profiling(mut self, interval: Duration) -> Self {
+        self.profile = Some(Profile::new(interval, Instant::now()));
+        self
+    }
+
+    // times every handler, and logs p50/p99 per message type every "interval". replies to rpcs
+    // are timed too, under their own type ("read_ok", ...), along with the callback they ran.
+    pub fn with_handler_latencies(mut self, interval: Duration) -> Self {
+        self.latencies = Some(HandlerLatencies::new(interval, Instant::now()));
+        self
+    }
+
+    // logs the node's message counters (see "Node::counters") every "interval".
+    pub fn with_counter_reports(mut self, interval: Duration) -> Self {
+        self.counter_reports = Some(interval);
+        self
+    }
+
+    // output is written once "size" messages are buffered or "delay" passed since the first
+    // one. with no delay (the default), whatever is queued gets written without waiting.
+    pub fn with_write_batching(mut self, size: usize, delay: Duration) -> Self {
+        self.batch_size = size.max(1);
+        self.batch_delay = delay;
+        self
+    }
+
+    // the runner's part of "Config": its "batch_window" delays the output, see
+    // "with_write_batching". the node gets the rest with "Node::configure".
+    pub fn with_config(self, config: &Config) -> Self {
+        let size = self.batch_size;
+        self.with_write_batching(size, config.batch_window)
+    }
+
+    pub fn with_drain_timeout(mut self, drain_timeout: Duration) -> Self {
+        self.drain_timeout = drain_timeout;
+        self
+    }
+
+    // setting the flag has the same effect as SIGTERM.
+    pub fn stop_handle(&self) -> Arc<AtomicBool> {
+        self.stop.clone()
+    }
+
+    // records every message received and sent, and every tick, see "record::replay".
+    pub fn with_recording<F: Write + Send + 'static>(mut self, recording: F) -> Self {
+        self.recorder = Some(Recorder::new(recording, self.node.now()));
+        self
+    }
+
+    // writes the gossip the node sends to "output", see "viz::GossipTrace". "at_ms" counts from
+    // when the runner was built.
+    pub fn with_gossip_trace<F: Write + Send + 'static>(
+        mut self,
+        output: F,
+        format: TraceFormat,
+    ) -> Self {
+        self.gossip_trace = Some((GossipTrace::new(output, format), Instant::now()));
+        self
+    }
+
+    pub fn with_queue_capacity(mut self, queue_capacity: usize) -> Self {
+        self.queue_capacity = queue_capacity;
+        self
+    }
+
+    // input is handled by "workers" threads, each with a queue of its own, and every source
+    // ("src") sticks to one worker: its messages are handled in order, while a client isn't
+    // queued behind the gossip its node got just before. the processing thread parses the
+    // input, hands it out and ticks. handlers take the whole node, each holds it (under a lock)
+    // only while it runs.
+    pub fn with_source_shards(mut self, workers: usize) -> Self {
+        self.shards = Some(workers.max(1));
+        self
+    }
+
+    pub fn with_tick_interval(mut self, tick_interval: Duration) -> Self {
+        self.tick_interval = tick_interval;
+        self
+    }
+
+    pub fn node(&self) -> &Node {
+        &self.node
+    }
+
+    // lines read but not processed yet.
+    pub fn inbound_depth(&self) -> &QueueDepth {
+        &self.inbound
+    }
+
+    // batches of replies produced but not written yet.
+    pub fn outbound_depth(&self) -> &QueueDepth {
+        &self.outbound
+    }
+
+    // runs until the input is closed (or fails), or SIGTERM/SIGINT arrives, then shuts the node down.
+    // on a signal, new requests are no longer accepted but replies to pending rpcs and to
+    // messages in the outbox (see "Node::send_reliably", retried meanwhile) still are, until
+    // none is left or "drain_timeout" passes. shutdown hooks run and the output is
+    // flushed in both cases. a second signal while draining exits the process right away.
     pub fn start(&mut self) {
-        let mut buffer = String::new();
-        while let Ok(_) = self.stdin.read_line(&mut buffer) {
-            let reply = serde_json::from_str::<Message>(buffer.trim_end())
-                .map_err(|error| error.into())
-                .and_then(|message| self.node.process(message))
-                .map(|replies| replies.iter().for_each(|reply| self.write(reply)));
+        logging::init(self.verbosity, self.log_format);
+        let (input, mut output) = self.io.take().expect("Runner can only be started once.");
+        // the handlers are removed on return, runners started one after another (e.g. in
+        // tests) don't pile them up.
+        let mut signals = Vec::new();
+        for signal in [SIGTERM, SIGINT] {
+            // registered first, it sees the flag as the previous signal left it.
+            let shutdown =
+                signal_hook::flag::register_conditional_shutdown(signal, 1, self.stop.clone());
+            let flag = signal_hook::flag::register(signal, self.stop.clone());
+            signals.extend(
+                [shutdown, flag].map(|id| id.expect("Signal handler should be registered.")),
+            );
+        }
+
+        let (line_tx, line_rx) = mpsc::sync_channel::<Vec<u8>>(self.queue_capacity);
+        // line buffers go back to the reader once parsed, so it doesn't allocate per line.
+        let (free_tx, free_rx) = mpsc::sync_channel::<Vec<u8>>(self.queue_capacity);
+        let lines = Inbound {
+            rx: line_rx,
+            free: free_tx,
+        };
+        // replies travel unserialized, the writer thread pays for serde, not the handlers.
+        let (out_tx, out_rx) = mpsc::sync_channel::<Replies>(self.queue_capacity);
+        let out = Outbound {
+            tx: out_tx,
+            depth: self.outbound.clone(),
+        };
+
+        let inbound = self.inbound.clone();
+        let reader = thread::spawn(move || {
+            // EOF (maelstrom is done with us) or a read error ends the input.
+            let mut input = LineSplitter::new(input);
+            let buffer = || free_rx.try_recv().unwrap_or_default();
+            let line = |line| {
+                inbound.push();
+                line_tx.send(line).is_ok()
+            };
+            while input.next_chunk(buffer, line) {}
+        });
+
+        let outbound = self.outbound.clone();
+        let (batch_size, batch_delay) = (self.batch_size, self.batch_delay);
+        let writer = thread::spawn(move || {
+            // replies are serialized into "batch" and written with a single call once
+            // "batch_size" messages are buffered, "batch_delay" passed since the first one,
+            // or (without a delay) the queue runs dry.
+            let mut batch = Vec::new();
+            let mut buffered = 0;
+            let mut deadline: Option<Instant> = None;
+            loop {
+                let next = match deadline {
+                    None => out_rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+                    Some(deadline) => {
+                        out_rx.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                    }
+                };
+                match next {
+                    // without a delay, what's queued already goes out in the same write.
+                    Ok(replies) if batch_delay.is_zero() => {
+                        buffered += Self::serialize(&mut batch, &replies, &outbound);
+                        while buffered < batch_size {
+                            match out_rx.try_recv() {
+                                Ok(replies) => {
+                                    buffered += Self::serialize(&mut batch, &replies, &outbound)
+                                }
+                                Err(_) => break,
+                            }
+                        }
+                    }
+                    Ok(replies) => {
+                        buffered += Self::serialize(&mut batch, &replies, &outbound);
+                        let deadline = *deadline.get_or_insert(Instant::now() + batch_delay);
+                        if buffered < batch_size && Instant::now() < deadline {
+                            continue;
+                        }
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+                Self::write_batch(&mut output, &mut batch);
+                buffered = 0;
+                deadline = None;
+            }
+            Self::write_batch(&mut output, &mut batch);
+        });
 
-            if reply.is_err() {
-                let e = reply.unwrap_err();
-                eprintln!("{e}");
+        // the counters are shared with the node, reporting them doesn't hold up processing.
+        // the thread ends when "reporting" is dropped.
+        let (reporting, reports) = mpsc::channel::<()>();
+        let reporter = self.counter_reports.map(|interval| {
+            let counters = self.node.shared_counters();
+            thread::spawn(move || {
+                while let Err(RecvTimeoutError::Timeout) = reports.recv_timeout(interval) {
+                    info!("{}", counters.report());
+                }
+            })
+        });
+
+        let mut next_tick = Instant::now() + self.tick_interval;
+        let stop = self.stop.clone();
+        let mut eof = self.pump(
+            &lines,
+            &out,
+            &mut next_tick,
+            |_| true,
+            |_| stop.load(Ordering::Relaxed),
+        );
+        if !eof {
+            let deadline = Instant::now() + self.drain_timeout;
+            eof = self.pump(
+                &lines,
+                &out,
+                &mut next_tick,
+                |message| message.body.in_reply_to().is_some(),
+                |node| {
+                    let drained = node.outbox().is_none_or(Outbox::is_empty);
+                    (node.pending_rpcs() == 0 && drained) || Instant::now() >= deadline
+                },
+            );
+        }
+
+        self.node.shutdown();
+        drop(reporting);
+        if let Some(reporter) = reporter {
+            reporter.join().expect("Reporter thread should not panic.");
+        }
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.flush();
+        }
+        drop(self.gossip_trace.take());
+        drop(out);
+        writer.join().expect("Writer thread should not panic.");
+        // after a signal the reader is likely blocked on input that will never come.
+        drop(lines);
+        if eof {
+            reader.join().expect("Reader thread should not panic.");
+        }
+        for id in signals {
+            signal_hook::low_level::unregister(id);
+        }
+    }
+
+    // handles input and ticks until "done", returns true if the input ended first.
+    // input that isn't "accept"ed is dropped.
+    fn pump<A, D>(
+        &mut self,
+        lines: &Inbound,
+        out: &Outbound,
+        next_tick: &mut Instant,
+        accept: A,
+        done: D,
+    ) -> bool
+    where
+        A: Fn(&Message) -> bool + Sync,
+        D: Fn(&Node) -> bool,
+    {
+        if let Some(workers) = self.shards {
+            return self.sharded(workers, lines, out, next_tick, accept, done);
+        }
+        while !done(&self.node) {
+            // wait for input, but no longer than the next tick is due.
+            let timeout = next_tick.saturating_duration_since(Instant::now());
+            match lines.rx.recv_timeout(timeout) {
+                Ok(mut line) => {
+                    self.inbound.pop();
+                    let started = Instant::now();
+                    let replies = self.process(&mut line, &accept);
+                    if let Some(profile) = self.profile.as_mut() {
+                        profile.record(started.elapsed());
+                    }
+                    out.emit(replies);
+                    line.clear();
+                    let _ = lines.free.try_send(line); // the reader has enough spares otherwise.
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return true,
             }
-            buffer.clear();
+            self.housekeep(out, next_tick);
         }
+        false
     }
 
-    fn write(&mut self, message: &Message) {
-        let reply =
-            serde_json::to_string(message).expect("Interpreter should serialize the message.");
-        let mut lock = self.stdout.lock();
-        writeln!(lock, "{}", reply).expect("A message should be written to STDOUT.");
+    // "pump" with "workers" threads, see "with_source_shards". the runner is shared with them
+    // behind a lock, and the workers finish what's queued for them before this returns.
+    fn sharded<A, D>(
+        &mut self,
+        workers: usize,
+        lines: &Inbound,
+        out: &Outbound,
+        next_tick: &mut Instant,
+        accept: A,
+        done: D,
+    ) -> bool
+    where
+        A: Fn(&Message) -> bool + Sync,
+        D: Fn(&Node) -> bool,
+    {
+        let (inbound, capacity) = (self.inbound.clone(), self.queue_capacity);
+        let runner = Mutex::new(self);
+        thread::scope(|scope| {
+            let shards: Vec<SyncSender<Message>> = (0..workers)
+                .map(|_| {
+                    let (tx, rx) = mpsc::sync_channel::<Message>(capacity);
+                    let (runner, accept) = (&runner, &accept);
+                    scope.spawn(move || {
+                        for message in rx {
+                            let mut runner = locked(runner);
+                            let started = Instant::now();
+                            let replies = runner.handle(message, accept);
+                            if let Some(profile) = runner.profile.as_mut() {
+                                profile.record(started.elapsed());
+                            }
+                            drop(runner);
+                            out.emit(replies);
+                        }
+                    });
+                    tx
+                })
+                .collect();
+
+            while !done(&locked(&runner).node) {
+                let timeout = next_tick.saturating_duration_since(Instant::now());
+                match lines.rx.recv_timeout(timeout) {
+                    Ok(mut line) => {
+                        inbound.pop();
+                        let message = parse_line(&mut line)
+                            .inspect_err(|e| warn!(error = %e, "unparsable input"));
+                        match message {
+                            // handled right away, what comes after it can't beat it to a worker.
+                            Ok(message) if message.body.key() == Type::Init => {
+                                let replies = locked(&runner).handle(message, &accept);
+                                out.emit(replies);
+                            }
+                            Ok(message) => shards[shard(&message.src, workers)]
+                                .send(message)
+                                .expect("Workers should outlive the processing stage."),
+                            Err(_) => {}
+                        }
+                        line.clear();
+                        let _ = lines.free.try_send(line);
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => return true,
+                }
+                locked(&runner).housekeep(out, next_tick);
+            }
+            false
+        })
+    }
+
+    // ticks the node, and logs the reports, once "next_tick" is due.
+    fn housekeep(&mut self, out: &Outbound, next_tick: &mut Instant) {
+        let now = Instant::now();
+        if now < *next_tick {
+            return;
+        }
+        *next_tick = now + self.tick_interval;
+        out.emit(self.tick(now));
+        if let Some(report) = self.profile.as_mut().and_then(|p| p.report(now)) {
+            info!("{report}");
+        }
+        if let Some(report) = self.latencies.as_mut().and_then(|l| l.report(now)) {
+            info!("{report}");
+        }
+    }
+
+    // appends "replies" to "batch", one per line, now that they're off the queue.
+    fn serialize(batch: &mut Vec<u8>, replies: &Replies, outbound: &QueueDepth) -> usize {
+        outbound.pop();
+        for reply in replies {
+            write_message(batch, reply).expect("Interpreter should serialize the message.");
+            batch.push(b'\n');
+        }
+        replies.len()
+    }
+
+    fn write_batch(output: &mut W, batch: &mut Vec<u8>) {
+        if !batch.is_empty() {
+            output
+                .write_all(batch)
+                .expect("Messages should be written to output.");
+            batch.clear();
+        }
+        output.flush().expect("Output should be flushed.");
+    }
+
+    fn process<A>(&mut self, line: &mut [u8], accept: A) -> Result<Replies>
+    where
+        A: Fn(&Message) -> bool,
+    {
+        let message = parse_line(line).inspect_err(|e| warn!(error = %e, "unparsable input"))?;
+        self.handle(message, accept)
+    }
+
+    fn handle<A>(&mut self, message: Message, accept: A) -> Result<Replies>
+    where
+        A: Fn(&Message) -> bool,
+    {
+        if !accept(&message) {
+            return Ok(Replies::new());
+        }
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.received(self.node.now(), &message);
+        }
+        let key = message.body.key();
+        let started = Instant::now();
+        let replies = self.node.process(message);
+        if let Some(latencies) = self.latencies.as_mut() {
+            latencies.record(key, started.elapsed());
+        }
+        let replies = replies?;
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.sent(self.node.now(), &replies);
+        }
+        self.trace(&replies);
+        Ok(replies)
+    }
+
+    fn tick(&mut self, now: Instant) -> Result<Replies> {
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.tick(now);
+        }
+        let replies = self.node.tick(now)?;
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.sent(now, &replies);
+        }
+        self.trace(&replies);
+        Ok(replies)
+    }
+
+    fn trace(&mut self, replies: &Replies) {
+        if let Some((trace, started)) = self.gossip_trace.as_mut() {
+            trace.sent(started.elapsed().as_millis() as u64, replies);
+        }
+    }
+}
+
+// the runner, as the workers of "with_source_shards" share it.
+fn locked<T>(runner: &Mutex<T>) -> MutexGuard<'_, T> {
+    runner.lock().expect("Runner should not be poisoned.")
+}
+
+// receiving half of the reader -> processing channel.
+struct Inbound {
+    rx: Receiver<Vec<u8>>,
+    free: SyncSender<Vec<u8>>,
+}
+
+// sending half of the processing -> writer channel.
+struct Outbound {
+    tx: SyncSender<Replies>,
+    depth: Arc<QueueDepth>,
+}
+
+impl Outbound {
+    // all replies of one handler (or tick) go out as one batch.
+    // blocks while the writer is "capacity" batches behind.
+    fn emit(&self, replies: Result<Replies>) {
+        match replies {
+            Ok(replies) if replies.is_empty() => {}
+            Ok(replies) => {
+                self.depth.push();
+                self.tx
+                    .send(replies)
+                    .expect("Writer thread should outlive the processing stage.");
+            }
+            Err(_) => {} // already traced by whoever failed.
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{Handler, MessageId, Sink, Type, Workload};
+    use crate::record::{replay, Event};
+    use std::collections::{HashMap, HashSet};
+    use std::io::Cursor;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::{Arc, Mutex};
+
+    // cloneable in-memory writer, so the test can look at what the writer thread wrote,
+    // and how many writes it took.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>, Arc<Mutex<usize>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            *self.1.lock().unwrap() += 1;
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_parse_line_context() {
+        let error = parse_line(&mut b"{\"src\":\"c1\"}\n".to_vec()).unwrap_err();
+        assert!(error.to_string().starts_with("error=\""));
+        assert!(error.to_string().ends_with(r#" input={"src":"c1"}"#));
+    }
+
+    #[test]
+    fn test_runner_in_memory_io() {
+        let input = concat!(
+            r#"{"src":"c1","dest":"n1","body":{"type":"init","msg_id":1,"node_id":"n1","node_ids":["n1"]}}"#,
+            "\n",
+            "not json\n",
+            r#"{"src":"c1","dest":"n1","body":{"type":"init","msg_id":2,"node_id":"n1","node_ids":["n1"]}}"#,
+            "\n",
+        );
+        let output = SharedBuffer::default();
+        let mut runner = Runner::with_io(Node::default(), Cursor::new(input), output.clone());
+        runner.start(); // returns on EOF.
+
+        let output = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(
+            output,
+            "{\"src\":\"n1\",\"dest\":\"c1\",\"body\":{\"type\":\"init_ok\",\"in_reply_to\":1}}\n"
+        );
+        assert_eq!(runner.node().node_id(), "n1");
+    }
+
+    #[test]
+    fn test_runner_bounded_queues() {
+        let mut input = String::from(
+            r#"{"src":"c1","dest":"n1","body":{"type":"init","msg_id":1,"node_id":"n1","node_ids":["n1"]}}"#,
+        );
+        for msg_id in 2..=50 {
+            input.push_str(&format!(
+                "\n{{\"src\":\"c1\",\"dest\":\"n1\",\"body\":{{\"type\":\"echo\",\"msg_id\":{msg_id},\"echo\":\"hi\"}}}}"
+            ));
+        }
+        let echo = |node: &mut Node, msg: Message, out: &mut dyn Sink| match msg.body {
+            Workload::Echo { msg_id, echo } => {
+                let body = Workload::echo_ok(msg_id, node.gen_msg_id(), echo);
+                out.send(node.reply(msg.src, body));
+                Ok(())
+            }
+            _ => unreachable!(),
+        };
+        let node = Node::new(HashMap::from([(Type::Echo, echo as Handler)]));
+        let output = SharedBuffer::default();
+        let mut runner = Runner::with_io(node, Cursor::new(input), output.clone())
+            .with_queue_capacity(2)
+            .with_write_batching(20, Duration::from_secs(60));
+        runner.start();
+
+        // nothing got lost, and the reader never ran more than a queue ahead.
+        let output_writes = output.1.lock().unwrap();
+        let output = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(output.lines().count(), 50);
+        assert_eq!(*output_writes, 3); // batches of 20, 20, and the rest on EOF.
+        assert!(runner.inbound_depth().peak() <= 4);
+        assert!(runner.outbound_depth().peak() <= 4);
+        assert_eq!(runner.inbound_depth().current(), 0);
+    }
+
+    #[test]
+    fn test_runner_source_shards() {
+        let mut input = String::from(
+            r#"{"src":"c1","dest":"n1","body":{"type":"init","msg_id":1,"node_id":"n1","node_ids":["n1"]}}"#,
+        );
+        let sources = ["c2", "c3", "n2", "n3", "n4", "n5"];
+        for msg_id in 2..=40 {
+            let src = sources[msg_id % sources.len()];
+            input.push_str(&format!(
+                "\n{{\"src\":\"{src}\",\"dest\":\"n1\",\"body\":{{\"type\":\"echo\",\"msg_id\":{msg_id},\"echo\":\"hi\"}}}}"
+            ));
+        }
+        input.push_str("\nnot json");
+        static THREADS: Mutex<Vec<thread::ThreadId>> = Mutex::new(Vec::new());
+        let echo = |node: &mut Node, msg: Message, out: &mut dyn Sink| match msg.body {
+            Workload::Echo { msg_id, echo } => {
+                THREADS.lock().unwrap().push(thread::current().id());
+                let body = Workload::echo_ok(msg_id, node.gen_msg_id(), echo);
+                out.send(node.reply(msg.src, body));
+                Ok(())
+            }
+            _ => unreachable!(),
+        };
+        let node = Node::new(HashMap::from([(Type::Echo, echo as Handler)]));
+        let output = SharedBuffer::default();
+        let mut runner = Runner::with_io(node, Cursor::new(input), output.clone())
+            .with_queue_capacity(8)
+            .with_source_shards(3);
+        runner.start();
+
+        // every source got all its replies, in the order it asked.
+        let output = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        let replies: Vec<Message> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(replies.len(), 40);
+        for dest in sources {
+            let answered: Vec<MessageId> = replies
+                .iter()
+                .filter(|reply| reply.dest == dest)
+                .filter_map(|reply| reply.body.in_reply_to())
+                .collect();
+            assert!(answered.windows(2).all(|pair| pair[0] < pair[1]));
+        }
+        assert_eq!(runner.inbound_depth().current(), 0);
+        // handled by the workers, not the processing thread.
+        let threads: HashSet<_> = THREADS.lock().unwrap().iter().copied().collect();
+        assert!(threads.len() > 1);
+        assert!(!threads.contains(&thread::current().id()));
+    }
+
+    #[test]
+    fn test_runner_record_and_replay() {
+        let input = concat!(
+            r#"{"src":"c1","dest":"n1","body":{"type":"init","msg_id":1,"node_id":"n1","node_ids":["n1"]}}"#,
+            "\n",
+            r#"{"src":"c1","dest":"n1","body":{"type":"init","msg_id":2,"node_id":"n1","node_ids":["n1"]}}"#,
+            "\n",
+        );
+        let recording = SharedBuffer::default();
+        let mut runner =
+            Runner::with_io(Node::default(), Cursor::new(input), SharedBuffer::default())
+                .with_recording(recording.clone());
+        runner.start();
+
+        let recording = recording.0.lock().unwrap().clone();
+        let events = recording
+            .lines()
+            .map(|line| serde_json::from_str::<Event>(&line.unwrap()).unwrap())
+            .collect::<Vec<_>>();
+        let recorded_sent = events
+            .iter()
+            .filter_map(|event| match event {
+                Event::Sent { message, .. } => Some(message.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(recorded_sent.len(), 1);
+        assert_eq!(
+            events
+                .iter()
+                .filter(|event| matches!(event, Event::Received { .. }))
+                .count(),
+            2
+        );
+
+        let replayed_sent = replay(&mut Node::default(), Cursor::new(recording)).unwrap();
+        assert_eq!(replayed_sent, recorded_sent);
+    }
+
+    #[test]
+    fn test_replay_follows_recorded_times() {
+        // an rpc sent 5s into the run, timing out after 1s: a tick at 5.5s is too early for
+        // it, however late the replay gets to that tick.
+        fn forward(node: &mut Node, _: Message, out: &mut dyn Sink) -> Result<()> {
+            let body = Workload::Echo {
+                msg_id: node.gen_msg_id(),
+                echo: "hi".to_owned(),
+            };
+            let timeout = Duration::from_secs(1);
+            let request = node.rpc_with_timeout("n2".into(), body, timeout, |_, reply| {
+                Ok(Replies::from_iter([reply]))
+            })?;
+            out.send(request);
+            Ok(())
+        }
+        let recording = [
+            r#"{"event":"received","at":0,"message":{"src":"c1","dest":"n1","body":{"type":"init","msg_id":1,"node_id":"n1","node_ids":["n1","n2"]}}}"#,
+            r#"{"event":"received","at":5000000,"message":{"src":"c1","dest":"n1","body":{"type":"echo","msg_id":2,"echo":"hi"}}}"#,
+            r#"{"event":"tick","at":5500000}"#,
+            r#"{"event":"tick","at":6500000}"#,
+        ];
+        let replayed = |events: usize| {
+            let mut node = Node::new(HashMap::from([(Type::Echo, forward as Handler)]));
+            let recording = recording[..events].join("\n");
+            replay(&mut node, Cursor::new(recording)).unwrap()
+        };
+        // "init_ok" and the rpc, still waiting for its reply.
+        assert_eq!(replayed(3).len(), 2);
+        let sent = replayed(4);
+        assert_eq!(sent.len(), 3);
+        assert!(matches!(
+            sent[2].body,
+            Workload::Error {
+                code: crate::core::code::TIMEOUT,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_runner_stop_drains_and_flushes() {
+        // input never ends, like maelstrom's pipe when the node gets killed.
+        let input = QuietInput {
+            input: Cursor::new(concat!(
+                r#"{"src":"c1","dest":"n1","body":{"type":"init","msg_id":1,"node_id":"n1","node_ids":["n1","n2"]}}"#,
+                "\n",
+            )),
+            quiet: Duration::from_secs(3600),
+        };
+        let mut node = Node::default();
+        node.add_shutdown_hook(|node| {
+            node.push_broadcast_message(1).unwrap();
+        });
+        let output = SharedBuffer::default();
+        let mut runner = Runner::with_io(node, BufReader::new(input), output.clone())
+            .with_tick_interval(Duration::from_millis(10))
+            .with_drain_timeout(Duration::from_millis(50));
+
+        let stop = runner.stop_handle();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            stop.store(true, Ordering::Relaxed);
+        });
+        let started = Instant::now();
+        runner.start();
+
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(runner.node().broadcast_messages(), vec![1]);
+        let output = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        assert!(output.contains("init_ok"));
+    }
+
+    #[test]
+    fn test_runner_stop_drains_outbox() {
+        let mut node = Node::default();
+        let init = r#"{"src":"c1","dest":"n1","body":{"type":"init","msg_id":1,"node_id":"n1","node_ids":["n1","n2"]}}"#;
+        node.process(serde_json::from_str(init).unwrap()).unwrap();
+        let body = Workload::Broadcast {
+            msg_id: node.gen_msg_id(),
+            message: 7,
+            hops: None,
+        };
+        node.send_reliably("n2".into(), body).unwrap();
+        // the acknowledgment only comes in once the runner is draining.
+        let stopped = Arc::new(AtomicBool::new(false));
+        let input = AfterStop {
+            stopped: stopped.clone(),
+            input: Cursor::new(concat!(
+                r#"{"src":"n2","dest":"n1","body":{"type":"broadcast_ok","in_reply_to":1,"msg_id":4}}"#,
+                "\n",
+            )),
+        };
+        let mut runner = Runner::with_io(node, BufReader::new(input), SharedBuffer::default())
+            .with_tick_interval(Duration::from_millis(10))
+            .with_drain_timeout(Duration::from_secs(10));
+
+        let stop = runner.stop_handle();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            stop.store(true, Ordering::Relaxed);
+            stopped.store(true, Ordering::Relaxed);
+        });
+        let started = Instant::now();
+        runner.start();
+
+        // the drain waited for the acknowledgment, and not for the timeout.
+        assert!(runner.node().outbox().unwrap().is_empty());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    // stays quiet until the runner is stopped, then hands out its input a bit later, once the
+    // runner is draining, and stays quiet for good.
+    struct AfterStop {
+        stopped: Arc<AtomicBool>,
+        input: Cursor<&'static str>,
+    }
+
+    impl std::io::Read for AfterStop {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let deadline = Instant::now() + Duration::from_secs(10);
+            while !self.stopped.load(Ordering::Relaxed) && Instant::now() < deadline {
+                thread::sleep(Duration::from_millis(1));
+            }
+            if self.input.position() == 0 {
+                thread::sleep(Duration::from_millis(50));
+            }
+            let read = self.input.read(buf)?;
+            if read == 0 {
+                thread::sleep(Duration::from_secs(3600));
+            }
+            Ok(read)
+        }
+    }
+
+    // hands out its input, then stays quiet for a while before closing.
+    struct QuietInput {
+        input: Cursor<&'static str>,
+        quiet: Duration,
+    }
+
+    impl std::io::Read for QuietInput {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let read = self.input.read(buf)?;
+            if read == 0 {
+                thread::sleep(std::mem::take(&mut self.quiet));
+            }
+            Ok(read)
+        }
+    }
+
+    // hands out its input, then stays quiet until the node was ticked "ticks" times (or a
+    // generous deadline passed, so a broken runner fails the test rather than hanging it).
+    struct QuietUntilTicked {
+        input: Cursor<&'static str>,
+        ticks: &'static AtomicUsize,
+        until: usize,
+    }
+
+    impl std::io::Read for QuietUntilTicked {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let read = self.input.read(buf)?;
+            if read == 0 {
+                let deadline = Instant::now() + Duration::from_secs(10);
+                while self.ticks.load(Ordering::SeqCst) < self.until && Instant::now() < deadline {
+                    thread::sleep(Duration::from_millis(1));
+                }
+            }
+            Ok(read)
+        }
+    }
+
+    #[test]
+    fn test_runner_idle_ticks() {
+        static TICKS: AtomicUsize = AtomicUsize::new(0);
+        let input = QuietUntilTicked {
+            input: Cursor::new(concat!(
+                r#"{"src":"c1","dest":"n1","body":{"type":"init","msg_id":1,"node_id":"n1","node_ids":["n1"]}}"#,
+                "\n",
+            )),
+            ticks: &TICKS,
+            until: 3,
+        };
+        let mut node = Node::default();
+        node.add_tick_hook(|node, _| {
+            node.push_broadcast_message(node.broadcast_messages().len() as u64)?;
+            TICKS.fetch_add(1, Ordering::SeqCst);
+            Ok(Replies::new())
+        });
+        let mut runner = Runner::with_io(node, BufReader::new(input), SharedBuffer::default())
+            .with_tick_interval(Duration::from_millis(10));
+        runner.start();
+
+        // no input arrived while the reader was quiet, the node got ticked regardless.
+        assert!(runner.node().broadcast_messages().len() >= 3);
     }
 }
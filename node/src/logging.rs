@@ -0,0 +1,54 @@
+use tracing_subscriber::EnvFilter;
+
+// how much goes to STDERR, maelstrom keeps all of it for every node.
+// "RUST_LOG" takes precedence when set, e.g. "RUST_LOG=node=debug,node::raft=trace".
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Verbosity {
+    // nothing at all.
+    Off,
+    // errors, warnings and the periodic reports (profiling, ...).
+    #[default]
+    Errors,
+    // every message handled and every reply sent, on top of "Errors".
+    Messages,
+}
+
+impl Verbosity {
+    fn filter(self) -> &'static str {
+        match self {
+            Verbosity::Off => "off",
+            Verbosity::Errors => "info",
+            Verbosity::Messages => "debug",
+        }
+    }
+}
+
+// how log lines are written.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    // human readable lines.
+    #[default]
+    Text,
+    // one JSON object per line, with the fields of the event and of the message span
+    // (type, src, msg_id, ...) as keys, for post-processing node logs into timelines.
+    Json,
+}
+
+// installs a subscriber writing to STDERR (STDOUT belongs to maelstrom).
+// the runners call it on start, it's a no-op when a subscriber is already installed.
+pub fn init(verbosity: Verbosity, format: LogFormat) {
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(verbosity.filter()));
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr);
+    let _ = match format {
+        LogFormat::Text => builder.try_init(),
+        LogFormat::Json => builder
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(false)
+            .try_init(),
+    };
+}
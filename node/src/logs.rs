@@ -0,0 +1,328 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::core::{LogKey, LogMessage, Offset};
+use crate::helper::{Error, Result};
+use crate::storage::Storage;
+
+// key prefixes in the storage behind "Logs::open".
+const SEGMENTS: &str = "log/";
+const COMMITS: &str = "committed/";
+
+// append-only log for a single key, offsets are assigned in increasing order.
+#[derive(Debug, Default, Clone)]
+pub struct Log {
+    entries: Vec<(Offset, LogMessage)>,
+    next_offset: Offset,
+}
+
+impl Log {
+    pub fn append(&mut self, message: LogMessage) -> Offset {
+        let offset = self.next_offset;
+        self.entries.push((offset, message));
+        self.next_offset += 1;
+        offset
+    }
+
+    // segments are restored in any order, keep entries sorted.
+    fn insert(&mut self, offset: Offset, message: LogMessage) {
+        let at = self.entries.partition_point(|(o, _)| *o < offset);
+        if self.entries.get(at).map(|(o, _)| *o) != Some(offset) {
+            self.entries.insert(at, (offset, message));
+        }
+        self.next_offset = self.next_offset.max(offset + 1);
+    }
+
+    // entries are sorted by offset, so binary search for the first one at or after "from".
+    pub fn read_from(&self, from: Offset) -> &[(Offset, LogMessage)] {
+        let start = self.entries.partition_point(|(offset, _)| *offset < from);
+        &self.entries[start..]
+    }
+
+    pub fn next_offset(&self) -> Offset {
+        self.next_offset
+    }
+
+    // drops the entries below "offset", the next offset stays.
+    pub fn truncate(&mut self, offset: Offset) {
+        let end = self.entries.partition_point(|(o, _)| *o < offset);
+        self.entries.drain(..end);
+    }
+}
+
+// entries per segment unless set with "Logs::set_segment_size".
+pub const DEFAULT_SEGMENT_SIZE: u64 = 1024;
+
+// in memory only by default. opened on a storage, every entry and commit is written through
+// to it before it's acknowledged, and a restarted node gets them all back. entries go to
+// segments of "segment_size" offsets each, "log/<key>/<segment>" in the storage, and commits
+// to "committed/<key>". a segment entirely below the committed offset of its key is removed,
+// from memory too: the entries consumers are done with don't pile up for the whole run, but
+// polling below the committed offset no longer returns them.
+pub struct Logs {
+    logs: HashMap<LogKey, Log>,
+    committed: HashMap<LogKey, Offset>,
+    storage: Option<Box<dyn Storage>>,
+    segment_size: u64,
+    // the highest offset in every stored segment, by key.
+    segments: HashMap<LogKey, BTreeMap<u64, Offset>>,
+}
+
+impl Default for Logs {
+    fn default() -> Self {
+        Self {
+            logs: HashMap::new(),
+            committed: HashMap::new(),
+            storage: None,
+            segment_size: DEFAULT_SEGMENT_SIZE,
+            segments: HashMap::new(),
+        }
+    }
+}
+
+impl Logs {
+    // the logs "storage" holds, writing through to it from then on.
+    pub fn open(storage: impl Storage + 'static) -> Result<Self> {
+        let mut logs = Logs::default();
+        for name in storage.keys(SEGMENTS)? {
+            let Some((key, segment)) = name[SEGMENTS.len()..].rsplit_once('/') else {
+                continue;
+            };
+            let (key, segment) = (key.to_owned(), segment.parse()?);
+            for record in storage.scan(&name)? {
+                let (offset, message) = serde_json::from_slice(&record)?;
+                logs.remember(&key, segment, offset);
+                logs.logs
+                    .entry(key.clone())
+                    .or_default()
+                    .insert(offset, message);
+            }
+        }
+        for name in storage.keys(COMMITS)? {
+            if let Some(offset) = storage.get(&name)? {
+                let key = name[COMMITS.len()..].to_owned();
+                logs.commit_in_memory(key, serde_json::from_slice(&offset)?);
+            }
+        }
+        logs.storage = Some(Box::new(storage));
+        Ok(logs)
+    }
+
+    // offsets per segment from now on, segments already written keep theirs.
+    pub fn set_segment_size(&mut self, entries: u64) {
+        self.segment_size = entries.max(1);
+    }
+
+    pub fn append(&mut self, key: LogKey, message: LogMessage) -> Result<Offset> {
+        let offset = self.next_offset(&key);
+        self.insert(key, offset, message)?;
+        Ok(offset)
+    }
+
+    // an entry replicated from the owner of "key": the next offset, or one already held (a
+    // retry), which is ignored. one past the next would leave a hole, and is refused.
+    pub fn insert(&mut self, key: LogKey, offset: Offset, message: LogMessage) -> Result<()> {
+        let next = self.next_offset(&key);
+        if offset < next {
+            return Ok(());
+        }
+        if offset > next {
+            return Err(Box::new(Error::OffsetGap { next, offset }));
+        }
+        if let Some(storage) = self.storage.as_mut() {
+            let segment = offset / self.segment_size;
+            let record = serde_json::to_vec(&(offset, message))?;
+            storage.append(&format!("{SEGMENTS}{key}/{segment}"), &record)?;
+            self.remember(&key, segment, offset);
+        }
+        self.logs.entry(key).or_default().insert(offset, message);
+        Ok(())
+    }
+
+    pub fn next_offset(&self, key: &LogKey) -> Offset {
+        self.logs.get(key).map(Log::next_offset).unwrap_or(0)
+    }
+
+    pub fn poll(
+        &self,
+        offsets: &HashMap<LogKey, Offset>,
+    ) -> HashMap<LogKey, Vec<(Offset, LogMessage)>> {
+        offsets
+            .iter()
+            .filter_map(|(key, from)| {
+                self.logs
+                    .get(key)
+                    .map(|log| (key.clone(), log.read_from(*from).to_vec()))
+            })
+            .collect()
+    }
+
+    // committed offsets only ever move forward.
+    pub fn commit(&mut self, offsets: HashMap<LogKey, Offset>) -> Result<()> {
+        for (key, offset) in offsets {
+            if !self.commit_in_memory(key.clone(), offset) {
+                continue;
+            }
+            if let Some(storage) = self.storage.as_mut() {
+                storage.put(&format!("{COMMITS}{key}"), &serde_json::to_vec(&offset)?)?;
+                self.truncate(&key, offset)?;
+            }
+        }
+        Ok(())
+    }
+
+    // true if the committed offset moved.
+    fn commit_in_memory(&mut self, key: LogKey, offset: Offset) -> bool {
+        match self.committed.get(&key) {
+            Some(committed) if *committed >= offset => false,
+            _ => {
+                self.committed.insert(key, offset);
+                true
+            }
+        }
+    }
+
+    fn remember(&mut self, key: &LogKey, segment: u64, offset: Offset) {
+        let highest = self.segments.entry(key.clone()).or_default();
+        let highest = highest.entry(segment).or_insert(offset);
+        *highest = (*highest).max(offset);
+    }
+
+    // removes the segments of "key" below "committed". the last one stays whatever it holds,
+    // the next offset is recovered from it.
+    fn truncate(&mut self, key: &LogKey, committed: Offset) -> Result<()> {
+        let (Some(storage), Some(segments)) = (self.storage.as_mut(), self.segments.get_mut(key))
+        else {
+            return Ok(());
+        };
+        let done: Vec<(u64, Offset)> = segments
+            .iter()
+            .rev()
+            .skip(1)
+            .filter(|(_, highest)| **highest < committed)
+            .map(|(segment, highest)| (*segment, *highest))
+            .collect();
+        let Some(below) = done.iter().map(|(_, highest)| highest + 1).max() else {
+            return Ok(());
+        };
+        for (segment, _) in done {
+            storage.remove(&format!("{SEGMENTS}{key}/{segment}"))?;
+            segments.remove(&segment);
+        }
+        if let Some(log) = self.logs.get_mut(key) {
+            log.truncate(below);
+        }
+        Ok(())
+    }
+
+    pub fn committed(&self, keys: &[LogKey]) -> HashMap<LogKey, Offset> {
+        keys.iter()
+            .filter_map(|key| self.committed.get(key).map(|offset| (key.clone(), *offset)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_offsets_increase() {
+        let mut logs = Logs::default();
+        assert_eq!(logs.append("k1".to_owned(), 10).unwrap(), 0);
+        assert_eq!(logs.append("k1".to_owned(), 11).unwrap(), 1);
+        assert_eq!(logs.append("k2".to_owned(), 20).unwrap(), 0);
+        assert_eq!(logs.append("k1".to_owned(), 12).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_log_insert_in_order() {
+        let mut logs = Logs::default();
+        logs.insert("k1".to_owned(), 0, 10).unwrap();
+        // a hole is refused, a retry of an entry held is ignored.
+        assert!(logs.insert("k1".to_owned(), 2, 12).is_err());
+        logs.insert("k1".to_owned(), 1, 11).unwrap();
+        logs.insert("k1".to_owned(), 1, 99).unwrap();
+        logs.insert("k1".to_owned(), 2, 12).unwrap();
+        assert_eq!(logs.next_offset(&"k1".to_owned()), 3);
+
+        let polled = logs.poll(&HashMap::from([("k1".to_owned(), 0)]));
+        assert_eq!(polled["k1"], vec![(0, 10), (1, 11), (2, 12)]);
+    }
+
+    #[test]
+    fn test_log_poll() {
+        let mut logs = Logs::default();
+        for message in [10, 11, 12] {
+            logs.append("k1".to_owned(), message).unwrap();
+        }
+
+        let offsets = HashMap::from([("k1".to_owned(), 1), ("k3".to_owned(), 0)]);
+        let polled = logs.poll(&offsets);
+        assert_eq!(polled.len(), 1); // unknown key is left out.
+        assert_eq!(polled["k1"], vec![(1, 11), (2, 12)]);
+    }
+
+    #[test]
+    fn test_log_commit_is_monotonic() {
+        let mut logs = Logs::default();
+        logs.commit(HashMap::from([("k1".to_owned(), 5)])).unwrap();
+        logs.commit(HashMap::from([("k1".to_owned(), 3)])).unwrap();
+
+        let committed = logs.committed(&["k1".to_owned(), "k2".to_owned()]);
+        assert_eq!(committed, HashMap::from([("k1".to_owned(), 5)]));
+    }
+
+    #[test]
+    fn test_logs_segments() {
+        use crate::storage::MemoryStorage;
+        use std::sync::{Arc, Mutex};
+
+        let disk = Arc::new(Mutex::new(MemoryStorage::default()));
+        let mut logs = Logs::open(disk.clone()).unwrap();
+        logs.set_segment_size(2);
+        for message in [10, 11, 12, 13, 14] {
+            logs.append("k1".to_owned(), message).unwrap();
+        }
+        logs.append("k2".to_owned(), 20).unwrap();
+        let segments = || disk.keys("log/").unwrap();
+        assert_eq!(
+            segments(),
+            vec!["log/k1/0", "log/k1/1", "log/k1/2", "log/k2/0"]
+        );
+
+        // offsets 0 and 1 were consumed, 2 and 3 only in part.
+        logs.commit(HashMap::from([("k1".to_owned(), 3)])).unwrap();
+        assert_eq!(segments(), vec!["log/k1/1", "log/k1/2", "log/k2/0"]);
+        let from_start = HashMap::from([("k1".to_owned(), 0)]);
+        assert_eq!(logs.poll(&from_start)["k1"][0], (2, 12));
+        // the last segment stays, the next offset is in it.
+        logs.commit(HashMap::from([("k2".to_owned(), 9)])).unwrap();
+        assert_eq!(segments(), vec!["log/k1/1", "log/k1/2", "log/k2/0"]);
+
+        let mut logs = Logs::open(disk.clone()).unwrap();
+        assert_eq!(logs.poll(&from_start)["k1"].len(), 3);
+        assert_eq!(logs.committed(&["k1".to_owned()])["k1"], 3);
+        assert_eq!(logs.append("k2".to_owned(), 21).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_logs_reopen() {
+        use crate::storage::FileStorage;
+
+        let path = std::env::temp_dir().join(format!("logs-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut logs = Logs::open(FileStorage::open(&path).unwrap()).unwrap();
+        logs.append("k1".to_owned(), 10).unwrap();
+        logs.insert("k1".to_owned(), 1, 14).unwrap();
+        logs.commit(HashMap::from([("k1".to_owned(), 4)])).unwrap();
+        logs.commit(HashMap::from([("k1".to_owned(), 1)])).unwrap();
+        drop(logs);
+
+        let mut logs = Logs::open(FileStorage::open(&path).unwrap()).unwrap();
+        let polled = logs.poll(&HashMap::from([("k1".to_owned(), 0)]));
+        assert_eq!(polled["k1"], vec![(0, 10), (1, 14)]);
+        assert_eq!(logs.committed(&["k1".to_owned()])["k1"], 4);
+        assert_eq!(logs.append("k1".to_owned(), 15).unwrap(), 2);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
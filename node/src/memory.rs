@@ -0,0 +1,102 @@
+use std::mem::size_of;
+
+use serde::Serialize;
+
+use crate::core::{BroadcastMessage, Message, Workload};
+
+// roughly what a message kept in memory takes, its strings and lists included.
+const MESSAGE_BYTES: usize = size_of::<Message>() + 64;
+
+// what the node's growing state takes, in bytes, roughly: counted from how many entries are
+// kept, not from the allocations behind them. see "Node::memory_usage".
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct MemoryUsage {
+    // broadcast values seen, and the hops they took.
+    pub seen: usize,
+    // requests and replies, see "Node::enable_reply_cache".
+    pub reply_cache: usize,
+    // messages waiting for an acknowledgement, see "Node::send_reliably".
+    pub outbox: usize,
+}
+
+impl MemoryUsage {
+    // "values" seen (in a list and a set), "hops" recorded for them.
+    pub(crate) fn new(values: usize, hops: usize, requests: usize, unacknowledged: usize) -> Self {
+        Self {
+            seen: values * 2 * size_of::<BroadcastMessage>()
+                + hops * (size_of::<BroadcastMessage>() + size_of::<u32>()),
+            reply_cache: requests * (size_of::<Workload>() + MESSAGE_BYTES),
+            outbox: unacknowledged * MESSAGE_BYTES,
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.seen + self.reply_cache + self.outbox
+    }
+}
+
+// tells when the state grows past "threshold" bytes, and when it's back under it, see
+// "Node::enable_memory_guard".
+#[derive(Debug)]
+pub(crate) struct MemoryGuard {
+    threshold: usize,
+    over: bool,
+}
+
+// what changed since the last check.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Crossing {
+    Above,
+    Below,
+}
+
+impl MemoryGuard {
+    pub(crate) fn new(threshold: usize) -> Self {
+        Self {
+            threshold,
+            over: false,
+        }
+    }
+
+    pub(crate) fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    pub(crate) fn is_over(&self) -> bool {
+        self.over
+    }
+
+    pub(crate) fn check(&mut self, usage: &MemoryUsage) -> Option<Crossing> {
+        let over = usage.total() > self.threshold;
+        if over == self.over {
+            return None;
+        }
+        self.over = over;
+        Some(if over {
+            Crossing::Above
+        } else {
+            Crossing::Below
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_guard() {
+        let mut guard = MemoryGuard::new(10_000);
+        let small = MemoryUsage::new(10, 10, 1, 1);
+        let large = MemoryUsage::new(1_000, 1_000, 0, 0);
+        assert!(small.total() < 10_000 && large.total() > 10_000);
+
+        assert_eq!(guard.check(&small), None);
+        assert_eq!(guard.check(&large), Some(Crossing::Above));
+        // told once, not on every check.
+        assert_eq!(guard.check(&large), None);
+        assert!(guard.is_over());
+        assert_eq!(guard.check(&small), Some(Crossing::Below));
+        assert!(!guard.is_over());
+    }
+}
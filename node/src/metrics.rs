@@ -0,0 +1,562 @@
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use crate::core::{Message, NodeId, Type, Workload};
+use serde_json::{json, Value};
+
+// number of items in a queue between two runner stages, shared by both ends.
+// an item counts from the moment a producer starts pushing it until the consumer is done
+// taking it, so the depth of a full queue can briefly read up to "capacity + 2".
+#[derive(Debug, Default)]
+pub struct QueueDepth {
+    current: AtomicUsize,
+    peak: AtomicUsize,
+}
+
+impl QueueDepth {
+    pub fn current(&self) -> usize {
+        self.current.load(Ordering::Relaxed)
+    }
+
+    // highest depth seen so far.
+    pub fn peak(&self) -> usize {
+        self.peak.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn push(&self) {
+        let depth = self.current.fetch_add(1, Ordering::Relaxed) + 1;
+        self.peak.fetch_max(depth, Ordering::Relaxed);
+    }
+
+    pub(crate) fn pop(&self) {
+        self.current.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+// processing time and throughput of the runner, reported (and reset) every "interval".
+#[derive(Debug)]
+pub struct Profile {
+    interval: Duration,
+    window_start: Instant,
+    messages: u64,
+    busy: Duration,
+    slowest: Duration,
+}
+
+impl Profile {
+    pub fn new(interval: Duration, now: Instant) -> Self {
+        Self {
+            interval,
+            window_start: now,
+            messages: 0,
+            busy: Duration::ZERO,
+            slowest: Duration::ZERO,
+        }
+    }
+
+    pub fn record(&mut self, elapsed: Duration) {
+        self.messages += 1;
+        self.busy += elapsed;
+        self.slowest = self.slowest.max(elapsed);
+    }
+
+    // a one line summary of the window, once the interval is over.
+    pub fn report(&mut self, now: Instant) -> Option<String> {
+        let window = now.saturating_duration_since(self.window_start);
+        if window < self.interval {
+            return None;
+        }
+        let rate = self.messages as f64 / window.as_secs_f64();
+        let mean = self
+            .busy
+            .checked_div(self.messages as u32)
+            .unwrap_or_default();
+        let load = self.busy.as_secs_f64() / window.as_secs_f64() * 100.0;
+        let report = format!(
+            "profile: {} msgs in {:.1?} ({rate:.0} msgs/s), mean {mean:?}, max {:?}, busy {load:.1}%",
+            self.messages, window, self.slowest,
+        );
+        *self = Profile::new(self.interval, now);
+        Some(report)
+    }
+}
+
+// 2^SUB_BITS buckets per power of two, quantiles are off by at most 1/8th.
+const SUB_BITS: u32 = 3;
+const SUB_BUCKETS: u64 = 1 << SUB_BITS;
+
+// log-linear histogram of durations (in nanoseconds), fixed size whatever the sample count.
+#[derive(Clone, Debug)]
+pub struct Histogram {
+    counts: Vec<u64>,
+    total: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            counts: vec![0; Self::index(u64::MAX) + 1],
+            total: 0,
+        }
+    }
+}
+
+impl Histogram {
+    pub fn record(&mut self, elapsed: Duration) {
+        let nanos = u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX);
+        self.counts[Self::index(nanos)] += 1;
+        self.total += 1;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.total
+    }
+
+    // upper bound of the bucket holding the "q"th quantile, zero when empty.
+    pub fn quantile(&self, q: f64) -> Duration {
+        let target = ((q * self.total as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (index, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= target {
+                let upper = Self::lower_bound(index + 1) - 1;
+                return Duration::from_nanos(u64::try_from(upper).unwrap_or(u64::MAX));
+            }
+        }
+        Duration::ZERO
+    }
+
+    // values below SUB_BUCKETS get a bucket each, above that every power of two is split
+    // into SUB_BUCKETS buckets.
+    fn index(nanos: u64) -> usize {
+        if nanos < SUB_BUCKETS {
+            return nanos as usize;
+        }
+        let shift = 63 - nanos.leading_zeros() - SUB_BITS;
+        let sub = (nanos >> shift) & (SUB_BUCKETS - 1);
+        (((shift as u64 + 1) << SUB_BITS) + sub) as usize
+    }
+
+    // the last bucket ends past u64::MAX, hence u128.
+    fn lower_bound(index: usize) -> u128 {
+        let index = index as u128;
+        if index < SUB_BUCKETS as u128 {
+            return index;
+        }
+        let shift = (index >> SUB_BITS) - 1;
+        let sub = index & (SUB_BUCKETS as u128 - 1);
+        (SUB_BUCKETS as u128 + sub) << shift
+    }
+}
+
+// time spent in each handler, keyed by message type, reported (and reset) every "interval".
+#[derive(Debug)]
+pub struct HandlerLatencies {
+    interval: Duration,
+    window_start: Instant,
+    handlers: HashMap<Type, Histogram>,
+}
+
+impl HandlerLatencies {
+    pub fn new(interval: Duration, now: Instant) -> Self {
+        Self {
+            interval,
+            window_start: now,
+            handlers: HashMap::new(),
+        }
+    }
+
+    pub fn record(&mut self, key: Type, elapsed: Duration) {
+        self.handlers.entry(key).or_default().record(elapsed);
+    }
+
+    pub fn histogram(&self, key: &Type) -> Option<&Histogram> {
+        self.handlers.get(key)
+    }
+
+    // p50/p99 of every handler that ran during the window, once the interval is over.
+    pub fn report(&mut self, now: Instant) -> Option<String> {
+        if now.saturating_duration_since(self.window_start) < self.interval {
+            return None;
+        }
+        let mut handlers: Vec<String> = self
+            .handlers
+            .iter()
+            .map(|(key, histogram)| {
+                format!(
+                    "{key:?} {} msgs p50 {:?} p99 {:?}",
+                    histogram.count(),
+                    histogram.quantile(0.5),
+                    histogram.quantile(0.99),
+                )
+            })
+            .collect();
+        handlers.sort();
+        *self = HandlerLatencies::new(self.interval, now);
+        Some(format!("latency: {}", handlers.join(", ")))
+    }
+}
+
+// smoothed round trip time of the rpcs to one peer, estimated the way TCP does (RFC 6298).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RttEstimator {
+    srtt: Duration,
+    rttvar: Duration,
+    samples: u64,
+}
+
+impl RttEstimator {
+    pub fn new(sample: Duration) -> Self {
+        Self {
+            srtt: sample,
+            rttvar: sample / 2,
+            samples: 1,
+        }
+    }
+
+    pub fn record(&mut self, sample: Duration) {
+        let deviation = self.srtt.abs_diff(sample);
+        self.rttvar = self.rttvar * 3 / 4 + deviation / 4;
+        self.srtt = self.srtt * 7 / 8 + sample / 8;
+        self.samples += 1;
+    }
+
+    // moving average of the round trip times.
+    pub fn srtt(&self) -> Duration {
+        self.srtt
+    }
+
+    // moving average of how far samples are from "srtt".
+    pub fn rttvar(&self) -> Duration {
+        self.rttvar
+    }
+
+    pub fn samples(&self) -> u64 {
+        self.samples
+    }
+
+    // how long to wait for a reply before retrying, well above the usual round trip.
+    pub fn timeout(&self) -> Duration {
+        self.srtt + self.rttvar * 4
+    }
+}
+
+// counts by message type, a fixed atomic each: counting is one atomic add, no lock. custom
+// bodies are counted by their own "type", see "Body".
+#[derive(Debug)]
+struct TypeCounts {
+    known: [AtomicU64; Type::ALL.len()],
+    custom: Counts<String>,
+}
+
+impl Default for TypeCounts {
+    fn default() -> Self {
+        Self {
+            known: std::array::from_fn(|_| AtomicU64::new(0)),
+            custom: Counts::default(),
+        }
+    }
+}
+
+impl TypeCounts {
+    fn bump(&self, body: &Workload) {
+        match body.custom_type() {
+            Some(name) => self.custom.bump(name),
+            None => {
+                self.known[body.key() as usize].fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn get(&self, name: &str) -> u64 {
+        Type::ALL
+            .iter()
+            .position(|key| key.name() == name)
+            .map_or_else(
+                || self.custom.get(name),
+                |index| self.known[index].load(Ordering::Relaxed),
+            )
+    }
+
+    // the types counted at least once, by name.
+    fn sorted(&self) -> BTreeMap<String, u64> {
+        let mut sorted = self.custom.sorted();
+        let known = Type::ALL
+            .iter()
+            .zip(&self.known)
+            .map(|(key, count)| (key.name().to_owned(), count.load(Ordering::Relaxed)))
+            .filter(|(_, count)| *count > 0);
+        sorted.extend(known);
+        sorted
+    }
+}
+
+// counts by peer, bumped through a shared reference: a peer counted before takes one atomic add
+// under a read lock, only a peer seen for the first time takes the write lock. the peers are
+// few and known early, the lock is all but never written.
+#[derive(Debug)]
+struct Counts<K> {
+    counts: RwLock<HashMap<K, AtomicU64>>,
+}
+
+impl<K> Default for Counts<K> {
+    fn default() -> Self {
+        Self {
+            counts: RwLock::default(),
+        }
+    }
+}
+
+impl<K: Hash + Eq + Ord + Clone> Counts<K> {
+    fn bump<Q>(&self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        let counts = self.counts.read().expect("Counts should not be poisoned.");
+        if let Some(count) = counts.get(key) {
+            count.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        drop(counts);
+        let mut counts = self.counts.write().expect("Counts should not be poisoned.");
+        counts
+            .entry(key.to_owned())
+            .or_default()
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn get<Q>(&self, key: &Q) -> u64
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let counts = self.counts.read().expect("Counts should not be poisoned.");
+        counts
+            .get(key)
+            .map_or(0, |count| count.load(Ordering::Relaxed))
+    }
+
+    // keys sorted, so two snapshots are easy to diff.
+    fn sorted(&self) -> BTreeMap<K, u64> {
+        let counts = self.counts.read().expect("Counts should not be poisoned.");
+        counts
+            .iter()
+            .map(|(key, count)| (key.clone(), count.load(Ordering::Relaxed)))
+            .collect()
+    }
+}
+
+// messages received and sent by a node since it started, by type ("type" tag, replies
+// included) and by peer. these are what the efficiency challenges' msgs-per-op budgets count.
+// shared with other threads through "Node::shared_counters", counting takes a shared reference.
+#[derive(Debug, Default)]
+pub struct MessageCounters {
+    received: TypeCounts,
+    sent: TypeCounts,
+    received_from: Counts<NodeId>,
+    sent_to: Counts<NodeId>,
+}
+
+impl MessageCounters {
+    pub fn received(&self, message: &Message) {
+        self.received.bump(&message.body);
+        self.received_from.bump(&message.src);
+    }
+
+    pub fn sent(&self, message: &Message) {
+        self.sent.bump(&message.body);
+        self.sent_to.bump(&message.dest);
+    }
+
+    // messages of type "name" received so far.
+    pub fn received_count(&self, name: &str) -> u64 {
+        self.received.get(name)
+    }
+
+    pub fn sent_count(&self, name: &str) -> u64 {
+        self.sent.get(name)
+    }
+
+    pub fn received_from(&self, peer: &str) -> u64 {
+        self.received_from.get(peer)
+    }
+
+    pub fn sent_to(&self, peer: &str) -> u64 {
+        self.sent_to.get(peer)
+    }
+
+    // keys sorted, so two snapshots are easy to diff.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "received": self.received.sorted(),
+            "sent": self.sent.sorted(),
+            "received_from": self.received_from.sorted(),
+            "sent_to": self.sent_to.sorted(),
+        })
+    }
+
+    // a one line summary, e.g. "messages: received broadcast=10 read=2, sent broadcast_ok=10 ...".
+    pub fn report(&self) -> String {
+        let line = |counts: &TypeCounts| {
+            counts
+                .sorted()
+                .iter()
+                .map(|(name, count)| format!("{name}={count}"))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        format!(
+            "messages: received {}, sent {}",
+            line(&self.received),
+            line(&self.sent)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_report() {
+        let start = Instant::now();
+        let mut profile = Profile::new(Duration::from_secs(1), start);
+        profile.record(Duration::from_millis(10));
+        profile.record(Duration::from_millis(30));
+        assert_eq!(profile.report(start + Duration::from_millis(500)), None);
+
+        let report = profile.report(start + Duration::from_secs(2)).unwrap();
+        assert_eq!(
+            report,
+            "profile: 2 msgs in 2.0s (1 msgs/s), mean 20ms, max 30ms, busy 2.0%"
+        );
+        // a new window starts after every report.
+        assert!(profile
+            .report(start + Duration::from_secs(4))
+            .unwrap()
+            .starts_with("profile: 0 msgs"));
+    }
+
+    #[test]
+    fn test_histogram_quantiles() {
+        let mut histogram = Histogram::default();
+        assert_eq!(histogram.quantile(0.5), Duration::ZERO);
+        for micros in 1..=100 {
+            histogram.record(Duration::from_micros(micros));
+        }
+        assert_eq!(histogram.count(), 100);
+        // within a bucket (1/8th) of the exact value, never below it.
+        let p50 = histogram.quantile(0.5);
+        assert!(p50 >= Duration::from_micros(50) && p50 <= Duration::from_micros(57));
+        let p99 = histogram.quantile(0.99);
+        assert!(p99 >= Duration::from_micros(99) && p99 <= Duration::from_micros(112));
+
+        histogram.record(Duration::MAX);
+        assert_eq!(histogram.quantile(1.0), Duration::from_nanos(u64::MAX));
+    }
+
+    #[test]
+    fn test_handler_latencies_report() {
+        let start = Instant::now();
+        let mut latencies = HandlerLatencies::new(Duration::from_secs(1), start);
+        latencies.record(Type::Read, Duration::from_nanos(3));
+        latencies.record(Type::Broadcast, Duration::from_nanos(5));
+        latencies.record(Type::Broadcast, Duration::from_nanos(7));
+        assert_eq!(latencies.histogram(&Type::Broadcast).unwrap().count(), 2);
+        assert_eq!(latencies.report(start + Duration::from_millis(500)), None);
+
+        assert_eq!(
+            latencies.report(start + Duration::from_secs(1)).unwrap(),
+            "latency: Broadcast 2 msgs p50 5ns p99 7ns, Read 1 msgs p50 3ns p99 3ns"
+        );
+        assert!(latencies.histogram(&Type::Broadcast).is_none());
+    }
+
+    #[test]
+    fn test_message_counters() {
+        let counters = MessageCounters::default();
+        let broadcast = serde_json::from_str::<Message>(
+            r#"{"src":"n2","dest":"n1","body":{"type":"broadcast","message":1,"msg_id":1}}"#,
+        )
+        .unwrap();
+        counters.received(&broadcast);
+        counters.received(&broadcast);
+        let reply = serde_json::from_str::<Message>(
+            r#"{"src":"n1","dest":"n2","body":{"type":"broadcast_ok","in_reply_to":1,"msg_id":1}}"#,
+        )
+        .unwrap();
+        counters.sent(&reply);
+        // a workload's own message, counted by its "type".
+        let batch = serde_json::from_str::<Message>(
+            r#"{"src":"n1","dest":"n2","body":{"type":"broadcast_batch","messages":[1],"msg_id":2}}"#,
+        )
+        .unwrap();
+        counters.sent(&batch);
+
+        assert_eq!(counters.received_count("broadcast"), 2);
+        assert_eq!(counters.sent_count("broadcast_ok"), 1);
+        assert_eq!(counters.sent_count("broadcast_batch"), 1);
+        assert_eq!(counters.sent_count("custom"), 0);
+        assert_eq!(counters.received_from("n2"), 2);
+        assert_eq!(counters.sent_to("n3"), 0);
+        assert_eq!(
+            counters.report(),
+            "messages: received broadcast=2, sent broadcast_batch=1 broadcast_ok=1"
+        );
+        assert_eq!(counters.to_json()["sent_to"], json!({"n2": 2}));
+    }
+
+    #[test]
+    fn test_message_counters_concurrent() {
+        let counters = MessageCounters::default();
+        let broadcast = serde_json::from_str::<Message>(
+            r#"{"src":"n2","dest":"n1","body":{"type":"broadcast","message":1,"msg_id":1}}"#,
+        )
+        .unwrap();
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..1_000 {
+                        counters.received(&broadcast);
+                    }
+                });
+            }
+        });
+        assert_eq!(counters.received_count("broadcast"), 4_000);
+        assert_eq!(counters.received_from("n2"), 4_000);
+    }
+
+    #[test]
+    fn test_rtt_estimator() {
+        let mut rtt = RttEstimator::new(Duration::from_millis(80));
+        assert_eq!(rtt.timeout(), Duration::from_millis(240));
+
+        rtt.record(Duration::from_millis(160));
+        assert_eq!(rtt.srtt(), Duration::from_millis(90));
+        assert_eq!(rtt.rttvar(), Duration::from_millis(50));
+        assert_eq!(rtt.samples(), 2);
+
+        // steady round trips shrink the variance, and the timeout with it.
+        for _ in 0..50 {
+            rtt.record(Duration::from_millis(90));
+        }
+        assert!(rtt.timeout() < Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_queue_depth_peak() {
+        let depth = QueueDepth::default();
+        depth.push();
+        depth.push();
+        depth.pop();
+        depth.push();
+        depth.pop();
+        assert_eq!(depth.current(), 1);
+        assert_eq!(depth.peak(), 2);
+    }
+}
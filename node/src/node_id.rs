@@ -0,0 +1,258 @@
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+// id of a node, client or service ("n1", "c3", "lin-kv").
+// ids are kept inline, so parsing one doesn't allocate, copies (every reply, every neighbor
+// list) are free and nothing is shared between threads. maelstrom's ids are a few bytes long,
+// longer ones are rejected. hashing and ordering go by the string, as "Borrow<str>" requires.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct NodeId {
+    len: u8,
+    // zeroed past "len", so the derived equality compares the strings.
+    bytes: [u8; NodeId::MAX_LEN],
+}
+
+// what an id names, told by its shape: "n1" is a node, "c3" a client, anything else
+// ("lin-kv", "seq-kv") a service.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NodeKind<'a> {
+    Node(u32),
+    Client(u32),
+    Service(&'a str),
+}
+
+impl NodeId {
+    // the longest id a handle holds, in bytes.
+    pub const MAX_LEN: usize = 23;
+
+    // "None" if "id" is longer than "MAX_LEN".
+    pub fn new(id: &str) -> Option<Self> {
+        if id.len() > Self::MAX_LEN {
+            return None;
+        }
+        let mut bytes = [0; Self::MAX_LEN];
+        bytes[..id.len()].copy_from_slice(id.as_bytes());
+        Some(NodeId {
+            len: id.len() as u8,
+            bytes,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        let bytes = &self.bytes[..self.len as usize];
+        // "new" only ever copies a whole "&str" in.
+        std::str::from_utf8(bytes).expect("Node id should be UTF-8.")
+    }
+
+    pub fn kind(&self) -> NodeKind<'_> {
+        // a prefix and digits only, "n" alone or "n-1" aren't nodes.
+        let numbered = |prefix| {
+            let digits = self.as_str().strip_prefix(prefix)?;
+            match digits.bytes().all(|byte| byte.is_ascii_digit()) {
+                true => digits.parse().ok(),
+                false => None,
+            }
+        };
+        if let Some(number) = numbered('n') {
+            NodeKind::Node(number)
+        } else if let Some(number) = numbered('c') {
+            NodeKind::Client(number)
+        } else {
+            NodeKind::Service(self.as_str())
+        }
+    }
+
+    pub fn is_node(&self) -> bool {
+        matches!(self.kind(), NodeKind::Node(_))
+    }
+
+    pub fn is_client(&self) -> bool {
+        matches!(self.kind(), NodeKind::Client(_))
+    }
+
+    pub fn is_service(&self) -> bool {
+        matches!(self.kind(), NodeKind::Service(_))
+    }
+
+    // for ids written in the code or the configuration, an id that doesn't fit is a bug.
+    fn known(id: &str) -> Self {
+        NodeId::new(id)
+            .unwrap_or_else(|| panic!("Node id {id:?} is longer than {} bytes.", Self::MAX_LEN))
+    }
+}
+
+impl Hash for NodeId {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state)
+    }
+}
+
+impl PartialOrd for NodeId {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for NodeId {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+// the empty id, used before a node is initialized.
+impl Default for NodeId {
+    fn default() -> Self {
+        NodeId::known("")
+    }
+}
+
+impl Deref for NodeId {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl Borrow<str> for NodeId {
+    fn borrow(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for NodeId {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl From<&str> for NodeId {
+    fn from(id: &str) -> Self {
+        NodeId::known(id)
+    }
+}
+
+impl From<String> for NodeId {
+    fn from(id: String) -> Self {
+        NodeId::known(&id)
+    }
+}
+
+impl From<&String> for NodeId {
+    fn from(id: &String) -> Self {
+        NodeId::known(id)
+    }
+}
+
+impl PartialEq<str> for NodeId {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for NodeId {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialEq<NodeId> for &str {
+    fn eq(&self, other: &NodeId) -> bool {
+        *self == other.as_str()
+    }
+}
+
+impl fmt::Display for NodeId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Debug for NodeId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl Serialize for NodeId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for NodeId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct NodeIdVisitor;
+
+        impl Visitor<'_> for NodeIdVisitor {
+            type Value = NodeId;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a node id of at most {} bytes", NodeId::MAX_LEN)
+            }
+
+            fn visit_str<E: de::Error>(self, id: &str) -> Result<NodeId, E> {
+                NodeId::new(id).ok_or_else(|| E::invalid_length(id.len(), &self))
+            }
+        }
+
+        deserializer.deserialize_str(NodeIdVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_node_id_parsed() {
+        let a = serde_json::from_str::<NodeId>(r#""n1""#).unwrap();
+        let b = NodeId::from("n1");
+        assert_eq!(a, b);
+        assert_eq!(a, "n1");
+        assert_eq!(serde_json::to_string(&a).unwrap(), r#""n1""#);
+
+        let topology = serde_json::from_str::<std::collections::HashMap<NodeId, Vec<NodeId>>>(
+            r#"{"n1":["n2","n3"]}"#,
+        )
+        .unwrap();
+        assert_eq!(topology["n1"], vec![NodeId::from("n2"), NodeId::from("n3")]);
+    }
+
+    #[test]
+    fn test_node_id_order() {
+        // by name, like the strings.
+        assert!(NodeId::from("zz-late") > NodeId::from("n7"));
+        assert!(NodeId::from("a-late") < NodeId::from("n7"));
+        assert!(NodeId::from("n1") < NodeId::from("n10"));
+        assert!(NodeId::from("") < NodeId::from("n1"));
+    }
+
+    #[test]
+    fn test_node_id_too_long() {
+        let longest = "x".repeat(NodeId::MAX_LEN);
+        assert_eq!(NodeId::new(&longest).unwrap(), longest.as_str());
+        assert_eq!(NodeId::new(&format!("{longest}x")), None);
+        let json = format!(r#""{longest}x""#);
+        assert!(serde_json::from_str::<NodeId>(&json).is_err());
+    }
+
+    #[test]
+    fn test_node_id_kind() {
+        assert_eq!(NodeId::from("n12").kind(), NodeKind::Node(12));
+        assert_eq!(NodeId::from("c3").kind(), NodeKind::Client(3));
+        assert_eq!(NodeId::from("lin-kv").kind(), NodeKind::Service("lin-kv"));
+        assert!(NodeId::from("c3").is_client());
+        assert!(NodeId::from("seq-kv").is_service());
+        // not quite nodes.
+        for id in ["", "n", "n+1", "n1a", "cx", "n99999999999"] {
+            assert!(NodeId::from(id).is_service(), "{id}");
+        }
+    }
+}
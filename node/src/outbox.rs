@@ -0,0 +1,187 @@
+use std::collections::{BTreeMap, HashMap};
+use std::time::Instant;
+
+use crate::core::{Message, MessageId, Workload};
+use crate::metrics::RttEstimator;
+use crate::node_id::NodeId;
+use crate::retry::RetryPolicy;
+
+struct Entry {
+    message: Message,
+    attempts: u32,
+    next_at: Instant,
+    // handed off to another peer, see "Node::enable_hinted_handoff".
+    hinted: bool,
+}
+
+// messages sent until their destination acknowledges them with any reply, see
+// "Node::send_reliably". a message is sent again, unchanged, whenever "policy" says it waited
+// long enough, and dropped once it gives up.
+pub struct Outbox {
+    policy: Box<dyn RetryPolicy>,
+    entries: BTreeMap<MessageId, Entry>,
+}
+
+impl Outbox {
+    pub fn new(policy: impl RetryPolicy + 'static) -> Self {
+        Self {
+            policy: Box::new(policy),
+            entries: BTreeMap::new(),
+        }
+    }
+
+    // "message" has just been sent.
+    pub fn push(
+        &mut self,
+        msg_id: MessageId,
+        message: Message,
+        now: Instant,
+        rtt: Option<&RttEstimator>,
+    ) {
+        if let Some(timeout) = self.policy.timeout(1, rtt) {
+            let entry = Entry {
+                message,
+                attempts: 1,
+                next_at: now + timeout,
+                hinted: false,
+            };
+            self.entries.insert(msg_id, entry);
+        }
+    }
+
+    // true if the reply acknowledged a message of the outbox.
+    pub fn ack(&mut self, src: &NodeId, in_reply_to: MessageId) -> bool {
+        match self.entries.get(&in_reply_to) {
+            Some(entry) if entry.message.dest == *src => {
+                self.entries.remove(&in_reply_to);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // a message no longer worth sending, e.g. a duplicate of another one.
+    pub(crate) fn remove(&mut self, msg_id: MessageId) {
+        self.entries.remove(&msg_id);
+    }
+
+    // gives up on the messages to "dest", e.g. a node that left the cluster.
+    pub(crate) fn remove_to(&mut self, dest: &NodeId) {
+        self.entries.retain(|_, entry| entry.message.dest != *dest);
+    }
+
+    // the messages to send again by "now", in the order they were first sent. the ones to a
+    // peer in "slow" (see "Liveness::Slow") wait another timeout instead, the attempt doesn't
+    // count: more copies won't get there sooner.
+    pub fn due(
+        &mut self,
+        now: Instant,
+        rtts: &HashMap<NodeId, RttEstimator>,
+        slow: &[NodeId],
+    ) -> Vec<Message> {
+        let mut due = Vec::new();
+        let policy = &mut self.policy;
+        self.entries.retain(|_, entry| {
+            if entry.next_at > now {
+                return true;
+            }
+            let rtt = rtts.get(&entry.message.dest);
+            if slow.contains(&entry.message.dest) {
+                if let Some(timeout) = policy.timeout(entry.attempts, rtt) {
+                    entry.next_at = now + timeout;
+                }
+                return true;
+            }
+            entry.attempts += 1;
+            match policy.timeout(entry.attempts, rtt) {
+                Some(timeout) => {
+                    entry.next_at = now + timeout;
+                    due.push(entry.message.clone());
+                    true
+                }
+                None => false,
+            }
+        });
+        due
+    }
+
+    // the messages to a peer in "unreachable" not handed off yet, from now on they are. hints
+    // themselves are never handed off.
+    pub(crate) fn unhinted(&mut self, unreachable: &[NodeId]) -> Vec<Message> {
+        self.entries
+            .values_mut()
+            .filter(|entry| !entry.hinted && unreachable.contains(&entry.message.dest))
+            .filter(|entry| !matches!(entry.message.body, Workload::Hint { .. }))
+            .map(|entry| {
+                entry.hinted = true;
+                entry.message.clone()
+            })
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn messages(&self) -> impl Iterator<Item = &Message> {
+        self.entries.values().map(|entry| &entry.message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::retry::{Exponential, RetryPolicy};
+    use std::time::Duration;
+
+    #[test]
+    fn test_outbox_backoff() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let policy = Exponential::new(Duration::from_millis(100), Duration::from_millis(300));
+        let mut outbox = Outbox::new(policy.max_attempts(5));
+        let message = Message {
+            src: "n1".into(),
+            dest: "n2".into(),
+            body: Workload::Broadcast {
+                msg_id: 1,
+                message: 7,
+                hops: None,
+            },
+        };
+        outbox.push(1, message.clone(), start, None);
+
+        // sent again after 100ms, then 200ms later, then every 300ms.
+        let rtts = HashMap::new();
+        let resent: Vec<u64> = (0..=1_000)
+            .step_by(10)
+            .filter(|ms| !outbox.due(at(*ms), &rtts, &[]).is_empty())
+            .collect();
+        assert_eq!(resent, vec![100, 300, 600, 900]);
+
+        // only a reply from the destination acknowledges it.
+        assert!(!outbox.ack(&"n3".into(), 1));
+        assert!(!outbox.ack(&"n2".into(), 2));
+        assert!(outbox.ack(&"n2".into(), 1));
+        assert!(outbox.is_empty());
+        assert!(outbox.due(at(2_000), &rtts, &[]).is_empty());
+
+        // a message is dropped once the policy gives up on it.
+        outbox.push(2, message.clone(), start, None);
+        for ms in (0..=2_000).step_by(10) {
+            outbox.due(at(ms), &rtts, &[]);
+        }
+        assert!(outbox.is_empty());
+
+        // not resent to a slow peer, nor given up on.
+        outbox.push(3, message, start, None);
+        for ms in (0..=2_000).step_by(10) {
+            assert!(outbox.due(at(ms), &rtts, &["n2".into()]).is_empty());
+        }
+        assert_eq!(outbox.due(at(2_100), &rtts, &[]).len(), 1);
+    }
+}
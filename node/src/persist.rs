@@ -0,0 +1,94 @@
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+
+use crate::helper::Result;
+use crate::node_id::NodeId;
+use crate::storage::{FileStorage, SpawnedStorage, Storage};
+
+const STATE: &str = "state";
+
+// where "Node::enable_persistence" keeps the state of a node between runs: a value in a
+// "Storage", replaced as a whole on every save. a directory means a "FileStorage" at
+// "<dir>/<node_id>.log", opened once the node id is known.
+pub(crate) struct Persistence {
+    dir: Option<PathBuf>,
+    storage: Option<Box<dyn Storage>>,
+    interval: Duration,
+    saved_at: Option<Instant>,
+}
+
+impl Persistence {
+    pub(crate) fn new(dir: PathBuf, interval: Duration) -> Self {
+        Self {
+            dir: Some(dir),
+            storage: None,
+            interval,
+            saved_at: None,
+        }
+    }
+
+    pub(crate) fn with_storage(storage: Box<dyn Storage>, interval: Duration) -> Self {
+        Self {
+            dir: None,
+            storage: Some(storage),
+            interval,
+            saved_at: None,
+        }
+    }
+
+    fn storage(&mut self, node_id: &NodeId) -> Result<&mut Box<dyn Storage>> {
+        if self.storage.is_none() {
+            let dir = self
+                .dir
+                .as_ref()
+                .expect("Persistence should have a storage or a dir.");
+            let storage = FileStorage::open(dir.join(format!("{node_id}.log")))?;
+            self.storage = Some(Box::new(SpawnedStorage::spawn(storage)));
+        }
+        Ok(self.storage.as_mut().expect("Storage was just opened."))
+    }
+
+    // None on the first run.
+    pub(crate) fn load(&mut self, node_id: &NodeId) -> Result<Option<Value>> {
+        match self.storage(node_id)?.get(STATE)? {
+            Some(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub(crate) fn save(&mut self, node_id: &NodeId, state: &Value, now: Instant) -> Result<()> {
+        let bytes = serde_json::to_vec(state)?;
+        self.storage(node_id)?.put(STATE, &bytes)?;
+        self.saved_at = Some(now);
+        Ok(())
+    }
+
+    // the write-ahead log, see "Node::enable_wal": records appended to "stream" before the node
+    // acts on them, replayed on "init".
+    pub(crate) fn append(&mut self, node_id: &NodeId, stream: &str, record: &Value) -> Result<()> {
+        let bytes = serde_json::to_vec(record)?;
+        self.storage(node_id)?.append(&wal(stream), &bytes)
+    }
+
+    // what the records of "stream" held was saved some other way.
+    pub(crate) fn truncate(&mut self, node_id: &NodeId, stream: &str) -> Result<()> {
+        self.storage(node_id)?.remove(&wal(stream))
+    }
+
+    pub(crate) fn records(&mut self, node_id: &NodeId, stream: &str) -> Result<Vec<Value>> {
+        let records = self.storage(node_id)?.scan(&wal(stream))?;
+        let records = records.iter().map(|record| serde_json::from_slice(record));
+        Ok(records.collect::<std::result::Result<_, _>>()?)
+    }
+
+    pub(crate) fn due(&self, now: Instant) -> bool {
+        self.saved_at
+            .is_none_or(|saved_at| now.saturating_duration_since(saved_at) >= self.interval)
+    }
+}
+
+fn wal(stream: &str) -> String {
+    format!("wal/{stream}")
+}
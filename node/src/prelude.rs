@@ -0,0 +1,17 @@
+// what a workload binary needs, in one import: "use node::prelude::*;".
+// the modules behind it stay public for the less common parts (raft, txn, crdt, ...), this is
+// the surface binaries are written against and that is kept stable.
+
+pub use crate::core::{
+    code, smallvec, Body, BodyHandler, BroadcastMessage, Callback, CodeId, EvictionHook, Handler,
+    KvKey, KvValue, LogKey, LogMessage, Message, MessageId, Node, NodeId, Offset, Replies,
+    ShutdownHook, Sink, TickHook, Timestamp, Type, Workload, LIN_KV, SEQ_KV,
+};
+pub use crate::helper::{Error, Result};
+pub use crate::logging::{LogFormat, Verbosity};
+pub use crate::node_id::NodeKind;
+pub use crate::retry::{Adaptive, Exponential, Fixed, RetryPolicy};
+pub use crate::{expect_body, Runner};
+
+#[cfg(feature = "async")]
+pub use crate::async_runner::{AsyncHandler, AsyncRunner, Ctx};
@@ -0,0 +1,165 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::core::{Message, Node, NodeId, Replies, Workload};
+use crate::helper::{Error, Result};
+
+type QuorumCallback = Box<dyn FnOnce(&mut Node, Result<Vec<Message>>) -> Result<Replies> + Send>;
+
+struct Quorum {
+    needed: usize,
+    outstanding: usize,
+    replies: Vec<Message>,
+    callback: Option<QuorumCallback>,
+}
+
+impl Node {
+    // sends "body" to every peer (each copy with a fresh msg_id) and calls "callback" once,
+    // either with the first "quorum" successful replies, or with an error as soon as the
+    // quorum can't be reached anymore because of error replies or timeouts.
+    pub fn quorum_rpc<F>(
+        &mut self,
+        peers: &[NodeId],
+        body: Workload,
+        quorum: usize,
+        timeout: Duration,
+        callback: F,
+    ) -> Result<Replies>
+    where
+        F: FnOnce(&mut Node, Result<Vec<Message>>) -> Result<Replies> + Send + 'static,
+    {
+        let state = Arc::new(Mutex::new(Quorum {
+            needed: quorum,
+            outstanding: peers.len(),
+            replies: Vec::new(),
+            callback: Some(Box::new(callback)),
+        }));
+        if peers.len() < quorum {
+            return Self::resolve(self, &state);
+        }
+
+        let mut requests = Replies::with_capacity(peers.len());
+        for peer in peers {
+            let mut body = body.clone();
+            if body.msg_id().is_some() {
+                body.set_msg_id(self.gen_msg_id());
+            }
+            let state = state.clone();
+            let request = self.rpc_with_timeout(*peer, body, timeout, move |node, reply| {
+                {
+                    let mut quorum = Self::quorum(&state);
+                    quorum.outstanding -= 1;
+                    if !matches!(reply.body, Workload::Error { .. }) {
+                        quorum.replies.push(reply);
+                    }
+                }
+                Self::resolve(node, &state)
+            })?;
+            requests.push(request);
+        }
+        Ok(requests)
+    }
+
+    // a callback that panicked can't leave the count half done, the lock is taken and let go
+    // around it.
+    fn quorum(state: &Mutex<Quorum>) -> MutexGuard<'_, Quorum> {
+        state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn resolve(node: &mut Node, state: &Mutex<Quorum>) -> Result<Replies> {
+        let mut quorum = Self::quorum(state);
+        let reached = quorum.replies.len() >= quorum.needed;
+        let unreachable = quorum.replies.len() + quorum.outstanding < quorum.needed;
+        if !reached && !unreachable {
+            return Ok(Replies::new());
+        }
+        let Some(callback) = quorum.callback.take() else {
+            return Ok(Replies::new()); // already resolved, late replies are dropped.
+        };
+        let outcome = if reached {
+            Ok(std::mem::take(&mut quorum.replies))
+        } else {
+            Err(Box::new(Error::QuorumNotReached {
+                got: quorum.replies.len(),
+                needed: quorum.needed,
+            }) as Box<dyn std::error::Error>)
+        };
+        drop(quorum);
+        callback(node, outcome)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{code, smallvec};
+    use std::time::Instant;
+
+    fn node() -> Node {
+        let mut node = Node::default();
+        let json = r#"{"src":"c1","dest":"n1","body":{"type":"init","msg_id":1,"node_id":"n1","node_ids":["n1","n2","n3","n4"]}}"#;
+        let _ = node.process(serde_json::from_str::<Message>(json).unwrap());
+        node
+    }
+
+    fn peers() -> Vec<NodeId> {
+        ["n2", "n3", "n4"].map(NodeId::from).to_vec()
+    }
+
+    fn reply(node: &mut Node, src: &str, body: Workload) -> Replies {
+        let message = Message {
+            src: src.into(),
+            dest: "n1".into(),
+            body,
+        };
+        node.process(message).unwrap()
+    }
+
+    fn outcome(_: &mut Node, outcome: Result<Vec<Message>>) -> Result<Replies> {
+        let body = match outcome {
+            Ok(replies) => Workload::write_ok(replies.len() as u32, 0),
+            Err(_) => Workload::error(0, code::TIMEOUT, String::new()),
+        };
+        Ok(smallvec![Message {
+            src: "n1".into(),
+            dest: "c1".into(),
+            body,
+        }])
+    }
+
+    #[test]
+    fn test_quorum_reached() {
+        let mut node = node();
+        let body = Workload::read(0, "k".into());
+        let timeout = Duration::from_secs(1);
+        let requests = node
+            .quorum_rpc(&peers(), body, 2, timeout, outcome)
+            .unwrap();
+        assert_eq!(requests.len(), 3);
+        let msg_ids: Vec<_> = requests.iter().map(|r| r.body.msg_id().unwrap()).collect();
+        assert_eq!(msg_ids, vec![1, 2, 3]);
+
+        assert!(reply(&mut node, "n2", Workload::kv_read_ok(1, 0, 1.into())).is_empty());
+        let done = reply(&mut node, "n3", Workload::kv_read_ok(2, 0, 1.into()));
+        assert_eq!(done[0].body, Workload::write_ok(2, 0));
+
+        // the straggler doesn't resolve the quorum twice.
+        assert!(reply(&mut node, "n4", Workload::kv_read_ok(3, 0, 1.into())).is_empty());
+    }
+
+    #[test]
+    fn test_quorum_not_reached() {
+        let mut node = node();
+        let body = Workload::read(0, "k".into());
+        let timeout = Duration::from_millis(100);
+        node.quorum_rpc(&peers(), body, 2, timeout, outcome)
+            .unwrap();
+
+        let error = Workload::error(1, code::KEY_DOES_NOT_EXIST, String::new());
+        assert!(reply(&mut node, "n2", error).is_empty());
+        let done = node.tick(Instant::now() + timeout).unwrap();
+        assert!(matches!(done[0].body, Workload::Error { .. }));
+    }
+}
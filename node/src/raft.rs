@@ -0,0 +1,1017 @@
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::core::NodeId;
+
+pub type Term = u64;
+pub type LogIndex = u64;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct Entry {
+    pub term: Term,
+    pub command: Value,
+    // a configuration change instead of a command, see "Raft::change_membership".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config: Option<Membership>,
+}
+
+// who votes: one set of nodes, or the old and the new one while going from one to the other
+// (joint consensus, raft paper §6), when elections and commits need a majority of each.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum Membership {
+    Stable(Vec<NodeId>),
+    Joint { old: Vec<NodeId>, new: Vec<NodeId> },
+}
+
+impl Membership {
+    // every node in the configuration, old ones first.
+    pub fn members(&self) -> Vec<NodeId> {
+        match self {
+            Membership::Stable(nodes) => nodes.clone(),
+            Membership::Joint { old, new } => {
+                let added = new.iter().filter(|n| !old.contains(n));
+                old.iter().chain(added).cloned().collect()
+            }
+        }
+    }
+
+    pub fn contains(&self, id: &NodeId) -> bool {
+        match self {
+            Membership::Stable(nodes) => nodes.contains(id),
+            Membership::Joint { old, new } => old.contains(id) || new.contains(id),
+        }
+    }
+
+    // true if the nodes "agree" holds for are a majority of every set.
+    fn quorum(&self, agree: impl Fn(&NodeId) -> bool) -> bool {
+        let majority =
+            |nodes: &[NodeId]| nodes.iter().filter(|n| agree(n)).count() > nodes.len() / 2;
+        match self {
+            Membership::Stable(nodes) => majority(nodes),
+            Membership::Joint { old, new } => majority(old) && majority(new),
+        }
+    }
+}
+
+// the state machine as of "last_index", standing in for the entries up to there.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct Snapshot {
+    pub last_index: LogIndex,
+    pub last_term: Term,
+    pub membership: Membership,
+    pub state: Value,
+}
+
+// carried inside "Workload::Raft", the host assigns the msg_id.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RaftRpc {
+    RequestVote {
+        term: Term,
+        candidate_id: NodeId,
+        last_log_index: LogIndex,
+        last_log_term: Term,
+    },
+    RequestVoteResult {
+        term: Term,
+        vote_granted: bool,
+    },
+    AppendEntries {
+        term: Term,
+        leader_id: NodeId,
+        prev_log_index: LogIndex,
+        prev_log_term: Term,
+        entries: Vec<Entry>,
+        leader_commit: LogIndex,
+        // echoed in the result, see "Raft::linearizable_read".
+        #[serde(default)]
+        round: u64,
+    },
+    AppendEntriesResult {
+        term: Term,
+        success: bool,
+        match_index: LogIndex,
+        #[serde(default)]
+        round: u64,
+    },
+    // for a follower that's behind the leader's snapshot, answered with "AppendEntriesResult".
+    InstallSnapshot {
+        term: Term,
+        leader_id: NodeId,
+        snapshot: Snapshot,
+    },
+}
+
+impl RaftRpc {
+    fn term(&self) -> Term {
+        match self {
+            RaftRpc::RequestVote { term, .. }
+            | RaftRpc::RequestVoteResult { term, .. }
+            | RaftRpc::AppendEntries { term, .. }
+            | RaftRpc::AppendEntriesResult { term, .. }
+            | RaftRpc::InstallSnapshot { term, .. } => *term,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Role {
+    Follower,
+    Candidate,
+    Leader,
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    // the actual timeout is randomized between "election_timeout" and twice of it.
+    pub election_timeout: Duration,
+    pub heartbeat_interval: Duration,
+    // applied entries kept in the log before "Raft::wants_snapshot", none never asks.
+    pub snapshot_threshold: Option<usize>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            election_timeout: Duration::from_millis(300),
+            heartbeat_interval: Duration::from_millis(50),
+            snapshot_threshold: Some(1000),
+        }
+    }
+}
+
+pub type Outbox = Vec<(NodeId, RaftRpc)>;
+
+pub type ReadId = u64;
+
+// a read the leader serves once a majority acknowledged a heartbeat round sent after it, and
+// the log is applied up to "index".
+struct PendingRead {
+    id: ReadId,
+    term: Term,
+    round: u64,
+    // the commit index when it started, none until an entry of "term" is committed.
+    index: Option<LogIndex>,
+    acks: HashSet<NodeId>,
+}
+
+// Raft is driven by the host: it feeds incoming rpcs and clock ticks,
+// and sends whatever (destination, rpc) pairs come back.
+pub struct Raft {
+    id: NodeId,
+    // the members of "membership" other than this node.
+    peers: Vec<NodeId>,
+    // the latest configuration in the log, committed or not, and the index of its entry. before
+    // any, the snapshot's, or "initial" the node was created with.
+    membership: Membership,
+    membership_index: LogIndex,
+    initial: Membership,
+    config: Config,
+
+    role: Role,
+    term: Term,
+    voted_for: Option<NodeId>,
+    leader: Option<NodeId>,
+    // entry at index "i" is stored at "log[i - snapshot_index - 1]".
+    log: Vec<Entry>,
+    snapshot: Option<Snapshot>,
+    // installed from the leader, for the host to restore, see "installed_snapshot".
+    installed: Option<Value>,
+    commit_index: LogIndex,
+    last_applied: LogIndex,
+
+    votes: HashSet<NodeId>,
+    next_index: HashMap<NodeId, LogIndex>,
+    match_index: HashMap<NodeId, LogIndex>,
+    // heartbeat rounds of the leader, and the reads waiting on one.
+    round: u64,
+    reads: Vec<PendingRead>,
+    next_read_id: ReadId,
+
+    election_deadline: Instant,
+    heartbeat_deadline: Instant,
+    rng: u64,
+}
+
+impl Raft {
+    // "node_ids" is the whole cluster, this node included. a node that isn't in it doesn't start
+    // elections, it waits for a leader to add it, see "change_membership".
+    pub fn new(id: NodeId, node_ids: &[NodeId], config: Config, now: Instant) -> Self {
+        let peers = node_ids.iter().filter(|n| **n != id).cloned().collect();
+        let initial = Membership::Stable(node_ids.to_vec());
+        let seed = id.bytes().fold(0x9e3779b97f4a7c15_u64, |seed, b| {
+            seed.rotate_left(8) ^ b as u64
+        });
+        let mut raft = Self {
+            id,
+            peers,
+            membership: initial.clone(),
+            membership_index: 0,
+            initial,
+            config,
+            role: Role::Follower,
+            term: 0,
+            voted_for: None,
+            leader: None,
+            log: Vec::new(),
+            snapshot: None,
+            installed: None,
+            commit_index: 0,
+            last_applied: 0,
+            votes: HashSet::new(),
+            next_index: HashMap::new(),
+            match_index: HashMap::new(),
+            round: 0,
+            reads: Vec::new(),
+            next_read_id: 0,
+            election_deadline: now,
+            heartbeat_deadline: now,
+            rng: seed | 1,
+        };
+        raft.reset_election_deadline(now);
+        raft
+    }
+
+    pub fn role(&self) -> Role {
+        self.role
+    }
+
+    pub fn term(&self) -> Term {
+        self.term
+    }
+
+    pub fn leader(&self) -> Option<&NodeId> {
+        self.leader.as_ref()
+    }
+
+    pub fn is_leader(&self) -> bool {
+        self.role == Role::Leader
+    }
+
+    pub fn membership(&self) -> &Membership {
+        &self.membership
+    }
+
+    pub fn commit_index(&self) -> LogIndex {
+        self.commit_index
+    }
+
+    pub fn last_log_index(&self) -> LogIndex {
+        self.snapshot_index() + self.log.len() as LogIndex
+    }
+
+    // the last index the snapshot covers, 0 without one.
+    pub fn snapshot_index(&self) -> LogIndex {
+        self.snapshot
+            .as_ref()
+            .map_or(0, |snapshot| snapshot.last_index)
+    }
+
+    pub fn latest_snapshot(&self) -> Option<&Snapshot> {
+        self.snapshot.as_ref()
+    }
+
+    // true once the log holds "Config::snapshot_threshold" applied entries, time for the host
+    // to hand its state to "snapshot".
+    pub fn wants_snapshot(&self) -> bool {
+        self.config.snapshot_threshold.is_some_and(|threshold| {
+            (self.last_applied - self.snapshot_index()) as usize >= threshold
+        })
+    }
+
+    // "state" is the state machine with every entry applied so far. it replaces them in the
+    // log, followers that still need them get the snapshot instead.
+    pub fn snapshot(&mut self, state: Value) {
+        let last_index = self.last_applied;
+        if last_index <= self.snapshot_index() {
+            return;
+        }
+        let last_term = self.term_at(last_index);
+        let (_, membership) = self.membership_at(last_index);
+        self.log
+            .drain(..(last_index - self.snapshot_index()) as usize);
+        self.snapshot = Some(Snapshot {
+            last_index,
+            last_term,
+            membership,
+            state,
+        });
+    }
+
+    // the state a leader's snapshot replaced this node's with, once. the host restores its state
+    // machine from it before applying further entries.
+    pub fn installed_snapshot(&mut self) -> Option<Value> {
+        self.installed.take()
+    }
+
+    // appends a command to the leader's log, followers return the leader they know of.
+    pub fn propose(&mut self, command: Value) -> Result<(LogIndex, Outbox), Option<NodeId>> {
+        if !self.is_leader() {
+            return Err(self.leader);
+        }
+        Ok(self.replicate(command, None))
+    }
+
+    // moves the cluster to "node_ids" in two steps: the leader appends the joint configuration
+    // of the old and the new nodes, once that's committed the new one alone, and steps down if
+    // it isn't part of it. each takes effect as soon as it's in a log, committed or not. the
+    // returned index is the joint entry's. followers return the leader they know of, the leader
+    // returns itself while a change is still in progress, to be retried later.
+    pub fn change_membership(
+        &mut self,
+        node_ids: &[NodeId],
+    ) -> Result<(LogIndex, Outbox), Option<NodeId>> {
+        if !self.is_leader() {
+            return Err(self.leader);
+        }
+        let Membership::Stable(old) = &self.membership else {
+            return Err(Some(self.id));
+        };
+        if self.membership_index > self.commit_index {
+            return Err(Some(self.id));
+        }
+        let joint = Membership::Joint {
+            old: old.clone(),
+            new: node_ids.to_vec(),
+        };
+        Ok(self.replicate(Value::Null, Some(joint)))
+    }
+
+    // appends to the leader's log and sends it along.
+    fn replicate(&mut self, command: Value, config: Option<Membership>) -> (LogIndex, Outbox) {
+        self.push(command, config);
+        let index = self.last_log_index();
+        self.advance_commit_index(); // single node cluster commits right away.
+        let outbox = self
+            .peers
+            .clone()
+            .into_iter()
+            .map(|peer| self.append_entries(peer))
+            .collect();
+        (index, outbox)
+    }
+
+    fn push(&mut self, command: Value, config: Option<Membership>) {
+        let changed = config.is_some();
+        self.log.push(Entry {
+            term: self.term,
+            command,
+            config,
+        });
+        if changed {
+            self.refresh_membership();
+        }
+    }
+
+    // a read that's linearizable without going through the log (ReadIndex, raft dissertation
+    // §6.4): the leader notes its commit index and sends a round of heartbeats, returned along
+    // with the read's id. once a majority acknowledged them and the log is applied up to that
+    // index, "ready_reads" returns the id and the host answers from its state machine. a leader
+    // that hasn't committed an entry of its term yet (e.g. a no-op proposed when elected) holds
+    // the read until it has. followers return the leader they know of, and a read is dropped if
+    // the leader steps down, the host answers it with an error or a redirect once it times out.
+    pub fn linearizable_read(&mut self) -> Result<(ReadId, Outbox), Option<NodeId>> {
+        if !self.is_leader() {
+            return Err(self.leader);
+        }
+        self.next_read_id += 1;
+        self.round += 1;
+        let index = (self.term_at(self.commit_index) == self.term).then_some(self.commit_index);
+        self.reads.push(PendingRead {
+            id: self.next_read_id,
+            term: self.term,
+            round: self.round,
+            index,
+            acks: HashSet::new(),
+        });
+        let outbox = self
+            .peers
+            .clone()
+            .into_iter()
+            .map(|peer| self.append_entries(peer))
+            .collect();
+        Ok((self.next_read_id, outbox))
+    }
+
+    // the reads that can be served now, see "linearizable_read". each is returned once.
+    pub fn ready_reads(&mut self) -> Vec<ReadId> {
+        let (term, leader) = (self.term, self.is_leader());
+        self.reads.retain(|read| leader && read.term == term);
+        let (membership, id, applied) = (&self.membership, &self.id, self.last_applied);
+        let ready = |read: &PendingRead| {
+            membership.quorum(|n| n == id || read.acks.contains(n))
+                && read.index.is_some_and(|index| index <= applied)
+        };
+        let ids = self
+            .reads
+            .iter()
+            .filter(|read| ready(read))
+            .map(|read| read.id);
+        let ids = ids.collect();
+        self.reads.retain(|read| !ready(read));
+        ids
+    }
+
+    fn confirm_reads(&mut self, peer: NodeId, round: u64) {
+        for read in self.reads.iter_mut().filter(|read| read.round <= round) {
+            read.acks.insert(peer);
+        }
+    }
+
+    // hands every committed but not yet applied entry to the state machine, in log order.
+    // configuration changes are raft's own, they're skipped.
+    pub fn apply_committed<F>(&mut self, mut apply: F)
+    where
+        F: FnMut(LogIndex, &Value),
+    {
+        while self.last_applied < self.commit_index {
+            self.last_applied += 1;
+            let entry = self.entry(self.last_applied);
+            if entry.config.is_none() {
+                apply(self.last_applied, &entry.command);
+            }
+        }
+    }
+
+    pub fn tick(&mut self, now: Instant) -> Outbox {
+        match self.role {
+            Role::Leader if now >= self.heartbeat_deadline => {
+                self.heartbeat_deadline = now + self.config.heartbeat_interval;
+                self.peers
+                    .clone()
+                    .into_iter()
+                    .map(|peer| self.append_entries(peer))
+                    .collect()
+            }
+            Role::Follower | Role::Candidate if now >= self.election_deadline => {
+                self.start_election(now)
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn handle(&mut self, now: Instant, src: NodeId, rpc: RaftRpc) -> Outbox {
+        if rpc.term() > self.term {
+            self.term = rpc.term();
+            self.voted_for = None;
+            self.role = Role::Follower;
+        }
+
+        match rpc {
+            RaftRpc::RequestVote {
+                term,
+                candidate_id,
+                last_log_index,
+                last_log_term,
+            } => {
+                let up_to_date = (last_log_term, last_log_index)
+                    >= (self.last_log_term(), self.last_log_index());
+                let vote_granted = term == self.term
+                    && up_to_date
+                    && self.voted_for.as_ref().is_none_or(|v| *v == candidate_id);
+                if vote_granted {
+                    self.voted_for = Some(candidate_id);
+                    self.reset_election_deadline(now);
+                }
+                let term = self.term;
+                vec![(src, RaftRpc::RequestVoteResult { term, vote_granted })]
+            }
+            RaftRpc::RequestVoteResult { term, vote_granted } => {
+                if self.role == Role::Candidate && term == self.term && vote_granted {
+                    self.votes.insert(src);
+                    if self.membership.quorum(|n| self.votes.contains(n)) {
+                        return self.become_leader(now);
+                    }
+                }
+                Vec::new()
+            }
+            RaftRpc::AppendEntries {
+                term,
+                leader_id,
+                prev_log_index,
+                prev_log_term,
+                entries,
+                leader_commit,
+                round,
+            } => {
+                if term < self.term {
+                    return vec![(src, self.append_entries_result(false, 0, round))];
+                }
+                self.role = Role::Follower;
+                self.leader = Some(leader_id);
+                self.reset_election_deadline(now);
+
+                // the snapshot holds committed entries only, they match the leader's.
+                let consistent = prev_log_index <= self.snapshot_index()
+                    || (prev_log_index <= self.last_log_index()
+                        && self.term_at(prev_log_index) == prev_log_term);
+                if !consistent {
+                    return vec![(src, self.append_entries_result(false, 0, round))];
+                }
+
+                let mut index = prev_log_index;
+                let mut changed = false;
+                for entry in entries {
+                    index += 1;
+                    if index <= self.snapshot_index() {
+                        continue;
+                    }
+                    if index <= self.last_log_index() {
+                        if self.entry(index).term == entry.term {
+                            continue;
+                        }
+                        // conflicting suffix, drop it.
+                        self.log
+                            .truncate((index - self.snapshot_index()) as usize - 1);
+                        changed = true;
+                    }
+                    changed |= entry.config.is_some();
+                    self.log.push(entry);
+                }
+                if changed {
+                    self.refresh_membership();
+                }
+                if leader_commit > self.commit_index {
+                    self.commit_index = self.commit_index.max(leader_commit.min(index));
+                }
+                vec![(src, self.append_entries_result(true, index, round))]
+            }
+            RaftRpc::AppendEntriesResult {
+                term,
+                success,
+                match_index,
+                round,
+            } => {
+                if self.role != Role::Leader || term != self.term {
+                    return Vec::new();
+                }
+                // a failure too: "src" still takes this node for its leader.
+                self.confirm_reads(src, round);
+                if success {
+                    let matched = self.match_index.entry(src).or_default();
+                    *matched = (*matched).max(match_index);
+                    self.next_index.insert(src, match_index + 1);
+                    self.advance_commit_index();
+                    Vec::new()
+                } else {
+                    // walk back one entry at a time until the logs agree, or the snapshot.
+                    let next = self.next_index.entry(src).or_insert(1);
+                    *next = (*next - 1).max(1);
+                    vec![self.append_entries(src)]
+                }
+            }
+            RaftRpc::InstallSnapshot {
+                term,
+                leader_id,
+                snapshot,
+            } => {
+                if term < self.term {
+                    return vec![(src, self.append_entries_result(false, 0, 0))];
+                }
+                self.role = Role::Follower;
+                self.leader = Some(leader_id);
+                self.reset_election_deadline(now);
+
+                let last_index = snapshot.last_index;
+                if last_index > self.snapshot_index() {
+                    self.install(snapshot);
+                }
+                vec![(src, self.append_entries_result(true, last_index, 0))]
+            }
+        }
+    }
+
+    // entries past the snapshot stay if the logs agree on its last one.
+    fn install(&mut self, snapshot: Snapshot) {
+        let last_index = snapshot.last_index;
+        if last_index <= self.last_log_index() && self.term_at(last_index) == snapshot.last_term {
+            self.log
+                .drain(..(last_index - self.snapshot_index()) as usize);
+        } else {
+            self.log.clear();
+        }
+        self.commit_index = self.commit_index.max(last_index);
+        if self.last_applied < last_index {
+            self.last_applied = last_index;
+            self.installed = Some(snapshot.state.clone());
+        }
+        self.snapshot = Some(snapshot);
+        self.refresh_membership();
+    }
+
+    // the configuration in effect at "index", and the index of its entry.
+    fn membership_at(&self, index: LogIndex) -> (LogIndex, Membership) {
+        let mut indexes = (self.snapshot_index() + 1..=index).rev();
+        let latest = indexes.find_map(|i| self.entry(i).config.clone().map(|c| (i, c)));
+        latest.unwrap_or_else(|| match &self.snapshot {
+            Some(snapshot) => (snapshot.last_index, snapshot.membership.clone()),
+            None => (0, self.initial.clone()),
+        })
+    }
+
+    fn refresh_membership(&mut self) {
+        (self.membership_index, self.membership) = self.membership_at(self.last_log_index());
+        let members = self.membership.members().into_iter();
+        self.peers = members.filter(|n| *n != self.id).collect();
+    }
+
+    fn start_election(&mut self, now: Instant) -> Outbox {
+        if !self.membership.contains(&self.id) {
+            // not added yet, or removed.
+            self.reset_election_deadline(now);
+            return Vec::new();
+        }
+        self.role = Role::Candidate;
+        self.term += 1;
+        self.voted_for = Some(self.id);
+        self.leader = None;
+        self.votes = HashSet::from([self.id]);
+        self.reset_election_deadline(now);
+
+        if self.membership.quorum(|n| self.votes.contains(n)) {
+            return self.become_leader(now);
+        }
+        let rpc = RaftRpc::RequestVote {
+            term: self.term,
+            candidate_id: self.id,
+            last_log_index: self.last_log_index(),
+            last_log_term: self.last_log_term(),
+        };
+        self.peers.iter().map(|peer| (*peer, rpc.clone())).collect()
+    }
+
+    fn become_leader(&mut self, now: Instant) -> Outbox {
+        self.role = Role::Leader;
+        self.leader = Some(self.id);
+        let next = self.last_log_index() + 1;
+        self.next_index = self.peers.iter().map(|p| (*p, next)).collect();
+        self.match_index = self.peers.iter().map(|p| (*p, 0)).collect();
+        self.heartbeat_deadline = now; // assert leadership right away.
+        self.tick(now)
+    }
+
+    fn append_entries(&self, peer: NodeId) -> (NodeId, RaftRpc) {
+        let next = self.next_index.get(&peer).copied().unwrap_or(1).max(1);
+        if let Some(snapshot) = self.snapshot.as_ref().filter(|s| next <= s.last_index) {
+            let rpc = RaftRpc::InstallSnapshot {
+                term: self.term,
+                leader_id: self.id,
+                snapshot: snapshot.clone(),
+            };
+            return (peer, rpc);
+        }
+        let prev_log_index = next - 1;
+        let rpc = RaftRpc::AppendEntries {
+            term: self.term,
+            leader_id: self.id,
+            prev_log_index,
+            prev_log_term: self.term_at(prev_log_index),
+            entries: self.log[(prev_log_index - self.snapshot_index()) as usize..].to_vec(),
+            leader_commit: self.commit_index,
+            round: self.round,
+        };
+        (peer, rpc)
+    }
+
+    fn append_entries_result(&self, success: bool, match_index: LogIndex, round: u64) -> RaftRpc {
+        RaftRpc::AppendEntriesResult {
+            term: self.term,
+            success,
+            match_index,
+            round,
+        }
+    }
+
+    // only entries of the current term are committed by counting replicas (raft paper §5.4.2).
+    fn advance_commit_index(&mut self) {
+        for index in (self.commit_index + 1..=self.last_log_index()).rev() {
+            if self.entry(index).term != self.term {
+                break;
+            }
+            let replicated =
+                |n: &NodeId| *n == self.id || self.match_index.get(n).is_some_and(|m| *m >= index);
+            if self.membership.quorum(replicated) {
+                self.commit_index = index;
+                break;
+            }
+        }
+        if self.term_at(self.commit_index) == self.term {
+            for read in self.reads.iter_mut().filter(|read| read.index.is_none()) {
+                read.index = Some(self.commit_index);
+            }
+        }
+        if self.role != Role::Leader || self.membership_index > self.commit_index {
+            return;
+        }
+        // the joint configuration is committed, on to the new one. it goes out with the next
+        // heartbeat.
+        if let Membership::Joint { new, .. } = &self.membership {
+            let stable = Membership::Stable(new.clone());
+            self.push(Value::Null, Some(stable));
+            self.advance_commit_index();
+        } else if !self.membership.contains(&self.id) {
+            self.role = Role::Follower;
+            self.leader = None;
+        }
+    }
+
+    // past the snapshot.
+    fn entry(&self, index: LogIndex) -> &Entry {
+        &self.log[(index - self.snapshot_index()) as usize - 1]
+    }
+
+    // the term of the entry at "index", the snapshot's last one included. 0 for index 0.
+    fn term_at(&self, index: LogIndex) -> Term {
+        match &self.snapshot {
+            Some(snapshot) if index == snapshot.last_index => snapshot.last_term,
+            _ if index == 0 => 0,
+            _ => self.entry(index).term,
+        }
+    }
+
+    fn last_log_term(&self) -> Term {
+        self.log.last().map(|e| e.term).unwrap_or_else(|| {
+            self.snapshot
+                .as_ref()
+                .map_or(0, |snapshot| snapshot.last_term)
+        })
+    }
+
+    fn reset_election_deadline(&mut self, now: Instant) {
+        // xorshift, good enough to keep nodes from timing out in lockstep.
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        let timeout = self.config.election_timeout;
+        let jitter = self.rng % (timeout.as_millis() as u64).max(1);
+        self.election_deadline = now + timeout + Duration::from_millis(jitter);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cluster(now: Instant) -> HashMap<NodeId, Raft> {
+        let ids: Vec<NodeId> = ["n1", "n2", "n3"].map(NodeId::from).to_vec();
+        ids.iter()
+            .map(|id| (*id, Raft::new(*id, &ids, Config::default(), now)))
+            .collect()
+    }
+
+    // delivers messages until the cluster goes quiet.
+    fn deliver(rafts: &mut HashMap<NodeId, Raft>, now: Instant, src: &str, mut outbox: Outbox) {
+        let mut queue: Vec<(NodeId, NodeId, RaftRpc)> = outbox
+            .drain(..)
+            .map(|(dest, rpc)| (src.into(), dest, rpc))
+            .collect();
+        while let Some((src, dest, rpc)) = queue.pop() {
+            let raft = rafts.get_mut(&dest).unwrap();
+            for (next, rpc) in raft.handle(now, src, rpc) {
+                queue.push((dest, next, rpc));
+            }
+        }
+    }
+
+    #[test]
+    fn test_raft_election() {
+        let now = Instant::now();
+        let mut rafts = cluster(now);
+
+        let later = now + Duration::from_secs(1);
+        let outbox = rafts.get_mut("n1").unwrap().tick(later);
+        assert_eq!(rafts["n1"].role(), Role::Candidate);
+        deliver(&mut rafts, later, "n1", outbox);
+
+        assert!(rafts["n1"].is_leader());
+        assert_eq!(rafts["n2"].leader(), Some(&"n1".into()));
+        assert_eq!(rafts["n3"].term(), rafts["n1"].term());
+    }
+
+    #[test]
+    fn test_raft_replication() {
+        let now = Instant::now();
+        let mut rafts = cluster(now);
+        let later = now + Duration::from_secs(1);
+        let outbox = rafts.get_mut("n1").unwrap().tick(later);
+        deliver(&mut rafts, later, "n1", outbox);
+
+        assert_eq!(
+            rafts.get_mut("n2").unwrap().propose(1.into()).unwrap_err(),
+            Some("n1".into())
+        );
+        let (index, outbox) = rafts.get_mut("n1").unwrap().propose(1.into()).unwrap();
+        assert_eq!(index, 1);
+        deliver(&mut rafts, later, "n1", outbox);
+        assert_eq!(rafts["n1"].commit_index(), 1);
+
+        // followers learn the commit index on the next heartbeat.
+        let heartbeat = later + Duration::from_secs(1);
+        let outbox = rafts.get_mut("n1").unwrap().tick(heartbeat);
+        deliver(&mut rafts, heartbeat, "n1", outbox);
+        for raft in rafts.values_mut() {
+            let mut applied = Vec::new();
+            raft.apply_committed(|index, command| applied.push((index, command.clone())));
+            assert_eq!(applied, vec![(1, Value::from(1))]);
+        }
+    }
+
+    #[test]
+    fn test_raft_snapshot() {
+        let now = Instant::now();
+        let mut rafts = cluster(now);
+        let later = now + Duration::from_secs(1);
+        let outbox = rafts.get_mut("n1").unwrap().tick(later);
+        deliver(&mut rafts, later, "n1", outbox);
+
+        // the leader applies 3 commands, sums them up and drops them from its log.
+        let leader = rafts.get_mut("n1").unwrap();
+        leader.config.snapshot_threshold = Some(3);
+        let mut outboxes = Vec::new();
+        for command in [1, 2, 3] {
+            outboxes.push(leader.propose(command.into()).unwrap().1);
+        }
+        for outbox in outboxes {
+            deliver(&mut rafts, later, "n1", outbox);
+        }
+        let leader = rafts.get_mut("n1").unwrap();
+        let mut sum = 0;
+        leader.apply_committed(|_, command| sum += command.as_u64().unwrap());
+        assert!(leader.wants_snapshot());
+        leader.snapshot(sum.into());
+        assert!(!leader.wants_snapshot());
+        assert_eq!((leader.snapshot_index(), leader.last_log_index()), (3, 3));
+        assert!(leader.log.is_empty());
+
+        // n3 lost everything, it gets the snapshot and then the entries past it.
+        let ids: Vec<NodeId> = ["n1", "n2", "n3"].map(NodeId::from).to_vec();
+        rafts.insert(
+            "n3".into(),
+            Raft::new("n3".into(), &ids, Config::default(), now),
+        );
+        let (index, outbox) = rafts.get_mut("n1").unwrap().propose(4.into()).unwrap();
+        assert_eq!(index, 4);
+        deliver(&mut rafts, later, "n1", outbox);
+        let heartbeat = later + Duration::from_secs(1);
+        let outbox = rafts.get_mut("n1").unwrap().tick(heartbeat);
+        deliver(&mut rafts, heartbeat, "n1", outbox);
+
+        let follower = rafts.get_mut("n3").unwrap();
+        assert_eq!(follower.snapshot_index(), 3);
+        assert_eq!(follower.last_log_index(), 4);
+        let mut sum = follower.installed_snapshot().unwrap().as_u64().unwrap();
+        assert_eq!(follower.installed_snapshot(), None);
+        follower.apply_committed(|_, command| sum += command.as_u64().unwrap());
+        assert_eq!(sum, 10);
+        assert_eq!(rafts["n1"].match_index["n3"], 4);
+    }
+
+    #[test]
+    fn test_raft_linearizable_read() {
+        let now = Instant::now();
+        let mut rafts = cluster(now);
+        let later = now + Duration::from_secs(1);
+        let outbox = rafts.get_mut("n1").unwrap().tick(later);
+        deliver(&mut rafts, later, "n1", outbox);
+        let read = |rafts: &mut HashMap<NodeId, Raft>| {
+            rafts.get_mut("n1").unwrap().linearizable_read().unwrap()
+        };
+        let ready = |rafts: &mut HashMap<NodeId, Raft>| rafts.get_mut("n1").unwrap().ready_reads();
+
+        assert_eq!(
+            rafts
+                .get_mut("n2")
+                .unwrap()
+                .linearizable_read()
+                .unwrap_err(),
+            Some("n1".into())
+        );
+        // confirmed, but nothing of the leader's term is committed yet.
+        let (first, outbox) = read(&mut rafts);
+        deliver(&mut rafts, later, "n1", outbox);
+        assert!(ready(&mut rafts).is_empty());
+        let (_, outbox) = rafts.get_mut("n1").unwrap().propose(7.into()).unwrap();
+        deliver(&mut rafts, later, "n1", outbox);
+        assert!(ready(&mut rafts).is_empty()); // and not applied.
+        rafts.get_mut("n1").unwrap().apply_committed(|_, _| {});
+        assert_eq!(ready(&mut rafts), vec![first]);
+        assert!(ready(&mut rafts).is_empty());
+
+        // waits for the heartbeats it sent, not earlier ones.
+        let (second, outbox) = read(&mut rafts);
+        assert_eq!(outbox.len(), 2);
+        assert!(ready(&mut rafts).is_empty());
+        deliver(&mut rafts, later, "n1", outbox);
+        assert_eq!(ready(&mut rafts), vec![second]);
+
+        // dropped once the leader steps down.
+        let (_, outbox) = read(&mut rafts);
+        let vote = RaftRpc::RequestVote {
+            term: 9,
+            candidate_id: "n3".into(),
+            last_log_index: 1,
+            last_log_term: 1,
+        };
+        rafts
+            .get_mut("n1")
+            .unwrap()
+            .handle(later, "n3".into(), vote);
+        deliver(&mut rafts, later, "n1", outbox);
+        assert!(ready(&mut rafts).is_empty());
+    }
+
+    #[test]
+    fn test_raft_membership_change() {
+        let now = Instant::now();
+        let mut rafts = cluster(now);
+        let later = now + Duration::from_secs(1);
+        let outbox = rafts.get_mut("n1").unwrap().tick(later);
+        deliver(&mut rafts, later, "n1", outbox);
+        let old: Vec<NodeId> = ["n1", "n2", "n3"].map(NodeId::from).to_vec();
+        let new: Vec<NodeId> = ["n2", "n3", "n4"].map(NodeId::from).to_vec();
+        let mut joining = Raft::new("n4".into(), &old, Config::default(), now);
+        // not a member yet, it waits.
+        assert!(joining.tick(later).is_empty());
+        rafts.insert("n4".into(), joining);
+        let (_, outbox) = rafts.get_mut("n1").unwrap().propose(7.into()).unwrap();
+        deliver(&mut rafts, later, "n1", outbox);
+
+        // n4 in, n1 out.
+        let leader = rafts.get_mut("n1").unwrap();
+        let (_, outbox) = leader.change_membership(&new).unwrap();
+        assert_eq!(
+            leader.change_membership(&old).unwrap_err(),
+            Some("n1".into())
+        );
+        deliver(&mut rafts, later, "n1", outbox);
+        let leader = rafts.get_mut("n1").unwrap();
+        assert_eq!(leader.membership(), &Membership::Stable(new.clone()));
+        assert!(leader.is_leader()); // until the new configuration is committed.
+        let outbox = leader.tick(later + Duration::from_millis(100));
+        deliver(&mut rafts, later, "n1", outbox);
+        assert!(!rafts["n1"].is_leader());
+        assert_eq!(rafts["n4"].membership(), &Membership::Stable(new));
+        let mut applied = Vec::new();
+        let n4 = rafts.get_mut("n4").unwrap();
+        n4.apply_committed(|_, command| applied.push(command.clone()));
+        assert_eq!(applied, vec![Value::from(7)]);
+
+        // the new configuration elects a leader of its own, the removed node stays out.
+        let much_later = later + Duration::from_secs(10);
+        assert!(rafts.get_mut("n1").unwrap().tick(much_later).is_empty());
+        let outbox = rafts.get_mut("n4").unwrap().tick(much_later);
+        deliver(&mut rafts, much_later, "n4", outbox);
+        assert!(rafts["n4"].is_leader());
+    }
+
+    #[test]
+    fn test_raft_truncates_conflicting_entries() {
+        let now = Instant::now();
+        let ids: Vec<NodeId> = ["n1", "n2"].map(NodeId::from).to_vec();
+        let mut follower = Raft::new("n2".into(), &ids, Config::default(), now);
+        let stale = |term| Entry {
+            term,
+            command: Value::Null,
+            config: None,
+        };
+
+        let rpc = RaftRpc::AppendEntries {
+            term: 1,
+            leader_id: "n1".into(),
+            prev_log_index: 0,
+            prev_log_term: 0,
+            entries: vec![stale(1), stale(1)],
+            leader_commit: 0,
+            round: 0,
+        };
+        follower.handle(now, "n1".into(), rpc);
+        assert_eq!(follower.last_log_index(), 2);
+
+        // new leader in term 2 overwrote index 2.
+        let rpc = RaftRpc::AppendEntries {
+            term: 2,
+            leader_id: "n1".into(),
+            prev_log_index: 1,
+            prev_log_term: 1,
+            entries: vec![stale(2)],
+            leader_commit: 2,
+            round: 0,
+        };
+        let outbox = follower.handle(now, "n1".into(), rpc);
+        assert_eq!(
+            outbox[0].1,
+            RaftRpc::AppendEntriesResult {
+                term: 2,
+                success: true,
+                match_index: 2,
+                round: 0
+            }
+        );
+        assert_eq!(follower.entry(2).term, 2);
+        assert_eq!(follower.commit_index(), 2);
+    }
+}
@@ -0,0 +1,98 @@
+use std::io::{BufRead, Write};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::clock::ManualClock;
+use crate::core::{Message, Node};
+use crate::helper::Result;
+
+// one line of a recording, "at" is in microseconds since the recording started.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    Received { at: u64, message: Message },
+    Sent { at: u64, message: Message },
+    Tick { at: u64 },
+}
+
+// appends every event of a run to "output" as json lines. times come from the caller, on the
+// node's clock ("Node::now"), so a run on a "ManualClock" records the same times every time.
+pub struct Recorder {
+    start: Instant,
+    output: Box<dyn Write + Send>,
+}
+
+impl Recorder {
+    // "start" is when the recording starts, on the clock the later times are read from.
+    pub fn new<W: Write + Send + 'static>(output: W, start: Instant) -> Self {
+        Self {
+            start,
+            output: Box::new(output),
+        }
+    }
+
+    pub fn received(&mut self, now: Instant, message: &Message) {
+        let at = self.elapsed(now);
+        self.record(Event::Received {
+            at,
+            message: message.clone(),
+        });
+    }
+
+    pub fn sent(&mut self, now: Instant, messages: &[Message]) {
+        let at = self.elapsed(now);
+        for message in messages {
+            self.record(Event::Sent {
+                at,
+                message: message.clone(),
+            });
+        }
+    }
+
+    pub fn tick(&mut self, now: Instant) {
+        let at = self.elapsed(now);
+        self.record(Event::Tick { at });
+    }
+
+    pub fn flush(&mut self) {
+        self.output
+            .flush()
+            .expect("Recording should be flushed on shutdown.");
+    }
+
+    fn elapsed(&self, now: Instant) -> u64 {
+        now.saturating_duration_since(self.start).as_micros() as u64
+    }
+
+    fn record(&mut self, event: Event) {
+        let line = serde_json::to_string(&event).expect("Recorder should serialize the event.");
+        writeln!(self.output, "{line}").expect("An event should be written to the recording.");
+    }
+}
+
+// feeds the received messages and ticks of a recording back through "node", in order,
+// and returns what the node sent. the node runs on a "ManualClock" set to the recorded time
+// of every event (its wall clock starting at the unix epoch), so rpc timeouts, retries and
+// leases expire at the same point of the run, however fast the replay goes. recorded "sent"
+// events are only there to compare.
+pub fn replay<R: BufRead>(node: &mut Node, recording: R) -> Result<Vec<Message>> {
+    let clock = ManualClock::new(0);
+    node.set_clock(clock.clone());
+    let mut sent = Vec::new();
+    for line in recording.lines() {
+        let event = serde_json::from_str::<Event>(&line?)?;
+        let (Event::Received { at, .. } | Event::Sent { at, .. } | Event::Tick { at }) = event;
+        clock.set_elapsed(Duration::from_micros(at));
+        let replies = match event {
+            Event::Received { message, .. } => node.process(message),
+            Event::Tick { .. } => node.tick(node.now()),
+            Event::Sent { .. } => continue,
+        };
+        // errors were traced by the node, like they were while recording.
+        if let Ok(replies) = replies {
+            sent.extend(replies);
+        }
+    }
+    Ok(sent)
+}
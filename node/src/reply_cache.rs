@@ -0,0 +1,149 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use crate::core::{Message, MessageId, Workload};
+use crate::node_id::NodeId;
+
+pub(crate) enum Lookup {
+    // first time the request is seen, it's up to the handler.
+    New,
+    // handled, but the reply is still to come (e.g. waiting on an rpc), for up to
+    // "IN_FLIGHT_TTL".
+    InFlight,
+    Replay(Message),
+}
+
+// a request left unanswered this long is taken to have been dropped (e.g. the rpc it waited on
+// timed out without a reply), a retry is handled again rather than ignored forever.
+pub(crate) const IN_FLIGHT_TTL: Duration = Duration::from_secs(10);
+
+// a request, the reply to it once sent, and when it was last handled.
+type Entry = (Workload, Option<Message>, Instant);
+
+#[derive(Default)]
+struct Requests {
+    entries: HashMap<MessageId, Entry>,
+    // oldest first, with when they were first seen.
+    order: VecDeque<(MessageId, Instant)>,
+}
+
+// the replies to the last "capacity" requests of every client and peer, by "msg_id", see
+// "Node::enable_reply_cache".
+pub(crate) struct ReplyCache {
+    capacity: usize,
+    senders: HashMap<NodeId, Requests>,
+}
+
+impl ReplyCache {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            senders: HashMap::new(),
+        }
+    }
+
+    // a request not seen before is remembered as in flight. a retry is the same request all
+    // over again, a different body under a known "msg_id" is a new request.
+    pub(crate) fn lookup(
+        &mut self,
+        src: &NodeId,
+        msg_id: MessageId,
+        body: &Workload,
+        now: Instant,
+    ) -> Lookup {
+        let requests = self.senders.entry(*src).or_default();
+        match requests.entries.get_mut(&msg_id) {
+            Some((request, Some(reply), _)) if request == body => {
+                return Lookup::Replay(reply.clone())
+            }
+            Some((request, None, handled)) if request == body && now < *handled + IN_FLIGHT_TTL => {
+                return Lookup::InFlight
+            }
+            Some(entry) => {
+                *entry = (body.clone(), None, now);
+                return Lookup::New;
+            }
+            None => {}
+        }
+        if requests.order.len() == self.capacity {
+            if let Some((oldest, _)) = requests.order.pop_front() {
+                requests.entries.remove(&oldest);
+            }
+        }
+        requests.entries.insert(msg_id, (body.clone(), None, now));
+        requests.order.push_back((msg_id, now));
+        Lookup::New
+    }
+
+    // only replies to requests in flight are kept.
+    pub(crate) fn replied(&mut self, reply: &Message) {
+        let Some(in_reply_to) = reply.body.in_reply_to() else {
+            return;
+        };
+        let entry = self
+            .senders
+            .get_mut(&reply.dest)
+            .and_then(|requests| requests.entries.get_mut(&in_reply_to));
+        if let Some((_, entry @ None, _)) = entry {
+            *entry = Some(reply.clone());
+        }
+    }
+
+    // a request that failed without a reply is handled again when retried.
+    pub(crate) fn forget(&mut self, src: &NodeId, msg_id: MessageId) {
+        if let Some(requests) = self.senders.get_mut(src) {
+            if requests.entries.remove(&msg_id).is_some() {
+                requests.order.retain(|(id, _)| *id != msg_id);
+            }
+        }
+    }
+
+    // forgets the requests first seen before "before", retries of those are handled again.
+    // returns how many were forgotten.
+    pub(crate) fn prune(&mut self, before: Instant) -> usize {
+        let mut pruned = 0;
+        for requests in self.senders.values_mut() {
+            while let Some((msg_id, _)) = requests.order.front().filter(|(_, at)| *at < before) {
+                requests.entries.remove(msg_id);
+                requests.order.pop_front();
+                pruned += 1;
+            }
+        }
+        self.senders
+            .retain(|_, requests| !requests.order.is_empty());
+        pruned
+    }
+
+    // requests remembered, of every sender.
+    pub(crate) fn len(&self) -> usize {
+        self.senders
+            .values()
+            .map(|requests| requests.entries.len())
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reply_cache_in_flight_expires() {
+        let mut cache = ReplyCache::new(16);
+        let (c1, start) = ("c1".into(), Instant::now());
+        let body = Workload::read(1, "k".into());
+        assert!(matches!(cache.lookup(&c1, 1, &body, start), Lookup::New));
+        assert!(matches!(
+            cache.lookup(&c1, 1, &body, start),
+            Lookup::InFlight
+        ));
+        // never answered, a retry is handled again, and in flight from then on.
+        let later = start + IN_FLIGHT_TTL;
+        assert!(matches!(cache.lookup(&c1, 1, &body, later), Lookup::New));
+        assert!(matches!(
+            cache.lookup(&c1, 1, &body, later),
+            Lookup::InFlight
+        ));
+        assert_eq!(cache.len(), 1);
+    }
+}
@@ -0,0 +1,170 @@
+use std::time::Duration;
+
+use crate::metrics::RttEstimator;
+use crate::rng::Rng;
+
+// how long to wait for a reply, attempt after attempt, before trying again: "Node::rpc_with_retry"
+// and the outbox ("Node::send_reliably") go by one. policies combine, e.g.
+// "Exponential::new(base, max).with_jitter(0.2, seed).max_attempts(5)".
+pub trait RetryPolicy: Send {
+    // "attempt" is 1 for the first one, None gives up before it. "rtt" is what's known of the
+    // round trips to the peer, if anything.
+    fn timeout(&mut self, attempt: u32, rtt: Option<&RttEstimator>) -> Option<Duration>;
+
+    // adds up to "ratio" of every timeout, drawn from a generator seeded with "seed", so that
+    // nodes retrying the same thing don't do it in lockstep.
+    fn with_jitter(self, ratio: f64, seed: u64) -> Jittered<Self>
+    where
+        Self: Sized,
+    {
+        Jittered {
+            inner: self,
+            ratio,
+            rng: Rng::new(seed),
+        }
+    }
+
+    fn max_attempts(self, attempts: u32) -> MaxAttempts<Self>
+    where
+        Self: Sized,
+    {
+        MaxAttempts {
+            inner: self,
+            attempts,
+        }
+    }
+}
+
+// the same timeout every time.
+#[derive(Clone, Copy, Debug)]
+pub struct Fixed(pub Duration);
+
+impl RetryPolicy for Fixed {
+    fn timeout(&mut self, _: u32, _: Option<&RttEstimator>) -> Option<Duration> {
+        Some(self.0)
+    }
+}
+
+// "base", then twice as long every attempt, up to "max".
+#[derive(Clone, Copy, Debug)]
+pub struct Exponential {
+    base: Duration,
+    max: Duration,
+}
+
+impl Exponential {
+    pub fn new(base: Duration, max: Duration) -> Self {
+        Self { base, max }
+    }
+}
+
+impl RetryPolicy for Exponential {
+    fn timeout(&mut self, attempt: u32, _: Option<&RttEstimator>) -> Option<Duration> {
+        let factor = 1 << attempt.saturating_sub(1).min(16);
+        Some(self.base.saturating_mul(factor).min(self.max))
+    }
+}
+
+// per peer: the timeout its round trips call for ("RttEstimator::timeout"), "fallback" until
+// there's any, doubled on every attempt since a retry means it was too short, up to "max".
+#[derive(Clone, Copy, Debug)]
+pub struct Adaptive {
+    fallback: Duration,
+    max: Duration,
+}
+
+impl Adaptive {
+    pub fn new(fallback: Duration, max: Duration) -> Self {
+        Self { fallback, max }
+    }
+}
+
+impl RetryPolicy for Adaptive {
+    fn timeout(&mut self, attempt: u32, rtt: Option<&RttEstimator>) -> Option<Duration> {
+        let timeout = rtt.map_or(self.fallback, RttEstimator::timeout);
+        let factor = 1 << attempt.saturating_sub(1).min(16);
+        Some(timeout.saturating_mul(factor).min(self.max))
+    }
+}
+
+#[derive(Debug)]
+pub struct Jittered<P> {
+    inner: P,
+    ratio: f64,
+    rng: Rng,
+}
+
+impl<P: RetryPolicy> RetryPolicy for Jittered<P> {
+    fn timeout(&mut self, attempt: u32, rtt: Option<&RttEstimator>) -> Option<Duration> {
+        let timeout = self.inner.timeout(attempt, rtt)?;
+        Some(timeout.mul_f64(1.0 + self.ratio * self.rng.next_f64()))
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct MaxAttempts<P> {
+    inner: P,
+    attempts: u32,
+}
+
+impl<P: RetryPolicy> RetryPolicy for MaxAttempts<P> {
+    fn timeout(&mut self, attempt: u32, rtt: Option<&RttEstimator>) -> Option<Duration> {
+        if attempt > self.attempts {
+            return None;
+        }
+        self.inner.timeout(attempt, rtt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timeouts(policy: &mut dyn RetryPolicy, rtt: Option<&RttEstimator>) -> Vec<Option<u64>> {
+        (1..=5)
+            .map(|attempt| {
+                policy
+                    .timeout(attempt, rtt)
+                    .map(|timeout| timeout.as_millis() as u64)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_retry_policies() {
+        let ms = Duration::from_millis;
+        assert_eq!(timeouts(&mut Fixed(ms(50)), None), vec![Some(50); 5]);
+        assert_eq!(
+            timeouts(
+                &mut Exponential::new(ms(100), ms(500)).max_attempts(4),
+                None
+            ),
+            vec![Some(100), Some(200), Some(400), Some(500), None]
+        );
+
+        // adaptive timeouts follow the peer's round trips once there are any.
+        let mut adaptive = Adaptive::new(ms(1_000), ms(2_000));
+        assert_eq!(
+            timeouts(&mut adaptive, None)[..2],
+            [Some(1_000), Some(2_000)]
+        );
+        let rtt = RttEstimator::new(ms(40));
+        let timeout = rtt.timeout().as_millis() as u64;
+        assert_eq!(
+            timeouts(&mut adaptive, Some(&rtt))[..2],
+            [Some(timeout), Some(timeout * 2)]
+        );
+
+        // jitter only ever adds, up to the ratio, and is the same for the same seed.
+        let mut jittered = Fixed(ms(100)).with_jitter(0.5, 7);
+        let drawn = timeouts(&mut jittered, None);
+        assert!(drawn
+            .iter()
+            .all(|timeout| (100..=150).contains(&timeout.unwrap())));
+        assert!(drawn.iter().any(|timeout| *timeout != Some(100)));
+        assert_eq!(
+            drawn,
+            timeouts(&mut Fixed(ms(100)).with_jitter(0.5, 7), None)
+        );
+    }
+}
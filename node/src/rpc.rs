@@ -0,0 +1,290 @@
+use std::collections::HashMap;
+use std::time::Instant;
+
+use serde_json::{json, Value};
+
+use crate::actor::{Actor, Component, Reply};
+use crate::core::{Callback, MessageId};
+use crate::metrics::RttEstimator;
+use crate::node_id::NodeId;
+
+// an outstanding request, timed out requests get a synthesized "timeout" error reply.
+struct PendingRpc {
+    dest: NodeId,
+    sent_at: Instant,
+    deadline: Option<Instant>,
+    callback: Callback,
+}
+
+// the rpcs a node is waiting on, by msg_id, and the round trip times of those answered so far.
+// the node registers a request as it goes out, hands over the replies and the ticks, and gets
+// back the callbacks that are due. running them is up to the node.
+#[derive(Default)]
+pub(crate) struct RpcTracker {
+    pending: HashMap<MessageId, PendingRpc>,
+    rtts: HashMap<NodeId, RttEstimator>,
+}
+
+pub(crate) enum RpcRequest {
+    Register {
+        msg_id: MessageId,
+        dest: NodeId,
+        now: Instant,
+        deadline: Option<Instant>,
+        callback: Callback,
+    },
+    Complete {
+        in_reply_to: MessageId,
+        now: Instant,
+        reply: Reply<Option<Callback>>,
+    },
+    Cancel {
+        msg_id: MessageId,
+    },
+    Expired {
+        now: Instant,
+        reply: Reply<Vec<(MessageId, NodeId, Callback)>>,
+    },
+    Forget {
+        peer: NodeId,
+        reply: Reply<Vec<(MessageId, Callback)>>,
+    },
+    IsPending {
+        msg_id: MessageId,
+        reply: Reply<bool>,
+    },
+    Len {
+        reply: Reply<usize>,
+    },
+    Rtts {
+        reply: Reply<HashMap<NodeId, RttEstimator>>,
+    },
+    State {
+        now: Instant,
+        reply: Reply<Vec<Value>>,
+    },
+}
+
+impl RpcTracker {
+    // the callback waiting on the reply to "in_reply_to", if any. the round trip is recorded.
+    fn complete(&mut self, in_reply_to: MessageId, now: Instant) -> Option<Callback> {
+        let pending = self.pending.remove(&in_reply_to)?;
+        let rtt = now.saturating_duration_since(pending.sent_at);
+        self.rtts
+            .entry(pending.dest)
+            .and_modify(|estimator| estimator.record(rtt))
+            .or_insert_with(|| RttEstimator::new(rtt));
+        Some(pending.callback)
+    }
+
+    // the rpcs past their deadline by "now", in msg_id order.
+    fn expired(&mut self, now: Instant) -> Vec<(MessageId, NodeId, Callback)> {
+        self.take(|pending| pending.deadline.is_some_and(|deadline| deadline <= now))
+            .into_iter()
+            .map(|(msg_id, pending)| (msg_id, pending.dest, pending.callback))
+            .collect()
+    }
+
+    // "peer" left the cluster: its round trip times are of no use anymore, and the rpcs sent to
+    // it won't be answered.
+    fn forget(&mut self, peer: &NodeId) -> Vec<(MessageId, Callback)> {
+        self.rtts.remove(peer);
+        self.take(|pending| pending.dest == *peer)
+            .into_iter()
+            .map(|(msg_id, pending)| (msg_id, pending.callback))
+            .collect()
+    }
+
+    fn take(&mut self, taken: impl Fn(&PendingRpc) -> bool) -> Vec<(MessageId, PendingRpc)> {
+        let mut msg_ids: Vec<MessageId> = self
+            .pending
+            .iter()
+            .filter(|(_, pending)| taken(pending))
+            .map(|(msg_id, _)| *msg_id)
+            .collect();
+        msg_ids.sort();
+        msg_ids
+            .into_iter()
+            .filter_map(|msg_id| Some((msg_id, self.pending.remove(&msg_id)?)))
+            .collect()
+    }
+
+    // the rpcs waiting, in msg_id order, for "dump_state".
+    fn to_json(&self, now: Instant) -> Vec<Value> {
+        let mut pending: Vec<(&MessageId, &PendingRpc)> = self.pending.iter().collect();
+        pending.sort_by_key(|(msg_id, _)| **msg_id);
+        pending
+            .into_iter()
+            .map(|(msg_id, pending)| {
+                json!({
+                    "msg_id": msg_id,
+                    "dest": pending.dest,
+                    "deadline_ms": pending
+                        .deadline
+                        .map(|deadline| deadline.saturating_duration_since(now).as_millis()),
+                })
+            })
+            .collect()
+    }
+}
+
+// answers nobody waits on anymore are dropped.
+impl Component for RpcTracker {
+    type Request = RpcRequest;
+
+    fn handle(&mut self, request: RpcRequest) {
+        match request {
+            RpcRequest::Register {
+                msg_id,
+                dest,
+                now,
+                deadline,
+                callback,
+            } => {
+                let pending = PendingRpc {
+                    dest,
+                    sent_at: now,
+                    deadline,
+                    callback,
+                };
+                self.pending.insert(msg_id, pending);
+            }
+            RpcRequest::Complete {
+                in_reply_to,
+                now,
+                reply,
+            } => {
+                let _ = reply.send(self.complete(in_reply_to, now));
+            }
+            RpcRequest::Cancel { msg_id } => {
+                self.pending.remove(&msg_id);
+            }
+            RpcRequest::Expired { now, reply } => {
+                let _ = reply.send(self.expired(now));
+            }
+            RpcRequest::Forget { peer, reply } => {
+                let _ = reply.send(self.forget(&peer));
+            }
+            RpcRequest::IsPending { msg_id, reply } => {
+                let _ = reply.send(self.pending.contains_key(&msg_id));
+            }
+            RpcRequest::Len { reply } => {
+                let _ = reply.send(self.pending.len());
+            }
+            RpcRequest::Rtts { reply } => {
+                let _ = reply.send(self.rtts.clone());
+            }
+            RpcRequest::State { now, reply } => {
+                let _ = reply.send(self.to_json(now));
+            }
+        }
+    }
+}
+
+// the node's handle on its "RpcTracker", running on a thread of its own.
+pub(crate) struct Rpcs(Actor<RpcTracker>);
+
+impl Default for Rpcs {
+    fn default() -> Self {
+        Rpcs(Actor::spawn("rpcs", RpcTracker::default()))
+    }
+}
+
+impl Rpcs {
+    // "msg_id" went out to "dest" at "now", "deadline" is when it times out, if ever.
+    pub(crate) fn register(
+        &self,
+        msg_id: MessageId,
+        dest: NodeId,
+        now: Instant,
+        deadline: Option<Instant>,
+        callback: Callback,
+    ) {
+        self.0.tell(RpcRequest::Register {
+            msg_id,
+            dest,
+            now,
+            deadline,
+            callback,
+        });
+    }
+
+    pub(crate) fn complete(&self, in_reply_to: MessageId, now: Instant) -> Option<Callback> {
+        self.0.ask(|reply| RpcRequest::Complete {
+            in_reply_to,
+            now,
+            reply,
+        })
+    }
+
+    // no reply is waited on anymore, e.g. the request was never sent.
+    pub(crate) fn cancel(&self, msg_id: MessageId) {
+        self.0.tell(RpcRequest::Cancel { msg_id });
+    }
+
+    pub(crate) fn expired(&self, now: Instant) -> Vec<(MessageId, NodeId, Callback)> {
+        self.0.ask(|reply| RpcRequest::Expired { now, reply })
+    }
+
+    pub(crate) fn forget(&self, peer: NodeId) -> Vec<(MessageId, Callback)> {
+        self.0.ask(|reply| RpcRequest::Forget { peer, reply })
+    }
+
+    pub(crate) fn is_pending(&self, msg_id: MessageId) -> bool {
+        self.0.ask(|reply| RpcRequest::IsPending { msg_id, reply })
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.0.ask(|reply| RpcRequest::Len { reply })
+    }
+
+    pub(crate) fn rtts(&self) -> HashMap<NodeId, RttEstimator> {
+        self.0.ask(|reply| RpcRequest::Rtts { reply })
+    }
+
+    pub(crate) fn rtt(&self, peer: &str) -> Option<RttEstimator> {
+        self.rtts().remove(peer)
+    }
+
+    pub(crate) fn to_json(&self, now: Instant) -> Vec<Value> {
+        self.0.ask(|reply| RpcRequest::State { now, reply })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::Replies;
+    use std::time::Duration;
+
+    fn callback() -> Callback {
+        Box::new(|_, _| Ok(Replies::new()))
+    }
+
+    #[test]
+    fn test_rpc_tracker() {
+        let now = Instant::now();
+        let at = |ms| now + Duration::from_millis(ms);
+        let rpcs = Rpcs::default();
+        rpcs.register(1, "n2".into(), now, None, callback());
+        rpcs.register(2, "n3".into(), now, Some(at(100)), callback());
+        rpcs.register(3, "n3".into(), now, Some(at(50)), callback());
+        rpcs.register(4, "n4".into(), now, None, callback());
+        assert_eq!(rpcs.len(), 4);
+
+        assert!(rpcs.complete(1, at(20)).is_some());
+        assert!(rpcs.complete(1, at(30)).is_none());
+        assert_eq!(rpcs.rtt("n2").unwrap().srtt(), Duration::from_millis(20));
+        assert!(rpcs.rtt("n3").is_none());
+
+        assert!(rpcs.expired(at(40)).is_empty());
+        let expired: Vec<MessageId> = rpcs.expired(at(100)).into_iter().map(|e| e.0).collect();
+        assert_eq!(expired, vec![2, 3]);
+
+        let orphaned: Vec<MessageId> = rpcs.forget("n4".into()).into_iter().map(|e| e.0).collect();
+        assert_eq!(orphaned, vec![4]);
+        rpcs.forget("n2".into());
+        assert!(rpcs.rtt("n2").is_none());
+        assert_eq!(rpcs.len(), 0);
+    }
+}
@@ -0,0 +1,64 @@
+use std::collections::BTreeMap;
+
+use crate::core::BroadcastMessage;
+
+pub type Seq = u64;
+
+// total order through a single sequencer: it numbers every message and
+// every node delivers them strictly in sequence order, buffering gaps.
+#[derive(Debug, Default, Clone)]
+pub struct Sequencer {
+    next_seq: Seq,
+    next_deliver: Seq,
+    pending: BTreeMap<Seq, BroadcastMessage>,
+    delivered: Vec<BroadcastMessage>,
+}
+
+impl Sequencer {
+    // only called on the sequencer node.
+    pub fn assign(&mut self) -> Seq {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        seq
+    }
+
+    // returns the messages that became deliverable, in order.
+    pub fn receive(&mut self, seq: Seq, message: BroadcastMessage) -> Vec<BroadcastMessage> {
+        if seq >= self.next_deliver {
+            self.pending.insert(seq, message);
+        }
+        let mut delivered = Vec::new();
+        while let Some(message) = self.pending.remove(&self.next_deliver) {
+            self.next_deliver += 1;
+            delivered.push(message);
+        }
+        self.delivered.extend(delivered.iter().copied());
+        delivered
+    }
+
+    pub fn delivered(&self) -> &[BroadcastMessage] {
+        &self.delivered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sequencer_delivers_in_order() {
+        let mut sequencer = Sequencer::default();
+        assert_eq!(sequencer.receive(1, 20), Vec::<BroadcastMessage>::new());
+        assert_eq!(sequencer.receive(2, 30), Vec::<BroadcastMessage>::new());
+        assert_eq!(sequencer.receive(0, 10), vec![10, 20, 30]);
+        assert_eq!(sequencer.receive(1, 20), Vec::<BroadcastMessage>::new()); // duplicate.
+        assert_eq!(sequencer.delivered(), &[10, 20, 30]);
+    }
+
+    #[test]
+    fn test_sequencer_assign() {
+        let mut sequencer = Sequencer::default();
+        assert_eq!(sequencer.assign(), 0);
+        assert_eq!(sequencer.assign(), 1);
+    }
+}
@@ -0,0 +1,59 @@
+use std::collections::HashMap;
+
+use crate::core::{code, CodeId, MessageId};
+use crate::node_id::NodeId;
+
+// client sessions, see "Node::enable_sessions". a client's requests are expected in the order
+// of their "msg_id"s, the way maelstrom clients send them. nothing is kept of what a client
+// read or wrote: a client talks to one node, whose store never goes back to an older write
+// ("txn::Store" is last-writer-wins), so the client reads its writes and its reads are
+// monotonic without a session checking them.
+#[derive(Debug, Default)]
+pub struct Sessions {
+    // the last request of every client.
+    clients: HashMap<NodeId, MessageId>,
+}
+
+impl Sessions {
+    // a request with a "msg_id" past the client's last one starts being served. one that isn't
+    // is a stale duplicate, retried after its reply was forgotten or reordered behind newer
+    // requests, and handling it again could undo what came since.
+    pub fn admit(&mut self, client: &NodeId, msg_id: MessageId) -> Result<(), (CodeId, String)> {
+        match self.clients.get(client) {
+            Some(&last) if msg_id <= last => Err((
+                code::ABORT,
+                format!("stale request {msg_id}, {client} is past {last}"),
+            )),
+            _ => {
+                self.clients.insert(*client, msg_id);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.clients.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.clients.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sessions_admit() {
+        let mut sessions = Sessions::default();
+        let (c1, c2) = ("c1".into(), "c2".into());
+        assert!(sessions.admit(&c1, 1).is_ok());
+        assert!(sessions.admit(&c1, 3).is_ok());
+        assert_eq!(sessions.admit(&c1, 3).unwrap_err().0, code::ABORT);
+        assert_eq!(sessions.admit(&c1, 2).unwrap_err().0, code::ABORT);
+        // every client counts on its own.
+        assert!(sessions.admit(&c2, 2).is_ok());
+        assert_eq!(sessions.len(), 2);
+    }
+}
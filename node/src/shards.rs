@@ -0,0 +1,33 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use crate::node_id::NodeId;
+
+// which of "workers" threads handles the messages of "src", see "Runner::with_source_shards".
+// a source always lands on the same worker, which handles its messages in the order they
+// arrived. the hasher isn't seeded, every run routes the same way.
+pub(crate) fn shard(src: &NodeId, workers: usize) -> usize {
+    let mut hasher = DefaultHasher::new();
+    src.hash(&mut hasher);
+    (hasher.finish() % workers.max(1) as u64) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_shard_by_source() {
+        let sources: Vec<NodeId> = (1..=20).map(|n| NodeId::from(format!("n{n}"))).collect();
+        let shards: Vec<usize> = sources.iter().map(|src| shard(src, 4)).collect();
+        assert!(shards.iter().all(|&shard| shard < 4));
+        // stable, and spread over the workers.
+        assert_eq!(
+            shards,
+            sources.iter().map(|src| shard(src, 4)).collect::<Vec<_>>()
+        );
+        assert!(shards.iter().collect::<HashSet<_>>().len() > 1);
+        assert_eq!(shard(&sources[0], 1), 0);
+    }
+}
@@ -0,0 +1,104 @@
+use std::io::{BufRead, ErrorKind};
+
+// how much input the runners ask for at once.
+pub(crate) const READ_CHUNK: usize = 64 * 1024;
+
+// reads newline-delimited input a chunk at a time, whatever one read returned (a burst of
+// messages takes a single call), and splits it into lines itself. a line cut in two at the end
+// of a chunk is carried over to the next one.
+pub(crate) struct LineSplitter<R> {
+    input: R,
+    partial: Vec<u8>,
+}
+
+impl<R: BufRead> LineSplitter<R> {
+    pub(crate) fn new(input: R) -> Self {
+        Self {
+            input,
+            partial: Vec::new(),
+        }
+    }
+
+    // hands every line completed by the next chunk to "line", newline included, each in a
+    // buffer from "buffer". the last line goes out without one if the input ends without one.
+    // returns false once the input ended (or failed), or "line" returned false.
+    pub(crate) fn next_chunk<B, L>(&mut self, mut buffer: B, mut line: L) -> bool
+    where
+        B: FnMut() -> Vec<u8>,
+        L: FnMut(Vec<u8>) -> bool,
+    {
+        let chunk = match self.input.fill_buf() {
+            Ok(chunk) => chunk,
+            Err(e) if e.kind() == ErrorKind::Interrupted => return true,
+            Err(_) => return false,
+        };
+        if chunk.is_empty() {
+            if !self.partial.is_empty() {
+                let mut last = buffer();
+                last.append(&mut self.partial);
+                line(last);
+            }
+            return false;
+        }
+
+        let mut start = 0;
+        while let Some(newline) = chunk[start..].iter().position(|byte| *byte == b'\n') {
+            let end = start + newline + 1;
+            let mut next = buffer();
+            next.append(&mut self.partial);
+            next.extend_from_slice(&chunk[start..end]);
+            start = end;
+            if !line(next) {
+                self.input.consume(start);
+                return false;
+            }
+        }
+        self.partial.extend_from_slice(&chunk[start..]);
+        let read = chunk.len();
+        self.input.consume(read);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufReader;
+
+    fn split(input: &str, capacity: usize) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut splitter = LineSplitter::new(BufReader::with_capacity(capacity, input.as_bytes()));
+        while splitter.next_chunk(Vec::new, |line| {
+            lines.push(String::from_utf8(line).unwrap());
+            true
+        }) {}
+        lines
+    }
+
+    #[test]
+    fn test_line_splitter() {
+        let input = "{\"a\":1}\n{\"b\":2}\n\n{\"c\":3}";
+        let expected = ["{\"a\":1}\n", "{\"b\":2}\n", "\n", "{\"c\":3}"];
+        assert_eq!(split(input, READ_CHUNK), expected);
+        // lines cut at every chunk boundary come out whole.
+        for capacity in 1..=input.len() {
+            assert_eq!(split(input, capacity), expected, "capacity {capacity}");
+        }
+        assert!(split("", 4).is_empty());
+        assert_eq!(split("{}\n", 2), ["{}\n"]);
+    }
+
+    #[test]
+    fn test_line_splitter_stops() {
+        let input = "1\n2\n3\n";
+        let mut splitter = LineSplitter::new(input.as_bytes());
+        let mut lines = Vec::new();
+        assert!(!splitter.next_chunk(Vec::new, |line| {
+            lines.push(line);
+            lines.len() < 2
+        }));
+        assert_eq!(lines, [b"1\n".to_vec(), b"2\n".to_vec()]);
+        // the rest is still there.
+        assert!(splitter.next_chunk(Vec::new, |line| line == b"3\n"));
+    }
+}
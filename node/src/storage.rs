@@ -0,0 +1,456 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::actor::{Actor, Component, Reply};
+use crate::helper::Result;
+
+// where durable state goes, kept out of the workloads: values under a key ("put" replaces,
+// "get" reads back) and sequences of records under a key ("append" adds, "scan" reads back in
+// order). "MemoryStorage" forgets everything with the process, "FileStorage" doesn't.
+pub trait Storage: Send {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>>;
+    fn put(&mut self, key: &str, value: &[u8]) -> Result<()>;
+    fn append(&mut self, key: &str, record: &[u8]) -> Result<()>;
+    fn scan(&self, key: &str) -> Result<Vec<Vec<u8>>>;
+    // drops the value and the records under "key".
+    fn remove(&mut self, key: &str) -> Result<()>;
+    // the keys holding a value or records that start with "prefix", sorted.
+    fn keys(&self, prefix: &str) -> Result<Vec<String>>;
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct MemoryStorage {
+    values: HashMap<String, Vec<u8>>,
+    records: HashMap<String, Vec<Vec<u8>>>,
+}
+
+impl Storage for MemoryStorage {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.values.get(key).cloned())
+    }
+
+    fn put(&mut self, key: &str, value: &[u8]) -> Result<()> {
+        self.values.insert(key.to_owned(), value.to_vec());
+        Ok(())
+    }
+
+    fn append(&mut self, key: &str, record: &[u8]) -> Result<()> {
+        self.records
+            .entry(key.to_owned())
+            .or_default()
+            .push(record.to_vec());
+        Ok(())
+    }
+
+    fn scan(&self, key: &str) -> Result<Vec<Vec<u8>>> {
+        Ok(self.records.get(key).cloned().unwrap_or_default())
+    }
+
+    fn remove(&mut self, key: &str) -> Result<()> {
+        self.values.remove(key);
+        self.records.remove(key);
+        Ok(())
+    }
+
+    fn keys(&self, prefix: &str) -> Result<Vec<String>> {
+        let keys = self.values.keys().chain(self.records.keys());
+        let keys: BTreeSet<&String> = keys.filter(|key| key.starts_with(prefix)).collect();
+        Ok(keys.into_iter().cloned().collect())
+    }
+}
+
+// a storage shared with whoever gave it to the node: a test keeps it while the node crashes,
+// and gives it to the restarted one, see "Network::crash".
+impl<S: Storage> Storage for Arc<Mutex<S>> {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        shared(self).get(key)
+    }
+
+    fn put(&mut self, key: &str, value: &[u8]) -> Result<()> {
+        shared(self).put(key, value)
+    }
+
+    fn append(&mut self, key: &str, record: &[u8]) -> Result<()> {
+        shared(self).append(key, record)
+    }
+
+    fn scan(&self, key: &str) -> Result<Vec<Vec<u8>>> {
+        shared(self).scan(key)
+    }
+
+    fn remove(&mut self, key: &str) -> Result<()> {
+        shared(self).remove(key)
+    }
+
+    fn keys(&self, prefix: &str) -> Result<Vec<String>> {
+        shared(self).keys(prefix)
+    }
+}
+
+fn shared<S>(storage: &Mutex<S>) -> MutexGuard<'_, S> {
+    storage
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+const PUT: u8 = 0;
+const APPEND: u8 = 1;
+const REMOVE: u8 = 2;
+// files smaller than this aren't worth compacting.
+const COMPACT_AFTER: u64 = 1 << 20;
+
+// every write is an entry appended to one file: "[op][key length][key][value length][value]",
+// lengths as 4 bytes little endian. reads are served from memory, rebuilt from the file when
+// it's opened. a torn entry at the end (a crash mid-write) is cut off then, the ones before it
+// are intact. once the file is more than twice what it holds (replaced values, removed keys),
+// it's rewritten with the live entries and swapped in.
+pub struct FileStorage {
+    path: PathBuf,
+    file: File,
+    len: u64,
+    // what a compacted file would take.
+    live: u64,
+    memory: MemoryStorage,
+}
+
+impl FileStorage {
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(error) if error.kind() == ErrorKind::NotFound => Vec::new(),
+            Err(error) => return Err(Box::new(error)),
+        };
+        let mut at = 0;
+        let mut entries = Vec::new();
+        while let Some((op, key, value, next)) = entry(&bytes, at) {
+            entries.push((op, key, value));
+            at = next;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(&path)?;
+        file.set_len(at as u64)?;
+        let mut storage = Self {
+            path,
+            file,
+            len: at as u64,
+            live: 0,
+            memory: MemoryStorage::default(),
+        };
+        for (op, key, value) in entries {
+            storage.remember(op, &key, value);
+        }
+        storage.file.seek(SeekFrom::Start(storage.len))?;
+        Ok(storage)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // applies an entry to memory.
+    fn remember(&mut self, op: u8, key: &str, value: &[u8]) {
+        let memory = &mut self.memory;
+        let replaced = match op {
+            PUT => {
+                self.live += encoded_len(key, value);
+                memory.values.insert(key.to_owned(), value.to_vec())
+            }
+            APPEND => {
+                self.live += encoded_len(key, value);
+                let records = memory.records.entry(key.to_owned()).or_default();
+                records.push(value.to_vec());
+                None
+            }
+            _ => {
+                let records = memory.records.remove(key).unwrap_or_default();
+                let dropped = records.iter().map(|record| encoded_len(key, record));
+                self.live -= dropped.sum::<u64>();
+                memory.values.remove(key)
+            }
+        };
+        if let Some(replaced) = replaced {
+            self.live -= encoded_len(key, &replaced);
+        }
+    }
+
+    fn write(&mut self, op: u8, key: &str, value: &[u8]) -> Result<()> {
+        let encoded = encode(op, key, value);
+        self.file.write_all(&encoded)?;
+        // on disk before the caller goes on, not only handed to the OS: a write survives a
+        // power loss, not just the process going down.
+        self.file.sync_data()?;
+        self.len += encoded.len() as u64;
+        self.remember(op, key, value);
+        if self.len > COMPACT_AFTER && self.len > 2 * self.live {
+            self.compact()?;
+        }
+        Ok(())
+    }
+
+    // the live entries, written to a new file that replaces the old one.
+    fn compact(&mut self) -> Result<()> {
+        let mut bytes = Vec::new();
+        for (key, value) in &self.memory.values {
+            bytes.extend(encode(PUT, key, value));
+        }
+        for (key, records) in &self.memory.records {
+            for record in records {
+                bytes.extend(encode(APPEND, key, record));
+            }
+        }
+        let temporary = self.path.with_extension("compacting");
+        let mut file = File::create(&temporary)?;
+        file.write_all(&bytes)?;
+        file.sync_data()?;
+        fs::rename(&temporary, &self.path)?;
+        self.file = OpenOptions::new().read(true).write(true).open(&self.path)?;
+        self.len = bytes.len() as u64;
+        self.file.seek(SeekFrom::Start(self.len))?;
+        Ok(())
+    }
+}
+
+impl Storage for FileStorage {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.memory.get(key)
+    }
+
+    fn put(&mut self, key: &str, value: &[u8]) -> Result<()> {
+        self.write(PUT, key, value)
+    }
+
+    fn append(&mut self, key: &str, record: &[u8]) -> Result<()> {
+        self.write(APPEND, key, record)
+    }
+
+    fn scan(&self, key: &str) -> Result<Vec<Vec<u8>>> {
+        self.memory.scan(key)
+    }
+
+    fn remove(&mut self, key: &str) -> Result<()> {
+        self.write(REMOVE, key, &[])
+    }
+
+    fn keys(&self, prefix: &str) -> Result<Vec<String>> {
+        self.memory.keys(prefix)
+    }
+}
+
+fn encoded_len(key: &str, value: &[u8]) -> u64 {
+    (9 + key.len() + value.len()) as u64
+}
+
+fn encode(op: u8, key: &str, value: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(encoded_len(key, value) as usize);
+    bytes.push(op);
+    bytes.extend((key.len() as u32).to_le_bytes());
+    bytes.extend(key.as_bytes());
+    bytes.extend((value.len() as u32).to_le_bytes());
+    bytes.extend(value);
+    bytes
+}
+
+// the entry at "at", and where the next one starts. none at the end, or past a torn entry.
+fn entry(bytes: &[u8], at: usize) -> Option<(u8, String, &[u8], usize)> {
+    let mut rest = bytes.get(at..)?;
+    let mut op = [0];
+    rest.read_exact(&mut op).ok()?;
+    let key = chunk(&mut rest)?;
+    let value = chunk(&mut rest)?;
+    let next = bytes.len() - rest.len();
+    let key = String::from_utf8(key.to_vec()).ok()?;
+    (op[0] <= REMOVE).then_some((op[0], key, value, next))
+}
+
+fn chunk<'a>(rest: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let mut len = [0; 4];
+    rest.read_exact(&mut len).ok()?;
+    let len = u32::from_le_bytes(len) as usize;
+    let chunk = rest.get(..len)?;
+    *rest = &rest[len..];
+    Some(chunk)
+}
+
+// errors cross threads as their message.
+type Answer<T> = std::result::Result<T, String>;
+
+pub(crate) enum StorageRequest {
+    Get {
+        key: String,
+        reply: Reply<Answer<Option<Vec<u8>>>>,
+    },
+    Put {
+        key: String,
+        value: Vec<u8>,
+        reply: Reply<Answer<()>>,
+    },
+    Append {
+        key: String,
+        record: Vec<u8>,
+        reply: Reply<Answer<()>>,
+    },
+    Scan {
+        key: String,
+        reply: Reply<Answer<Vec<Vec<u8>>>>,
+    },
+    Remove {
+        key: String,
+        reply: Reply<Answer<()>>,
+    },
+    Keys {
+        prefix: String,
+        reply: Reply<Answer<Vec<String>>>,
+    },
+}
+
+struct Stored(Box<dyn Storage>);
+
+impl Component for Stored {
+    type Request = StorageRequest;
+
+    fn handle(&mut self, request: StorageRequest) {
+        fn answer<T>(reply: Reply<Answer<T>>, result: Result<T>) {
+            let _ = reply.send(result.map_err(|e| e.to_string()));
+        }
+        let storage = &mut self.0;
+        match request {
+            StorageRequest::Get { key, reply } => answer(reply, storage.get(&key)),
+            StorageRequest::Put { key, value, reply } => answer(reply, storage.put(&key, &value)),
+            StorageRequest::Append { key, record, reply } => {
+                answer(reply, storage.append(&key, &record))
+            }
+            StorageRequest::Scan { key, reply } => answer(reply, storage.scan(&key)),
+            StorageRequest::Remove { key, reply } => answer(reply, storage.remove(&key)),
+            StorageRequest::Keys { prefix, reply } => answer(reply, storage.keys(&prefix)),
+        }
+    }
+}
+
+// a storage on a thread of its own, what the node's persistence and logs write to. every call
+// waits for the storage to answer: a write is done when it returns, as with the storage itself.
+pub(crate) struct SpawnedStorage(Actor<Stored>);
+
+impl SpawnedStorage {
+    pub(crate) fn spawn(storage: impl Storage + 'static) -> Self {
+        SpawnedStorage(Actor::spawn("storage", Stored(Box::new(storage))))
+    }
+}
+
+impl Storage for SpawnedStorage {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let key = key.to_owned();
+        Ok(self.0.ask(|reply| StorageRequest::Get { key, reply })?)
+    }
+
+    fn put(&mut self, key: &str, value: &[u8]) -> Result<()> {
+        let (key, value) = (key.to_owned(), value.to_vec());
+        Ok(self
+            .0
+            .ask(|reply| StorageRequest::Put { key, value, reply })?)
+    }
+
+    fn append(&mut self, key: &str, record: &[u8]) -> Result<()> {
+        let (key, record) = (key.to_owned(), record.to_vec());
+        Ok(self
+            .0
+            .ask(|reply| StorageRequest::Append { key, record, reply })?)
+    }
+
+    fn scan(&self, key: &str) -> Result<Vec<Vec<u8>>> {
+        let key = key.to_owned();
+        Ok(self.0.ask(|reply| StorageRequest::Scan { key, reply })?)
+    }
+
+    fn remove(&mut self, key: &str) -> Result<()> {
+        let key = key.to_owned();
+        Ok(self.0.ask(|reply| StorageRequest::Remove { key, reply })?)
+    }
+
+    fn keys(&self, prefix: &str) -> Result<Vec<String>> {
+        let prefix = prefix.to_owned();
+        Ok(self.0.ask(|reply| StorageRequest::Keys { prefix, reply })?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exercise(storage: &mut dyn Storage) {
+        assert_eq!(storage.get("state").unwrap(), None);
+        assert!(storage.scan("log").unwrap().is_empty());
+        storage.put("state", b"one").unwrap();
+        storage.put("state", b"two").unwrap();
+        storage.append("log", b"a").unwrap();
+        storage.append("log", b"").unwrap();
+        storage.append("log", b"c").unwrap();
+        assert_eq!(storage.get("state").unwrap(), Some(b"two".to_vec()));
+        assert_eq!(
+            storage.scan("log").unwrap(),
+            vec![b"a".to_vec(), vec![], b"c".to_vec()]
+        );
+        storage.append("old", b"x").unwrap();
+        assert_eq!(storage.keys("").unwrap(), vec!["log", "old", "state"]);
+        storage.remove("old").unwrap();
+        assert!(storage.keys("o").unwrap().is_empty());
+        assert!(storage.scan("old").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_memory_storage() {
+        exercise(&mut MemoryStorage::default());
+    }
+
+    #[test]
+    fn test_file_storage() {
+        let path = std::env::temp_dir()
+            .join(format!("storage-{}", std::process::id()))
+            .join("n1.log");
+        let _ = fs::remove_file(&path);
+        exercise(&mut FileStorage::open(&path).unwrap());
+
+        // what was written is there after a restart, a torn entry at the end isn't.
+        let mut torn = fs::read(&path).unwrap();
+        let len = torn.len();
+        torn.extend(&encode(APPEND, "log", b"d")[..6]);
+        fs::write(&path, torn).unwrap();
+        let mut storage = FileStorage::open(&path).unwrap();
+        assert_eq!(storage.get("state").unwrap(), Some(b"two".to_vec()));
+        assert_eq!(storage.scan("log").unwrap().len(), 3);
+        assert_eq!(fs::metadata(&path).unwrap().len(), len as u64);
+        storage.append("log", b"d").unwrap();
+        let storage = FileStorage::open(&path).unwrap();
+        assert_eq!(storage.scan("log").unwrap()[3], b"d");
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_file_storage_compacts() {
+        let path = std::env::temp_dir().join(format!("storage-compact-{}.log", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut storage = FileStorage::open(&path).unwrap();
+        let value = vec![7; 64 * 1024];
+        for _ in 0..64 {
+            storage.put("state", &value).unwrap();
+        }
+        storage.append("log", b"a").unwrap();
+        // 64 copies were written, about one is kept.
+        assert!(fs::metadata(&path).unwrap().len() < 8 * value.len() as u64);
+        let storage = FileStorage::open(&path).unwrap();
+        assert_eq!(storage.get("state").unwrap(), Some(value));
+        assert_eq!(storage.scan("log").unwrap(), vec![b"a".to_vec()]);
+        fs::remove_file(&path).unwrap();
+    }
+}
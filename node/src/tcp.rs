@@ -0,0 +1,82 @@
+use std::io::{self, BufReader, ErrorKind, Write};
+use std::net::{TcpListener, TcpStream};
+
+use tracing::warn;
+
+use crate::core::Node;
+use crate::split::READ_CHUNK;
+use crate::Runner;
+
+// the node over a TCP connection instead of STDIN/STDOUT, same newline-delimited JSON both
+// ways: "glomers echo --listen 127.0.0.1:7000", then "nc 127.0.0.1 7000" and type messages.
+impl Runner<BufReader<TcpStream>, Connection> {
+    // waits for one connection on "listener" and runs the node over it, until it's closed
+    // (like STDIN) or a signal arrives.
+    pub fn accept(node: Node, listener: &TcpListener) -> io::Result<Self> {
+        let (stream, _) = listener.accept()?;
+        stream.set_nodelay(true)?;
+        let reader = BufReader::with_capacity(READ_CHUNK, stream.try_clone()?);
+        Ok(Runner::with_io(node, reader, Connection(stream)))
+    }
+}
+
+// the writing half of the connection. a peer that went away (netcat closed without
+// half-closing) doesn't take the node down with it, what's left to write is dropped.
+pub struct Connection(TcpStream);
+
+fn gone(e: &io::Error) -> bool {
+    matches!(e.kind(), ErrorKind::BrokenPipe | ErrorKind::ConnectionReset)
+}
+
+impl Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.0.write(buf) {
+            Err(e) if gone(&e) => {
+                warn!(error = %e, "connection closed, output dropped");
+                Ok(buf.len())
+            }
+            written => written,
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.0.flush() {
+            Err(e) if gone(&e) => Ok(()),
+            flushed => flushed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{Message, Workload};
+    use std::io::{BufRead, Read};
+    use std::net::Shutdown;
+    use std::thread;
+
+    #[test]
+    fn test_runner_over_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let mut runner = Runner::accept(Node::default(), &listener).unwrap();
+            runner.start(); // returns once the client half-closes.
+            runner.node().node_id()
+        });
+
+        let mut client = TcpStream::connect(addr).unwrap();
+        let init = r#"{"src":"c1","dest":"n1","body":{"type":"init","msg_id":1,"node_id":"n1","node_ids":["n1"]}}"#;
+        writeln!(client, "{init}").unwrap();
+        writeln!(client, "not json").unwrap();
+        let mut lines = BufReader::new(client.try_clone().unwrap()).lines();
+        let reply: Message = serde_json::from_str(&lines.next().unwrap().unwrap()).unwrap();
+        assert_eq!(reply.body, Workload::InitOk { in_reply_to: 1 });
+
+        client.shutdown(Shutdown::Write).unwrap();
+        assert_eq!(server.join().unwrap(), "n1");
+        let mut rest = String::new();
+        client.read_to_string(&mut rest).unwrap();
+        assert!(rest.is_empty());
+    }
+}
@@ -0,0 +1,104 @@
+use crate::core::{Message, MessageId, Workload};
+
+// the parts of "echo_ok" and "broadcast_ok" replies that never change, in the order serde
+// writes the fields. "src", "dest", the ids and the echoed text are spliced in between.
+const SRC: &[u8] = br#"{"src":"#;
+const DEST: &[u8] = br#","dest":"#;
+const ECHO_OK: &[u8] = br#","body":{"type":"echo_ok","in_reply_to":"#;
+const BROADCAST_OK: &[u8] = br#","body":{"type":"broadcast_ok","in_reply_to":"#;
+const MSG_ID: &[u8] = br#","msg_id":"#;
+const ECHO: &[u8] = br#","echo":"#;
+const END: &[u8] = b"}}";
+
+// writes "message" the way "serde_json::to_writer" would, byte for byte, when it's one of the
+// fixed-shape replies sent at a high rate. anything else goes through serde.
+pub fn write_message(output: &mut Vec<u8>, message: &Message) -> serde_json::Result<()> {
+    match &message.body {
+        Workload::EchoOk {
+            in_reply_to,
+            msg_id,
+            echo,
+        } => {
+            head(output, message, ECHO_OK, *in_reply_to, *msg_id)?;
+            output.extend_from_slice(ECHO);
+            string(output, echo)?;
+        }
+        Workload::BroadcastOk {
+            in_reply_to,
+            msg_id,
+        } => head(output, message, BROADCAST_OK, *in_reply_to, *msg_id)?,
+        _ => return serde_json::to_writer(output, message),
+    }
+    output.extend_from_slice(END);
+    Ok(())
+}
+
+fn head(
+    output: &mut Vec<u8>,
+    message: &Message,
+    body: &[u8],
+    in_reply_to: MessageId,
+    msg_id: MessageId,
+) -> serde_json::Result<()> {
+    let mut ids = itoa::Buffer::new();
+    output.extend_from_slice(SRC);
+    string(output, &message.src)?;
+    output.extend_from_slice(DEST);
+    string(output, &message.dest)?;
+    output.extend_from_slice(body);
+    output.extend_from_slice(ids.format(in_reply_to).as_bytes());
+    output.extend_from_slice(MSG_ID);
+    output.extend_from_slice(ids.format(msg_id).as_bytes());
+    Ok(())
+}
+
+// node ids and most echoes need no escaping, and are copied as they are.
+fn string(output: &mut Vec<u8>, value: &str) -> serde_json::Result<()> {
+    if value
+        .bytes()
+        .any(|byte| byte < 0x20 || byte == b'"' || byte == b'\\')
+    {
+        return serde_json::to_writer(output, value);
+    }
+    output.push(b'"');
+    output.extend_from_slice(value.as_bytes());
+    output.push(b'"');
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::message::msg;
+
+    #[test]
+    fn test_template_matches_serde() {
+        let echo_ok = |echo: &str| Workload::EchoOk {
+            in_reply_to: 1,
+            msg_id: 2,
+            echo: echo.to_owned(),
+        };
+        let broadcast_ok = Workload::BroadcastOk {
+            in_reply_to: 0,
+            msg_id: u32::MAX,
+        };
+        let messages = [
+            msg().from("n1").to("c1").body(echo_ok("Please echo 35")),
+            msg()
+                .from("n1")
+                .to("c1")
+                .body(echo_ok("a \"quoted\"\n\\ echo")),
+            msg().from("n1").to("c1").body(echo_ok("")),
+            msg().from("n1").to("n2").body(broadcast_ok),
+            msg().from("n1").to("c1").id(3).broadcast(7),
+        ];
+        for message in messages {
+            let mut output = Vec::new();
+            write_message(&mut output, &message).unwrap();
+            assert_eq!(
+                String::from_utf8(output).unwrap(),
+                serde_json::to_string(&message).unwrap()
+            );
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::error;
+use std::fmt::{Display, Formatter};
+
+use crate::core::{BroadcastMessage, Message, NodeId, Workload};
+
+// the values some node didn't end up with, or has without them ever being broadcast, by node.
+#[derive(Debug, PartialEq)]
+pub struct NotConverged {
+    pub injected: usize,
+    pub missing: BTreeMap<NodeId, Vec<BroadcastMessage>>,
+    pub unexpected: BTreeMap<NodeId, Vec<BroadcastMessage>>,
+}
+
+impl Display for NotConverged {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "broadcast did not converge on {} values:", self.injected)?;
+        for (node_id, values) in &self.missing {
+            write!(f, "\n  {node_id} is missing {values:?}")?;
+        }
+        for (node_id, values) in &self.unexpected {
+            write!(f, "\n  {node_id} has values never broadcast {values:?}")?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for NotConverged {}
+
+// what every node read last, checked against the values clients broadcast. e.g.
+// Convergence::new(0..10), then "read_ok" with the final reads of all nodes, then "check".
+#[derive(Debug, Default)]
+pub struct Convergence {
+    injected: BTreeSet<BroadcastMessage>,
+    reads: BTreeMap<NodeId, BTreeSet<BroadcastMessage>>,
+}
+
+impl Convergence {
+    pub fn new(injected: impl IntoIterator<Item = BroadcastMessage>) -> Self {
+        Self {
+            injected: injected.into_iter().collect(),
+            reads: BTreeMap::new(),
+        }
+    }
+
+    pub fn inject(&mut self, value: BroadcastMessage) {
+        self.injected.insert(value);
+    }
+
+    // a node's answer to a broadcast "read", the last one of a node counts. other messages
+    // (kv "read_ok"s included) are ignored.
+    pub fn read_ok(&mut self, reply: &Message) {
+        if let Workload::ReadOk {
+            messages: Some(messages),
+            ..
+        } = &reply.body
+        {
+            self.read(reply.src, messages.iter().copied());
+        }
+    }
+
+    // what "node_id" has, when read off the node itself rather than from a reply.
+    pub fn read(&mut self, node_id: NodeId, values: impl IntoIterator<Item = BroadcastMessage>) {
+        self.reads.insert(node_id, values.into_iter().collect());
+    }
+
+    // every node that was read has every value injected, and nothing else.
+    pub fn check(&self) -> Result<(), NotConverged> {
+        let mut missing = BTreeMap::new();
+        let mut unexpected = BTreeMap::new();
+        for (node_id, read) in &self.reads {
+            let values: Vec<BroadcastMessage> = self.injected.difference(read).copied().collect();
+            if !values.is_empty() {
+                missing.insert(*node_id, values);
+            }
+            let values: Vec<BroadcastMessage> = read.difference(&self.injected).copied().collect();
+            if !values.is_empty() {
+                unexpected.insert(*node_id, values);
+            }
+        }
+        if missing.is_empty() && unexpected.is_empty() {
+            return Ok(());
+        }
+        Err(NotConverged {
+            injected: self.injected.len(),
+            missing,
+            unexpected,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::message::msg;
+    use std::sync::Arc;
+
+    fn read_ok(node_id: &str, messages: Vec<BroadcastMessage>) -> Message {
+        let body = Workload::ReadOk {
+            in_reply_to: 1,
+            msg_id: 2,
+            messages: Some(Arc::new(messages)),
+            value: None,
+        };
+        msg().from(node_id).to("c1").body(body)
+    }
+
+    #[test]
+    fn test_convergence() {
+        let mut convergence = Convergence::new(1..=4);
+        convergence.read_ok(&read_ok("n1", vec![4, 2, 1, 3]));
+        convergence.read_ok(&read_ok("n2", vec![1]));
+        convergence.read_ok(&read_ok("n3", vec![1, 2, 3, 9]));
+        let error = convergence.check().unwrap_err();
+        assert_eq!(error.missing.len(), 2);
+        assert_eq!(error.missing["n2"], vec![2, 3, 4]);
+        assert_eq!(error.missing["n3"], vec![4]);
+        assert_eq!(error.unexpected["n3"], vec![9]);
+        assert_eq!(
+            error.to_string(),
+            "broadcast did not converge on 4 values:\n  n2 is missing [2, 3, 4]\n  \
+             n3 is missing [4]\n  n3 has values never broadcast [9]"
+        );
+
+        // a later read replaces an earlier one.
+        convergence.read_ok(&read_ok("n2", vec![1, 2, 3, 4]));
+        convergence.read("n3".into(), 1..=4);
+        assert!(convergence.check().is_ok());
+    }
+}
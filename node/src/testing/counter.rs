@@ -0,0 +1,309 @@
+use std::collections::HashMap;
+use std::error;
+use std::fmt::{Display, Formatter};
+
+use crate::core::{code, Message, MessageId, NodeId, Workload};
+
+// an "add" to a grow-only counter, as far as its client could tell.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Add {
+    pub delta: u64,
+    pub start: u64,
+    // none when the client never got a definite answer (timeout, crash): the add may have
+    // taken effect at any point after "start", or not at all.
+    pub end: Option<u64>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Read {
+    pub value: u64,
+    pub start: u64,
+    pub end: u64,
+}
+
+// how far off a read was.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Miscount {
+    // that much of what was acknowledged before the read (staleness allowed for) is missing.
+    Lost(u64),
+    // that much more than all the adds sent before the read add up to, some were counted twice.
+    DoubleApplied(u64),
+}
+
+// reads that don't add up, see "CounterHistory::check".
+#[derive(Debug, PartialEq)]
+pub struct Miscounted {
+    // what the acknowledged adds sum up to, and all of them, unknown outcomes included.
+    pub acknowledged: u64,
+    pub possible: u64,
+    pub reads: Vec<(Read, Miscount)>,
+    // no read started once the adds settled, the final value was never checked.
+    pub unsettled: bool,
+}
+
+impl Display for Miscounted {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "counter adds up to {}", self.acknowledged)?;
+        if self.possible > self.acknowledged {
+            write!(f, " ({} with unacknowledged adds)", self.possible)?;
+        }
+        write!(f, ", but:")?;
+        for (read, miscount) in &self.reads {
+            let Read { value, start, end } = read;
+            match miscount {
+                Miscount::Lost(lost) => {
+                    write!(f, "\n  [{start}, {end}] read {value}, {lost} lost")?
+                }
+                Miscount::DoubleApplied(extra) => write!(
+                    f,
+                    "\n  [{start}, {end}] read {value}, {extra} double-applied"
+                )?,
+            }
+        }
+        if self.unsettled {
+            write!(f, "\n  it was never read after the adds settled")?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for Miscounted {}
+
+// "add" and "read" operations of a grow-only counter workload, built from the requests clients
+// sent and the replies they got. times are in any unit, as long as it's the same for all.
+#[derive(Debug, Default)]
+pub struct CounterHistory {
+    invoked: HashMap<(NodeId, MessageId), (u64, Message)>,
+    adds: Vec<Add>,
+    reads: Vec<Read>,
+}
+
+// the "delta" of an "add" request, which isn't a "Workload" of its own.
+fn delta(body: &Workload) -> Option<u64> {
+    match body {
+        Workload::Custom(body) if body["type"] == "add" => body["delta"].as_u64(),
+        _ => None,
+    }
+}
+
+impl CounterHistory {
+    pub fn push_add(&mut self, add: Add) {
+        self.adds.push(add);
+    }
+
+    pub fn push_read(&mut self, read: Read) {
+        self.reads.push(read);
+    }
+
+    // a request from a client, other messages are ignored.
+    pub fn invoke(&mut self, at: u64, request: &Message) {
+        let counter = matches!(request.body, Workload::Read { key: None, .. })
+            || delta(&request.body).is_some();
+        if let (true, Some(msg_id)) = (counter, request.body.msg_id()) {
+            self.invoked
+                .insert((request.src, msg_id), (at, request.clone()));
+        }
+    }
+
+    // a reply to a client, matched with its request by "in_reply_to".
+    pub fn complete(&mut self, at: u64, reply: &Message) {
+        let Some(in_reply_to) = reply.body.in_reply_to() else {
+            return;
+        };
+        let Some((start, request)) = self.invoked.remove(&(reply.dest, in_reply_to)) else {
+            return;
+        };
+        // timeouts and crashes leave the outcome open, other errors mean nothing happened.
+        let indefinite = matches!(
+            reply.body,
+            Workload::Error {
+                code: code::TIMEOUT | code::CRASH,
+                ..
+            }
+        );
+        match (delta(&request.body), &reply.body) {
+            (Some(delta), Workload::Custom(body)) if body["type"] == "add_ok" => {
+                self.push_add(Add {
+                    delta,
+                    start,
+                    end: Some(at),
+                })
+            }
+            (Some(delta), _) if indefinite => self.push_add(Add {
+                delta,
+                start,
+                end: None,
+            }),
+            (None, Workload::ReadOk { value, .. }) => {
+                if let Some(value) = value.as_ref().and_then(|value| value.as_u64()) {
+                    self.push_read(Read {
+                        value,
+                        start,
+                        end: at,
+                    });
+                }
+            }
+            // a read with an unknown outcome tells nothing, neither does a definite failure.
+            _ => {}
+        }
+    }
+
+    // completed adds, plus the ones still waiting for a reply.
+    pub fn adds(&self) -> Vec<Add> {
+        let mut adds = self.adds.clone();
+        for (start, request) in self.invoked.values() {
+            if let Some(delta) = delta(&request.body) {
+                adds.push(Add {
+                    delta,
+                    start: *start,
+                    end: None,
+                });
+            }
+        }
+        adds
+    }
+
+    // every read counts at least the adds acknowledged "staleness" before it started (0 for
+    // lin-kv, the window a seq-kv read may lag behind by otherwise), and at most all the adds
+    // sent before it ended. and once the adds settled, some read has to have checked the total.
+    pub fn check(&self, staleness: u64) -> Result<(), Miscounted> {
+        let adds = self.adds();
+        let mut reads = Vec::new();
+        for read in &self.reads {
+            let counted: u64 = adds
+                .iter()
+                .filter(|add| add.end.is_some_and(|end| end + staleness <= read.start))
+                .map(|add| add.delta)
+                .sum();
+            let possible: u64 = adds
+                .iter()
+                .filter(|add| add.start < read.end)
+                .map(|add| add.delta)
+                .sum();
+            if read.value < counted {
+                reads.push((read.clone(), Miscount::Lost(counted - read.value)));
+            } else if read.value > possible {
+                reads.push((read.clone(), Miscount::DoubleApplied(read.value - possible)));
+            }
+        }
+        reads.sort_by_key(|(read, _)| read.start);
+
+        let settled = adds
+            .iter()
+            .map(|add| add.end.unwrap_or(add.start) + staleness)
+            .max()
+            .unwrap_or_default();
+        let unsettled = !self.reads.iter().any(|read| read.start >= settled);
+        if reads.is_empty() && !unsettled {
+            return Ok(());
+        }
+        let acknowledged = adds.iter().filter(|add| add.end.is_some());
+        Err(Miscounted {
+            acknowledged: acknowledged.map(|add| add.delta).sum(),
+            possible: adds.iter().map(|add| add.delta).sum(),
+            reads,
+            unsettled,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::message::msg;
+    use serde_json::json;
+
+    fn add(start: u64, end: Option<u64>, delta: u64) -> Add {
+        Add { delta, start, end }
+    }
+
+    fn read(start: u64, end: u64, value: u64) -> Read {
+        Read { value, start, end }
+    }
+
+    #[test]
+    fn test_counter_adds_up() {
+        let mut history = CounterHistory::default();
+        history.push_add(add(0, Some(10), 3));
+        history.push_add(add(5, Some(20), 4));
+        // concurrent with the second add, it may or may not count it.
+        history.push_read(read(12, 18, 3));
+        history.push_read(read(15, 25, 7));
+        history.push_read(read(30, 31, 7));
+        assert!(history.check(0).is_ok());
+
+        // counting one twice.
+        history.push_read(read(40, 41, 10));
+        // losing one.
+        history.push_read(read(21, 22, 3));
+        let error = history.check(0).unwrap_err();
+        assert_eq!(
+            error.reads,
+            vec![
+                (read(21, 22, 3), Miscount::Lost(4)),
+                (read(40, 41, 10), Miscount::DoubleApplied(3)),
+            ]
+        );
+        assert!(!error.unsettled);
+        assert_eq!(
+            error.to_string(),
+            "counter adds up to 7, but:\n  [21, 22] read 3, 4 lost\n  \
+             [40, 41] read 10, 3 double-applied"
+        );
+    }
+
+    #[test]
+    fn test_counter_staleness_and_unknown_outcomes() {
+        let mut history = CounterHistory::default();
+        history.push_add(add(0, Some(10), 3));
+        // timed out, it may have been applied or not.
+        history.push_add(add(0, None, 5));
+        // a stale read, within the window.
+        history.push_read(read(30, 31, 0));
+        assert_eq!(history.check(0).unwrap_err().reads[0].1, Miscount::Lost(3));
+        let error = history.check(50).unwrap_err();
+        assert!(error.reads.is_empty());
+        assert!(error.unsettled);
+        assert_eq!(
+            error.to_string(),
+            "counter adds up to 3 (8 with unacknowledged adds), but:\n  \
+             it was never read after the adds settled"
+        );
+
+        // either way is fine once settled.
+        history.push_read(read(60, 61, 3));
+        history.push_read(read(70, 71, 8));
+        assert!(history.check(50).is_ok());
+    }
+
+    #[test]
+    fn test_counter_history_from_messages() {
+        let mut history = CounterHistory::default();
+        let add = |msg_id, delta| json!({"type": "add", "msg_id": msg_id, "delta": delta});
+        history.invoke(0, &msg().body(Workload::Custom(add(1, 2))));
+        history.invoke(0, &msg().from("c2").body(Workload::Custom(add(1, 5))));
+        history.invoke(1, &msg().from("c3").body(Workload::Custom(add(1, 1))));
+        let add_ok = json!({"type": "add_ok", "in_reply_to": 1, "msg_id": 1});
+        history.complete(2, &msg().from("n1").to("c1").body(Workload::Custom(add_ok)));
+        let timeout = Workload::error(1, code::TIMEOUT, "timed out".into());
+        history.complete(3, &msg().from("n1").to("c2").body(timeout));
+        // c3's add is never answered.
+
+        history.invoke(4, &msg().id(2).read());
+        let read_ok = Workload::ReadOk {
+            in_reply_to: 2,
+            msg_id: 3,
+            messages: None,
+            value: Some(json!(9)),
+        };
+        history.complete(5, &msg().from("n1").to("c1").body(read_ok));
+
+        assert_eq!(history.adds().len(), 3);
+        let error = history.check(0).unwrap_err();
+        assert_eq!((error.acknowledged, error.possible), (2, 8));
+        assert_eq!(
+            error.reads,
+            vec![(read(4, 5, 9), Miscount::DoubleApplied(1))]
+        );
+    }
+}
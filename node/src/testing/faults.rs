@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::core::{CodeId, Message, Replies, Type};
+use crate::rng::Rng;
+
+// what befalls a message whose handler was picked for a fault.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Fault {
+    // the handler isn't run, a request gets an error with this code instead.
+    Fail(CodeId),
+    // the handler runs, its replies are held back this long and sent by a later tick.
+    Delay(Duration),
+    // the handler runs, its replies are lost.
+    Drop,
+}
+
+// faults injected in a node's dispatch path, per handler, see "Node::faults". whether a fault
+// strikes is drawn from a seeded generator, so a test sees the same faults on every run.
+pub struct Faults {
+    rng: Rng,
+    faults: HashMap<Type, Vec<(Fault, f64)>>,
+    delayed: Vec<(Instant, Message)>,
+    // time of the last tick, delays count from there so they follow a "Network"'s clock.
+    now: Option<Instant>,
+    injected: usize,
+}
+
+impl Faults {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: Rng::new(seed),
+            faults: HashMap::new(),
+            delayed: Vec::new(),
+            now: None,
+            injected: 0,
+        }
+    }
+
+    pub fn reseed(&mut self, seed: u64) -> &mut Self {
+        self.rng = Rng::new(seed);
+        self
+    }
+
+    // "fault" strikes the messages handled by "key" with the given probability. faults
+    // injected for the same handler are drawn in order, the first one striking wins.
+    pub fn inject(&mut self, key: Type, fault: Fault, probability: f64) -> &mut Self {
+        self.faults
+            .entry(key)
+            .or_default()
+            .push((fault, probability));
+        self
+    }
+
+    pub fn clear(&mut self) {
+        self.faults.clear();
+    }
+
+    // how many times a fault struck so far.
+    pub fn injected(&self) -> usize {
+        self.injected
+    }
+
+    pub(crate) fn draw(&mut self, key: &Type) -> Option<Fault> {
+        let faults = self.faults.get(key)?;
+        let fault = faults
+            .iter()
+            .find(|(_, probability)| self.rng.chance(*probability))
+            .map(|(fault, _)| fault.clone())?;
+        self.injected += 1;
+        Some(fault)
+    }
+
+    pub(crate) fn hold(&mut self, delay: Duration, replies: Replies) {
+        let due = self.now.unwrap_or_else(Instant::now) + delay;
+        self.delayed
+            .extend(replies.into_iter().map(|reply| (due, reply)));
+    }
+
+    // delayed replies due by "now".
+    pub(crate) fn release(&mut self, now: Instant) -> Replies {
+        self.now = Some(now);
+        let (due, held) = std::mem::take(&mut self.delayed)
+            .into_iter()
+            .partition(|(due, _)| *due <= now);
+        self.delayed = held;
+        due.into_iter().map(|(_, reply)| reply).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{code, Handler, Node, Sink, Workload};
+    use crate::testing::message::{init, msg};
+
+    fn echo(node: &mut Node, msg: Message, out: &mut dyn Sink) -> crate::helper::Result<()> {
+        let Workload::Echo { msg_id, echo } = msg.body else {
+            unreachable!();
+        };
+        let body = Workload::echo_ok(msg_id, node.gen_msg_id(), echo);
+        out.send(node.reply(msg.src, body));
+        Ok(())
+    }
+
+    fn node() -> Node {
+        let mut node = Node::new(HashMap::from([(Type::Echo, echo as Handler)]));
+        init(&mut node, "n1", &["n1"]);
+        node
+    }
+
+    #[test]
+    fn test_fail_and_drop() {
+        let mut node = node();
+        node.faults()
+            .inject(Type::Echo, Fault::Fail(code::TEMPORARILY_UNAVAILABLE), 1.0);
+        let replies = node.process(msg().echo("hi")).unwrap();
+        assert!(matches!(
+            replies[0].body,
+            Workload::Error {
+                in_reply_to: 1,
+                code: code::TEMPORARILY_UNAVAILABLE,
+                ..
+            }
+        ));
+
+        node.faults().clear();
+        node.faults().inject(Type::Echo, Fault::Drop, 1.0);
+        assert!(node.process(msg().echo("hi")).unwrap().is_empty());
+        // the handler did run.
+        assert_eq!(node.state()["msg_counter"], 1);
+        assert_eq!(node.faults().injected(), 2);
+    }
+
+    #[test]
+    fn test_delay() {
+        let mut node = node();
+        node.faults()
+            .inject(Type::Echo, Fault::Delay(Duration::from_millis(100)), 1.0);
+        // delays count from the last tick.
+        let start = Instant::now();
+        node.tick(start).unwrap();
+        assert!(node.process(msg().echo("hi")).unwrap().is_empty());
+        assert!(node
+            .tick(start + Duration::from_millis(50))
+            .unwrap()
+            .is_empty());
+        let replies = node.tick(start + Duration::from_millis(100)).unwrap();
+        assert!(matches!(
+            replies[0].body,
+            Workload::EchoOk { in_reply_to: 1, .. }
+        ));
+    }
+
+    #[test]
+    fn test_faults_are_seeded() {
+        let dropped = |seed| {
+            let mut node = node();
+            node.faults()
+                .reseed(seed)
+                .inject(Type::Echo, Fault::Drop, 0.5);
+            (1..=50)
+                .filter(|msg_id| {
+                    let replies = node.process(msg().id(*msg_id).echo("hi")).unwrap();
+                    replies.is_empty()
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(dropped(1), dropped(1));
+        assert_ne!(dropped(1), dropped(2));
+    }
+}
@@ -0,0 +1,388 @@
+use std::collections::{HashMap, HashSet};
+use std::error;
+use std::fmt::{Display, Formatter};
+use std::io::BufRead;
+
+use crate::core::{code, KvKey, KvValue, Message, MessageId, NodeId, Workload};
+use crate::helper::Result;
+use crate::record::Event;
+
+// what a register operation did, as far as its client could tell.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Op {
+    // the value read, none when the key didn't exist.
+    Read(Option<KvValue>),
+    Write(KvValue),
+    // "ok" tells whether the swap happened, "create" is "create_if_not_exists".
+    Cas {
+        from: KvValue,
+        to: KvValue,
+        create: bool,
+        ok: bool,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Operation {
+    pub key: KvKey,
+    pub op: Op,
+    pub start: u64,
+    // none when the client never got a definite answer (timeout, crash): the operation may
+    // have taken effect at any point after "start", or not at all.
+    pub end: Option<u64>,
+}
+
+// no order of the operations on "key" respects both real time and a single register's
+// semantics.
+#[derive(Debug)]
+pub struct NotLinearizable {
+    pub key: KvKey,
+    pub operations: Vec<Operation>,
+}
+
+impl Display for NotLinearizable {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "history of key {} is not linearizable:", self.key)?;
+        for operation in &self.operations {
+            let end = operation.end.map_or("?".to_owned(), |end| end.to_string());
+            writeln!(f, "  [{}, {end}] {:?}", operation.start, operation.op)?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for NotLinearizable {}
+
+// register operations (read, write, cas) of a kv workload, built from the requests clients
+// sent and the replies they got. times are in any unit, as long as it's the same for all.
+#[derive(Debug, Default)]
+pub struct History {
+    invoked: HashMap<(NodeId, MessageId), (u64, Message)>,
+    operations: Vec<Operation>,
+}
+
+impl History {
+    pub fn push(&mut self, operation: Operation) {
+        self.operations.push(operation);
+    }
+
+    // a request from a client, other messages are ignored.
+    pub fn invoke(&mut self, at: u64, request: &Message) {
+        let kv = matches!(
+            request.body,
+            Workload::Read { key: Some(_), .. } | Workload::Write { .. } | Workload::Cas { .. }
+        );
+        if let (true, Some(msg_id)) = (kv, request.body.msg_id()) {
+            self.invoked
+                .insert((request.src, msg_id), (at, request.clone()));
+        }
+    }
+
+    // a reply to a client, matched with its request by "in_reply_to".
+    pub fn complete(&mut self, at: u64, reply: &Message) {
+        let Some(in_reply_to) = reply.body.in_reply_to() else {
+            return;
+        };
+        let Some((start, request)) = self.invoked.remove(&(reply.dest, in_reply_to)) else {
+            return;
+        };
+        // timeouts and crashes leave the outcome open, other errors mean nothing happened.
+        let indefinite = matches!(
+            reply.body,
+            Workload::Error {
+                code: code::TIMEOUT | code::CRASH,
+                ..
+            }
+        );
+        let failed = matches!(
+            reply.body,
+            Workload::Error {
+                code: code::KEY_DOES_NOT_EXIST | code::PRECONDITION_FAILED,
+                ..
+            }
+        );
+        let end = (!indefinite).then_some(at);
+        let (key, op) = match (request.body, &reply.body) {
+            (Workload::Read { key: Some(key), .. }, Workload::ReadOk { value, .. }) => {
+                (key, Op::Read(value.clone()))
+            }
+            (Workload::Read { key: Some(key), .. }, _) if failed => (key, Op::Read(None)),
+            (Workload::Write { key, value, .. }, Workload::WriteOk { .. }) => {
+                (key, Op::Write(value))
+            }
+            (Workload::Write { key, value, .. }, _) if indefinite => (key, Op::Write(value)),
+            (
+                Workload::Cas {
+                    key,
+                    from,
+                    to,
+                    create_if_not_exists,
+                    ..
+                },
+                body,
+            ) if indefinite || failed || matches!(body, Workload::CasOk { .. }) => {
+                let op = Op::Cas {
+                    from,
+                    to,
+                    create: create_if_not_exists,
+                    ok: !failed,
+                };
+                (key, op)
+            }
+            // a read with an unknown outcome tells nothing, neither does a definite failure.
+            _ => return,
+        };
+        self.push(Operation {
+            key,
+            op,
+            start,
+            end,
+        });
+    }
+
+    // what the node received from and sent to clients (ids starting with "c") during a run
+    // recorded with "Runner::with_recording".
+    pub fn from_recording<R: BufRead>(recording: R) -> Result<Self> {
+        let mut history = History::default();
+        for line in recording.lines() {
+            match serde_json::from_str::<Event>(&line?)? {
+                Event::Received { at, message } if message.src.is_client() => {
+                    history.invoke(at, &message)
+                }
+                Event::Sent { at, message } if message.dest.is_client() => {
+                    history.complete(at, &message)
+                }
+                _ => {}
+            }
+        }
+        Ok(history)
+    }
+
+    // completed operations, plus the writes and swaps still waiting for a reply.
+    pub fn operations(&self) -> Vec<Operation> {
+        let mut operations = self.operations.clone();
+        for (start, request) in self.invoked.values() {
+            let (key, op) = match &request.body {
+                Workload::Write { key, value, .. } => (key, Op::Write(value.clone())),
+                Workload::Cas {
+                    key,
+                    from,
+                    to,
+                    create_if_not_exists,
+                    ..
+                } => {
+                    let op = Op::Cas {
+                        from: from.clone(),
+                        to: to.clone(),
+                        create: *create_if_not_exists,
+                        ok: true,
+                    };
+                    (key, op)
+                }
+                _ => continue,
+            };
+            operations.push(Operation {
+                key: key.clone(),
+                op,
+                start: *start,
+                end: None,
+            });
+        }
+        operations
+    }
+
+    pub fn check(&self) -> std::result::Result<(), NotLinearizable> {
+        check(&self.operations())
+    }
+}
+
+// linearizability is compositional: every key is checked as a register of its own,
+// initially absent.
+pub fn check(operations: &[Operation]) -> std::result::Result<(), NotLinearizable> {
+    let mut registers: Vec<(&KvKey, Vec<&Operation>)> = Vec::new();
+    for operation in operations {
+        match registers.iter_mut().find(|(key, _)| **key == operation.key) {
+            Some((_, register)) => register.push(operation),
+            None => registers.push((&operation.key, vec![operation])),
+        }
+    }
+    for (key, register) in registers {
+        let mut linearized = vec![false; register.len()];
+        if !search(&register, &mut linearized, None, &mut HashSet::new()) {
+            let mut operations: Vec<Operation> = register.into_iter().cloned().collect();
+            operations.sort_by_key(|operation| operation.start);
+            return Err(NotLinearizable {
+                key: key.clone(),
+                operations,
+            });
+        }
+    }
+    Ok(())
+}
+
+// depth-first search for a linearization (Wing & Gong), remembering the dead ends: the same
+// set of linearized operations leading to the same value can't succeed a second time.
+fn search(
+    operations: &[&Operation],
+    linearized: &mut Vec<bool>,
+    value: Option<KvValue>,
+    dead_ends: &mut HashSet<(Vec<bool>, String)>,
+) -> bool {
+    let pending = || {
+        operations
+            .iter()
+            .zip(linearized.iter())
+            .filter(|(_, linearized)| !**linearized)
+            .map(|(operation, _)| operation)
+    };
+    // operations without a reply may never have happened.
+    if pending().all(|operation| operation.end.is_none()) {
+        return true;
+    }
+    let state = (
+        linearized.clone(),
+        serde_json::to_string(&value).unwrap_or_default(),
+    );
+    if !dead_ends.insert(state) {
+        return false;
+    }
+
+    // only operations started before the first pending one returned can go next.
+    let horizon = pending()
+        .filter_map(|operation| operation.end)
+        .min()
+        .unwrap_or(u64::MAX);
+    for i in 0..operations.len() {
+        if linearized[i] || operations[i].start > horizon {
+            continue;
+        }
+        if let Some(next) = apply(operations[i], &value) {
+            linearized[i] = true;
+            if search(operations, linearized, next, dead_ends) {
+                return true;
+            }
+            linearized[i] = false;
+        }
+    }
+    false
+}
+
+// the register's value after "operation", none if it couldn't have returned what it did.
+fn apply(operation: &Operation, value: &Option<KvValue>) -> Option<Option<KvValue>> {
+    match &operation.op {
+        Op::Read(read) => (read == value).then(|| value.clone()),
+        Op::Write(written) => Some(Some(written.clone())),
+        Op::Cas {
+            from,
+            to,
+            create,
+            ok,
+        } => {
+            let holds = value.as_ref() == Some(from) || (value.is_none() && *create);
+            // an operation without a reply that didn't swap had no effect, which the search
+            // already covers by leaving it out.
+            match (holds, *ok || operation.end.is_none()) {
+                (true, true) => Some(Some(to.clone())),
+                (false, false) => Some(value.clone()),
+                _ => None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::message::msg;
+
+    fn op(op: Op, start: u64, end: Option<u64>) -> Operation {
+        Operation {
+            key: 1.into(),
+            op,
+            start,
+            end,
+        }
+    }
+
+    fn read(value: u64) -> Op {
+        Op::Read(Some(value.into()))
+    }
+
+    fn write(value: u64) -> Op {
+        Op::Write(value.into())
+    }
+
+    #[test]
+    fn test_sequential_and_concurrent() {
+        assert!(check(&[op(write(1), 0, Some(1)), op(read(1), 2, Some(3))]).is_ok());
+        // a stale read, after the write returned.
+        assert!(check(&[
+            op(write(1), 0, Some(1)),
+            op(write(2), 2, Some(3)),
+            op(read(1), 4, Some(5)),
+        ])
+        .is_err());
+        // concurrent with the second write, either value is fine.
+        assert!(check(&[
+            op(write(1), 0, Some(1)),
+            op(write(2), 2, Some(5)),
+            op(read(1), 3, Some(4)),
+        ])
+        .is_ok());
+        assert!(check(&[op(Op::Read(None), 0, Some(1))]).is_ok());
+    }
+
+    #[test]
+    fn test_cas_and_unknown_outcomes() {
+        let cas = |from: u64, to: u64, ok| Op::Cas {
+            from: from.into(),
+            to: to.into(),
+            create: false,
+            ok,
+        };
+        assert!(check(&[
+            op(write(1), 0, Some(1)),
+            op(cas(1, 2, true), 2, Some(3)),
+            op(cas(1, 3, false), 4, Some(5)),
+            op(read(2), 6, Some(7)),
+        ])
+        .is_ok());
+        assert!(check(&[op(write(1), 0, Some(1)), op(cas(1, 2, false), 2, Some(3))]).is_err());
+
+        // a write that timed out may show up later, or never.
+        let timed_out = op(write(2), 2, None);
+        assert!(check(&[
+            op(write(1), 0, Some(1)),
+            timed_out.clone(),
+            op(read(2), 9, Some(10))
+        ])
+        .is_ok());
+        assert!(check(&[
+            op(write(1), 0, Some(1)),
+            timed_out,
+            op(read(1), 9, Some(10))
+        ])
+        .is_ok());
+        // but not before it was sent.
+        assert!(check(&[op(read(2), 0, Some(1)), op(write(2), 2, None)]).is_err());
+    }
+
+    #[test]
+    fn test_history_from_messages() {
+        let mut history = History::default();
+        history.invoke(0, &msg().id(1).write(1.into(), 5.into()));
+        history.complete(1, &msg().from("n1").to("c1").body(Workload::write_ok(1, 1)));
+        history.invoke(2, &msg().from("c2").id(1).kv_read(1.into()));
+        let stale = Workload::kv_read_ok(1, 2, 4.into());
+        history.complete(3, &msg().from("n1").to("c2").body(stale));
+        // still waiting for its reply.
+        history.invoke(4, &msg().id(2).write(1.into(), 4.into()));
+
+        assert_eq!(history.operations().len(), 3);
+        let error = history.check().unwrap_err();
+        assert_eq!(error.key, 1);
+        assert!(error
+            .to_string()
+            .starts_with("history of key 1 is not linearizable:"));
+    }
+}
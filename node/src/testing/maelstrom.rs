@@ -0,0 +1,279 @@
+use std::fmt::{Display, Formatter};
+use std::io::BufRead;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use serde_json::{Map, Number, Value};
+
+use crate::core::{Message, Node};
+use crate::helper::Result;
+
+// messages delivered during a maelstrom run, in order, from its "jepsen.log" when run with
+// "--log-net-recv": every line with ":recv" followed by a message, written as edn (or json).
+// other lines are skipped, so the whole log can be given as is.
+pub fn parse_log<R: BufRead>(log: R) -> Result<Vec<Message>> {
+    let mut messages = Vec::new();
+    for line in log.lines() {
+        let line = line?;
+        let Some((_, message)) = line.split_once(":recv ") else {
+            continue;
+        };
+        let value = match serde_json::from_str::<Value>(message) {
+            Ok(value) => value,
+            Err(_) => edn(message)?,
+        };
+        messages.push(serde_json::from_value(value)?);
+    }
+    Ok(messages)
+}
+
+// a reply of the replayed node that differs from the one it sent during the run.
+#[derive(Debug, PartialEq)]
+pub struct Mismatch {
+    pub request: Message,
+    pub expected: Option<Message>,
+    pub actual: Option<Message>,
+}
+
+impl Display for Mismatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let json = |message: &Option<Message>| {
+            message.as_ref().map_or("nothing".to_owned(), |message| {
+                serde_json::to_string(message).unwrap_or_default()
+            })
+        };
+        write!(
+            f,
+            "request {}: expected {}, got {}",
+            serde_json::to_string(&self.request).unwrap_or_default(),
+            json(&self.expected),
+            json(&self.actual),
+        )
+    }
+}
+
+// feeds the requests clients sent to "node_id" through a fresh "node", in order, and compares
+// its replies with those of the run, by client and "in_reply_to". message ids the node
+// generated are left out of the comparison. messages between nodes and from services aren't
+// replayed, so requests whose reply depended on them will show up as mismatches.
+pub fn replay(node: &mut Node, node_id: &str, messages: &[Message]) -> Vec<Mismatch> {
+    let mut requests = Vec::new();
+    let mut expected = Vec::new();
+    let mut actual = Vec::new();
+    for message in messages {
+        if message.dest == node_id && message.src.is_client() {
+            requests.push(message.clone());
+            // errors were traced by the node, the run's reply (if any) will be missing.
+            if let Ok(replies) = node.process(message.clone()) {
+                actual.extend(replies.into_iter().filter(|reply| reply.dest.is_client()));
+            }
+        } else if message.src == node_id && message.dest.is_client() {
+            expected.push(message.clone());
+        }
+    }
+
+    let mut mismatches = Vec::new();
+    for request in requests {
+        let Some(msg_id) = request.body.msg_id() else {
+            continue;
+        };
+        let take = |replies: &mut Vec<Message>| {
+            let position = replies.iter().position(|reply| {
+                reply.dest == request.src && reply.body.in_reply_to() == Some(msg_id)
+            })?;
+            Some(replies.remove(position))
+        };
+        let (expected, actual) = (take(&mut expected), take(&mut actual));
+        let same = match (&expected, &actual) {
+            (Some(expected), Some(actual)) => normalized(expected) == normalized(actual),
+            (None, None) => true,
+            _ => false,
+        };
+        if !same {
+            mismatches.push(Mismatch {
+                request,
+                expected,
+                actual,
+            });
+        }
+    }
+    mismatches
+}
+
+// replays the log of a run, panicking with every mismatch, for regression tests built from
+// failed runs.
+pub fn assert_replays<R: BufRead>(node: &mut Node, node_id: &str, log: R) {
+    let messages = parse_log(log).expect("Maelstrom log should parse.");
+    let mismatches = replay(node, node_id, &messages);
+    if !mismatches.is_empty() {
+        let mismatches: Vec<String> = mismatches.iter().map(ToString::to_string).collect();
+        panic!("replies differ from the run:\n{}", mismatches.join("\n"));
+    }
+}
+
+fn normalized(message: &Message) -> Value {
+    let mut body = serde_json::to_value(&message.body).unwrap_or_default();
+    if let Some(body) = body.as_object_mut() {
+        body.remove("msg_id");
+    }
+    body
+}
+
+// reads the edn maelstrom writes messages in, as json: keywords become strings, lists and sets arrays,
+// and map keys that aren't strings their printed form.
+fn edn(input: &str) -> Result<Value> {
+    let mut chars = input.chars().peekable();
+    let value = edn_value(&mut chars)?;
+    Ok(value)
+}
+
+fn edn_value(chars: &mut Peekable<Chars>) -> Result<Value> {
+    skip_whitespace(chars);
+    let value = match chars.next() {
+        Some('{') => {
+            let mut map = Map::new();
+            for pair in edn_sequence(chars, '}')?.chunks(2) {
+                let [key, value] = pair else {
+                    return Err("edn map with an odd number of forms".into());
+                };
+                let key = match key {
+                    Value::String(key) => key.clone(),
+                    key => key.to_string(),
+                };
+                map.insert(key, value.clone());
+            }
+            Value::Object(map)
+        }
+        Some('[') => Value::Array(edn_sequence(chars, ']')?),
+        Some('(') => Value::Array(edn_sequence(chars, ')')?),
+        Some('#') if chars.next_if_eq(&'{').is_some() => Value::Array(edn_sequence(chars, '}')?),
+        Some('"') => {
+            let mut string = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => match chars.next() {
+                        Some('n') => string.push('\n'),
+                        Some('t') => string.push('\t'),
+                        Some('r') => string.push('\r'),
+                        Some(c) => string.push(c),
+                        None => return Err("unterminated edn string".into()),
+                    },
+                    Some(c) => string.push(c),
+                    None => return Err("unterminated edn string".into()),
+                }
+            }
+            Value::String(string)
+        }
+        Some(':') => Value::String(edn_token(chars)),
+        Some(c) => {
+            let token = format!("{c}{}", edn_token(chars));
+            match token.as_str() {
+                "nil" => Value::Null,
+                "true" => Value::Bool(true),
+                "false" => Value::Bool(false),
+                number => {
+                    let number = number.trim_end_matches(['N', 'M']);
+                    match number.parse::<i64>() {
+                        Ok(number) => Value::from(number),
+                        Err(_) => number
+                            .parse::<f64>()
+                            .ok()
+                            .and_then(Number::from_f64)
+                            .map(Value::Number)
+                            .ok_or(format!("unexpected edn token {token:?}"))?,
+                    }
+                }
+            }
+        }
+        None => return Err("unexpected end of edn".into()),
+    };
+    Ok(value)
+}
+
+fn edn_sequence(chars: &mut Peekable<Chars>, end: char) -> Result<Vec<Value>> {
+    let mut values = Vec::new();
+    loop {
+        skip_whitespace(chars);
+        match chars.peek() {
+            Some(c) if *c == end => {
+                chars.next();
+                return Ok(values);
+            }
+            Some(_) => values.push(edn_value(chars)?),
+            None => return Err(format!("expected {end:?} before the end of edn").into()),
+        }
+    }
+}
+
+fn edn_token(chars: &mut Peekable<Chars>) -> String {
+    let mut token = String::new();
+    while let Some(c) = chars.next_if(|c| !c.is_whitespace() && !",{}[]()\"".contains(*c)) {
+        token.push(c);
+    }
+    token
+}
+
+// commas are whitespace in edn.
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.next_if(|c| c.is_whitespace() || *c == ',').is_some() {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{Handler, Sink, Type, Workload};
+    use std::collections::HashMap;
+
+    const LOG: &str = r#"INFO [2024-01-01 00:00:00,000] jepsen node n1 - maelstrom.db Setting up n1
+INFO [2024-01-01 00:00:00,001] jepsen worker nemesis - jepsen.maelstrom.net :recv {:id 0, :src "c0", :dest "n1", :body {:type "init", :node_id "n1", :node_ids ["n1" "n2"], :msg_id 1}}
+INFO [2024-01-01 00:00:00,002] jepsen worker nemesis - jepsen.maelstrom.net :recv {:id 1, :src "n1", :dest "c0", :body {:type "init_ok", :in_reply_to 1}}
+INFO [2024-01-01 00:00:00,003] jepsen worker 0 - jepsen.maelstrom.net :send {:id 2, :src "c1", :dest "n1", :body {:type "echo", :echo "hi \"there\"", :msg_id 1}}
+INFO [2024-01-01 00:00:00,004] jepsen worker 0 - jepsen.maelstrom.net :recv {:id 2, :src "c1", :dest "n1", :body {:type "echo", :echo "hi \"there\"", :msg_id 1}}
+INFO [2024-01-01 00:00:00,005] jepsen worker 0 - jepsen.maelstrom.net :recv {:id 3, :src "n1", :dest "c1", :body {:type "echo_ok", :echo "hi \"there\"", :in_reply_to 1, :msg_id 7}}
+INFO [2024-01-01 00:00:00,006] jepsen worker 0 - jepsen.maelstrom.net :recv {"src":"c1","dest":"n1","body":{"type":"echo","echo":"json","msg_id":2}}
+INFO [2024-01-01 00:00:00,007] jepsen worker 0 - jepsen.maelstrom.net :recv {"src":"n1","dest":"c1","body":{"type":"echo_ok","echo":"json","in_reply_to":2,"msg_id":8}}"#;
+
+    fn echo(node: &mut Node, msg: Message, out: &mut dyn Sink) -> Result<()> {
+        let Workload::Echo { msg_id, echo } = msg.body else {
+            unreachable!();
+        };
+        let body = Workload::echo_ok(msg_id, node.gen_msg_id(), echo);
+        out.send(node.reply(msg.src, body));
+        Ok(())
+    }
+
+    fn node() -> Node {
+        Node::new(HashMap::from([(Type::Echo, echo as Handler)]))
+    }
+
+    #[test]
+    fn test_parse_log() {
+        let messages = parse_log(LOG.as_bytes()).unwrap();
+        assert_eq!(messages.len(), 6);
+        assert_eq!(messages[0].body.key(), Type::Init);
+        let Workload::Echo { echo, .. } = &messages[2].body else {
+            panic!("expected echo");
+        };
+        assert_eq!(echo, r#"hi "there""#);
+
+        let value = edn(r#"{:a [1 2.5 nil] :b #{true} 3 (:c)}"#).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({"a": [1, 2.5, null], "b": [true], "3": ["c"]})
+        );
+        assert!(edn("{:a 1").is_err());
+    }
+
+    #[test]
+    fn test_replay() {
+        assert!(replay(&mut node(), "n1", &parse_log(LOG.as_bytes()).unwrap()).is_empty());
+        assert_replays(&mut node(), "n1", LOG.as_bytes());
+
+        let altered = LOG.replace(r#""echo":"json","in"#, r#""echo":"jsonl","in"#);
+        let mismatches = replay(&mut node(), "n1", &parse_log(altered.as_bytes()).unwrap());
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(mismatches[0].request.body.msg_id(), Some(2));
+        assert!(mismatches[0].to_string().contains(r#"got {"src":"n1""#));
+    }
+}
@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+
+use crate::core::{
+    BroadcastMessage, KvKey, KvValue, LogMessage, Message, MessageId, Node, NodeId, Offset,
+    Workload,
+};
+use crate::txn::Op;
+
+// starts a message from "c1" to "n1" with msg_id 1, e.g. msg().to("n2").id(5).broadcast(1000).
+pub fn msg() -> MessageBuilder {
+    MessageBuilder {
+        src: "c1".into(),
+        dest: "n1".into(),
+        msg_id: 1,
+    }
+}
+
+// runs the init sequence on "node", as maelstrom would, and checks it was accepted.
+pub fn init(node: &mut Node, node_id: &str, node_ids: &[&str]) {
+    let replies = node
+        .process(msg().to(node_id).init(node_ids))
+        .expect("Node should accept init.");
+    assert!(
+        matches!(
+            replies[..],
+            [Message {
+                body: Workload::InitOk { .. },
+                ..
+            }]
+        ),
+        "Node should answer init with init_ok, got {replies:?}."
+    );
+}
+
+// addressing of a message under construction, the workload methods finish it.
+#[derive(Clone, Debug)]
+pub struct MessageBuilder {
+    src: NodeId,
+    dest: NodeId,
+    msg_id: MessageId,
+}
+
+impl MessageBuilder {
+    pub fn from(mut self, src: &str) -> Self {
+        self.src = src.into();
+        self
+    }
+
+    pub fn to(mut self, dest: &str) -> Self {
+        self.dest = dest.into();
+        self
+    }
+
+    pub fn id(mut self, msg_id: MessageId) -> Self {
+        self.msg_id = msg_id;
+        self
+    }
+
+    // any body, for the workloads without a shortcut below.
+    pub fn body(self, body: Workload) -> Message {
+        Message {
+            src: self.src,
+            dest: self.dest,
+            body,
+        }
+    }
+
+    // the node id is the destination.
+    pub fn init(self, node_ids: &[&str]) -> Message {
+        let body = Workload::Init {
+            msg_id: self.msg_id,
+            node_id: self.dest,
+            node_ids: node_ids.iter().map(|id| (*id).into()).collect(),
+        };
+        self.body(body)
+    }
+
+    pub fn echo(self, echo: &str) -> Message {
+        let body = Workload::Echo {
+            msg_id: self.msg_id,
+            echo: echo.to_owned(),
+        };
+        self.body(body)
+    }
+
+    pub fn generate(self) -> Message {
+        let body = Workload::Generate {
+            msg_id: self.msg_id,
+            n: None,
+        };
+        self.body(body)
+    }
+
+    pub fn generate_batch(self, n: usize) -> Message {
+        let body = Workload::Generate {
+            msg_id: self.msg_id,
+            n: Some(n),
+        };
+        self.body(body)
+    }
+
+    pub fn broadcast(self, message: BroadcastMessage) -> Message {
+        let body = Workload::Broadcast {
+            msg_id: self.msg_id,
+            message,
+            hops: None,
+        };
+        self.body(body)
+    }
+
+    // the broadcast workload's read, without a key.
+    pub fn read(self) -> Message {
+        let body = Workload::Read {
+            msg_id: self.msg_id,
+            key: None,
+        };
+        self.body(body)
+    }
+
+    pub fn topology(self, topology: &[(&str, &[&str])]) -> Message {
+        let topology = topology
+            .iter()
+            .map(|(node, neighbors)| {
+                let neighbors = neighbors.iter().map(|id| (*id).into()).collect();
+                ((*node).into(), neighbors)
+            })
+            .collect();
+        let body = Workload::Topology {
+            msg_id: self.msg_id,
+            topology,
+        };
+        self.body(body)
+    }
+
+    pub fn send(self, key: &str, msg: LogMessage) -> Message {
+        let body = Workload::Send {
+            msg_id: self.msg_id,
+            key: key.to_owned(),
+            msg,
+        };
+        self.body(body)
+    }
+
+    pub fn poll(self, offsets: &[(&str, Offset)]) -> Message {
+        let body = Workload::Poll {
+            msg_id: self.msg_id,
+            offsets: offsets_map(offsets),
+        };
+        self.body(body)
+    }
+
+    pub fn commit_offsets(self, offsets: &[(&str, Offset)]) -> Message {
+        let body = Workload::CommitOffsets {
+            msg_id: self.msg_id,
+            offsets: offsets_map(offsets),
+        };
+        self.body(body)
+    }
+
+    pub fn list_committed_offsets(self, keys: &[&str]) -> Message {
+        let body = Workload::ListCommittedOffsets {
+            msg_id: self.msg_id,
+            keys: keys.iter().map(|key| (*key).to_owned()).collect(),
+        };
+        self.body(body)
+    }
+
+    pub fn txn(self, txn: Vec<Op>) -> Message {
+        let body = Workload::Txn {
+            msg_id: self.msg_id,
+            txn,
+        };
+        self.body(body)
+    }
+
+    // the kv services' read.
+    pub fn kv_read(self, key: KvKey) -> Message {
+        let msg_id = self.msg_id;
+        self.body(Workload::read(msg_id, key))
+    }
+
+    pub fn write(self, key: KvKey, value: KvValue) -> Message {
+        let msg_id = self.msg_id;
+        self.body(Workload::write(msg_id, key, value))
+    }
+
+    pub fn cas(self, key: KvKey, from: KvValue, to: KvValue) -> Message {
+        let msg_id = self.msg_id;
+        self.body(Workload::cas(msg_id, key, from, to, false))
+    }
+
+    pub fn dump_state(self) -> Message {
+        let body = Workload::DumpState {
+            msg_id: self.msg_id,
+        };
+        self.body(body)
+    }
+}
+
+fn offsets_map(offsets: &[(&str, Offset)]) -> HashMap<String, Offset> {
+    offsets
+        .iter()
+        .map(|(key, offset)| ((*key).to_owned(), *offset))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_builder() {
+        assert_eq!(
+            serde_json::to_string(&msg().from("c2").to("n3").id(5).broadcast(1000)).unwrap(),
+            r#"{"src":"c2","dest":"n3","body":{"type":"broadcast","msg_id":5,"message":1000}}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&msg().topology(&[("n1", &["n2"])])).unwrap(),
+            r#"{"src":"c1","dest":"n1","body":{"type":"topology","msg_id":1,"topology":{"n1":["n2"]}}}"#
+        );
+        assert_eq!(msg().to("n2").init(&["n1", "n2"]).body, {
+            Workload::Init {
+                msg_id: 1,
+                node_id: "n2".into(),
+                node_ids: vec!["n1".into(), "n2".into()],
+            }
+        });
+    }
+
+    #[test]
+    fn test_init() {
+        let mut node = Node::default();
+        init(&mut node, "n2", &["n1", "n2"]);
+        assert_eq!(node.node_id(), "n2");
+        assert_eq!(node.peers(), vec!["n1"]);
+    }
+}
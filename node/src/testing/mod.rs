@@ -0,0 +1,10 @@
+// helpers for exercising nodes in-process, from this crate's tests and the workload crates' tests.
+
+pub mod convergence;
+pub mod counter;
+pub mod faults;
+pub mod linearizability;
+pub mod maelstrom;
+pub mod message;
+pub mod network;
+pub mod sim;
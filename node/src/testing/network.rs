@@ -0,0 +1,382 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::time::{Duration, Instant};
+
+use crate::clock::{Clock, ManualClock};
+use crate::core::{Message, Node, NodeId, Workload};
+use crate::rng::Rng;
+use crate::viz::GossipTrace;
+
+struct InFlight {
+    deliver_at: u64,
+    seq: u64, // keeps delivery order stable for messages due at the same time.
+    message: Message,
+}
+
+// in-process cluster: messages sent by nodes are routed to each other after a (virtual)
+// delay, may be dropped, and are blocked between partitioned nodes. messages to anybody
+// else (clients, services) are collected in an outbox for the test to inspect.
+// time is virtual, in milliseconds, and every random choice comes from a seeded generator:
+// the same seed and the same calls replay the same run, see "sim::simulate". nodes share a
+// manual clock following virtual time, their rpc deadlines and unique ids included.
+pub struct Network {
+    nodes: BTreeMap<NodeId, Node>,
+    // every node, the crashed ones included.
+    members: Vec<NodeId>,
+    crashed: BTreeSet<NodeId>,
+    in_flight: Vec<InFlight>,
+    outbox: Vec<Message>,
+    now: u64,
+    seq: u64,
+    delay: (u64, u64),
+    drop_probability: f64,
+    partitions: HashSet<(NodeId, NodeId)>,
+    rng: Rng,
+    clock: ManualClock,
+    gossip_trace: Option<GossipTrace>,
+}
+
+impl Network {
+    // creates "count" nodes named n1..nN with "factory" and runs the init sequence on each.
+    pub fn new<F>(count: usize, factory: F) -> Self
+    where
+        F: Fn() -> Node,
+    {
+        let node_ids: Vec<NodeId> = (1..=count).map(|i| format!("n{i}").into()).collect();
+        let clock = ManualClock::new(1_700_000_000_000);
+        let mut network = Self {
+            nodes: BTreeMap::new(),
+            members: node_ids.clone(),
+            crashed: BTreeSet::new(),
+            in_flight: Vec::new(),
+            outbox: Vec::new(),
+            now: 0,
+            seq: 0,
+            delay: (1, 1),
+            drop_probability: 0.0,
+            partitions: HashSet::new(),
+            rng: Rng::new(0),
+            clock,
+            gossip_trace: None,
+        };
+        for node_id in &node_ids {
+            network.start(node_id, factory());
+        }
+        network
+    }
+
+    // runs the init sequence on "node" and adds it as "node_id".
+    fn start(&mut self, node_id: &NodeId, mut node: Node) {
+        node.set_clock(self.clock.clone());
+        let init = Message {
+            src: "c0".into(),
+            dest: *node_id,
+            body: Workload::Init {
+                msg_id: 0,
+                node_id: *node_id,
+                node_ids: self.members.clone(),
+            },
+        };
+        node.process(init).expect("Node should accept init.");
+        self.nodes.insert(*node_id, node);
+    }
+
+    // kills "node_id" without a shutdown: it's gone with whatever it only had in memory, and
+    // messages to it (in flight or sent later) are lost until "restart". whatever it kept in a
+    // storage the test still holds (see "storage::Storage") is all it gets back.
+    pub fn crash(&mut self, node_id: &str) {
+        let node_id = NodeId::from(node_id);
+        self.nodes
+            .remove(&node_id)
+            .expect("Node should be running.");
+        self.in_flight.retain(|m| m.message.dest != node_id);
+        self.crashed.insert(node_id);
+    }
+
+    // "node" takes the place of the crashed "node_id", from a fresh init as after a restart.
+    pub fn restart(&mut self, node_id: &str, node: Node) {
+        let node_id = NodeId::from(node_id);
+        assert!(
+            self.crashed.remove(&node_id),
+            "{node_id} should have crashed."
+        );
+        self.start(&node_id, node);
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = Rng::new(seed);
+        self
+    }
+
+    // writes the gossip between nodes to "trace", timed in virtual ms, lost messages included.
+    pub fn with_gossip_trace(mut self, trace: GossipTrace) -> Self {
+        self.gossip_trace = Some(trace);
+        self
+    }
+
+    // the network's generator, for scenarios to draw their own choices from the same seed.
+    pub fn rng(&mut self) -> &mut Rng {
+        &mut self.rng
+    }
+
+    // virtual time, in milliseconds since the network was created.
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    // virtual time, as an instant the nodes' ticks can be given.
+    pub fn clock(&self) -> Instant {
+        self.clock.now()
+    }
+
+    // the running nodes.
+    pub fn node_ids(&self) -> Vec<NodeId> {
+        self.nodes.keys().cloned().collect()
+    }
+
+    pub fn node(&self, node_id: &str) -> &Node {
+        &self.nodes[node_id]
+    }
+
+    pub fn node_mut(&mut self, node_id: &str) -> &mut Node {
+        self.nodes.get_mut(node_id).expect("Node should exist.")
+    }
+
+    // delay of every inter-node message, in virtual milliseconds, picked uniformly in [min, max].
+    pub fn set_delay(&mut self, min: u64, max: u64) {
+        self.delay = (min, max.max(min));
+    }
+
+    pub fn set_drop_probability(&mut self, probability: f64) {
+        self.drop_probability = probability;
+    }
+
+    // cuts every link between the two groups, in both directions.
+    pub fn partition(&mut self, left: &[&str], right: &[&str]) {
+        for a in left {
+            for b in right {
+                self.partitions.insert(((*a).into(), (*b).into()));
+                self.partitions.insert(((*b).into(), (*a).into()));
+            }
+        }
+    }
+
+    pub fn heal(&mut self) {
+        self.partitions.clear();
+    }
+
+    // injects a message, e.g. a client request.
+    pub fn send(&mut self, message: Message) {
+        self.enqueue(message, 0);
+    }
+
+    // messages addressed to something other than a node of this network.
+    pub fn take_outbox(&mut self) -> Vec<Message> {
+        std::mem::take(&mut self.outbox)
+    }
+
+    // delivers the next message, returns false once nothing is in flight.
+    pub fn step(&mut self) -> bool {
+        let Some(next) = self
+            .in_flight
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, m)| (m.deliver_at, m.seq))
+            .map(|(i, _)| i)
+        else {
+            return false;
+        };
+        let InFlight {
+            deliver_at,
+            message,
+            ..
+        } = self.in_flight.swap_remove(next);
+        self.advance_to(deliver_at);
+
+        // injected for a node that has crashed since.
+        let Some(node) = self.nodes.get_mut(&message.dest) else {
+            return true;
+        };
+        // errors are traced by the node.
+        if let Ok(replies) = node.process(message) {
+            replies.into_iter().for_each(|reply| self.route(reply));
+        }
+        true
+    }
+
+    // delivers messages until the network is quiet or "max_steps" were taken.
+    pub fn run(&mut self, max_steps: usize) -> usize {
+        let mut steps = 0;
+        while steps < max_steps && self.step() {
+            steps += 1;
+        }
+        steps
+    }
+
+    // advances virtual time by "duration" milliseconds, delivering messages as they come due
+    // and ticking every node every "tick_interval" milliseconds. returns the messages delivered.
+    pub fn run_for(&mut self, duration: u64, tick_interval: u64) -> usize {
+        let until = self.now + duration;
+        let tick_interval = tick_interval.max(1);
+        let mut next_tick = self.now + tick_interval;
+        let mut steps = 0;
+        loop {
+            let next_delivery = self.in_flight.iter().map(|m| m.deliver_at).min();
+            match next_delivery {
+                Some(at) if at <= until && at <= next_tick => {
+                    self.step();
+                    steps += 1;
+                }
+                _ if next_tick <= until => {
+                    self.advance_to(next_tick);
+                    self.tick(self.clock());
+                    next_tick += tick_interval;
+                }
+                _ => break,
+            }
+        }
+        self.advance_to(until);
+        steps
+    }
+
+    // ticks every node, routing whatever they send.
+    pub fn tick(&mut self, now: Instant) {
+        let node_ids = self.node_ids();
+        for node_id in node_ids {
+            if let Ok(replies) = self.node_mut(&node_id).tick(now) {
+                replies.into_iter().for_each(|reply| self.route(reply));
+            }
+        }
+    }
+
+    fn advance_to(&mut self, now: u64) {
+        self.now = self.now.max(now);
+        self.clock.set_elapsed(Duration::from_millis(self.now));
+    }
+
+    fn route(&mut self, message: Message) {
+        let crashed = self.crashed.contains(&message.dest);
+        if !crashed && !self.nodes.contains_key(&message.dest) {
+            self.outbox.push(message);
+            return;
+        }
+        if let Some(trace) = self.gossip_trace.as_mut() {
+            trace.sent(self.now, std::slice::from_ref(&message));
+        }
+        if crashed || self.partitions.contains(&(message.src, message.dest)) {
+            return;
+        }
+        if self.rng.chance(self.drop_probability) {
+            return;
+        }
+        let (min, max) = self.delay;
+        let delay = self.rng.between(min, max);
+        self.enqueue(message, delay);
+    }
+
+    fn enqueue(&mut self, message: Message, delay: u64) {
+        self.seq += 1;
+        self.in_flight.push(InFlight {
+            deliver_at: self.now + delay,
+            seq: self.seq,
+            message,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{Handler, Sink, Type};
+    use crate::testing::message::msg;
+    use std::collections::HashMap;
+
+    // every node forwards "echo" to the next node, the last one replies to the client.
+    fn relay(node: &mut Node, msg: Message, out: &mut dyn Sink) -> crate::helper::Result<()> {
+        let ids = node.node_ids().to_vec();
+        let position = ids.iter().position(|id| *id == node.node_id()).unwrap();
+        let dest = ids.get(position + 1).cloned().unwrap_or("c1".into());
+        out.send(node.reply(dest, msg.body));
+        Ok(())
+    }
+
+    fn network_node() -> Node {
+        Node::new(HashMap::from([(Type::Echo, relay as Handler)]))
+    }
+
+    fn network() -> Network {
+        Network::new(3, network_node)
+    }
+
+    fn echo() -> Message {
+        msg().echo("hi")
+    }
+
+    #[test]
+    fn test_network_routes_between_nodes() {
+        let mut network = network();
+        network.send(echo());
+        assert_eq!(network.run(100), 3);
+        let outbox = network.take_outbox();
+        assert_eq!(outbox.len(), 1);
+        assert_eq!(outbox[0].src, "n3");
+    }
+
+    #[test]
+    fn test_network_partition_and_drops() {
+        let mut network = network();
+        network.partition(&["n2"], &["n3"]);
+        network.send(echo());
+        network.run(100);
+        assert!(network.take_outbox().is_empty());
+
+        network.heal();
+        network.set_drop_probability(1.0);
+        network.send(echo());
+        assert_eq!(network.run(100), 1); // only the injected message made it.
+        assert!(network.take_outbox().is_empty());
+    }
+
+    #[test]
+    fn test_network_crash_and_restart() {
+        let mut network = network();
+        network.send(echo());
+        network.step();
+        network.crash("n2");
+        assert_eq!(network.node_ids(), vec!["n1", "n3"]);
+        // in flight to n2 when it crashed, and sent to it since: lost.
+        assert_eq!(network.run(100), 0);
+        network.send(echo());
+        assert_eq!(network.run(100), 1);
+        assert!(network.take_outbox().is_empty());
+
+        // the restarted node is initialized again.
+        network.restart("n2", network_node());
+        assert_eq!(network.node("n2").node_ids().len(), 3);
+        network.send(echo());
+        network.run(100);
+        assert_eq!(network.take_outbox().len(), 1);
+    }
+
+    #[test]
+    fn test_network_is_deterministic() {
+        // which echoes make it through depends on the seed only.
+        let run = |seed| {
+            let mut network = network().with_seed(seed);
+            network.set_delay(1, 20);
+            network.set_drop_probability(0.3);
+            for msg_id in 0..20 {
+                let mut message = echo();
+                *message.body.msg_id_mut().unwrap() = msg_id;
+                network.send(message);
+            }
+            network.run_for(1_000, 100);
+            network
+                .take_outbox()
+                .iter()
+                .map(|message| message.body.msg_id().unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(run(1), run(1));
+        assert_ne!(run(1), run(2));
+    }
+}
@@ -0,0 +1,36 @@
+use std::panic::{self, AssertUnwindSafe};
+
+// seeds are taken from this variable when set, to rerun a failed simulation.
+pub const SEED_VAR: &str = "SIMULATION_SEED";
+
+// runs "scenario" once per seed, 0..runs, or only with the seed in SIMULATION_SEED when set.
+// a scenario builds everything random (network, workload, faults) from the seed it's given,
+// so a failing run is reproduced by its seed, which is printed before the panic propagates.
+pub fn simulate<F>(runs: u64, scenario: F)
+where
+    F: Fn(u64),
+{
+    let seeds: Vec<u64> = match std::env::var(SEED_VAR) {
+        Ok(seed) => vec![seed.parse().expect("SIMULATION_SEED should be a number.")],
+        Err(_) => (0..runs).collect(),
+    };
+    for seed in seeds {
+        if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| scenario(seed))) {
+            eprintln!("simulation failed with seed {seed}, rerun it with {SEED_VAR}={seed}");
+            panic::resume_unwind(payload);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_simulate_propagates_failures() {
+        let result = panic::catch_unwind(|| {
+            simulate(10, |seed| assert!(seed < 3));
+        });
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,233 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::Instant;
+
+use crate::core::{Message, MessageId, Replies, Type};
+use crate::node_id::NodeId;
+
+// "rate" tokens a second, up to "burst" saved up. starts full.
+#[derive(Clone, Debug)]
+pub struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    refilled_at: Option<Instant>,
+}
+
+impl TokenBucket {
+    pub fn new(rate: f64, burst: u32) -> Self {
+        let burst = f64::from(burst.max(1));
+        Self {
+            rate,
+            burst,
+            tokens: burst,
+            refilled_at: None,
+        }
+    }
+
+    pub fn try_take(&mut self, now: Instant) -> bool {
+        let elapsed = self
+            .refilled_at
+            .map_or(0.0, |at| now.saturating_duration_since(at).as_secs_f64());
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        self.refilled_at = Some(now);
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+// a message but for its msg_id and lamport timestamp, serialized, with its destination.
+type Payload = (NodeId, String);
+
+// requests to other nodes beyond what the bucket allows wait in a queue, in the order they were
+// sent, see "Node::enable_rate_limit". replies, heartbeats and messages to clients and services
+// are never held back.
+pub(crate) struct Throttle {
+    bucket: TokenBucket,
+    // with their payload, none for rpcs: a callback waits on that very msg_id.
+    queued: VecDeque<(Option<Payload>, Message)>,
+    // the msg_id of the message queued with each payload.
+    payloads: HashMap<Payload, Option<MessageId>>,
+}
+
+impl Throttle {
+    pub(crate) fn new(bucket: TokenBucket) -> Self {
+        Self {
+            bucket,
+            queued: VecDeque::new(),
+            payloads: HashMap::new(),
+        }
+    }
+
+    // what the bucket lets through of the queue, then of "messages". a message with the same
+    // payload as one already queued for the same destination is dropped, its "msg_id" is
+    // returned if it isn't the queued one's (it was sent again under a new one). rpcs, as told
+    // by "rpc", are never dropped.
+    pub(crate) fn admit<R>(
+        &mut self,
+        messages: Replies,
+        now: Instant,
+        rpc: R,
+    ) -> (Replies, Vec<MessageId>)
+    where
+        R: Fn(MessageId) -> bool,
+    {
+        let mut admitted = Replies::new();
+        let mut coalesced = Vec::new();
+        while !self.queued.is_empty() && self.bucket.try_take(now) {
+            if let Some((payload, message)) = self.queued.pop_front() {
+                if let Some(payload) = payload {
+                    self.payloads.remove(&payload);
+                }
+                admitted.push(message);
+            }
+        }
+        for mut message in messages {
+            if !limited(&message) {
+                admitted.push(message);
+                continue;
+            }
+            // nothing queued, nothing to coalesce with.
+            if self.queued.is_empty() && self.bucket.try_take(now) {
+                admitted.push(message);
+                continue;
+            }
+            let msg_id = message.body.msg_id();
+            if msg_id.is_some_and(&rpc) {
+                self.queued.push_back((None, message));
+                continue;
+            }
+            let payload = payload(&mut message);
+            match self.payloads.get(&payload) {
+                Some(queued) => {
+                    if msg_id != *queued {
+                        coalesced.extend(msg_id);
+                    }
+                }
+                None => {
+                    self.payloads.insert(payload.clone(), msg_id);
+                    self.queued.push_back((Some(payload), message));
+                }
+            }
+        }
+        (admitted, coalesced)
+    }
+
+    // "dest" left the cluster, what waits for it is dropped.
+    pub(crate) fn remove_to(&mut self, dest: &NodeId) {
+        self.queued.retain(|(_, message)| message.dest != *dest);
+        self.payloads
+            .retain(|(payload_dest, _), _| payload_dest != dest);
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.queued.len()
+    }
+}
+
+fn limited(message: &Message) -> bool {
+    message.dest.is_node()
+        && message.body.in_reply_to().is_none()
+        && message.body.key() != Type::Heartbeat
+}
+
+// the ids are zeroed for serializing and put back, the body isn't copied.
+fn payload(message: &mut Message) -> Payload {
+    let body = &mut message.body;
+    let msg_id = body.msg_id();
+    let lamport = body.lamport();
+    body.set_msg_id(0);
+    body.set_lamport(0);
+    let payload = serde_json::to_string(body).unwrap_or_default();
+    if let Some(msg_id) = msg_id {
+        body.set_msg_id(msg_id);
+    }
+    if let Some(lamport) = lamport {
+        body.set_lamport(lamport);
+    }
+    (message.dest, payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::smallvec;
+    use crate::core::Workload;
+    use std::time::Duration;
+
+    fn broadcast(dest: &str, msg_id: MessageId, message: u64) -> Message {
+        Message {
+            src: "n1".into(),
+            dest: dest.into(),
+            body: Workload::Broadcast {
+                msg_id,
+                message,
+                hops: None,
+            },
+        }
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(10.0, 2);
+        assert!(bucket.try_take(start));
+        assert!(bucket.try_take(start));
+        assert!(!bucket.try_take(start));
+        // a token every 100ms, never more than "burst".
+        assert!(!bucket.try_take(start + Duration::from_millis(50)));
+        assert!(bucket.try_take(start + Duration::from_millis(100)));
+        let later = start + Duration::from_secs(10);
+        assert_eq!((0..5).filter(|_| bucket.try_take(later)).count(), 2);
+    }
+
+    #[test]
+    fn test_throttle_coalesces() {
+        let start = Instant::now();
+        let mut throttle = Throttle::new(TokenBucket::new(10.0, 1));
+        let sent = smallvec![
+            broadcast("n2", 1, 7),
+            broadcast("n2", 2, 8),
+            broadcast("c1", 3, 9),
+        ];
+        let (admitted, coalesced) = throttle.admit(sent, start, |_| false);
+        assert_eq!(admitted.len(), 2); // the first one, and the one to a client.
+        assert!(coalesced.is_empty());
+
+        // the same payload queued twice goes out once, a retry of the queued one isn't
+        // reported as it's the same message.
+        let sent = smallvec![broadcast("n2", 4, 8), broadcast("n2", 2, 8)];
+        let (admitted, coalesced) = throttle.admit(sent, start, |_| false);
+        assert!(admitted.is_empty());
+        assert_eq!(coalesced, vec![4]);
+        assert_eq!(throttle.len(), 1);
+
+        let later = start + Duration::from_millis(100);
+        let (admitted, _) = throttle.admit(Replies::new(), later, |_| false);
+        assert_eq!(admitted.to_vec(), vec![broadcast("n2", 2, 8)]);
+        assert_eq!(throttle.len(), 0);
+    }
+
+    #[test]
+    fn test_throttle_keeps_rpcs() {
+        let start = Instant::now();
+        let mut throttle = Throttle::new(TokenBucket::new(10.0, 1));
+        let rpc = |msg_id| msg_id >= 2;
+        let sent = smallvec![
+            broadcast("n2", 1, 7),
+            broadcast("n2", 2, 7),
+            broadcast("n2", 3, 7),
+            broadcast("n2", 4, 8),
+        ];
+        let (admitted, coalesced) = throttle.admit(sent, start, rpc);
+        assert_eq!(admitted.len(), 1);
+        // the same payload, but callbacks wait on every one of them.
+        assert!(coalesced.is_empty());
+        assert_eq!(throttle.len(), 3);
+        let (_, coalesced) = throttle.admit(smallvec![broadcast("n2", 1, 8)], start, |_| false);
+        assert!(coalesced.is_empty());
+        assert_eq!(throttle.len(), 4);
+    }
+}
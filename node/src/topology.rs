@@ -0,0 +1,116 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::config::TopologyStrategy;
+use crate::core::NodeId;
+
+// the neighbors of every node under "strategy", none for "Maelstrom" whose topology is the one
+// it sends. a "Tree" links each node to its parent and its "fanout" children, in the order of
+// "node_ids", so gossip reaches everyone within twice its depth.
+pub fn generate(
+    strategy: TopologyStrategy,
+    node_ids: &[NodeId],
+    fanout: usize,
+) -> Option<HashMap<NodeId, Vec<NodeId>>> {
+    let mut topology: HashMap<NodeId, Vec<NodeId>> = node_ids
+        .iter()
+        .map(|node_id| (*node_id, Vec::new()))
+        .collect();
+    match strategy {
+        TopologyStrategy::Maelstrom => return None,
+        TopologyStrategy::Tree => {
+            let fanout = fanout.max(1);
+            for (i, child) in node_ids.iter().enumerate().skip(1) {
+                let parent = &node_ids[(i - 1) / fanout];
+                topology.get_mut(parent).unwrap().push(*child);
+                topology.get_mut(child).unwrap().push(*parent);
+            }
+        }
+        TopologyStrategy::Total => {
+            for (node_id, neighbors) in topology.iter_mut() {
+                neighbors.extend(node_ids.iter().filter(|n| *n != node_id).cloned());
+            }
+        }
+    }
+    Some(topology)
+}
+
+// the longest of the shortest paths between two nodes, in hops, following the edges of
+// "topology" ("n1": ["n2"] is n1 -> n2) as maelstrom's "topology" message gives them.
+// nodes that can't reach each other don't count.
+pub fn diameter(topology: &HashMap<NodeId, Vec<NodeId>>) -> u32 {
+    topology
+        .keys()
+        .map(|start| {
+            let mut seen = HashSet::from([start]);
+            let mut queue = VecDeque::from([(start, 0)]);
+            let mut farthest = 0;
+            while let Some((node, hops)) = queue.pop_front() {
+                farthest = hops;
+                for next in topology.get(node).into_iter().flatten() {
+                    if seen.insert(next) {
+                        queue.push_back((next, hops + 1));
+                    }
+                }
+            }
+            farthest
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn topology(edges: &[(&str, &[&str])]) -> HashMap<NodeId, Vec<NodeId>> {
+        edges
+            .iter()
+            .map(|(node, neighbors)| {
+                let neighbors = neighbors.iter().map(|n| (*n).into()).collect();
+                ((*node).into(), neighbors)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_diameter() {
+        let line = topology(&[
+            ("n1", &["n2"]),
+            ("n2", &["n1", "n3"]),
+            ("n3", &["n2", "n4"]),
+            ("n4", &["n3"]),
+        ]);
+        assert_eq!(diameter(&line), 3);
+
+        let total = topology(&[
+            ("n1", &["n2", "n3"]),
+            ("n2", &["n1", "n3"]),
+            ("n3", &["n1", "n2"]),
+        ]);
+        assert_eq!(diameter(&total), 1);
+
+        // edges go one way, n3 reaches nobody.
+        let chain = topology(&[("n1", &["n2"]), ("n2", &["n3"]), ("n3", &[])]);
+        assert_eq!(diameter(&chain), 2);
+        assert_eq!(diameter(&HashMap::new()), 0);
+    }
+
+    #[test]
+    fn test_generate() {
+        let ids: Vec<NodeId> = (1..=7).map(|i| format!("n{i}").into()).collect();
+        assert_eq!(generate(TopologyStrategy::Maelstrom, &ids, 2), None);
+
+        let tree = generate(TopologyStrategy::Tree, &ids, 2).unwrap();
+        assert_eq!(tree["n1"], ["n2", "n3"]);
+        assert_eq!(tree["n2"], ["n1", "n4", "n5"]);
+        assert_eq!(tree["n7"], ["n3"]);
+        assert_eq!(diameter(&tree), 4);
+
+        let total = generate(TopologyStrategy::Total, &ids, 2).unwrap();
+        assert_eq!(total["n3"].len(), 6);
+        assert_eq!(diameter(&total), 1);
+
+        let alone = generate(TopologyStrategy::Tree, &ids[..1], 0).unwrap();
+        assert_eq!(alone["n1"], Vec::<NodeId>::new());
+    }
+}
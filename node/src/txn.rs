@@ -0,0 +1,422 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+
+use crate::core::{code, CodeId, Timestamp};
+use crate::node_id::NodeId;
+
+pub type TxnKey = u64;
+pub type TxnValue = Value;
+// the lamport stamp of the transaction that wrote a value, and the node that ran it.
+pub type Version = (Timestamp, NodeId);
+
+// a micro-op travels as a ["r", key, value] / ["w", key, value] / ["append", key, value] triple.
+// txn-rw-register uses reads and writes, txn-list-append uses reads and appends.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Op {
+    Read(TxnKey, Option<TxnValue>),
+    Write(TxnKey, TxnValue),
+    Append(TxnKey, TxnValue),
+}
+
+impl Serialize for Op {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Op::Read(key, value) => ("r", key, value).serialize(serializer),
+            Op::Write(key, value) => ("w", key, value).serialize(serializer),
+            Op::Append(key, value) => ("append", key, value).serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Op {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (f, key, value) = <(String, TxnKey, Option<TxnValue>)>::deserialize(deserializer)?;
+        match (f.as_str(), value) {
+            ("r", value) => Ok(Op::Read(key, value)),
+            ("w", Some(value)) => Ok(Op::Write(key, value)),
+            ("append", Some(value)) => Ok(Op::Append(key, value)),
+            ("w" | "append", None) => Err(serde::de::Error::custom("op must carry a value")),
+            (f, _) => Err(serde::de::Error::unknown_variant(f, &["r", "w", "append"])),
+        }
+    }
+}
+
+impl Op {
+    pub fn key(&self) -> TxnKey {
+        match self {
+            Op::Read(key, _) | Op::Write(key, _) | Op::Append(key, _) => *key,
+        }
+    }
+}
+
+// in-memory key/value store, micro-ops of a transaction are applied in order.
+// every key remembers the version of the write it holds: the newest write wins, in whatever
+// order writes arrive, so replicas agree. appended values are kept ordered by the version of
+// the append, for the same reason, and an append older than the write a key holds is dropped,
+// the list it went to was replaced.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Store {
+    data: HashMap<TxnKey, TxnValue>,
+    // the version of the write every key holds, appends aside.
+    versions: HashMap<TxnKey, Version>,
+    // the version of every element of a list, in list order.
+    appended: HashMap<TxnKey, Vec<Version>>,
+}
+
+impl Store {
+    // returns the transaction with read values filled in. its writes are stamped 0, older
+    // than any other.
+    pub fn execute(&mut self, txn: Vec<Op>) -> Result<Vec<Op>, (CodeId, String)> {
+        self.execute_at(txn, 0, &NodeId::default())
+    }
+
+    // same as "execute", with the writes stamped "stamp" (e.g. the lamport time of the
+    // transaction) by "origin", the node that ran it. a write older than the one a key holds
+    // is skipped, the transaction still reports it, and so is an append older than it. a
+    // transaction appending to a value that isn't a list is rejected as a whole, before
+    // anything is applied.
+    pub fn execute_at(
+        &mut self,
+        txn: Vec<Op>,
+        stamp: Timestamp,
+        origin: &NodeId,
+    ) -> Result<Vec<Op>, (CodeId, String)> {
+        self.check_appends(&txn)?;
+        let version = (stamp, *origin);
+        let txn = txn
+            .into_iter()
+            .map(|op| match op {
+                Op::Read(key, _) => Op::Read(key, self.data.get(&key).cloned()),
+                Op::Write(key, value) => {
+                    if self
+                        .versions
+                        .get(&key)
+                        .is_none_or(|newest| *newest <= version)
+                    {
+                        self.write(key, value.clone(), version);
+                    }
+                    Op::Write(key, value)
+                }
+                Op::Append(key, value) => {
+                    // the list it went to was replaced by a newer write.
+                    if self
+                        .versions
+                        .get(&key)
+                        .is_none_or(|written| *written <= version)
+                    {
+                        self.append(key, value.clone(), version);
+                    }
+                    Op::Append(key, value)
+                }
+            })
+            .collect();
+        Ok(txn)
+    }
+
+    // applies the write set of a transaction replicated from "origin", op by op: an append to
+    // a value that isn't a list here (a write the origin hadn't seen) is skipped, the rest of
+    // the write set still is applied. the write, newer or older, wins on every replica either
+    // way, since a write that isn't a list replaces the appends around it. returns the ops
+    // skipped.
+    pub fn apply_at(&mut self, writes: Vec<Op>, stamp: Timestamp, origin: &NodeId) -> Vec<Op> {
+        writes
+            .into_iter()
+            .filter(|op| self.execute_at(vec![op.clone()], stamp, origin).is_err())
+            .collect()
+    }
+
+    // "value" replaces what "key" holds, but for the appends newer than the write that
+    // arrived before it, which stay after it.
+    fn write(&mut self, key: TxnKey, value: TxnValue, version: Version) {
+        self.versions.insert(key, version);
+        let (versions, values) = match value {
+            TxnValue::Array(values) => (vec![version; values.len()], values),
+            value => {
+                self.appended.remove(&key);
+                self.data.insert(key, value);
+                return;
+            }
+        };
+        let newer = match (self.appended.remove(&key), self.data.remove(&key)) {
+            (Some(appended), Some(TxnValue::Array(list))) => appended
+                .into_iter()
+                .zip(list)
+                .filter(|(appended, _)| *appended > version)
+                .collect(),
+            _ => Vec::new(),
+        };
+        let (newer_versions, newer_values): (Vec<_>, Vec<_>) = newer.into_iter().unzip();
+        self.appended
+            .insert(key, versions.into_iter().chain(newer_versions).collect());
+        let list = values.into_iter().chain(newer_values).collect();
+        self.data.insert(key, TxnValue::Array(list));
+    }
+
+    // "value" goes after every element of an older or the same append, before newer ones.
+    fn append(&mut self, key: TxnKey, value: TxnValue, version: Version) {
+        let versions = self.appended.entry(key).or_default();
+        let at = versions.partition_point(|appended| *appended <= version);
+        versions.insert(at, version);
+        if let TxnValue::Array(values) = self
+            .data
+            .entry(key)
+            .or_insert_with(|| TxnValue::Array(Vec::new()))
+        {
+            values.insert(at, value);
+        }
+    }
+
+    // every append of "txn" is to a list, or to a key without a value, as it will be once the
+    // writes before it in "txn" are applied.
+    fn check_appends(&self, txn: &[Op]) -> Result<(), (CodeId, String)> {
+        let mut lists: HashMap<TxnKey, bool> = HashMap::new();
+        for op in txn {
+            match op {
+                Op::Read(..) => {}
+                Op::Write(key, value) => {
+                    lists.insert(*key, value.is_array());
+                }
+                Op::Append(key, _) => {
+                    let list = match lists.get(key) {
+                        Some(list) => *list,
+                        None => self.data.get(key).is_none_or(TxnValue::is_array),
+                    };
+                    if !list {
+                        let text = format!("can't append to {key}, it isn't a list");
+                        return Err((code::TXN_CONFLICT, text));
+                    }
+                    lists.insert(*key, true);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, key: TxnKey) -> Option<&TxnValue> {
+        self.data.get(&key)
+    }
+
+    // of the write "key" holds, none for one never written.
+    pub fn version(&self, key: TxnKey) -> Option<&Version> {
+        self.versions.get(&key)
+    }
+}
+
+// only the last write to each key is visible once the transaction commits,
+// replicating intermediate values would let peers observe them (G1b).
+// appends don't overwrite each other, so all of them are kept.
+pub fn write_set(txn: &[Op]) -> Vec<Op> {
+    let mut writes: Vec<Op> = Vec::new();
+    for op in txn.iter().rev() {
+        match op {
+            Op::Write(key, _) => {
+                if !writes
+                    .iter()
+                    .any(|w| matches!(w, Op::Write(k, _) if k == key))
+                {
+                    writes.push(op.clone());
+                }
+            }
+            Op::Append(..) => writes.push(op.clone()),
+            Op::Read(..) => {}
+        }
+    }
+    writes.reverse();
+    writes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_op_serde() {
+        let json = r#"[["r",1,null],["w",1,6],["r",2,3]]"#;
+        let txn = serde_json::from_str::<Vec<Op>>(json).unwrap();
+        assert_eq!(
+            txn,
+            vec![
+                Op::Read(1, None),
+                Op::Write(1, 6.into()),
+                Op::Read(2, Some(3.into()))
+            ]
+        );
+        assert_eq!(serde_json::to_string(&txn).unwrap(), json);
+
+        let json = r#"[["append",1,3],["r",1,[1,2,3]]]"#;
+        let txn = serde_json::from_str::<Vec<Op>>(json).unwrap();
+        assert_eq!(
+            txn,
+            vec![
+                Op::Append(1, 3.into()),
+                Op::Read(1, Some(vec![1, 2, 3].into()))
+            ]
+        );
+        assert_eq!(serde_json::to_string(&txn).unwrap(), json);
+
+        assert!(serde_json::from_str::<Op>(r#"["w",1,null]"#).is_err());
+        assert!(serde_json::from_str::<Op>(r#"["append",1,null]"#).is_err());
+        assert!(serde_json::from_str::<Op>(r#"["x",1,2]"#).is_err());
+    }
+
+    #[test]
+    fn test_write_set() {
+        let txn = vec![
+            Op::Write(1, 1.into()),
+            Op::Read(2, Some(3.into())),
+            Op::Write(2, 4.into()),
+            Op::Write(1, 5.into()),
+            Op::Append(3, 1.into()),
+            Op::Append(3, 2.into()),
+        ];
+        assert_eq!(
+            write_set(&txn),
+            vec![
+                Op::Write(2, 4.into()),
+                Op::Write(1, 5.into()),
+                Op::Append(3, 1.into()),
+                Op::Append(3, 2.into())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_store_execute_append() {
+        let mut store = Store::default();
+        let txn = vec![
+            Op::Append(1, 1.into()),
+            Op::Read(1, None),
+            Op::Append(1, 2.into()),
+        ];
+        store.execute(txn).unwrap();
+        assert_eq!(
+            store.execute(vec![Op::Read(1, None), Op::Read(2, None)]),
+            Ok(vec![
+                Op::Read(1, Some(vec![1, 2].into())),
+                Op::Read(2, None)
+            ])
+        );
+    }
+
+    #[test]
+    fn test_store_append_to_non_list() {
+        let mut store = Store::default();
+        store.execute(vec![Op::Write(1, 5.into())]).unwrap();
+        let txn = vec![Op::Append(2, 1.into()), Op::Append(1, 6.into())];
+        let (code, _) = store.execute(txn).unwrap_err();
+        assert_eq!(code, code::TXN_CONFLICT);
+        // nothing of a rejected transaction is applied.
+        assert_eq!(store.get(1), Some(&5.into()));
+        assert_eq!(store.get(2), None);
+        // a list written earlier in the same transaction can be appended to.
+        let txn = vec![Op::Write(1, vec![1].into()), Op::Append(1, 2.into())];
+        store.execute(txn).unwrap();
+        assert_eq!(store.get(1), Some(&vec![1, 2].into()));
+    }
+
+    #[test]
+    fn test_store_appends_ordered_by_version() {
+        let (n1, n2): (NodeId, NodeId) = ("n1".into(), "n2".into());
+        let txns = [
+            (
+                vec![Op::Append(1, 1.into()), Op::Append(1, 2.into())],
+                3,
+                n1,
+            ),
+            (vec![Op::Append(1, 3.into())], 2, n2),
+            (vec![Op::Append(1, 4.into())], 3, n2),
+        ];
+        // replicas applying the same appends in any order end up with the same list.
+        let mut forward = Store::default();
+        for (txn, stamp, origin) in txns.iter().cloned() {
+            forward.execute_at(txn, stamp, &origin).unwrap();
+        }
+        let mut backward = Store::default();
+        for (txn, stamp, origin) in txns.iter().rev().cloned() {
+            backward.execute_at(txn, stamp, &origin).unwrap();
+        }
+        assert_eq!(forward.get(1), Some(&vec![3, 1, 2, 4].into()));
+        assert_eq!(backward.get(1), forward.get(1));
+    }
+
+    #[test]
+    fn test_store_append_older_than_write() {
+        let (n1, n2): (NodeId, NodeId) = ("n1".into(), "n2".into());
+        let write = (vec![Op::Write(1, vec![5].into())], 2, n1);
+        let append = (vec![Op::Append(1, 6.into())], 1, n2);
+        let newer = (vec![Op::Append(1, 7.into())], 3, n2);
+        // an append older than the write is dropped, whichever comes first. a newer one stays
+        // after the write, even when it arrived before it.
+        let apply = |txns: &[&(Vec<Op>, Timestamp, NodeId)]| {
+            let mut store = Store::default();
+            for (txn, stamp, origin) in txns.iter().cloned().cloned() {
+                store.execute_at(txn, stamp, &origin).unwrap();
+            }
+            store
+        };
+        let written_first = apply(&[&write, &append, &newer]);
+        let appended_first = apply(&[&append, &newer, &write]);
+        assert_eq!(written_first.get(1), Some(&vec![5, 7].into()));
+        assert_eq!(written_first, appended_first);
+    }
+
+    #[test]
+    fn test_store_apply_conflicting() {
+        let (n1, n2): (NodeId, NodeId) = ("n1".into(), "n2".into());
+        let mut store = Store::default();
+        store
+            .execute_at(vec![Op::Write(1, 5.into())], 1, &n2)
+            .unwrap();
+        // the append to a value that isn't a list is skipped, not the whole write set.
+        let writes = vec![Op::Append(1, 6.into()), Op::Write(2, 7.into())];
+        assert_eq!(
+            store.apply_at(writes, 2, &n1),
+            vec![Op::Append(1, 6.into())]
+        );
+        assert_eq!(store.get(1), Some(&5.into()));
+        assert_eq!(store.get(2), Some(&7.into()));
+    }
+
+    #[test]
+    fn test_store_execute() {
+        let mut store = Store::default();
+        let txn = vec![Op::Read(1, None), Op::Write(1, 6.into()), Op::Read(1, None)];
+        assert_eq!(
+            store.execute(txn),
+            Ok(vec![
+                Op::Read(1, None),
+                Op::Write(1, 6.into()),
+                Op::Read(1, Some(6.into()))
+            ])
+        );
+        assert_eq!(
+            store.execute(vec![Op::Read(1, None)]),
+            Ok(vec![Op::Read(1, Some(6.into()))])
+        );
+    }
+
+    #[test]
+    fn test_store_last_writer_wins() {
+        let mut store = Store::default();
+        let (n1, n2) = ("n1".into(), "n2".into());
+        store
+            .execute_at(vec![Op::Write(1, 5.into())], 10, &n1)
+            .unwrap();
+        // older writes replicated late are skipped, ties go to the larger node id.
+        store
+            .execute_at(vec![Op::Write(1, 3.into())], 4, &n2)
+            .unwrap();
+        assert_eq!(store.get(1), Some(&5.into()));
+        store
+            .execute_at(vec![Op::Write(1, 6.into())], 10, &n2)
+            .unwrap();
+        assert_eq!(store.get(1), Some(&6.into()));
+        store
+            .execute_at(vec![Op::Write(1, 7.into())], 10, &n1)
+            .unwrap();
+        assert_eq!(store.get(1), Some(&6.into()));
+        assert_eq!(store.version(1), Some(&(10, n2)));
+    }
+}
@@ -0,0 +1,512 @@
+use serde_json::{json, Value};
+
+use crate::node_id::NodeId;
+
+// what an id is generated from: the node, its position in "node_ids" and the wall clock.
+pub struct UidSource<'a> {
+    pub node_id: &'a NodeId,
+    pub index: u64,
+    pub unix_millis: u64,
+}
+
+// a scheme for ids unique across the cluster, see "Node::set_uid_generator".
+// "Snowflake" unless replaced.
+pub trait UidGenerator: Send {
+    fn generate(&mut self, source: &UidSource) -> String;
+
+    // state carried over a restart, see "Node::enable_persistence". it's a lease: ids stay within
+    // what was saved until the next save, so a node restored from it never repeats one.
+    fn save(&mut self, _unix_millis: u64) -> Value {
+        Value::Null
+    }
+
+    fn restore(&mut self, _state: &Value) {}
+
+    // true when the next id could go past the last save.
+    fn must_save(&self, _source: &UidSource) -> bool {
+        false
+    }
+}
+
+// how far a save leases ahead: ids of a counter, milliseconds of a "Snowflake".
+const COUNTER_LEASE: u64 = 10_000;
+const SNOWFLAKE_LEASE_MS: u64 = 1_000;
+
+const SNOWFLAKE_NODE_BITS: u32 = 32;
+const SNOWFLAKE_SEQUENCE_BITS: u32 = 48;
+
+// 128 bits ids, 32 hex digits: 48 bits of unix time in milliseconds, 32 bits of node index and
+// a 48 bits sequence within the millisecond. fixed width, so ids sort by time as strings too.
+#[derive(Debug, Default)]
+pub struct Snowflake {
+    millis: u64,
+    sequence: u64,
+    leased: u64,
+}
+
+impl Snowflake {
+    // time never goes back for ids, and once the sequence of a millisecond is exhausted,
+    // ids are taken from the next millisecond. the clock catches up with it eventually.
+    pub(crate) fn next(&mut self, index: u64, now_ms: u64) -> u128 {
+        assert!(
+            index < 1 << SNOWFLAKE_NODE_BITS,
+            "Unique id: too many nodes."
+        );
+        if now_ms > self.millis {
+            self.millis = now_ms;
+            self.sequence = 0;
+        } else if self.sequence + 1 < 1 << SNOWFLAKE_SEQUENCE_BITS {
+            self.sequence += 1;
+        } else {
+            self.millis += 1;
+            self.sequence = 0;
+        }
+        ((self.millis as u128) << (SNOWFLAKE_NODE_BITS + SNOWFLAKE_SEQUENCE_BITS))
+            | ((index as u128) << SNOWFLAKE_SEQUENCE_BITS)
+            | self.sequence as u128
+    }
+}
+
+impl UidGenerator for Snowflake {
+    fn generate(&mut self, source: &UidSource) -> String {
+        format!("{:032x}", self.next(source.index, source.unix_millis))
+    }
+
+    fn save(&mut self, unix_millis: u64) -> Value {
+        self.leased = self.millis.max(unix_millis) + SNOWFLAKE_LEASE_MS;
+        json!(self.leased)
+    }
+
+    // the sequence is spent, the next id is taken from past the lease.
+    fn restore(&mut self, state: &Value) {
+        if let Some(leased) = state.as_u64() {
+            self.millis = self.millis.max(leased);
+            self.sequence = (1 << SNOWFLAKE_SEQUENCE_BITS) - 1;
+            self.leased = leased;
+        }
+    }
+
+    fn must_save(&self, source: &UidSource) -> bool {
+        source.unix_millis.max(self.millis + 1) > self.leased
+    }
+}
+
+// the counter of a scheme, with how far it was leased, see "UidGenerator::save".
+#[derive(Debug, Default)]
+struct LeasedCounter {
+    counter: u64,
+    leased: u64,
+}
+
+impl LeasedCounter {
+    fn next(&mut self) -> u64 {
+        self.counter += 1;
+        self.counter
+    }
+
+    fn save(&mut self) -> Value {
+        self.leased = self.counter + COUNTER_LEASE;
+        json!(self.leased)
+    }
+
+    fn restore(&mut self, state: &Value) {
+        if let Some(leased) = state.as_u64() {
+            self.counter = self.counter.max(leased);
+            self.leased = leased;
+        }
+    }
+
+    fn must_save(&self) -> bool {
+        self.counter >= self.leased
+    }
+}
+
+const CROCKFORD: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+// the time part of ids never goes back: a clock stepping backwards keeps the last time until
+// it catches up, and the counter alone orders the ids in between.
+fn monotonic(last: &mut u64, now_ms: u64) -> u128 {
+    *last = now_ms.max(*last);
+    *last as u128 & 0xffff_ffff_ffff
+}
+
+// 128 bits ULIDs, 26 characters of Crockford's base32: 48 bits of unix time in milliseconds,
+// then 16 bits of node index and a 64 bits counter in place of the randomness. ids of a node
+// sort in the order they were generated.
+#[derive(Debug, Default)]
+pub struct Ulid {
+    millis: u64,
+    counter: LeasedCounter,
+}
+
+impl UidGenerator for Ulid {
+    fn generate(&mut self, source: &UidSource) -> String {
+        assert!(source.index < 1 << 16, "Unique id: too many nodes.");
+        let counter = self.counter.next();
+        let value = (monotonic(&mut self.millis, source.unix_millis) << 80)
+            | ((source.index as u128) << 64)
+            | counter as u128;
+        // 26 characters hold 130 bits, the first one only the top 3.
+        (0..26)
+            .map(|i| CROCKFORD[((value >> (125 - 5 * i)) & 0x1f) as usize] as char)
+            .collect()
+    }
+
+    fn save(&mut self, _unix_millis: u64) -> Value {
+        self.counter.save()
+    }
+
+    fn restore(&mut self, state: &Value) {
+        self.counter.restore(state);
+    }
+
+    fn must_save(&self, _source: &UidSource) -> bool {
+        self.counter.must_save()
+    }
+}
+
+// UUIDv7 (RFC 9562): 48 bits of unix time in milliseconds, the version, 12 bits of the counter
+// as the sub-millisecond sequence, the variant, then 16 bits of node index and 46 more bits of
+// the counter.
+#[derive(Debug, Default)]
+pub struct UuidV7 {
+    millis: u64,
+    counter: LeasedCounter,
+}
+
+impl UidGenerator for UuidV7 {
+    fn generate(&mut self, source: &UidSource) -> String {
+        assert!(source.index < 1 << 16, "Unique id: too many nodes.");
+        let counter = self.counter.next() as u128;
+        let value = (monotonic(&mut self.millis, source.unix_millis) << 80)
+            | (0x7 << 76)
+            | (((counter >> 46) & 0xfff) << 64)
+            | (0b10 << 62)
+            | ((source.index as u128) << 46)
+            | (counter & ((1 << 46) - 1));
+        format!(
+            "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+            value >> 96,
+            (value >> 80) & 0xffff,
+            (value >> 64) & 0xffff,
+            (value >> 48) & 0xffff,
+            value & 0xffff_ffff_ffff
+        )
+    }
+
+    fn save(&mut self, _unix_millis: u64) -> Value {
+        self.counter.save()
+    }
+
+    fn restore(&mut self, state: &Value) {
+        self.counter.restore(state);
+    }
+
+    fn must_save(&self, _source: &UidSource) -> bool {
+        self.counter.must_save()
+    }
+}
+
+// "n1-42": the node id and a counter, no clock involved. the shortest ids, but a node restarting
+// starts over from 1 unless it's persisted.
+#[derive(Debug, Default)]
+pub struct Counter {
+    counter: LeasedCounter,
+}
+
+impl UidGenerator for Counter {
+    fn generate(&mut self, source: &UidSource) -> String {
+        format!("{}-{}", source.node_id, self.counter.next())
+    }
+
+    fn save(&mut self, _unix_millis: u64) -> Value {
+        self.counter.save()
+    }
+
+    fn restore(&mut self, state: &Value) {
+        self.counter.restore(state);
+    }
+
+    fn must_save(&self, _source: &UidSource) -> bool {
+        self.counter.must_save()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UidScheme {
+    Snowflake,
+    Ulid,
+    UuidV7,
+    Counter,
+}
+
+// who generated an id: schemes with a clock embed the node index, "Counter" the node id.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum UidNode {
+    Index(u64),
+    Id(NodeId),
+}
+
+// the parts of an id, see "Uid::decode".
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Uid {
+    pub scheme: UidScheme,
+    pub unix_millis: Option<u64>,
+    pub node: UidNode,
+    pub sequence: u64,
+}
+
+impl Uid {
+    // takes an id generated by any of the schemes apart, or returns None for strings none of
+    // them generates. for ULIDs and UUIDv7s "sequence" is the counter of the node.
+    pub fn decode(id: &str) -> Option<Uid> {
+        let hex = |digits: &str| {
+            digits
+                .bytes()
+                .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+                .then(|| u128::from_str_radix(digits, 16).ok())
+                .flatten()
+        };
+        if id.len() == 32 {
+            let value = hex(id)?;
+            return Some(Uid {
+                scheme: UidScheme::Snowflake,
+                unix_millis: Some(
+                    (value >> (SNOWFLAKE_NODE_BITS + SNOWFLAKE_SEQUENCE_BITS)) as u64,
+                ),
+                node: UidNode::Index(((value >> SNOWFLAKE_SEQUENCE_BITS) & 0xffff_ffff) as u64),
+                sequence: (value & ((1 << SNOWFLAKE_SEQUENCE_BITS) - 1)) as u64,
+            });
+        }
+        if id.len() == 26 {
+            let value = id.bytes().try_fold(0u128, |value, b| {
+                let digit = CROCKFORD.iter().position(|c| *c == b)?;
+                Some((value << 5) | digit as u128)
+            })?;
+            return Some(Uid {
+                scheme: UidScheme::Ulid,
+                unix_millis: Some((value >> 80) as u64),
+                node: UidNode::Index(((value >> 64) & 0xffff) as u64),
+                sequence: value as u64,
+            });
+        }
+        if id.len() == 36 && [8, 13, 18, 23].iter().all(|i| id.as_bytes()[*i] == b'-') {
+            let value = hex(&id.replace('-', ""))?;
+            if (value >> 76) & 0xf != 0x7 || (value >> 62) & 0b11 != 0b10 {
+                return None;
+            }
+            return Some(Uid {
+                scheme: UidScheme::UuidV7,
+                unix_millis: Some((value >> 80) as u64),
+                node: UidNode::Index(((value >> 46) & 0xffff) as u64),
+                sequence: ((((value >> 64) & 0xfff) << 46) | (value & ((1 << 46) - 1))) as u64,
+            });
+        }
+        let (node_id, counter) = id.rsplit_once('-')?;
+        if node_id.is_empty() || !counter.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        Some(Uid {
+            scheme: UidScheme::Counter,
+            unix_millis: None,
+            node: UidNode::Id(node_id.into()),
+            sequence: counter.parse().ok()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use std::collections::HashSet;
+
+    type Scheme = fn() -> Box<dyn UidGenerator>;
+
+    fn generate(generator: &mut dyn UidGenerator, node_id: &str, index: u64, now: u64) -> String {
+        let node_id = node_id.into();
+        generator.generate(&UidSource {
+            node_id: &node_id,
+            index,
+            unix_millis: now,
+        })
+    }
+
+    #[test]
+    fn test_unique_ids_within_a_millisecond() {
+        let now = 1_700_000_000_000;
+        let (mut n1, mut n2) = (Snowflake::default(), Snowflake::default());
+        // more ids than the sequence holds, on both nodes.
+        let ids: HashSet<u128> = (0..5_000)
+            .flat_map(|_| [n1.next(0, now), n2.next(1, now)])
+            .collect();
+        assert_eq!(ids.len(), 10_000);
+        // the clock going back doesn't bring old ids back either.
+        assert!(!ids.contains(&n1.next(0, now - 1)));
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        // bursts of ids from random nodes, while the clock stands still, moves on or goes back.
+        #[test]
+        fn test_unique_ids_never_collide(
+            nodes in 1usize..8,
+            steps in prop::collection::vec((-3i64..4, any::<prop::sample::Index>(), 1usize..5_000), 1..8),
+        ) {
+            let mut cluster: Vec<Snowflake> = (0..nodes).map(|_| Snowflake::default()).collect();
+            let mut now: u64 = 1_700_000_000_000;
+            let mut ids = HashSet::new();
+            for (delta, node, burst) in steps {
+                now = now.saturating_add_signed(delta);
+                let index = node.index(nodes);
+                for _ in 0..burst {
+                    let id = cluster[index].next(index as u64, now);
+                    prop_assert!(ids.insert(id), "duplicate id {}", id);
+                }
+            }
+        }
+    }
+
+    // a burst far past the old 8 and 12 bits sequences, from node indexes past the old 8 and
+    // 10 bits, all within one millisecond.
+    #[test]
+    fn test_unique_ids_hammered() {
+        let now = 1_700_000_000_000;
+        let mut ids = HashSet::new();
+        let mut n1 = Snowflake::default();
+        for _ in 0..200_000 {
+            assert!(ids.insert(generate(&mut n1, "n1", 0, now)));
+        }
+        let mut cluster: Vec<Snowflake> = (0..2_000).map(|_| Snowflake::default()).collect();
+        for _ in 0..50 {
+            for (index, node) in cluster.iter_mut().enumerate().skip(1) {
+                assert!(ids.insert(generate(node, "n", index as u64, now)));
+            }
+        }
+        assert_eq!(ids.len(), 200_000 + 50 * 1_999);
+        // every id is 32 hex digits, and later ids of a node sort after earlier ones.
+        assert!(ids.iter().all(|id| id.len() == 32));
+        let indexes: HashSet<UidNode> =
+            ids.iter().map(|id| Uid::decode(id).unwrap().node).collect();
+        assert_eq!(indexes.len(), 2_000);
+        assert!(
+            generate(&mut n1, "n1", 0, now) > generate(&mut Snowflake::default(), "n1", 0, now)
+        );
+    }
+
+    // the clock stepping back, even by more than the sequence holds, never brings ids back, nor
+    // out of order.
+    #[test]
+    fn test_uid_clock_regression() {
+        let schemes: [Scheme; 3] = [
+            || Box::<Snowflake>::default(),
+            || Box::<Ulid>::default(),
+            || Box::<UuidV7>::default(),
+        ];
+        for scheme in schemes {
+            let mut generator = scheme();
+            let mut last = String::new();
+            for now in [
+                1_700_000_000_000,
+                1_700_000_000_500,
+                1_699_999_000_000,
+                1_700_000_000_501,
+            ] {
+                for _ in 0..5_000 {
+                    let id = generate(generator.as_mut(), "n1", 0, now);
+                    assert!(id > last, "{id} after {last}");
+                    last = id;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_uid_formats() {
+        let now = 1_700_000_000_000;
+
+        let snowflake = generate(&mut Snowflake::default(), "n2", 1, now);
+        assert_eq!(snowflake, "018bcfe5680000000001000000000000");
+
+        let ulid = generate(&mut Ulid::default(), "n2", 1, now);
+        assert_eq!(ulid, "01HF7YAT00000G000000000001");
+        let mut generator = Ulid::default();
+        let ulids: Vec<String> = (0..3)
+            .map(|_| generate(&mut generator, "n1", 0, now))
+            .collect();
+        assert!(ulids.windows(2).all(|pair| pair[0] < pair[1]));
+
+        let uuid = generate(&mut UuidV7::default(), "n2", 1, now);
+        assert_eq!(uuid, "018bcfe5-6800-7000-8000-400000000001");
+
+        let mut generator = Counter::default();
+        assert_eq!(generate(&mut generator, "n1", 0, now), "n1-1");
+        assert_eq!(generate(&mut generator, "n1", 0, now), "n1-2");
+    }
+
+    #[test]
+    fn test_uid_decode() {
+        let now = 1_700_000_000_000;
+        let schemes: [(UidScheme, Scheme); 4] = [
+            (UidScheme::Snowflake, || Box::<Snowflake>::default()),
+            (UidScheme::Ulid, || Box::<Ulid>::default()),
+            (UidScheme::UuidV7, || Box::<UuidV7>::default()),
+            (UidScheme::Counter, || Box::<Counter>::default()),
+        ];
+        for (scheme, generator) in schemes {
+            let mut generator = generator();
+            generate(generator.as_mut(), "n3", 2, now);
+            let uid = Uid::decode(&generate(generator.as_mut(), "n3", 2, now)).unwrap();
+            assert_eq!(uid.scheme, scheme);
+            if scheme == UidScheme::Counter {
+                assert_eq!(uid.unix_millis, None);
+                assert_eq!(uid.node, UidNode::Id("n3".into()));
+                assert_eq!(uid.sequence, 2);
+            } else {
+                assert_eq!(uid.unix_millis, Some(now));
+                assert_eq!(uid.node, UidNode::Index(2));
+                // snowflake sequences restart every millisecond, counters don't.
+                assert_eq!(
+                    uid.sequence,
+                    if scheme == UidScheme::Snowflake { 1 } else { 2 }
+                );
+            }
+        }
+
+        for id in [
+            "",
+            "42",
+            "n1-",
+            "-1",
+            "n1-x",
+            "018bcfe5-6800-4000-8000-400000000001",
+        ] {
+            assert_eq!(Uid::decode(id), None, "{id}");
+        }
+    }
+
+    #[test]
+    fn test_uid_schemes_never_collide() {
+        let now = 1_700_000_000_000;
+        let schemes: [Scheme; 3] = [
+            || Box::<Ulid>::default(),
+            || Box::<UuidV7>::default(),
+            || Box::<Counter>::default(),
+        ];
+        for scheme in schemes {
+            let mut cluster: Vec<Box<dyn UidGenerator>> = (0..3).map(|_| scheme()).collect();
+            let mut ids = HashSet::new();
+            for i in 0..3_000 {
+                let index = i % 3;
+                let id = generate(
+                    cluster[index].as_mut(),
+                    &format!("n{}", index + 1),
+                    index as u64,
+                    now,
+                );
+                assert!(ids.insert(id));
+            }
+        }
+    }
+}
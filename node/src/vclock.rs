@@ -0,0 +1,135 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::core::NodeId;
+
+// version vector keyed by node id, serialized as a plain {"n1": 2, "n2": 1} object.
+// nodes missing from the map are at zero.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct VectorClock {
+    clock: BTreeMap<NodeId, u64>,
+}
+
+impl VectorClock {
+    pub fn get(&self, node_id: &NodeId) -> u64 {
+        self.clock.get(node_id).copied().unwrap_or(0)
+    }
+
+    pub fn increment(&mut self, node_id: &NodeId) -> u64 {
+        let counter = self.clock.entry(*node_id).or_insert(0);
+        *counter += 1;
+        *counter
+    }
+
+    // pointwise maximum.
+    pub fn merge(&mut self, other: &VectorClock) {
+        for (node_id, counter) in &other.clock {
+            let entry = self.clock.entry(*node_id).or_insert(0);
+            *entry = (*entry).max(*counter);
+        }
+    }
+
+    pub fn is_concurrent(&self, other: &VectorClock) -> bool {
+        self.partial_cmp(other).is_none()
+    }
+}
+
+// an explicit zero is the same as a missing entry, as "PartialOrd" has it.
+impl PartialEq for VectorClock {
+    fn eq(&self, other: &Self) -> bool {
+        self.clock
+            .keys()
+            .chain(other.clock.keys())
+            .all(|node_id| self.get(node_id) == other.get(node_id))
+    }
+}
+
+impl Eq for VectorClock {}
+
+// "Less" means happened-before, "None" means concurrent.
+impl PartialOrd for VectorClock {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        let mut ordering = Ordering::Equal;
+        for node_id in self.clock.keys().chain(other.clock.keys()) {
+            match (ordering, self.get(node_id).cmp(&other.get(node_id))) {
+                (_, Ordering::Equal) => {}
+                (Ordering::Equal, next) => ordering = next,
+                (current, next) if current != next => return None,
+                _ => {}
+            }
+        }
+        Some(ordering)
+    }
+}
+
+// attaches a clock to a gossiped payload.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Versioned<T> {
+    pub clock: VectorClock,
+    pub value: T,
+}
+
+impl<T> Versioned<T> {
+    pub fn new(clock: VectorClock, value: T) -> Self {
+        Self { clock, value }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clock(entries: &[(&str, u64)]) -> VectorClock {
+        let mut clock = VectorClock::default();
+        for (node_id, counter) in entries {
+            for _ in 0..*counter {
+                clock.increment(&NodeId::from(*node_id));
+            }
+        }
+        clock
+    }
+
+    #[test]
+    fn test_vclock_compare() {
+        let a = clock(&[("n1", 1)]);
+        let b = clock(&[("n1", 2), ("n2", 1)]);
+        let c = clock(&[("n2", 2)]);
+        assert!(a < b);
+        assert!(b > a);
+        assert_eq!(a.partial_cmp(&a.clone()), Some(Ordering::Equal));
+        assert!(b.is_concurrent(&c));
+        assert_eq!(
+            VectorClock::default().partial_cmp(&clock(&[("n1", 0)])),
+            Some(Ordering::Equal)
+        );
+        // an explicit zero, e.g. from the wire.
+        let zero: VectorClock = serde_json::from_str(r#"{"n1":0}"#).unwrap();
+        assert_eq!(
+            VectorClock::default().partial_cmp(&zero),
+            Some(Ordering::Equal)
+        );
+        assert_eq!(VectorClock::default(), zero);
+        assert_ne!(zero, a);
+    }
+
+    #[test]
+    fn test_vclock_merge() {
+        let mut a = clock(&[("n1", 3), ("n2", 1)]);
+        a.merge(&clock(&[("n2", 2), ("n3", 1)]));
+        assert_eq!(a, clock(&[("n1", 3), ("n2", 2), ("n3", 1)]));
+    }
+
+    #[test]
+    fn test_vclock_serde() {
+        let versioned = Versioned::new(clock(&[("n1", 2)]), vec![1, 2]);
+        let json = serde_json::to_string(&versioned).unwrap();
+        assert_eq!(json, r#"{"clock":{"n1":2},"value":[1,2]}"#);
+        assert_eq!(
+            serde_json::from_str::<Versioned<Vec<u64>>>(&json).unwrap(),
+            versioned
+        );
+    }
+}
@@ -0,0 +1,245 @@
+use std::io::Write;
+use std::str::FromStr;
+
+use serde_json::{json, Value};
+use tracing::warn;
+
+use crate::core::{BroadcastMessage, Message, Workload};
+use crate::helper::Error;
+
+// how "GossipTrace" writes its events.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TraceFormat {
+    // one object per line, {"at_ms":15,"from":"n1","hops":1,"to":"n2","value":42}.
+    #[default]
+    Json,
+    // a graphviz digraph, one labelled edge per value forwarded: "dot -Tsvg".
+    Dot,
+}
+
+impl FromStr for TraceFormat {
+    type Err = Error;
+
+    fn from_str(name: &str) -> std::result::Result<Self, Error> {
+        match name {
+            "json" => Ok(TraceFormat::Json),
+            "dot" => Ok(TraceFormat::Dot),
+            _ => Err(Error::InvalidConfig {
+                name: "trace format".to_owned(),
+                value: name.to_owned(),
+            }),
+        }
+    }
+}
+
+// which node forwarded which broadcast value to whom, and when: gossip between nodes
+// ("broadcast", or a batch of values in a custom body), not what clients send or get back. "at_ms" counts from
+// wherever the caller's clock starts, see "Runner::with_gossip_trace" and
+// "Network::with_gossip_trace". a DOT graph is closed when the trace is dropped. the trace is
+// a debugging aid: once a write fails (a full disk, a closed pipe), it's off for good, and the
+// node goes on without it.
+pub struct GossipTrace {
+    format: TraceFormat,
+    output: Box<dyn Write + Send>,
+    started: bool,
+    failed: bool,
+}
+
+impl GossipTrace {
+    pub fn new<W: Write + Send + 'static>(output: W, format: TraceFormat) -> Self {
+        Self {
+            format,
+            output: Box::new(output),
+            started: false,
+            failed: false,
+        }
+    }
+
+    pub fn sent(&mut self, at_ms: u64, messages: &[Message]) {
+        if self.failed {
+            return;
+        }
+        for message in messages {
+            for (value, hops) in forwarded(message) {
+                self.edge(at_ms, message, value, hops);
+            }
+        }
+    }
+
+    fn start(&mut self) {
+        if !std::mem::replace(&mut self.started, true) && self.format == TraceFormat::Dot {
+            self.write("digraph gossip {");
+        }
+    }
+
+    fn edge(&mut self, at_ms: u64, message: &Message, value: BroadcastMessage, hops: u32) {
+        self.start();
+        let (from, to) = (message.src.as_str(), message.dest.as_str());
+        let line = match self.format {
+            TraceFormat::Json => json!({
+                "at_ms": at_ms,
+                "from": from,
+                "to": to,
+                "value": value,
+                "hops": hops,
+            })
+            .to_string(),
+            TraceFormat::Dot => {
+                format!(r#"  "{from}" -> "{to}" [label="{value} @{at_ms}ms hops={hops}"];"#)
+            }
+        };
+        self.write(&line);
+    }
+
+    fn write(&mut self, line: &str) {
+        if self.failed {
+            return;
+        }
+        if let Err(error) = writeln!(self.output, "{line}") {
+            warn!(%error, "gossip trace failed to write, tracing stops");
+            self.failed = true;
+        }
+    }
+}
+
+impl Drop for GossipTrace {
+    fn drop(&mut self) {
+        // nothing to do about a failed write on the way out.
+        if self.failed {
+            return;
+        }
+        if self.format == TraceFormat::Dot {
+            let header = if self.started {
+                ""
+            } else {
+                "digraph gossip {\n"
+            };
+            let _ = writeln!(self.output, "{header}}}");
+        }
+        let _ = self.output.flush();
+    }
+}
+
+// the values "message" forwards from one node to another, with the hops they took.
+fn forwarded(message: &Message) -> Vec<(BroadcastMessage, u32)> {
+    if !message.src.is_node() || !message.dest.is_node() {
+        return Vec::new();
+    }
+    match &message.body {
+        Workload::Broadcast { message, hops, .. } => vec![(*message, hops.unwrap_or(0))],
+        // a workload's own batch, e.g. the broadcast workload's "broadcast_batch": the values in
+        // "messages", "hops" the fewest any of them took.
+        Workload::Custom(body) => {
+            let Some(messages) = body.get("messages").and_then(Value::as_array) else {
+                return Vec::new();
+            };
+            let hops = body.get("hops").and_then(Value::as_u64).unwrap_or(0) as u32;
+            messages
+                .iter()
+                .filter_map(|value| Some((value.as_u64()?, hops)))
+                .collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::message::msg;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn trace(format: TraceFormat) -> String {
+        let output = Shared::default();
+        let mut trace = GossipTrace::new(output.clone(), format);
+        let gossip = |hops| Workload::Broadcast {
+            msg_id: 1,
+            message: 42,
+            hops: Some(hops),
+        };
+        let batch = Workload::Custom(json!({
+            "type": "broadcast_batch",
+            "msg_id": 2,
+            "messages": [7, 8],
+            "hops": 2,
+        }));
+        trace.sent(
+            15,
+            &[
+                msg().from("n1").to("n2").body(gossip(1)),
+                // from a client, and an acknowledgement: not gossip.
+                msg().from("c1").to("n1").broadcast(42),
+                msg().from("n2").to("n1").body(Workload::broadcast_ok(1, 3)),
+            ],
+        );
+        trace.sent(30, &[msg().from("n2").to("n3").body(batch)]);
+        drop(trace);
+        let output = output.0.lock().unwrap().clone();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_gossip_trace() {
+        assert_eq!(
+            trace(TraceFormat::Json),
+            concat!(
+                r#"{"at_ms":15,"from":"n1","hops":1,"to":"n2","value":42}"#,
+                "\n",
+                r#"{"at_ms":30,"from":"n2","hops":2,"to":"n3","value":7}"#,
+                "\n",
+                r#"{"at_ms":30,"from":"n2","hops":2,"to":"n3","value":8}"#,
+                "\n",
+            )
+        );
+        assert_eq!(
+            trace(TraceFormat::Dot),
+            concat!(
+                "digraph gossip {\n",
+                "  \"n1\" -> \"n2\" [label=\"42 @15ms hops=1\"];\n",
+                "  \"n2\" -> \"n3\" [label=\"7 @30ms hops=2\"];\n",
+                "  \"n2\" -> \"n3\" [label=\"8 @30ms hops=2\"];\n",
+                "}\n",
+            )
+        );
+        assert_eq!("dot".parse::<TraceFormat>().unwrap(), TraceFormat::Dot);
+        assert!("svg".parse::<TraceFormat>().is_err());
+    }
+
+    struct Broken;
+
+    impl Write for Broken {
+        fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+            Err(std::io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_gossip_trace_stops_on_failure() {
+        let mut trace = GossipTrace::new(Broken, TraceFormat::Json);
+        let gossip = msg().from("n1").to("n2").body(Workload::Broadcast {
+            msg_id: 1,
+            message: 42,
+            hops: Some(1),
+        });
+        trace.sent(15, std::slice::from_ref(&gossip));
+        trace.sent(30, &[gossip]);
+        assert!(trace.failed);
+    }
+}
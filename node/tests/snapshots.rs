@@ -0,0 +1,427 @@
+// golden snapshots of the wire format: every workload variant is serialized and compared with
+// its fixture in "tests/snapshots", and every fixture deserializes back to its sample. a field
+// renamed, reordered or made optional by a refactor fails here instead of in maelstrom.
+// after an intended change, regenerate the fixtures with
+// "UPDATE_SNAPSHOTS=1 cargo test -p node --test snapshots" and review the diff.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use node::core::{code, Message, Type, Workload};
+use node::raft::{Entry, Membership, RaftRpc, Snapshot};
+use node::txn::Op;
+use serde_json::json;
+
+// one sample per variant, a few more where the shape of the body varies.
+// maps hold a single entry so that their serialization is stable.
+fn samples() -> Vec<(&'static str, Workload)> {
+    vec![
+        (
+            "init",
+            Workload::Init {
+                msg_id: 1,
+                node_id: "n1".into(),
+                node_ids: vec!["n1".into(), "n2".into()],
+            },
+        ),
+        ("init_ok", Workload::InitOk { in_reply_to: 1 }),
+        (
+            "error",
+            Workload::error(
+                4,
+                code::KEY_DOES_NOT_EXIST,
+                "key 0 does not exist".to_owned(),
+            ),
+        ),
+        (
+            "echo",
+            Workload::Echo {
+                msg_id: 1,
+                echo: "hi".to_owned(),
+            },
+        ),
+        ("echo_ok", Workload::echo_ok(1, 2, "hi".to_owned())),
+        ("generate", Workload::Generate { msg_id: 1, n: None }),
+        ("generate_ok", Workload::generate_ok(1, 2, "abc".to_owned())),
+        (
+            "generate_batch",
+            Workload::Generate {
+                msg_id: 1,
+                n: Some(2),
+            },
+        ),
+        (
+            "generate_batch_ok",
+            Workload::generate_batch_ok(1, 2, vec!["abc".to_owned(), "abd".to_owned()]),
+        ),
+        (
+            "broadcast",
+            Workload::Broadcast {
+                msg_id: 1,
+                message: 1000,
+                hops: None,
+            },
+        ),
+        (
+            "broadcast_gossip",
+            Workload::Broadcast {
+                msg_id: 1,
+                message: 1000,
+                hops: Some(2),
+            },
+        ),
+        (
+            "broadcast_ok",
+            Workload::BroadcastOk {
+                in_reply_to: 1,
+                msg_id: 2,
+            },
+        ),
+        (
+            "read",
+            Workload::Read {
+                msg_id: 1,
+                key: None,
+            },
+        ),
+        ("read_kv", Workload::read(1, json!("k"))),
+        (
+            "read_ok",
+            Workload::ReadOk {
+                in_reply_to: 1,
+                msg_id: 2,
+                messages: Some(Arc::new(vec![1, 2])),
+                value: None,
+            },
+        ),
+        ("read_ok_kv", Workload::kv_read_ok(1, 0, json!(3))),
+        ("write", Workload::write(1, json!(0), json!(3))),
+        ("write_ok", Workload::write_ok(1, 2)),
+        (
+            "cas",
+            Workload::Cas {
+                msg_id: 1,
+                key: json!(0),
+                from: json!(3),
+                to: json!(4),
+                create_if_not_exists: true,
+            },
+        ),
+        ("cas_ok", Workload::cas_ok(1, 2)),
+        (
+            "topology",
+            Workload::Topology {
+                msg_id: 1,
+                topology: HashMap::from([("n1".into(), vec!["n2".into(), "n3".into()])]),
+            },
+        ),
+        (
+            "topology_ok",
+            Workload::TopologyOk {
+                in_reply_to: 1,
+                msg_id: 2,
+            },
+        ),
+        (
+            "send",
+            Workload::Send {
+                msg_id: 1,
+                key: "k1".to_owned(),
+                msg: 123,
+            },
+        ),
+        (
+            "send_ok",
+            Workload::SendOk {
+                in_reply_to: 1,
+                msg_id: 2,
+                offset: 1000,
+            },
+        ),
+        (
+            "poll",
+            Workload::Poll {
+                msg_id: 1,
+                offsets: HashMap::from([("k1".to_owned(), 1000)]),
+            },
+        ),
+        (
+            "poll_ok",
+            Workload::PollOk {
+                in_reply_to: 1,
+                msg_id: 2,
+                msgs: HashMap::from([("k1".to_owned(), vec![(1000, 9), (1001, 5)])]),
+            },
+        ),
+        (
+            "commit_offsets",
+            Workload::CommitOffsets {
+                msg_id: 1,
+                offsets: HashMap::from([("k1".to_owned(), 1000)]),
+            },
+        ),
+        (
+            "commit_offsets_ok",
+            Workload::CommitOffsetsOk {
+                in_reply_to: 1,
+                msg_id: 2,
+            },
+        ),
+        (
+            "list_committed_offsets",
+            Workload::ListCommittedOffsets {
+                msg_id: 1,
+                keys: vec!["k1".to_owned(), "k2".to_owned()],
+            },
+        ),
+        (
+            "list_committed_offsets_ok",
+            Workload::ListCommittedOffsetsOk {
+                in_reply_to: 1,
+                msg_id: 2,
+                offsets: HashMap::from([("k1".to_owned(), 1000)]),
+            },
+        ),
+        (
+            "txn",
+            Workload::Txn {
+                msg_id: 1,
+                txn: vec![
+                    Op::Read(1, None),
+                    Op::Write(1, json!(6)),
+                    Op::Append(2, json!(9)),
+                ],
+            },
+        ),
+        (
+            "txn_ok",
+            Workload::TxnOk {
+                in_reply_to: 1,
+                msg_id: 2,
+                txn: vec![Op::Read(1, Some(json!(3))), Op::Write(1, json!(6))],
+            },
+        ),
+        (
+            "raft_request_vote",
+            Workload::Raft {
+                msg_id: 1,
+                rpc: RaftRpc::RequestVote {
+                    term: 2,
+                    candidate_id: "n1".into(),
+                    last_log_index: 3,
+                    last_log_term: 1,
+                },
+            },
+        ),
+        (
+            "raft_request_vote_result",
+            Workload::Raft {
+                msg_id: 2,
+                rpc: RaftRpc::RequestVoteResult {
+                    term: 2,
+                    vote_granted: true,
+                },
+            },
+        ),
+        (
+            "raft_append_entries",
+            Workload::Raft {
+                msg_id: 3,
+                rpc: RaftRpc::AppendEntries {
+                    term: 2,
+                    leader_id: "n1".into(),
+                    prev_log_index: 3,
+                    prev_log_term: 1,
+                    entries: vec![Entry {
+                        term: 2,
+                        command: json!({"op": "write", "key": 0, "value": 3}),
+                        config: None,
+                    }],
+                    leader_commit: 3,
+                    round: 0,
+                },
+            },
+        ),
+        (
+            "raft_append_entries_result",
+            Workload::Raft {
+                msg_id: 4,
+                rpc: RaftRpc::AppendEntriesResult {
+                    term: 2,
+                    success: false,
+                    match_index: 0,
+                    round: 0,
+                },
+            },
+        ),
+        (
+            "raft_install_snapshot",
+            Workload::Raft {
+                msg_id: 5,
+                rpc: RaftRpc::InstallSnapshot {
+                    term: 2,
+                    leader_id: "n1".into(),
+                    snapshot: Snapshot {
+                        last_index: 3,
+                        last_term: 1,
+                        membership: Membership::Stable(vec!["n1".into(), "n2".into()]),
+                        state: json!({"0": 3}),
+                    },
+                },
+            },
+        ),
+        (
+            "sequence",
+            Workload::Sequence {
+                msg_id: 1,
+                message: 1000,
+            },
+        ),
+        ("sequence_ok", Workload::sequence_ok(1, 2, 7)),
+        (
+            "deliver",
+            Workload::Deliver {
+                msg_id: 1,
+                seq: 7,
+                message: 1000,
+            },
+        ),
+        ("deliver_ok", Workload::deliver_ok(1, 2)),
+        ("dump_state", Workload::DumpState { msg_id: 1 }),
+        (
+            "dump_state_ok",
+            Workload::DumpStateOk {
+                in_reply_to: 1,
+                msg_id: 2,
+                state: json!({"node_id": "n1"}),
+            },
+        ),
+        (
+            "heartbeat",
+            Workload::Heartbeat {
+                msg_id: 1,
+                lamport: 5,
+            },
+        ),
+        (
+            "hint",
+            Workload::Hint {
+                msg_id: 2,
+                message: Box::new(Message {
+                    src: "n1".into(),
+                    dest: "n3".into(),
+                    body: Workload::Broadcast {
+                        msg_id: 1,
+                        message: 1000,
+                        hops: None,
+                    },
+                }),
+            },
+        ),
+        ("hint_ok", Workload::hint_ok(2, 3)),
+        (
+            "join",
+            Workload::Join {
+                msg_id: 1,
+                node_id: "n4".into(),
+            },
+        ),
+        ("join_ok", Workload::join_ok(1, 2)),
+        (
+            "leave",
+            Workload::Leave {
+                msg_id: 1,
+                node_id: "n1".into(),
+            },
+        ),
+        ("leave_ok", Workload::leave_ok(1, 2)),
+        (
+            "custom",
+            Workload::Custom(json!({"type": "gossip", "msg_id": 1, "seen": [1, 2]})),
+        ),
+    ]
+}
+
+fn fixtures() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots")
+}
+
+#[test]
+fn test_workload_snapshots() {
+    let update = std::env::var_os("UPDATE_SNAPSHOTS").is_some();
+    let mut failures = Vec::new();
+    for (name, workload) in samples() {
+        let path = fixtures().join(format!("{name}.json"));
+        let serialized = serde_json::to_string(&workload).unwrap() + "\n";
+        if update {
+            fs::create_dir_all(fixtures()).unwrap();
+            fs::write(&path, &serialized).unwrap();
+            continue;
+        }
+        let Ok(fixture) = fs::read_to_string(&path) else {
+            failures.push(format!("{name}: no fixture, run with UPDATE_SNAPSHOTS=1"));
+            continue;
+        };
+        if serialized != fixture {
+            failures.push(format!(
+                "{name}: serialized as\n  {}but the fixture is\n  {}",
+                serialized, fixture
+            ));
+        }
+        match serde_json::from_str::<Workload>(&fixture) {
+            Ok(parsed) if parsed == workload => {}
+            Ok(parsed) => failures.push(format!("{name}: fixture parsed as {parsed:?}")),
+            Err(e) => failures.push(format!("{name}: fixture doesn't parse: {e}")),
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+// every variant has a sample, and every fixture a sample it was generated from.
+#[test]
+fn test_snapshots_cover_every_variant() {
+    let samples = samples();
+    let names: BTreeSet<&str> = samples.iter().map(|(name, _)| *name).collect();
+    assert_eq!(names.len(), samples.len(), "sample names should be unique");
+
+    // serde lists every type when it meets an unknown one, "custom" aside.
+    let error = serde_json::from_str::<Type>(r#""""#).unwrap_err();
+    let error = error.to_string();
+    let expected = error.split("expected one of ").nth(1).unwrap();
+    let mut variants: BTreeSet<&str> = expected
+        .split(" at line")
+        .next()
+        .unwrap()
+        .split(", ")
+        .map(|variant| variant.trim_matches('`'))
+        .collect();
+    variants.insert("custom");
+    let sampled: BTreeSet<&str> = samples
+        .iter()
+        .map(|(_, workload)| workload.name())
+        .collect();
+    assert_eq!(sampled, variants);
+
+    // and every variant its own key, named after it.
+    for (_, workload) in &samples {
+        let key = format!("{:?}", workload.key());
+        let name: String = workload.name().split('_').map(capitalized).collect();
+        assert_eq!(key, name);
+    }
+
+    for entry in fs::read_dir(fixtures()).unwrap() {
+        let file = entry.unwrap().file_name().into_string().unwrap();
+        let name = file.trim_end_matches(".json");
+        assert!(names.contains(name), "fixture {file} has no sample");
+    }
+}
+
+fn capitalized(word: &str) -> String {
+    let mut chars = word.chars();
+    chars
+        .next()
+        .map(|first| first.to_ascii_uppercase().to_string() + chars.as_str())
+        .unwrap_or_default()
+}
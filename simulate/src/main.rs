@@ -0,0 +1,435 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::Parser;
+use node::config::{Config, TopologyStrategy};
+use node::core::{Message, MessageId, NodeId, Workload};
+use node::rng::Rng;
+use node::testing::convergence::{Convergence, NotConverged};
+use node::testing::linearizability::History;
+use node::testing::message::msg;
+use node::testing::network::Network;
+use node::topology::generate;
+use node::viz::{GossipTrace, TraceFormat};
+
+const TICK_MS: u64 = 100;
+
+// the nodes of a workload in one process, on the in-memory network of "node::testing", under
+// a client workload that's either random ("--seed") or read from "--script". what comes out
+// is checked: replies match their requests, ids are unique, broadcast values reach every
+// node, kv histories are linearizable.
+#[derive(Parser, Debug)]
+#[command(
+    version,
+    about = "Runs a workload's nodes in one process and checks the outcome"
+)]
+struct Cli {
+    #[arg(help = "echo, unique-ids, broadcast or lin-kv")]
+    workload: String,
+    #[arg(long, default_value_t = 5, help = "Nodes, named n1..nN")]
+    nodes: usize,
+    #[arg(long, default_value_t = 100, help = "Random client requests to send")]
+    requests: u64,
+    #[arg(long, default_value_t = 0, help = "Seed of every random choice")]
+    seed: u64,
+    #[arg(
+        long,
+        help = "Client messages to send instead, one JSON message per line"
+    )]
+    script: Option<PathBuf>,
+    #[arg(long, default_value_t = 5, help = "Virtual ms between requests")]
+    interval_ms: u64,
+    #[arg(
+        long,
+        default_value_t = 1,
+        help = "Shortest delay between nodes, in ms"
+    )]
+    min_delay_ms: u64,
+    #[arg(
+        long,
+        default_value_t = 10,
+        help = "Longest delay between nodes, in ms"
+    )]
+    max_delay_ms: u64,
+    #[arg(
+        long,
+        default_value_t = 0.0,
+        help = "Probability a message between nodes is lost"
+    )]
+    drop: f64,
+    #[arg(
+        long,
+        default_value_t = 10_000,
+        help = "Virtual ms the nodes get to converge after the last request"
+    )]
+    settle_ms: u64,
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Write which node gossiped which value to whom, and when, to PATH"
+    )]
+    gossip_trace: Option<PathBuf>,
+    #[arg(
+        long,
+        default_value = "json",
+        help = "Format of the gossip trace: json (lines) or dot (graphviz)"
+    )]
+    trace_format: TraceFormat,
+}
+
+// what a run sent, got back and found, timestamps in virtual ms.
+#[derive(Debug, Default)]
+struct Outcome {
+    requests: Vec<(u64, Message)>,
+    replies: Vec<(u64, Message)>,
+    delivered: usize,
+    // since the last request, broadcast only.
+    converged_after: Option<u64>,
+    failures: Vec<String>,
+}
+
+fn random_request(workload: &str, rng: &mut Rng, nodes: usize, msg_id: MessageId) -> Message {
+    let client = format!("c{}", rng.between(1, 3));
+    let dest = format!("n{}", rng.between(1, nodes as u64));
+    let request = msg().from(&client).to(&dest).id(msg_id);
+    match workload {
+        "echo" => request.echo(&format!("hello {msg_id}")),
+        "unique-ids" => request.generate(),
+        "broadcast" => request.broadcast(msg_id.into()),
+        _ => {
+            let key = rng.between(0, 2);
+            match rng.between(0, 2) {
+                0 => request.kv_read(key.into()),
+                1 => request.write(key.into(), rng.between(0, 4).into()),
+                _ => request.cas(
+                    key.into(),
+                    rng.between(0, 4).into(),
+                    rng.between(0, 4).into(),
+                ),
+            }
+        }
+    }
+}
+
+fn simulate(cli: &Cli, script: Option<Vec<Message>>) -> Result<Outcome, String> {
+    let workload = cli.workload.as_str();
+    if !["echo", "unique-ids", "broadcast", "lin-kv"].contains(&workload) {
+        return Err(format!("no checks for workload {workload:?}"));
+    }
+    let config = Config::default();
+    let factory = || workloads::by_name(workload, &config).expect("Workload should exist.");
+    let mut network = Network::new(cli.nodes.max(1), factory).with_seed(cli.seed);
+    network.set_delay(cli.min_delay_ms, cli.max_delay_ms.max(cli.min_delay_ms));
+    network.set_drop_probability(cli.drop);
+    if let Some(path) = &cli.gossip_trace {
+        let file = File::create(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        let trace = GossipTrace::new(BufWriter::new(file), cli.trace_format);
+        network = network.with_gossip_trace(trace);
+    }
+    let mut outcome = Outcome::default();
+
+    // broadcast gossips over a binary tree.
+    if workload == "broadcast" {
+        let node_ids = network.node_ids();
+        let topology = generate(TopologyStrategy::Tree, &node_ids, 2).unwrap_or_default();
+        for node_id in node_ids {
+            let body = Workload::Topology {
+                msg_id: 0,
+                topology: topology.clone(),
+            };
+            network.send(msg().from("c0").to(&node_id).body(body));
+        }
+        outcome.delivered += network.run_for(TICK_MS, TICK_MS);
+        network.take_outbox();
+    }
+
+    let requests = match script {
+        Some(script) => script,
+        None => (1..=cli.requests)
+            .map(|msg_id| {
+                random_request(
+                    workload,
+                    network.rng(),
+                    cli.nodes.max(1),
+                    msg_id as MessageId,
+                )
+            })
+            .collect(),
+    };
+    for request in requests {
+        outcome.requests.push((network.now(), request.clone()));
+        network.send(request);
+        outcome.delivered += network.run_for(cli.interval_ms, TICK_MS);
+        let now = network.now();
+        outcome
+            .replies
+            .extend(network.take_outbox().into_iter().map(|reply| (now, reply)));
+    }
+
+    let last_request = network.now();
+    let mut settled = 0;
+    while settled < cli.settle_ms {
+        let pending = workload == "broadcast" && outcome.converged_after.is_none();
+        if pending && converged(&network, &outcome).is_ok() {
+            outcome.converged_after = Some(network.now() - last_request);
+        }
+        outcome.delivered += network.run_for(TICK_MS, TICK_MS);
+        settled += TICK_MS;
+        let now = network.now();
+        outcome
+            .replies
+            .extend(network.take_outbox().into_iter().map(|reply| (now, reply)));
+    }
+
+    outcome.failures = check(workload, &network, &outcome);
+    Ok(outcome)
+}
+
+// requests without a reply to their client.
+fn unanswered(outcome: &Outcome) -> usize {
+    let answered: HashSet<(&NodeId, MessageId)> = outcome
+        .replies
+        .iter()
+        .filter_map(|(_, reply)| Some((&reply.dest, reply.body.in_reply_to()?)))
+        .collect();
+    outcome
+        .requests
+        .iter()
+        .filter(|(_, request)| {
+            let msg_id = request.body.msg_id().unwrap_or_default();
+            !answered.contains(&(&request.src, msg_id))
+        })
+        .count()
+}
+
+// every node has every value any node has, acknowledged ones included.
+fn converged(network: &Network, outcome: &Outcome) -> Result<(), NotConverged> {
+    let mut convergence = Convergence::default();
+    for node_id in network.node_ids() {
+        let values = network.node(&node_id).broadcast_messages();
+        values.iter().for_each(|value| convergence.inject(*value));
+        convergence.read(node_id, values.iter().copied());
+    }
+    for (_, request) in &outcome.requests {
+        let Workload::Broadcast {
+            msg_id, message, ..
+        } = request.body
+        else {
+            continue;
+        };
+        let ok = outcome.replies.iter().any(|(_, reply)| {
+            reply.dest == request.src
+                && reply.body.name() == "broadcast_ok"
+                && reply.body.in_reply_to() == Some(msg_id)
+        });
+        if ok {
+            convergence.inject(message);
+        }
+    }
+    convergence.check()
+}
+
+fn check(workload: &str, network: &Network, outcome: &Outcome) -> Vec<String> {
+    let mut failures = Vec::new();
+    let unanswered = unanswered(outcome);
+    if unanswered > 0 {
+        failures.push(format!("{unanswered} requests got no reply"));
+    }
+    let errors = outcome
+        .replies
+        .iter()
+        .filter(|(_, reply)| reply.body.name() == "error")
+        .count();
+    match workload {
+        "echo" => {
+            let requests: HashMap<(&NodeId, MessageId), &String> = outcome
+                .requests
+                .iter()
+                .filter_map(|(_, request)| match &request.body {
+                    Workload::Echo { msg_id, echo } => Some(((&request.src, *msg_id), echo)),
+                    _ => None,
+                })
+                .collect();
+            for (_, reply) in &outcome.replies {
+                if let Workload::EchoOk {
+                    in_reply_to, echo, ..
+                } = &reply.body
+                {
+                    if requests.get(&(&reply.dest, *in_reply_to)) != Some(&echo) {
+                        failures.push(format!("{reply:?} doesn't echo its request"));
+                    }
+                }
+            }
+        }
+        "unique-ids" => {
+            let mut ids = HashSet::new();
+            let mut duplicates = 0;
+            for (_, reply) in &outcome.replies {
+                if let Workload::GenerateOk { id, ids: batch, .. } = &reply.body {
+                    for id in id.iter().chain(batch.iter().flatten()) {
+                        duplicates += usize::from(!ids.insert(id.clone()));
+                    }
+                }
+            }
+            if duplicates > 0 {
+                failures.push(format!("{duplicates} duplicate ids"));
+            }
+        }
+        "broadcast" => {
+            if let Err(error) = converged(network, outcome) {
+                failures.push(error.to_string());
+            }
+        }
+        _ => {
+            let mut history = History::default();
+            for (at, request) in &outcome.requests {
+                history.invoke(*at, request);
+            }
+            for (at, reply) in &outcome.replies {
+                history.complete(*at, reply);
+            }
+            if let Err(error) = history.check() {
+                failures.push(format!("not linearizable, {error}"));
+            }
+            return failures; // errors are expected answers to a kv.
+        }
+    }
+    if errors > 0 {
+        failures.push(format!("{errors} requests failed"));
+    }
+    failures
+}
+
+fn read_script(path: &PathBuf) -> Result<Vec<Message>, String> {
+    let script = fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    script
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line).map_err(|e| format!("{}:{}: {e}", path.display(), i + 1))
+        })
+        .collect()
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let script = match cli.script.as_ref().map(read_script).transpose() {
+        Ok(script) => script,
+        Err(error) => {
+            eprintln!("{error}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let outcome = match simulate(&cli, script) {
+        Ok(outcome) => outcome,
+        Err(error) => {
+            eprintln!("{error}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    println!(
+        "{} on {} nodes, seed {}: {} requests, {} replies, {} messages delivered",
+        cli.workload,
+        cli.nodes,
+        cli.seed,
+        outcome.requests.len(),
+        outcome.replies.len(),
+        outcome.delivered,
+    );
+    if let Some(after) = outcome.converged_after {
+        println!("converged {after}ms after the last request");
+    }
+    if outcome.failures.is_empty() {
+        println!("ok");
+        return ExitCode::SUCCESS;
+    }
+    for failure in &outcome.failures {
+        println!("FAILED: {failure}");
+    }
+    ExitCode::FAILURE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    fn cli(args: &[&str]) -> Cli {
+        Cli::try_parse_from([&["simulate"], args].concat()).unwrap()
+    }
+
+    #[test]
+    fn test_simulate_workloads() {
+        Cli::command().debug_assert();
+        for workload in ["echo", "unique-ids", "broadcast"] {
+            let outcome = simulate(&cli(&[workload, "--requests", "30"]), None).unwrap();
+            assert_eq!(outcome.requests.len(), 30);
+            assert!(
+                outcome.failures.is_empty(),
+                "{workload}: {:?}",
+                outcome.failures
+            );
+        }
+
+        // a single store is linearizable, one per node isn't.
+        let outcome = simulate(&cli(&["lin-kv", "--nodes", "1"]), None).unwrap();
+        assert!(outcome.failures.is_empty(), "{:?}", outcome.failures);
+        let outcome = simulate(&cli(&["lin-kv", "--nodes", "3", "--seed", "1"]), None).unwrap();
+        assert!(outcome.failures[0].starts_with("not linearizable"));
+
+        assert!(simulate(&cli(&["kafka"]), None).is_err());
+    }
+
+    #[test]
+    fn test_simulate_broadcast_lossy() {
+        let args = [
+            "broadcast",
+            "--drop",
+            "0.3",
+            "--requests",
+            "20",
+            "--seed",
+            "7",
+        ];
+        let outcome = simulate(&cli(&args), None).unwrap();
+        assert!(outcome.failures.is_empty(), "{:?}", outcome.failures);
+        assert!(outcome.converged_after.is_some());
+    }
+
+    #[test]
+    fn test_simulate_gossip_trace() {
+        let path = std::env::temp_dir().join(format!("simulate-trace-{}", std::process::id()));
+        let args = ["broadcast", "--nodes", "3", "--requests", "4"];
+        let args = [&args[..], &["--gossip-trace", path.to_str().unwrap()]].concat();
+        let outcome = simulate(&cli(&args), None).unwrap();
+        assert!(outcome.failures.is_empty(), "{:?}", outcome.failures);
+
+        // a binary tree of 3 nodes: every value crosses both edges, once each way at most.
+        let trace = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let edges: Vec<serde_json::Value> = trace
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert!((8..=16).contains(&edges.len()), "{trace}");
+        assert!(edges.iter().all(|edge| edge["from"] != edge["to"]));
+    }
+
+    #[test]
+    fn test_simulate_script() {
+        let script = vec![
+            msg().to("n2").id(1).broadcast(5),
+            msg().from("c2").to("n1").id(1).broadcast(6),
+            msg().to("n3").id(2).read(),
+        ];
+        let outcome = simulate(&cli(&["broadcast", "--nodes", "3"]), Some(script)).unwrap();
+        assert_eq!(outcome.replies.len(), 3);
+        assert!(outcome.failures.is_empty(), "{:?}", outcome.failures);
+        assert_eq!(outcome.converged_after, Some(0));
+    }
+}
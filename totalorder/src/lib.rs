@@ -0,0 +1,216 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use node::config::Config;
+use node::prelude::*;
+use node::sequencer::Seq;
+
+// numbers "message" and sends it to every other node, until they acknowledge it: a node missing
+// one would hold back every message after it.
+fn sequence(node: &mut Node, message: BroadcastMessage, out: &mut dyn Sink) -> Result<Seq> {
+    let seq = node.sequencer_mut().assign();
+    node.sequencer_mut().receive(seq, message);
+
+    for peer in node.peers() {
+        let body = Workload::Deliver {
+            msg_id: node.gen_msg_id(),
+            seq,
+            message,
+        };
+        out.send(node.send_reliably(peer, body)?);
+    }
+    Ok(seq)
+}
+
+// acknowledged once the message has its place in the order, a message the sequencer never got
+// would be lost otherwise.
+fn handler_broadcast(node: &mut Node, msg: Message, out: &mut dyn Sink) -> Result<()> {
+    expect_body!(
+        msg,
+        Broadcast {
+            msg_id,
+            message,
+            ..
+        }
+    );
+    let sequencer = node.node_ids().first().cloned().unwrap_or(node.node_id());
+    if sequencer == node.node_id() {
+        sequence(node, message, out)?;
+        out.send(node.reply_to((msg.src, msg_id), Workload::broadcast_ok));
+        return Ok(());
+    }
+
+    let body = Workload::Sequence {
+        msg_id: node.gen_msg_id(),
+        message,
+    };
+    let src = msg.src;
+    let request = node.rpc_reliably(sequencer, body, move |node, reply| match reply.body {
+        Workload::SequenceOk { .. } => Ok(smallvec![
+            node.reply_to((src, msg_id), Workload::broadcast_ok)
+        ]),
+        body => Err(Box::new(Error::ExpectedMessage {
+            found: body.key(),
+            expected: Type::SequenceOk,
+        })),
+    })?;
+    out.send(request);
+    Ok(())
+}
+
+// a retry of a "sequence" already numbered is answered from the reply cache, not numbered twice.
+fn handler_sequence(node: &mut Node, msg: Message, out: &mut dyn Sink) -> Result<()> {
+    expect_body!(msg, Sequence { msg_id, message });
+    let seq = sequence(node, message, out)?;
+    out.send(node.reply_to((msg.src, msg_id), |in_reply_to, msg_id| {
+        Workload::sequence_ok(in_reply_to, msg_id, seq)
+    }));
+    Ok(())
+}
+
+// duplicates are ignored by the sequencer, and acknowledged again.
+fn handler_deliver(node: &mut Node, msg: Message, out: &mut dyn Sink) -> Result<()> {
+    expect_body!(
+        msg,
+        Deliver {
+            msg_id,
+            seq,
+            message
+        }
+    );
+    node.sequencer_mut().receive(seq, message);
+    out.send(node.reply_to((msg.src, msg_id), Workload::deliver_ok));
+    Ok(())
+}
+
+fn handler_read(node: &mut Node, msg: Message, out: &mut dyn Sink) -> Result<()> {
+    expect_body!(msg, Read { msg_id, .. });
+    let messages = Some(Arc::new(node.sequencer().delivered().to_vec()));
+    let reply = node.reply_to((msg.src, msg_id), |in_reply_to, msg_id| Workload::ReadOk {
+        in_reply_to,
+        msg_id,
+        messages,
+        value: None,
+    });
+    out.send(reply);
+    Ok(())
+}
+
+// the sequencer sends to everyone, the topology is irrelevant.
+fn handler_topology(node: &mut Node, msg: Message, out: &mut dyn Sink) -> Result<()> {
+    expect_body!(msg, Topology { msg_id, .. });
+    out.send(node.reply_to((msg.src, msg_id), Workload::topology_ok));
+    Ok(())
+}
+
+pub fn create_node(config: &Config) -> Node {
+    let mut handlers: HashMap<Type, Handler> = HashMap::new();
+    handlers.insert(Type::Broadcast, handler_broadcast);
+    handlers.insert(Type::Sequence, handler_sequence);
+    handlers.insert(Type::Deliver, handler_deliver);
+    handlers.insert(Type::Read, handler_read);
+    handlers.insert(Type::Topology, handler_topology);
+    let mut node = Node::with_config(handlers, config);
+    node.enable_reply_cache(1_000);
+    node
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn init(node_id: &str) -> Node {
+        let mut node = create_node(&Config::default());
+        let init_json = format!(
+            r#"{{"src":"c1","dest":"{node_id}","body":{{"type":"init","msg_id":1,"node_id":"{node_id}","node_ids":["n1","n2"]}}}}"#
+        );
+        let init_message = serde_json::from_str::<Message>(&init_json).unwrap();
+        let _ = node.process(init_message);
+        node
+    }
+
+    // a fresh "msg_id" every time, a repeated one would be answered from the reply cache.
+    fn read(node: &mut Node, msg_id: MessageId) -> String {
+        let read_json =
+            format!(r#"{{"src":"c1","dest":"n1","body":{{"type":"read","msg_id":{msg_id}}}}}"#);
+        let read_message = serde_json::from_str::<Message>(&read_json).unwrap();
+        let reply = node.process(read_message).unwrap();
+        match &reply[0].body {
+            Workload::ReadOk { messages, .. } => format!("{messages:?}"),
+            _ => String::new(),
+        }
+    }
+
+    #[test]
+    fn test_totalorder() {
+        let mut sequencer = init("n1");
+        let mut follower = init("n2");
+
+        // n2 forwards to the sequencer, and acknowledges once the message is numbered.
+        let broadcast_json =
+            r#"{"src":"c1","dest":"n2","body":{"type":"broadcast","message":7,"msg_id":1}}"#;
+        let broadcast_message = serde_json::from_str::<Message>(broadcast_json).unwrap();
+        let replies = follower.process(broadcast_message).unwrap();
+        assert_eq!(replies.len(), 1);
+        assert_eq!(replies[0].dest, "n1");
+        assert!(matches!(replies[0].body, Workload::Sequence { .. }));
+
+        let broadcast_json =
+            r#"{"src":"c2","dest":"n1","body":{"type":"broadcast","message":3,"msg_id":1}}"#;
+        let broadcast_message = serde_json::from_str::<Message>(broadcast_json).unwrap();
+        let deliver_3 = sequencer.process(broadcast_message).unwrap().remove(0);
+        let mut sequenced = sequencer.process(replies[0].clone()).unwrap();
+        let deliver_7 = sequenced.remove(0);
+        assert!(matches!(
+            sequenced[0].body,
+            Workload::SequenceOk { seq: 1, .. }
+        ));
+        let broadcast_ok = follower.process(sequenced.remove(0)).unwrap();
+        assert_eq!(broadcast_ok[0].dest, "c1");
+        assert!(matches!(
+            broadcast_ok[0].body,
+            Workload::BroadcastOk { in_reply_to: 1, .. }
+        ));
+
+        // deliveries arrive out of order, but are applied in sequence order.
+        let deliver_ok = follower.process(deliver_7).unwrap();
+        assert!(matches!(deliver_ok[0].body, Workload::DeliverOk { .. }));
+        assert_eq!(read(&mut follower, 9), "Some([])");
+        follower.process(deliver_3).unwrap();
+        assert_eq!(read(&mut follower, 10), "Some([3, 7])");
+        assert_eq!(read(&mut sequencer, 11), "Some([3, 7])");
+    }
+
+    #[test]
+    fn test_totalorder_retries() {
+        let mut sequencer = init("n1");
+        let mut follower = init("n2");
+
+        let broadcast_json =
+            r#"{"src":"c1","dest":"n2","body":{"type":"broadcast","message":7,"msg_id":1}}"#;
+        let broadcast_message = serde_json::from_str::<Message>(broadcast_json).unwrap();
+        let sequence = follower.process(broadcast_message).unwrap().remove(0);
+
+        // the "sequence_ok" is lost, the retry is answered again without a second number.
+        let first = sequencer.process(sequence.clone()).unwrap();
+        let deliver = first[0].clone();
+        let retry = sequencer.process(sequence).unwrap();
+        assert_eq!(retry.len(), 1);
+        assert_eq!(
+            format!("{:?}", retry[0].body),
+            format!("{:?}", first[1].body)
+        );
+        assert_eq!(sequencer.sequencer().delivered(), &[7]);
+
+        // an unacknowledged "deliver" is still pending on the sequencer, acked by "deliver_ok".
+        assert_eq!(sequencer.outbox().unwrap().len(), 1);
+        let deliver_ok = follower.process(deliver).unwrap().remove(0);
+        sequencer.process(deliver_ok).unwrap();
+        assert!(sequencer.outbox().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_conformance() {
+        conformance::check(|| create_node(&Config::default()));
+    }
+}
@@ -0,0 +1,277 @@
+use std::collections::HashMap;
+
+use node::config::Config;
+use node::prelude::*;
+use node::txn::{write_set, Op};
+use serde::{Deserialize, Serialize};
+
+// the workload's own messages, see "Body".
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Replication {
+    // replicates the write set of a committed transaction, applied atomically by peers.
+    TxnReplicate {
+        msg_id: MessageId,
+        txn: Vec<Op>,
+        #[serde(default)]
+        lamport: Timestamp,
+    },
+    TxnReplicateOk {
+        in_reply_to: MessageId,
+        msg_id: MessageId,
+    },
+}
+
+impl Body for Replication {
+    const TYPES: &'static [&'static str] = &["txn_replicate", "txn_replicate_ok"];
+}
+
+fn handler_txn(node: &mut Node, msg: Message, out: &mut dyn Sink) -> Result<()> {
+    expect_body!(msg, Txn { msg_id, txn });
+    let stamp = node.lamport().tick();
+    let node_id = node.node_id();
+    let txn = match node.store_mut().execute_at(txn, stamp, &node_id) {
+        Ok(txn) => txn,
+        Err((code, text)) => {
+            out.send(node.reply(msg.src, Workload::error(msg_id, code, text)));
+            return Ok(());
+        }
+    };
+
+    // replicate asynchronously, the client doesn't wait for peers (total availability). a
+    // write set is sent until the peer acknowledges it, so a peer cut off by a partition gets
+    // it once the partition heals.
+    let writes = write_set(&txn);
+    if !writes.is_empty() {
+        let peers = node.node_ids().to_vec();
+        for peer in peers.into_iter().filter(|peer| *peer != node_id) {
+            let body = Replication::TxnReplicate {
+                msg_id: node.gen_msg_id(),
+                txn: writes.clone(),
+                // replicas order the writes by the stamp they ran at.
+                lamport: stamp,
+            };
+            out.send(node.send_reliably(peer, Workload::custom(&body)?)?);
+        }
+    }
+
+    let reply = node.reply_to((msg.src, msg_id), |in_reply_to, msg_id| {
+        Workload::txn_ok(in_reply_to, msg_id, txn)
+    });
+    out.send(reply);
+    Ok(())
+}
+
+fn handler_replication(
+    node: &mut Node,
+    msg: Message<Replication>,
+    out: &mut dyn Sink,
+) -> Result<()> {
+    let (msg_id, txn, lamport) = match msg.body {
+        Replication::TxnReplicate {
+            msg_id,
+            txn,
+            lamport,
+        } => (msg_id, txn, lamport),
+        // an acknowledgement the outbox had already, e.g. of a write set sent twice.
+        Replication::TxnReplicateOk { .. } => return Ok(()),
+    };
+    // the origin checked the appends against its own store, a replica holding something else
+    // (a write the origin hadn't seen yet) skips those appends and applies the rest.
+    node.store_mut().apply_at(txn, lamport, &msg.src);
+    let reply = Replication::TxnReplicateOk {
+        in_reply_to: msg_id,
+        msg_id: node.gen_msg_id(),
+    };
+    out.send(node.reply(msg.src, Workload::custom(&reply)?));
+    Ok(())
+}
+
+pub fn create_node(config: &Config) -> Node {
+    let mut handlers: HashMap<Type, Handler> = HashMap::new();
+    handlers.insert(Type::Txn, handler_txn);
+    let mut node = Node::with_config(handlers, config);
+    node.register_body(handler_replication);
+    // write sets are sent again until acknowledged, a retry mustn't append twice.
+    node.enable_reply_cache(config.reply_cache);
+    node.enable_sessions();
+    node
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use node::testing::message::{init, msg};
+    use serde_json::json;
+
+    // a replication message from a peer.
+    fn replication(src: &str, body: Replication) -> Message {
+        msg().from(src).body(Workload::custom(&body).unwrap())
+    }
+
+    #[test]
+    fn test_txn() {
+        let mut node = create_node(&Config::default());
+        init(&mut node, "n1", &["n1"]);
+
+        let txn = vec![
+            Op::Read(1, None),
+            Op::Write(1, json!(6)),
+            Op::Write(2, json!(9)),
+            Op::Read(1, None),
+        ];
+        let reply = node.process(msg().id(3).txn(txn));
+        assert!(reply.is_ok());
+
+        let reply = serde_json::to_string(&reply.unwrap().first().unwrap()).unwrap();
+        assert_eq!(
+            reply,
+            r#"{"src":"n1","dest":"c1","body":{"type":"txn_ok","in_reply_to":3,"msg_id":1,"txn":[["r",1,null],["w",1,6],["w",2,9],["r",1,6]]}}"#
+        );
+    }
+
+    #[test]
+    fn test_txn_list_append() {
+        let mut node = create_node(&Config::default());
+        init(&mut node, "n1", &["n1"]);
+
+        let txn = vec![
+            Op::Append(1, json!(6)),
+            Op::Append(1, json!(7)),
+            Op::Read(1, None),
+        ];
+        let reply = serde_json::to_string(&node.process(msg().id(3).txn(txn)).unwrap()[0]).unwrap();
+        assert_eq!(
+            reply,
+            r#"{"src":"n1","dest":"c1","body":{"type":"txn_ok","in_reply_to":3,"msg_id":1,"txn":[["append",1,6],["append",1,7],["r",1,[6,7]]]}}"#
+        );
+    }
+
+    #[test]
+    fn test_txn_append_to_non_list() {
+        let mut node = create_node(&Config::default());
+        init(&mut node, "n1", &["n1"]);
+
+        let txn = vec![Op::Write(1, json!(6)), Op::Append(1, json!(7))];
+        let reply = serde_json::to_string(&node.process(msg().id(3).txn(txn)).unwrap()[0]).unwrap();
+        assert!(reply.contains(r#""type":"error""#), "{reply}");
+        assert!(reply.contains(r#""code":30"#), "{reply}");
+    }
+
+    #[test]
+    fn test_txn_replicate() {
+        let mut node = create_node(&Config::default());
+        init(&mut node, "n1", &["n1", "n2"]);
+
+        let txn = vec![
+            Op::Write(1, json!(5)),
+            Op::Write(1, json!(6)),
+            Op::Read(2, None),
+        ];
+        let reply = node.process(msg().id(3).txn(txn)).unwrap();
+        assert_eq!(reply.len(), 2);
+        assert_eq!(reply[0].dest, "n2");
+        assert_eq!(
+            reply[0].clone().decode::<Replication>().unwrap().body,
+            Replication::TxnReplicate {
+                msg_id: 1,
+                txn: vec![Op::Write(1, json!(6))],
+                lamport: 1,
+            }
+        );
+
+        // the write set is sent until n2 acknowledges it.
+        assert_eq!(node.outbox().unwrap().len(), 1);
+        let ack = Replication::TxnReplicateOk {
+            in_reply_to: 1,
+            msg_id: 3,
+        };
+        let _ = node.process(replication("n2", ack));
+        assert!(node.outbox().unwrap().is_empty());
+
+        let replicate = Replication::TxnReplicate {
+            msg_id: 4,
+            txn: vec![Op::Write(2, json!(7))],
+            lamport: 0,
+        };
+        let reply = node.process(replication("n2", replicate)).unwrap();
+        assert_eq!(
+            serde_json::to_string(&reply[0]).unwrap(),
+            r#"{"src":"n1","dest":"n2","body":{"in_reply_to":4,"msg_id":3,"type":"txn_replicate_ok"}}"#
+        );
+
+        let reply = node.process(msg().id(5).txn(vec![Op::Read(2, None)]));
+        let reply = serde_json::to_string(&reply.unwrap()[0]).unwrap();
+        assert_eq!(
+            reply,
+            r#"{"src":"n1","dest":"c1","body":{"type":"txn_ok","in_reply_to":5,"msg_id":4,"txn":[["r",2,7]]}}"#
+        );
+    }
+
+    #[test]
+    fn test_txn_replicate_conflicting() {
+        let mut node = create_node(&Config::default());
+        init(&mut node, "n1", &["n1", "n2"]);
+        let mut process = |message: Message| {
+            let replies = node.process(message).unwrap();
+            serde_json::to_string(&replies.last()).unwrap()
+        };
+
+        process(msg().id(2).txn(vec![Op::Write(1, json!(5))]));
+        // n2 appended to 1 before it saw the write: the append is skipped, the rest applied,
+        // and the write set acknowledged all the same.
+        let replicate = || {
+            let body = Replication::TxnReplicate {
+                msg_id: 4,
+                txn: vec![Op::Append(1, json!(6)), Op::Append(2, json!(7))],
+                lamport: 1,
+            };
+            replication("n2", body)
+        };
+        let reply = process(replicate());
+        assert!(reply.contains(r#""type":"txn_replicate_ok""#), "{reply}");
+        // a retry is acknowledged again, and not appended twice.
+        let reply = process(replicate());
+        assert!(reply.contains(r#""type":"txn_replicate_ok""#), "{reply}");
+        let reply = process(msg().id(3).txn(vec![Op::Read(1, None), Op::Read(2, None)]));
+        assert!(
+            reply.contains(r#""txn":[["r",1,5],["r",2,[7]]]"#),
+            "{reply}"
+        );
+    }
+
+    #[test]
+    fn test_txn_session() {
+        let mut node = create_node(&Config::default());
+        init(&mut node, "n1", &["n1", "n2"]);
+        let mut process = |message: Message| {
+            // the reply to the client comes after what's replicated.
+            serde_json::to_string(&node.process(message).unwrap().last()).unwrap()
+        };
+
+        process(msg().id(1).txn(vec![Op::Read(1, None)]));
+        process(msg().id(2).txn(vec![Op::Write(1, json!(5))]));
+        // an older write from n2 arrives after it.
+        let older = Replication::TxnReplicate {
+            msg_id: 1,
+            txn: vec![Op::Write(1, json!(3))],
+            lamport: 1,
+        };
+        process(replication("n2", older));
+        let reply = process(msg().id(3).txn(vec![Op::Read(1, None)]));
+        assert!(reply.contains(r#""txn":[["r",1,5]]"#), "{reply}");
+        // the older write is skipped for everyone, not just the client that saw the newer one.
+        let reply = process(msg().from("c2").id(1).txn(vec![Op::Read(1, None)]));
+        assert!(reply.contains(r#""txn":[["r",1,5]]"#), "{reply}");
+
+        // a stale duplicate is rejected.
+        let reply = process(msg().id(2).txn(vec![Op::Write(1, json!(9))]));
+        assert!(reply.contains(r#""type":"error""#), "{reply}");
+        assert!(reply.contains(r#""code":14"#), "{reply}");
+    }
+
+    #[test]
+    fn test_conformance() {
+        conformance::check(|| create_node(&Config::default()));
+    }
+}
@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+
+use node::config::Config;
+use node::prelude::*;
+
+// a batch bigger than this is refused rather than built in memory.
+const MAX_BATCH: usize = 100_000;
+
+fn handler_generate(node: &mut Node, msg: Message, out: &mut dyn Sink) -> Result<()> {
+    expect_body!(msg, Generate { msg_id, n });
+    let reply = match n {
+        None => {
+            let id = node.gen_unique_id()?;
+            node.reply_to((msg.src, msg_id), |in_reply_to, msg_id| {
+                Workload::generate_ok(in_reply_to, msg_id, id)
+            })
+        }
+        Some(n) if n <= MAX_BATCH => {
+            let ids = node.gen_unique_ids(n)?;
+            node.reply_to((msg.src, msg_id), |in_reply_to, msg_id| {
+                Workload::generate_batch_ok(in_reply_to, msg_id, ids)
+            })
+        }
+        Some(n) => {
+            let text = format!("batch of {n} ids, at most {MAX_BATCH} allowed");
+            node.reply(
+                msg.src,
+                Workload::error(msg_id, code::MALFORMED_REQUEST, text),
+            )
+        }
+    };
+    out.send(reply);
+    Ok(())
+}
+
+pub fn create_node(config: &Config) -> Node {
+    let mut handlers: HashMap<Type, Handler> = HashMap::new();
+    handlers.insert(Type::Generate, handler_generate);
+    Node::with_config(handlers, config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uniqueids() {
+        let mut node = create_node(&Config::default());
+        let init_json = r#"{"src":"c1","dest":"n1","body":{"type":"init","msg_id":1,"node_id":"n1","node_ids":["n1","n2","n3"]}}"#;
+        let init_message = serde_json::from_str::<Message>(init_json).unwrap();
+        let _ = node.process(init_message);
+
+        let generate_json = r#"{"src":"c1","dest":"n1","body":{"type":"generate","msg_id":1}}"#;
+        let generate_message = serde_json::from_str::<Message>(generate_json).unwrap();
+        let reply = node.process(generate_message);
+        assert!(reply.is_ok());
+        assert!(match reply.unwrap().first().unwrap().body {
+            Workload::GenerateOk { in_reply_to, .. } => in_reply_to == 1,
+            _ => false,
+        });
+    }
+
+    #[test]
+    fn test_uniqueids_batch() {
+        let mut node = create_node(&Config::default());
+        let init_json = r#"{"src":"c1","dest":"n1","body":{"type":"init","msg_id":1,"node_id":"n1","node_ids":["n1","n2"]}}"#;
+        let _ = node.process(serde_json::from_str::<Message>(init_json).unwrap());
+
+        let generate_json =
+            r#"{"src":"c1","dest":"n1","body":{"type":"generate","msg_id":2,"n":1000}}"#;
+        let reply = node.process(serde_json::from_str::<Message>(generate_json).unwrap());
+        let ids = match &reply.unwrap()[0].body {
+            Workload::GenerateOk {
+                in_reply_to: 2,
+                id: None,
+                ids: Some(ids),
+                ..
+            } => ids.clone(),
+            body => panic!("unexpected reply {body:?}"),
+        };
+        assert_eq!(
+            ids.iter().collect::<std::collections::HashSet<_>>().len(),
+            1000
+        );
+
+        let generate_json =
+            r#"{"src":"c1","dest":"n1","body":{"type":"generate","msg_id":3,"n":1000000}}"#;
+        let reply = node.process(serde_json::from_str::<Message>(generate_json).unwrap());
+        assert!(matches!(
+            reply.unwrap()[0].body,
+            Workload::Error {
+                code: code::MALFORMED_REQUEST,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_conformance() {
+        conformance::check(|| create_node(&Config::default()));
+    }
+}
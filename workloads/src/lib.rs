@@ -0,0 +1,77 @@
+// every solution as a constructor of its node, for whatever runs one without its own binary:
+// the "glomers" binary, tests, simulations. "workloads::broadcast(&Config::from_env()?)" is
+// the node "target/release/broadcast" runs.
+use node::config::Config;
+use node::core::Node;
+
+pub fn echo(config: &Config) -> Node {
+    ::echo::create_node(config)
+}
+
+pub fn unique_ids(config: &Config) -> Node {
+    uniqueids::create_node(config)
+}
+
+pub fn broadcast(config: &Config) -> Node {
+    ::broadcast::create_node(config)
+}
+
+pub fn kafka(config: &Config) -> Node {
+    ::kafka::create_node(config)
+}
+
+pub fn txn(config: &Config) -> Node {
+    ::txn::create_node(config)
+}
+
+pub fn lin_kv(config: &Config) -> Node {
+    linkv::create_node(config)
+}
+
+pub fn total_order(config: &Config) -> Node {
+    totalorder::create_node(config)
+}
+
+// the names "by_name" knows, as maelstrom calls the workloads.
+pub const NAMES: &[&str] = &[
+    "echo",
+    "unique-ids",
+    "broadcast",
+    "kafka",
+    "txn",
+    "lin-kv",
+    "total-order",
+];
+
+// a workload, by one of "NAMES" or the name of its crate ("uniqueids").
+pub fn by_name(name: &str, config: &Config) -> Option<Node> {
+    let node = match name {
+        "echo" => echo(config),
+        "unique-ids" | "uniqueids" => unique_ids(config),
+        "broadcast" => broadcast(config),
+        "kafka" => kafka(config),
+        "txn" => txn(config),
+        "lin-kv" | "linkv" => lin_kv(config),
+        "total-order" | "totalorder" => total_order(config),
+        _ => return None,
+    };
+    Some(node)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use node::testing::message::init;
+
+    #[test]
+    fn test_workloads_by_name() {
+        for name in NAMES {
+            let mut node =
+                by_name(name, &Config::default()).unwrap_or_else(|| panic!("{name} should exist"));
+            init(&mut node, "n1", &["n1", "n2"]);
+            assert_eq!(node.node_id(), "n1");
+        }
+        assert!(by_name("linkv", &Config::default()).is_some());
+        assert!(by_name("counter", &Config::default()).is_none());
+    }
+}